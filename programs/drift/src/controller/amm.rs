@@ -167,10 +167,25 @@ pub fn update_spread_reserves(amm: &mut AMM) -> DriftResult {
 }
 
 pub fn update_spreads(amm: &mut AMM, reserve_price: u64) -> DriftResult<(u32, u32)> {
+    let inventory_skew = amm.calculate_inventory_skew()?;
+
+    // with inventory skew, retreat from the reference price the reserves are re-centered on
+    let oracle_reserve_price_spread_pct = if amm.inventory_skew_enabled()
+        && amm.historical_oracle_data.last_oracle_price > 0
+    {
+        let reference_price = amm_spread::calculate_reference_price(
+            amm.historical_oracle_data.last_oracle_price,
+            inventory_skew,
+        )?;
+        amm_spread::calculate_reference_reserve_price_spread_pct(reserve_price, reference_price)?
+    } else {
+        amm.last_oracle_reserve_price_spread_pct
+    };
+
     let (long_spread, short_spread) = if amm.curve_update_intensity > 0 {
         amm_spread::calculate_spread(
             amm.base_spread,
-            amm.last_oracle_reserve_price_spread_pct,
+            oracle_reserve_price_spread_pct,
             amm.last_oracle_conf_pct,
             amm.max_spread,
             amm.quote_asset_reserve,
@@ -196,6 +211,7 @@ pub fn update_spreads(amm: &mut AMM, reserve_price: u64) -> DriftResult<(u32, u3
 
     amm.long_spread = long_spread;
     amm.short_spread = short_spread;
+    amm.inventory_skew = inventory_skew.cast()?;
 
     update_spread_reserves(amm)?;
 
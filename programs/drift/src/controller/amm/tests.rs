@@ -99,6 +99,39 @@ fn concentration_coef_tests() {
     assert_eq!(open_asks, -28000000000);
 }
 
+#[test]
+fn update_spreads_with_inventory_skew() {
+    let amm = AMM {
+        base_asset_amount_with_amm: 50 * AMM_RESERVE_PRECISION as i128,
+        base_spread: 100,
+        curve_update_intensity: 100,
+        ..AMM::default_test()
+    };
+    let reserve_price = amm.reserve_price().unwrap();
+    assert_eq!(reserve_price, PRICE_PRECISION_I64 as u64);
+
+    let mut oracle_amm = amm;
+    update_spreads(&mut oracle_amm, reserve_price).unwrap();
+    assert_eq!(oracle_amm.inventory_skew, 0);
+
+    // users long half the liquidity available to unload, reference price is half the max skew above the oracle
+    let mut skewed_amm = AMM {
+        max_inventory_skew: 1000,
+        ..amm
+    };
+    assert_eq!(
+        skewed_amm.reference_price(PRICE_PRECISION_I64).unwrap(),
+        PRICE_PRECISION_I64 + 500
+    );
+
+    update_spreads(&mut skewed_amm, reserve_price).unwrap();
+    assert_eq!(skewed_amm.inventory_skew, 500);
+
+    // reserves below the reference, amm retreats from longs and bids closer to the reserve price
+    assert!(skewed_amm.long_spread > oracle_amm.long_spread);
+    assert!(skewed_amm.short_spread < oracle_amm.short_spread);
+}
+
 #[test]
 fn formualic_k_tests() {
     let mut market = PerpMarket {
@@ -57,6 +57,7 @@ use crate::state::margin_calculation::{MarginCalculation, MarginContext, MarketI
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::MarketStatus;
 use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::State;
@@ -80,6 +81,7 @@ pub fn liquidate_perp(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     slot: u64,
     now: i64,
     state: &State,
@@ -169,12 +171,14 @@ pub fn liquidate_perp(
         perp_market_map,
         spot_market_map,
         oracle_map,
+        perp_order_book_index_map,
         now,
         slot,
         OrderActionExplanation::Liquidation,
         None,
         None,
         None,
+        None,
     )?;
 
     let mut market = perp_market_map.get_ref_mut(&market_index)?;
@@ -648,6 +652,7 @@ pub fn liquidate_spot(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     now: i64,
     slot: u64,
     state: &State,
@@ -820,12 +825,14 @@ pub fn liquidate_spot(
         perp_market_map,
         spot_market_map,
         oracle_map,
+        perp_order_book_index_map,
         now,
         slot,
         OrderActionExplanation::Liquidation,
         None,
         None,
         None,
+        None,
     )?;
 
     // check if user exited liquidation territory
@@ -1145,6 +1152,7 @@ pub fn liquidate_borrow_for_perp_pnl(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     now: i64,
     slot: u64,
     liquidation_margin_buffer_ratio: u32,
@@ -1332,12 +1340,14 @@ pub fn liquidate_borrow_for_perp_pnl(
         perp_market_map,
         spot_market_map,
         oracle_map,
+        perp_order_book_index_map,
         now,
         slot,
         OrderActionExplanation::Liquidation,
         None,
         None,
         None,
+        None,
     )?;
 
     // check if user exited liquidation territory
@@ -1591,6 +1601,7 @@ pub fn liquidate_perp_pnl_for_deposit(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     now: i64,
     slot: u64,
     liquidation_margin_buffer_ratio: u32,
@@ -1786,12 +1797,14 @@ pub fn liquidate_perp_pnl_for_deposit(
         perp_market_map,
         spot_market_map,
         oracle_map,
+        perp_order_book_index_map,
         now,
         slot,
         OrderActionExplanation::Liquidation,
         None,
         None,
         None,
+        None,
     )?;
 
     let (safest_tier_spot_liability, safest_tier_perp_liability) =
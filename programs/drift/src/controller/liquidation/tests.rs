@@ -1,5 +1,6 @@
 pub mod liquidate_perp {
     use crate::math::constants::ONE_HOUR;
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use crate::state::state::State;
     use std::str::FromStr;
 
@@ -119,7 +120,7 @@ pub mod liquidate_perp {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
 
             ..User::default()
         };
@@ -158,6 +159,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -269,7 +271,7 @@ pub mod liquidate_perp {
                 open_asks: -BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
 
             ..User::default()
         };
@@ -308,6 +310,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -456,6 +459,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -553,7 +557,7 @@ pub mod liquidate_perp {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
 
             ..User::default()
         };
@@ -592,6 +596,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -743,6 +748,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -970,6 +976,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1078,7 +1085,7 @@ pub mod liquidate_perp {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
 
             ..User::default()
         };
@@ -1118,6 +1125,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1210,7 +1218,7 @@ pub mod liquidate_perp {
                 open_asks: -BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
 
             ..User::default()
         };
@@ -1250,6 +1258,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1374,6 +1383,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1529,6 +1539,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1554,6 +1565,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1599,6 +1611,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1624,6 +1637,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1669,6 +1683,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1806,6 +1821,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -1931,6 +1947,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -2059,6 +2076,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -2187,6 +2205,7 @@ pub mod liquidate_perp {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             slot,
             now,
             &state,
@@ -2200,6 +2219,7 @@ pub mod liquidate_perp {
 }
 
 pub mod liquidate_spot {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use crate::state::state::State;
     use std::ops::Deref;
     use std::str::FromStr;
@@ -2294,7 +2314,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2308,8 +2328,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2346,6 +2366,7 @@ pub mod liquidate_spot {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -2432,7 +2453,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_market = [SpotPosition::default(); 8];
+        let mut spot_market = [SpotPosition::default(); 16];
         spot_market[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2446,8 +2467,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions: spot_market,
             ..User::default()
         };
@@ -2484,6 +2505,7 @@ pub mod liquidate_spot {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -2510,6 +2532,7 @@ pub mod liquidate_spot {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -2597,7 +2620,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2611,8 +2634,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2648,6 +2671,7 @@ pub mod liquidate_spot {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -2689,6 +2713,7 @@ pub mod liquidate_spot {
         let strict_price_1 = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(oracle_price_data.price / 10),
+            confidence_offset: 0,
         };
         let strict_token_value_1 =
             get_strict_token_value(token_amount as i128, 6, &strict_price_1).unwrap();
@@ -2696,6 +2721,7 @@ pub mod liquidate_spot {
         let strict_price_2 = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(oracle_price_data.price * 2),
+            confidence_offset: 0,
         };
         let strict_token_value_2 =
             get_strict_token_value(token_amount as i128, 6, &strict_price_2).unwrap();
@@ -2703,6 +2729,7 @@ pub mod liquidate_spot {
         let strict_price_3 = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(oracle_price_data.price * 2),
+            confidence_offset: 0,
         };
         let strict_token_value_3 =
             get_strict_token_value(-(token_amount as i128), 6, &strict_price_3).unwrap();
@@ -2810,7 +2837,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2824,8 +2851,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2861,6 +2888,7 @@ pub mod liquidate_spot {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -2934,7 +2962,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2948,8 +2976,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2985,6 +3013,7 @@ pub mod liquidate_spot {
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -3059,7 +3088,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -3073,8 +3102,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -3110,6 +3139,7 @@ pub mod liquidate_spot {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -3189,7 +3219,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -3203,8 +3233,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -3243,6 +3273,7 @@ pub mod liquidate_spot {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -3288,6 +3319,7 @@ pub mod liquidate_spot {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -3333,6 +3365,7 @@ pub mod liquidate_spot {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -3430,7 +3463,7 @@ pub mod liquidate_spot {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -3444,8 +3477,8 @@ pub mod liquidate_spot {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -3483,6 +3516,7 @@ pub mod liquidate_spot {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -3514,6 +3548,7 @@ pub mod liquidate_spot {
 }
 
 pub mod liquidate_borrow_for_perp_pnl {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use std::ops::Deref;
     use std::str::FromStr;
 
@@ -3638,7 +3673,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -3646,7 +3681,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 100 * QUOTE_PRECISION_I64,
@@ -3681,6 +3716,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -3794,7 +3830,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -3802,7 +3838,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 105 * QUOTE_PRECISION_I64,
@@ -3838,6 +3874,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -3990,7 +4027,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -3998,7 +4035,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 80 * QUOTE_PRECISION_I64,
@@ -4033,6 +4070,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -4145,7 +4183,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -4153,7 +4191,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 100 * QUOTE_PRECISION_I64,
@@ -4189,6 +4227,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -4292,7 +4331,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -4300,7 +4339,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 100 * QUOTE_PRECISION_I64,
@@ -4336,6 +4375,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -4440,7 +4480,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -4448,7 +4488,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 107 * QUOTE_PRECISION_I64 / 50,
@@ -4484,6 +4524,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -4597,7 +4638,7 @@ pub mod liquidate_borrow_for_perp_pnl {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -4605,7 +4646,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: 1050 * QUOTE_PRECISION_I64,
@@ -4641,6 +4682,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -4687,6 +4729,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -4733,6 +4776,7 @@ pub mod liquidate_borrow_for_perp_pnl {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -4747,6 +4791,7 @@ pub mod liquidate_borrow_for_perp_pnl {
 }
 
 pub mod liquidate_perp_pnl_for_deposit {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use crate::state::state::State;
     use std::str::FromStr;
 
@@ -4870,7 +4915,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -4878,7 +4923,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -100 * QUOTE_PRECISION_I64,
@@ -4913,6 +4958,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -5026,7 +5072,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5034,7 +5080,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -91 * QUOTE_PRECISION_I64,
@@ -5069,6 +5115,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             MARGIN_PRECISION as u32 / 50,
@@ -5184,7 +5231,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5192,7 +5239,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -150 * QUOTE_PRECISION_I64,
@@ -5227,6 +5274,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -5339,7 +5387,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5347,7 +5395,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -100 * QUOTE_PRECISION_I64,
@@ -5383,6 +5431,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -5486,7 +5535,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5494,7 +5543,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -100 * QUOTE_PRECISION_I64,
@@ -5530,6 +5579,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -5634,7 +5684,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5642,7 +5692,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -91 * QUOTE_PRECISION_I64 / 50,
@@ -5677,6 +5727,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             MARGIN_PRECISION as u32 / 50,
@@ -5790,7 +5841,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5798,7 +5849,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -950 * QUOTE_PRECISION_I64,
@@ -5834,6 +5885,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -5880,6 +5932,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -5926,6 +5979,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             liquidation_buffer,
@@ -6035,7 +6089,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -6049,7 +6103,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -100 * QUOTE_PRECISION_I64,
@@ -6084,6 +6138,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -6111,6 +6166,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             &state,
@@ -6131,6 +6187,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -6154,6 +6211,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -6299,7 +6357,7 @@ pub mod liquidate_perp_pnl_for_deposit {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -6313,7 +6371,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 quote_asset_amount: -100 * QUOTE_PRECISION_I64,
@@ -6354,6 +6412,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -6375,6 +6434,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -6396,6 +6456,7 @@ pub mod liquidate_perp_pnl_for_deposit {
             &market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             now,
             slot,
             10,
@@ -6532,7 +6593,7 @@ pub mod resolve_perp_bankruptcy {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
             status: UserStatus::Bankrupt as u8,
             next_liquidation_id: 2,
             ..User::default()
@@ -6581,7 +6642,7 @@ pub mod resolve_perp_bankruptcy {
         assert_eq!(expected_market, market_map.get_ref(&0).unwrap().clone());
 
         let mut affected_long_user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: 5 * BASE_PRECISION_I64,
@@ -6592,7 +6653,7 @@ pub mod resolve_perp_bankruptcy {
                 last_cumulative_funding_rate: 1000 * FUNDING_RATE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
             ..User::default()
         };
 
@@ -6619,7 +6680,7 @@ pub mod resolve_perp_bankruptcy {
         assert_eq!(expected_affected_long_user, affected_long_user);
 
         let mut affected_short_user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: -5 * BASE_PRECISION_I64,
@@ -6630,7 +6691,7 @@ pub mod resolve_perp_bankruptcy {
                 last_cumulative_funding_rate: -1000 * FUNDING_RATE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
             ..User::default()
         };
 
@@ -6743,7 +6804,7 @@ pub mod resolve_perp_bankruptcy {
                 open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
             status: UserStatus::Bankrupt as u8,
             next_liquidation_id: 2,
             ..User::default()
@@ -6794,7 +6855,7 @@ pub mod resolve_perp_bankruptcy {
         assert_eq!(expected_market, market_map.get_ref(&0).unwrap().clone());
 
         let mut affected_long_user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: 5 * BASE_PRECISION_I64,
@@ -6805,7 +6866,7 @@ pub mod resolve_perp_bankruptcy {
                 last_cumulative_funding_rate: 1000 * FUNDING_RATE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
             ..User::default()
         };
 
@@ -6832,7 +6893,7 @@ pub mod resolve_perp_bankruptcy {
         assert_eq!(expected_affected_long_user, affected_long_user);
 
         let mut affected_short_user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: -5 * BASE_PRECISION_I64,
@@ -6843,7 +6904,7 @@ pub mod resolve_perp_bankruptcy {
                 last_cumulative_funding_rate: -1000 * FUNDING_RATE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
-            spot_positions: [SpotPosition::default(); 8],
+            spot_positions: [SpotPosition::default(); 16],
             ..User::default()
         };
 
@@ -6973,7 +7034,7 @@ pub mod resolve_spot_bankruptcy {
                 slot: 0,
                 ..Order::default()
             }),
-            perp_positions: [PerpPosition::default(); 8],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions: get_spot_positions(SpotPosition {
                 market_index: 0,
                 scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
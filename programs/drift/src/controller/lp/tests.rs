@@ -732,7 +732,7 @@ fn test_lp_margin_calc() {
         calculate_perp_position_value_and_pnl(
             &user.perp_positions[0],
             &market,
-            &oracle_price_data,
+            &StrictOraclePrice::test(oracle_price_data.price),
             &strict_quote_price,
             crate::math::margin::MarginRequirementType::Initial,
             0,
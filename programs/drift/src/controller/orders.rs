@@ -1,5 +1,5 @@
 use std::cell::RefMut;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::DerefMut;
 use std::u64;
 
@@ -7,7 +7,6 @@ use anchor_lang::prelude::*;
 use solana_program::msg;
 
 use crate::controller;
-use crate::controller::funding::settle_funding_payment;
 use crate::controller::position;
 use crate::controller::position::{
     add_new_position, decrease_open_bids_and_asks, get_position_index, increase_open_bids_and_asks,
@@ -29,6 +28,11 @@ use crate::get_then_update_id;
 use crate::load_mut;
 use crate::math::amm_jit::calculate_amm_jit_liquidity;
 use crate::math::auction::calculate_auction_prices;
+use crate::math::batch_auction::{
+    calculate_amm_batch_auction_liquidity, calculate_batch_auction_clearing_price,
+    calculate_batch_auction_fill_allocations, calculate_batch_auction_fill_amounts,
+    BatchAuctionOrder,
+};
 use crate::math::casting::Cast;
 use crate::math::constants::{
     BASE_PRECISION_U64, FEE_POOL_TO_REVENUE_POOL_THRESHOLD, FIVE_MINUTE, ONE_HOUR, PERP_DECIMALS,
@@ -41,7 +45,8 @@ use crate::math::fulfillment::{
 use crate::math::liquidation::validate_user_not_being_liquidated;
 use crate::math::matching::{
     are_orders_same_market_but_different_sides, calculate_fill_for_matched_orders,
-    calculate_filler_multiplier_for_matched_orders, do_orders_cross, is_maker_for_taker,
+    calculate_filler_multiplier_for_matched_orders, calculate_self_trade_prevention_decrements,
+    do_orders_cross, is_maker_for_taker,
 };
 use crate::math::oracle;
 use crate::math::oracle::{is_oracle_valid_for_action, DriftAction, OracleValidity};
@@ -50,7 +55,8 @@ use crate::math::spot_balance::{get_signed_token_amount, get_token_amount};
 use crate::math::stats::calculate_new_twap;
 use crate::math::{amm, fees, margin::*, orders::*};
 use crate::state::order_params::{
-    ModifyOrderParams, ModifyOrderPolicy, OrderParams, PlaceOrderOptions, PostOnlyParam,
+    BatchModifyOrderParams, ClientOrderIdPrefix, ModifyOrderParams, ModifyOrderPolicy, OrderParams,
+    PlaceOrderOptions, PostOnlyParam,
 };
 
 use crate::math::amm::calculate_amm_available_liquidity;
@@ -58,14 +64,18 @@ use crate::math::safe_unwrap::SafeUnwrap;
 use crate::math::spot_swap::select_margin_type_for_swap;
 use crate::print_error;
 use crate::state::events::{emit_stack, get_order_action_record, OrderActionRecord, OrderRecord};
-use crate::state::events::{OrderAction, OrderActionExplanation};
+use crate::state::events::{
+    ModifyOrderRecord, ModifyOrderResult, OrderAction, OrderActionExplanation,
+};
 use crate::state::fill_mode::FillMode;
 use crate::state::fulfillment::{PerpFulfillmentMethod, SpotFulfillmentMethod};
 use crate::state::margin_calculation::MarginContext;
 use crate::state::oracle::{OraclePriceData, StrictOraclePrice};
 use crate::state::oracle_map::OracleMap;
+use crate::state::order_book_index::{is_indexable_order, PerpOrderBookIndex};
 use crate::state::perp_market::{AMMLiquiditySplit, MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
 use crate::state::spot_fulfillment_params::{ExternalSpotFill, SpotFulfillmentParams};
 use crate::state::spot_market::{SpotBalanceType, SpotMarket};
 use crate::state::spot_market_map::SpotMarketMap;
@@ -73,14 +83,16 @@ use crate::state::state::FeeStructure;
 use crate::state::state::*;
 use crate::state::traits::Size;
 use crate::state::user::{
-    AssetType, Order, OrderStatus, OrderTriggerCondition, OrderType, UserStats,
+    AssetType, Order, OrderLinkType, OrderStatus, OrderTriggerCondition, OrderType,
+    SelfTradePreventionMode, TrailingStopType, TriggerPriceSource, UserStats,
 };
 use crate::state::user::{MarketType, User};
 use crate::state::user_map::{UserMap, UserStatsMap};
 use crate::validate;
 use crate::validation;
 use crate::validation::order::{
-    validate_order, validate_order_for_force_reduce_only, validate_spot_order,
+    validate_linked_order, validate_order, validate_order_for_force_reduce_only,
+    validate_spot_order,
 };
 
 #[cfg(test)]
@@ -97,6 +109,7 @@ pub fn place_perp_order(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     clock: &Clock,
     params: OrderParams,
     mut options: PlaceOrderOptions,
@@ -130,7 +143,7 @@ pub fn place_perp_order(
 
     if user.is_reduce_only() {
         validate!(
-            params.reduce_only,
+            params.reduce_only || params.close_position,
             ErrorCode::UserReduceOnly,
             "order must be reduce only"
         )?;
@@ -146,6 +159,7 @@ pub fn place_perp_order(
 
     if max_ts != 0 && max_ts < now {
         msg!("max_ts ({}) < now ({}), skipping order", max_ts, now);
+        perp_order_book_index_map.update_user_orders(&user_key, user)?;
         return Ok(());
     }
 
@@ -167,6 +181,18 @@ pub fn place_perp_order(
         }
     }
 
+    if params.client_order_id != 0
+        && user
+            .get_order_index_by_client_order_id(params.client_order_id)
+            .is_ok()
+    {
+        msg!(
+            "client_order_id is already in use {}",
+            params.client_order_id
+        );
+        return Err(ErrorCode::ClientOrderIdAlreadyInUse);
+    }
+
     let market_index = params.market_index;
     let market = &perp_market_map.get_ref(&market_index)?;
     let force_reduce_only = market.is_reduce_only()?;
@@ -183,20 +209,60 @@ pub fn place_perp_order(
         "Market is in settlement mode",
     )?;
 
+    validate!(
+        !market.is_batch_auction_market()
+            || params.post_only != PostOnlyParam::None
+            || matches!(
+                params.order_type,
+                OrderType::Limit | OrderType::TriggerLimit
+            ),
+        ErrorCode::InvalidBatchAuction,
+        "Batch auction market only takes limit orders"
+    )?;
+
+    if market.is_batch_auction_market() {
+        validate!(
+            params.oracle_price_offset.is_none(),
+            ErrorCode::InvalidBatchAuction,
+            "Batch auction market orders need a fixed price"
+        )?;
+
+        validate!(
+            !params.fill_or_kill,
+            ErrorCode::InvalidBatchAuction,
+            "Batch auction market orders can not be fill or kill, they only fill when the batch clears"
+        )?;
+    }
+
     let position_index = get_position_index(&user.perp_positions, market_index)
         .or_else(|_| add_new_position(&mut user.perp_positions, market_index))?;
 
     // Increment open orders for existing position
     let (existing_position_direction, order_base_asset_amount) = {
         validate!(
-            params.base_asset_amount >= market.amm.order_step_size,
+            params.close_position || params.base_asset_amount >= market.amm.order_step_size,
             ErrorCode::OrderAmountTooSmall,
             "params.base_asset_amount={} cannot be below market.amm.order_step_size={}",
             params.base_asset_amount,
             market.amm.order_step_size
         )?;
 
-        let base_asset_amount = if params.base_asset_amount == u64::MAX {
+        let base_asset_amount = if params.close_position {
+            validate!(
+                params.order_type != OrderType::Twap && params.display_base_asset_amount == 0,
+                ErrorCode::InvalidClosePositionOrder,
+                "close position orders cant be twap or iceberg orders"
+            )?;
+
+            // sized again when triggered or filled, the position may not exist yet for a pending
+            // child order
+            calculate_close_position_base_asset_amount(
+                user.perp_positions[position_index].base_asset_amount,
+                params.direction,
+                market.amm.order_step_size,
+            )?
+            .max(market.amm.order_step_size)
+        } else if params.base_asset_amount == u64::MAX {
             calculate_max_perp_order_size(
                 user,
                 position_index,
@@ -233,7 +299,7 @@ pub fn place_perp_order(
         "must be perp order"
     )?;
 
-    let new_order = Order {
+    let mut new_order = Order {
         status: OrderStatus::Open,
         order_type: params.order_type,
         market_type: params.market_type,
@@ -252,7 +318,7 @@ pub fn place_perp_order(
         base_asset_amount_filled: 0,
         quote_asset_amount_filled: 0,
         direction: params.direction,
-        reduce_only: params.reduce_only || force_reduce_only,
+        reduce_only: params.reduce_only || force_reduce_only || params.close_position,
         trigger_price: standardize_price(
             params.trigger_price.unwrap_or(0),
             market.amm.order_tick_size,
@@ -266,21 +332,112 @@ pub fn place_perp_order(
         auction_end_price,
         auction_duration,
         max_ts,
-        padding: [0; 3],
+        trailing_stop_type: params.trailing_stop_type,
+        link_id: params.link_id,
+        link_type: params.link_type,
+        client_order_id: params.client_order_id,
+        twap_next_slice_ts: 0,
+        hidden_base_asset_amount: 0,
+        slice_base_asset_amount: 0,
+        trailing_stop_offset: params.trailing_stop_offset,
+        twap_interval: 0,
+        max_slippage_bps: params.max_slippage_bps.unwrap_or(0),
+        twap_slices_remaining: 0,
+        trigger_market_index: params.trigger_market_index,
+        close_position: params.close_position,
+        trigger_price_source: params.trigger_price_source,
+        padding: [0; 16],
     };
 
     let valid_oracle_price = Some(oracle_map.get_price_data(&market.amm.oracle)?.price);
+
+    if params.trigger_price_source != TriggerPriceSource::Oracle {
+        validate!(
+            new_order.must_be_triggered(),
+            ErrorCode::InvalidOrderTrigger,
+            "Only trigger orders can have a trigger price source"
+        )?;
+
+        // fails if the trigger market doesn't exist or its price can't be used
+        get_trigger_reference_price(&new_order, perp_market_map, spot_market_map, oracle_map)?;
+    }
+
+    if new_order.is_trailing_stop() {
+        let trigger_reference_price = match get_trigger_reference_price(
+            &new_order,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+        )? {
+            Some(trigger_reference_price) => trigger_reference_price,
+            None => valid_oracle_price.safe_unwrap()?.unsigned_abs(),
+        };
+        new_order.trigger_price = calculate_trailing_stop_trigger_price(
+            &new_order,
+            trigger_reference_price,
+            market.amm.order_tick_size,
+        )?;
+    }
+
+    if new_order.is_twap() {
+        let (slice_base_asset_amount, next_slice_ts) = calculate_twap_order_schedule(
+            order_base_asset_amount,
+            params.twap_slice_count,
+            params.twap_interval,
+            market.amm.order_step_size,
+            now,
+        )?;
+        let first_slice_base_asset_amount = calculate_twap_slice_base_asset_amount(
+            order_base_asset_amount,
+            slice_base_asset_amount,
+            params.twap_slice_count,
+        );
+        new_order.base_asset_amount = first_slice_base_asset_amount;
+        new_order.hidden_base_asset_amount =
+            order_base_asset_amount.safe_sub(first_slice_base_asset_amount)?;
+        new_order.slice_base_asset_amount = slice_base_asset_amount;
+        new_order.twap_next_slice_ts = next_slice_ts;
+        new_order.twap_interval = params.twap_interval;
+        new_order.twap_slices_remaining = params.twap_slice_count.safe_sub(1)?;
+    }
+
+    validate!(
+        !(new_order.order_type == OrderType::Limit && new_order.trigger_price != 0),
+        ErrorCode::InvalidOrderTrigger,
+        "Limit order should not have trigger price"
+    )?;
+
+    if params.display_base_asset_amount != 0 {
+        validate!(
+            new_order.order_type == OrderType::Limit,
+            ErrorCode::InvalidIcebergOrder,
+            "Only limit orders can be iceberg orders"
+        )?;
+
+        let (display_base_asset_amount, hidden_base_asset_amount) = calculate_iceberg_order_sizes(
+            order_base_asset_amount,
+            params.display_base_asset_amount,
+            market.amm.order_step_size,
+        )?;
+        new_order.base_asset_amount = display_base_asset_amount;
+        new_order.hidden_base_asset_amount = hidden_base_asset_amount;
+        new_order.slice_base_asset_amount = display_base_asset_amount;
+    }
+
     match validate_order(&new_order, market, valid_oracle_price, slot) {
         Ok(()) => {}
         Err(ErrorCode::PlacePostOnlyLimitFailure)
             if params.post_only == PostOnlyParam::TryPostOnly =>
         {
             // just want place to succeeds without error if TryPostOnly
+            perp_order_book_index_map.update_user_orders(&user_key, user)?;
             return Ok(());
         }
         Err(err) => return Err(err),
     };
 
+    validate_linked_order(&new_order, &user.orders)?;
+
     let risk_increasing = is_new_order_risk_increasing(
         &new_order,
         user.perp_positions[position_index].base_asset_amount,
@@ -377,10 +534,55 @@ pub fn place_perp_order(
     emit_stack::<_, { OrderRecord::SIZE }>(order_record)?;
 
     user.update_last_active_slot(slot);
+    if options.update_heartbeat {
+        user.update_last_heartbeat_ts(now);
+    }
+
+    perp_order_book_index_map.update_user_orders(&user_key, user)?;
+
+    if market.is_batch_auction_market() {
+        validate_batch_auction_order_indexed(
+            perp_order_book_index_map,
+            &user_key,
+            &user.orders[new_order_index],
+        )?;
+    }
 
     Ok(())
 }
 
+/// Batch auctions clear the orders in the market's order book index, so an order resting in a batch
+/// auction market has to make it into the index. Checked after syncing, an order that is worse than
+/// the orders it would have to drop or from a user at the max number of orders is rejected
+fn validate_batch_auction_order_indexed(
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    user_key: &Pubkey,
+    order: &Order,
+) -> DriftResult {
+    if !is_indexable_order(order, order.market_index) {
+        return Ok(());
+    }
+
+    let perp_order_book_index = match perp_order_book_index_map.get_ref_mut(&order.market_index)? {
+        Some(perp_order_book_index) => perp_order_book_index,
+        None => {
+            msg!(
+                "Batch auction market {} orders need the market's order book index",
+                order.market_index
+            );
+            return Err(ErrorCode::InvalidBatchAuction);
+        }
+    };
+
+    validate!(
+        perp_order_book_index.contains_order(user_key, order.order_id),
+        ErrorCode::InvalidBatchAuction,
+        "Batch auction market {} order book index has no room for order {}",
+        order.market_index,
+        order.order_id
+    )
+}
+
 fn get_auction_params(
     params: &OrderParams,
     oracle_price_data: &OraclePriceData,
@@ -389,7 +591,7 @@ fn get_auction_params(
 ) -> DriftResult<(i64, i64, u8)> {
     if !matches!(
         params.order_type,
-        OrderType::Market | OrderType::Oracle | OrderType::Limit
+        OrderType::Market | OrderType::Oracle | OrderType::Limit | OrderType::Twap
     ) {
         return Ok((0_i64, 0_i64, 0_u8));
     }
@@ -444,12 +646,14 @@ pub fn cancel_orders(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     now: i64,
     slot: u64,
     explanation: OrderActionExplanation,
     market_type: Option<MarketType>,
     market_index: Option<u16>,
     direction: Option<PositionDirection>,
+    client_order_id_prefix: Option<ClientOrderIdPrefix>,
 ) -> DriftResult<Vec<u32>> {
     let mut canceled_order_ids: Vec<u32> = vec![];
     for order_index in 0..user.orders.len() {
@@ -473,6 +677,12 @@ pub fn cancel_orders(
             }
         }
 
+        if let Some(client_order_id_prefix) = client_order_id_prefix {
+            if !client_order_id_prefix.matches(user.orders[order_index].client_order_id) {
+                continue;
+            }
+        }
+
         canceled_order_ids.push(user.orders[order_index].order_id);
         cancel_order(
             order_index,
@@ -492,6 +702,8 @@ pub fn cancel_orders(
 
     user.update_last_active_slot(slot);
 
+    perp_order_book_index_map.update_user_orders(user_key, user)?;
+
     Ok(canceled_order_ids)
 }
 
@@ -501,6 +713,7 @@ pub fn cancel_order_by_order_id(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     clock: &Clock,
 ) -> DriftResult {
     let user_key = user.key();
@@ -529,6 +742,9 @@ pub fn cancel_order_by_order_id(
     )?;
 
     user.update_last_active_slot(clock.slot);
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+
+    perp_order_book_index_map.update_user_orders(&user_key, user)?;
 
     Ok(())
 }
@@ -539,6 +755,7 @@ pub fn cancel_order_by_user_order_id(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     clock: &Clock,
 ) -> DriftResult {
     let user_key = user.key();
@@ -571,11 +788,118 @@ pub fn cancel_order_by_user_order_id(
     )?;
 
     user.update_last_active_slot(clock.slot);
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+
+    perp_order_book_index_map.update_user_orders(&user_key, user)?;
+
+    Ok(())
+}
+
+pub fn cancel_order_by_client_order_id(
+    client_order_id: u64,
+    user: &AccountLoader<User>,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    clock: &Clock,
+) -> DriftResult {
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+    let order_index = match user.get_order_index_by_client_order_id(client_order_id) {
+        Ok(order_index) => order_index,
+        Err(_) => {
+            msg!("could not find client order id {}", client_order_id);
+            return Ok(());
+        }
+    };
+
+    cancel_order(
+        order_index,
+        user,
+        &user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock.unix_timestamp,
+        clock.slot,
+        OrderActionExplanation::None,
+        None,
+        0,
+        false,
+    )?;
+
+    user.update_last_active_slot(clock.slot);
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+
+    perp_order_book_index_map.update_user_orders(&user_key, user)?;
 
     Ok(())
 }
 
 pub fn cancel_order(
+    order_index: usize,
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    explanation: OrderActionExplanation,
+    filler_key: Option<&Pubkey>,
+    filler_reward: u64,
+    skip_log: bool,
+) -> DriftResult {
+    let order = user.orders[order_index];
+
+    cancel_order_without_linked_orders(
+        order_index,
+        user,
+        user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        now,
+        slot,
+        explanation,
+        filler_key,
+        filler_reward,
+        skip_log,
+    )?;
+
+    // pending children can never activate once their parent is canceled without a fill
+    if order.link_type == OrderLinkType::Parent && order.base_asset_amount_filled == 0 {
+        for linked_order_index in 0..user.orders.len() {
+            let linked_order = &user.orders[linked_order_index];
+            if linked_order.status != OrderStatus::Open
+                || !linked_order.is_pending_linked_order()
+                || !linked_order.is_linked_to(&order)
+            {
+                continue;
+            }
+
+            cancel_order_without_linked_orders(
+                linked_order_index,
+                user,
+                user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                OrderActionExplanation::CanceledByLinkedOrder,
+                filler_key,
+                0,
+                skip_log,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn cancel_order_without_linked_orders(
     order_index: usize,
     user: &mut User,
     user_key: &Pubkey,
@@ -676,6 +1000,7 @@ pub fn cancel_order(
 pub enum ModifyOrderId {
     UserOrderId(u8),
     OrderId(u32),
+    ClientOrderId(u64),
 }
 
 pub fn modify_order(
@@ -686,92 +1011,335 @@ pub fn modify_order(
     perp_market_map: &PerpMarketMap,
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     clock: &Clock,
 ) -> DriftResult {
     let user_key = user_loader.key();
     let mut user = load_mut!(user_loader)?;
 
-    let order_index = match order_id {
-        ModifyOrderId::UserOrderId(user_order_id) => {
-            match user.get_order_index_by_user_order_id(user_order_id) {
-                Ok(order_index) => order_index,
-                Err(e) => {
-                    msg!("User order id {} not found", user_order_id);
-                    if modify_order_params.policy == Some(ModifyOrderPolicy::MustModify) {
-                        return Err(e);
-                    } else {
-                        return Ok(());
-                    }
-                }
-            }
-        }
-        ModifyOrderId::OrderId(order_id) => match user.get_order_index(order_id) {
-            Ok(order_index) => order_index,
-            Err(e) => {
-                msg!("Order id {} not found", order_id);
-                if modify_order_params.policy == Some(ModifyOrderPolicy::MustModify) {
-                    return Err(e);
-                } else {
-                    return Ok(());
-                }
-            }
-        },
-    };
-
-    let existing_order = user.orders[order_index];
-
-    cancel_order(
-        order_index,
+    let existing_order = match cancel_order_for_modify(
+        order_id,
+        modify_order_params.policy == Some(ModifyOrderPolicy::MustModify),
         &mut user,
         &user_key,
         perp_market_map,
         spot_market_map,
         oracle_map,
-        clock.unix_timestamp,
-        clock.slot,
-        OrderActionExplanation::None,
-        None,
-        0,
-        false,
-    )?;
-
-    user.update_last_active_slot(clock.slot);
+        clock,
+    )? {
+        Some(existing_order) => existing_order,
+        None => return Ok(()),
+    };
 
     drop(user);
 
     let order_params =
         merge_modify_order_params_with_existing_order(&existing_order, &modify_order_params)?;
 
-    if order_params.market_type == MarketType::Perp {
-        place_perp_order(
+    place_modified_order(
+        state,
+        user_loader,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        perp_order_book_index_map,
+        clock,
+        order_params,
+        PlaceOrderOptions::default(),
+    )
+}
+
+/// Modifies or cancels and replaces each order in turn, checking margin once after the last
+/// replacement is placed. Every entry is validated before any order is touched and a failure after
+/// that fails the whole instruction. Under ModifyOrderPolicy::TryModify, orders that aren't found are
+/// skipped. Each result is emitted as a ModifyOrderRecord
+pub fn modify_orders(
+    params: &[BatchModifyOrderParams],
+    user_loader: &AccountLoader<User>,
+    state: &State,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    clock: &Clock,
+) -> DriftResult {
+    let user_key = user_loader.key();
+
+    validate_modify_orders(params, &*load!(user_loader)?)?;
+
+    let mut orders_placed = false;
+    let mut risk_increasing = false;
+
+    for (i, params) in params.iter().enumerate() {
+        let must_modify = params.modify_order_params.policy == Some(ModifyOrderPolicy::MustModify);
+
+        let mut record = ModifyOrderRecord {
+            ts: clock.unix_timestamp,
+            user: user_key,
+            order_id: params.order_id.unwrap_or(0),
+            user_order_id: params.user_order_id,
+            client_order_id: params.client_order_id.unwrap_or(0),
+            new_order_id: 0,
+            result: ModifyOrderResult::Skipped,
+        };
+
+        let existing_order = {
+            let mut user = load_mut!(user_loader)?;
+            cancel_order_for_modify(
+                get_modify_order_id(params),
+                must_modify,
+                &mut user,
+                &user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                clock,
+            )?
+        };
+
+        let existing_order = match existing_order {
+            Some(existing_order) => existing_order,
+            None => {
+                emit!(record);
+                continue;
+            }
+        };
+        record.order_id = existing_order.order_id;
+        record.user_order_id = existing_order.user_order_id;
+        record.client_order_id = existing_order.client_order_id;
+
+        let order_params = match params.replacement_order_params {
+            Some(replacement_order_params) => replacement_order_params,
+            None => merge_modify_order_params_with_existing_order(
+                &existing_order,
+                &params.modify_order_params,
+            )?,
+        };
+
+        let next_order_id_before = load!(user_loader)?.next_order_id;
+
+        place_modified_order(
             state,
             user_loader,
             perp_market_map,
             spot_market_map,
             oracle_map,
+            perp_order_book_index_map,
             clock,
             order_params,
-            PlaceOrderOptions::default(),
+            PlaceOrderOptions {
+                try_expire_orders: i == 0,
+                enforce_margin_check: false,
+                risk_increasing: false,
+                update_heartbeat: true,
+            },
         )?;
-    } else {
-        place_spot_order(
-            state,
-            user_loader,
+
+        let user = load!(user_loader)?;
+        if user.next_order_id != next_order_id_before {
+            orders_placed = true;
+            risk_increasing = risk_increasing || !order_params.reduce_only;
+            record.new_order_id = user.get_last_order_id();
+            record.result = ModifyOrderResult::Modified;
+        } else {
+            record.result = ModifyOrderResult::Failed;
+        }
+
+        emit!(record);
+    }
+
+    if orders_placed {
+        let user = load!(user_loader)?;
+        meets_place_order_margin_requirement(
+            &user,
             perp_market_map,
             spot_market_map,
             oracle_map,
-            clock,
-            order_params,
-            PlaceOrderOptions::default(),
+            risk_increasing,
         )?;
     }
 
+    perp_order_book_index_map.update_user_orders(&user_key, &*load!(user_loader)?)?;
+
     Ok(())
 }
 
-fn merge_modify_order_params_with_existing_order(
-    existing_order: &Order,
-    modify_order_params: &ModifyOrderParams,
+fn get_modify_order_id(params: &BatchModifyOrderParams) -> ModifyOrderId {
+    match (params.order_id, params.client_order_id) {
+        (Some(order_id), _) => ModifyOrderId::OrderId(order_id),
+        (None, Some(client_order_id)) => ModifyOrderId::ClientOrderId(client_order_id),
+        (None, None) => ModifyOrderId::UserOrderId(params.user_order_id),
+    }
+}
+
+/// Checks every entry against the user's orders before any is modified, so an invalid entry fails
+/// the instruction up front
+fn validate_modify_orders(params: &[BatchModifyOrderParams], user: &User) -> DriftResult {
+    for params in params.iter() {
+        let must_modify = params.modify_order_params.policy == Some(ModifyOrderPolicy::MustModify);
+
+        let order_index =
+            match get_order_index_to_modify(get_modify_order_id(params), must_modify, user)? {
+                Some(order_index) => order_index,
+                None => continue,
+            };
+
+        if params.replacement_order_params.is_none() {
+            merge_modify_order_params_with_existing_order(
+                &user.orders[order_index],
+                &params.modify_order_params,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the order being modified and checks it can be modified. Returns None if the order isn't
+/// found and the modify doesn't have to succeed
+fn get_order_index_to_modify(
+    order_id: ModifyOrderId,
+    must_modify: bool,
+    user: &User,
+) -> DriftResult<Option<usize>> {
+    let order_index = match order_id {
+        ModifyOrderId::UserOrderId(user_order_id) => {
+            match user.get_order_index_by_user_order_id(user_order_id) {
+                Ok(order_index) => order_index,
+                Err(e) => {
+                    msg!("User order id {} not found", user_order_id);
+                    if must_modify {
+                        return Err(e);
+                    } else {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        ModifyOrderId::OrderId(order_id) => match user.get_order_index(order_id) {
+            Ok(order_index) => order_index,
+            Err(e) => {
+                msg!("Order id {} not found", order_id);
+                if must_modify {
+                    return Err(e);
+                } else {
+                    return Ok(None);
+                }
+            }
+        },
+        ModifyOrderId::ClientOrderId(client_order_id) => {
+            match user.get_order_index_by_client_order_id(client_order_id) {
+                Ok(order_index) => order_index,
+                Err(e) => {
+                    msg!("Client order id {} not found", client_order_id);
+                    if must_modify {
+                        return Err(e);
+                    } else {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    };
+
+    let existing_order = user.orders[order_index];
+
+    validate!(
+        !existing_order.is_twap(),
+        ErrorCode::InvalidTwapOrder,
+        "Twap orders can not be modified"
+    )?;
+
+    validate!(
+        !existing_order.is_iceberg(),
+        ErrorCode::InvalidIcebergOrder,
+        "Iceberg orders can not be modified"
+    )?;
+
+    Ok(Some(order_index))
+}
+
+/// Cancels the order being modified and returns it. Returns None if the order isn't found and the
+/// modify doesn't have to succeed
+fn cancel_order_for_modify(
+    order_id: ModifyOrderId,
+    must_modify: bool,
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+) -> DriftResult<Option<Order>> {
+    let order_index = match get_order_index_to_modify(order_id, must_modify, user)? {
+        Some(order_index) => order_index,
+        None => return Ok(None),
+    };
+
+    let existing_order = user.orders[order_index];
+
+    // the replacement order keeps the link, so linked orders are left alone
+    cancel_order_without_linked_orders(
+        order_index,
+        user,
+        user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock.unix_timestamp,
+        clock.slot,
+        OrderActionExplanation::None,
+        None,
+        0,
+        false,
+    )?;
+
+    user.update_last_active_slot(clock.slot);
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+
+    Ok(Some(existing_order))
+}
+
+fn place_modified_order(
+    state: &State,
+    user_loader: &AccountLoader<User>,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    clock: &Clock,
+    order_params: OrderParams,
+    options: PlaceOrderOptions,
+) -> DriftResult {
+    if order_params.market_type == MarketType::Perp {
+        place_perp_order(
+            state,
+            user_loader,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            perp_order_book_index_map,
+            clock,
+            order_params,
+            options,
+        )
+    } else {
+        place_spot_order(
+            state,
+            user_loader,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            clock,
+            order_params,
+            options,
+        )
+    }
+}
+
+fn merge_modify_order_params_with_existing_order(
+    existing_order: &Order,
+    modify_order_params: &ModifyOrderParams,
 ) -> DriftResult<OrderParams> {
     let order_type = existing_order.order_type;
     let market_type = existing_order.market_type;
@@ -822,6 +1390,9 @@ fn merge_modify_order_params_with_existing_order(
     let auction_end_price = modify_order_params
         .auction_end_price
         .or(Some(existing_order.auction_end_price));
+    let max_slippage_bps = modify_order_params
+        .max_slippage_bps
+        .or(Some(existing_order.max_slippage_bps));
 
     Ok(OrderParams {
         order_type,
@@ -841,9 +1412,23 @@ fn merge_modify_order_params_with_existing_order(
         auction_duration,
         auction_start_price,
         auction_end_price,
+        trailing_stop_type: existing_order.trailing_stop_type,
+        trailing_stop_offset: existing_order.trailing_stop_offset,
+        link_id: existing_order.link_id,
+        link_type: existing_order.link_type,
+        twap_slice_count: 0,
+        twap_interval: 0,
+        fill_or_kill: false,
+        trigger_price_source: existing_order.trigger_price_source,
+        trigger_market_index: existing_order.trigger_market_index,
+        display_base_asset_amount: 0,
+        close_position: existing_order.close_position,
+        max_slippage_bps,
+        client_order_id: existing_order.client_order_id,
     })
 }
 
+/// Fills the order, then syncs the order book indexes with the taker's and makers' orders
 pub fn fill_perp_order(
     order_id: u32,
     state: &State,
@@ -852,6 +1437,50 @@ pub fn fill_perp_order(
     spot_market_map: &SpotMarketMap,
     perp_market_map: &PerpMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    filler: &AccountLoader<User>,
+    filler_stats: &AccountLoader<UserStats>,
+    makers_and_referrer: &UserMap,
+    makers_and_referrer_stats: &UserStatsMap,
+    jit_maker_order_id: Option<u32>,
+    clock: &Clock,
+    fill_mode: FillMode,
+) -> DriftResult<u64> {
+    let base_asset_amount = fill_perp_order_without_index_update(
+        order_id,
+        state,
+        user,
+        user_stats,
+        spot_market_map,
+        perp_market_map,
+        oracle_map,
+        perp_order_book_index_map,
+        filler,
+        filler_stats,
+        makers_and_referrer,
+        makers_and_referrer_stats,
+        jit_maker_order_id,
+        clock,
+        fill_mode,
+    )?;
+
+    perp_order_book_index_map.update_user_orders(&user.key(), &*load!(user)?)?;
+    for (maker_key, maker) in makers_and_referrer.0.iter() {
+        perp_order_book_index_map.update_user_orders(maker_key, &*load!(maker)?)?;
+    }
+
+    Ok(base_asset_amount)
+}
+
+fn fill_perp_order_without_index_update(
+    order_id: u32,
+    state: &State,
+    user: &AccountLoader<User>,
+    user_stats: &AccountLoader<UserStats>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     filler: &AccountLoader<User>,
     filler_stats: &AccountLoader<UserStats>,
     makers_and_referrer: &UserMap,
@@ -904,6 +1533,11 @@ pub fn fill_perp_order(
         "Market unavailable for fills"
     )?;
 
+    if market.is_batch_auction_market() {
+        msg!("market {} only fills in batch auctions", market_index);
+        return Ok(0);
+    }
+
     drop(market);
 
     validate!(
@@ -918,6 +1552,12 @@ pub fn fill_perp_order(
         "Order must be triggered first"
     )?;
 
+    validate!(
+        !user.orders[order_index].is_pending_linked_order(),
+        ErrorCode::LinkedOrderNotActive,
+        "Order must wait for its parent order to fill"
+    )?;
+
     if user.is_bankrupt() {
         msg!("user is bankrupt");
         return Ok(0);
@@ -946,6 +1586,8 @@ pub fn fill_perp_order(
     {
         let market = &mut perp_market_map.get_ref_mut(&market_index)?;
         amm_is_available &= market.status != MarketStatus::AmmPaused;
+        // signed quotes only fill against the quoting maker
+        amm_is_available &= fill_mode != FillMode::SignedQuote;
         validation::perp_market::validate_perp_market(market)?;
         validate!(
             market.is_active(now)?,
@@ -978,6 +1620,17 @@ pub fn fill_perp_order(
         None
     };
 
+    if should_release_twap_slice(&user.orders[order_index], now)? {
+        let market = perp_market_map.get_ref(&market_index)?;
+        release_twap_slice(
+            &mut user.orders[order_index],
+            oracle_map.get_price_data(&market.amm.oracle)?,
+            market.amm.order_tick_size,
+            state.min_perp_auction_duration,
+            slot,
+        )?;
+    }
+
     let is_filler_taker = user_key == filler_key;
     let is_filler_maker = makers_and_referrer.0.contains_key(&filler_key);
     let (mut filler, mut filler_stats) = if !is_filler_maker && !is_filler_taker {
@@ -991,22 +1644,57 @@ pub fn fill_perp_order(
         (None, None)
     };
 
-    let maker_orders_info = get_maker_orders_info(
+    let mut perp_order_book_index = perp_order_book_index_map.get_ref_mut(&market_index)?;
+    if let Some(perp_order_book_index) = perp_order_book_index.as_mut() {
+        // makers can have changed orders in instructions the index wasn't passed to
+        for (maker_key, maker) in makers_and_referrer.0.iter() {
+            if maker_key != &user_key {
+                perp_order_book_index.update_user_orders(maker_key, &*load!(maker)?)?;
+            }
+        }
+    }
+
+    let (maker_orders_info, taker_self_trade_decrement) = get_maker_orders_info(
         perp_market_map,
         spot_market_map,
         oracle_map,
         makers_and_referrer,
+        perp_order_book_index.as_deref(),
         &user_key,
         &user.orders[order_index],
+        &user.authority,
+        user.self_trade_prevention_mode,
         &mut filler.as_deref_mut(),
         &filler_key,
         state.perp_fee_structure.flat_filler_fee,
         oracle_price,
         jit_maker_order_id,
+        fill_mode,
         now,
         slot,
     )?;
 
+    drop(perp_order_book_index);
+
+    if taker_self_trade_decrement > 0 {
+        let taker_order_canceled = apply_self_trade_prevention_decrement(
+            order_index,
+            user,
+            &user_key,
+            taker_self_trade_decrement,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            &filler_key,
+        )?;
+
+        if taker_order_canceled {
+            return Ok(0);
+        }
+    }
+
     let referrer_info = get_referrer_info(
         user_stats,
         makers_and_referrer,
@@ -1030,17 +1718,41 @@ pub fn fill_perp_order(
         return Ok(0);
     }
 
+    if valid_oracle_price.is_none() && user.orders[order_index].has_max_slippage() {
+        // max slippage is measured from the oracle, cant fill until it's valid again
+        msg!(
+            "Cant fill order {} with max slippage without a valid oracle price",
+            order_id
+        );
+        if let Some(filler) = filler.as_deref_mut() {
+            filler.update_last_active_slot(slot);
+        }
+        return Ok(0);
+    }
+
     validate_perp_fill_possible(state, user, order_index, slot, makers_and_referrer.0.len())?;
 
     let should_expire_order = should_expire_order_before_fill(user, order_index, now)?;
 
+    let (order_step_size, order_tick_size) = {
+        let market = perp_market_map.get_ref(&market_index)?;
+        (market.amm.order_step_size, market.amm.order_tick_size)
+    };
+    update_close_position_order_size(user, order_index, order_step_size)?;
+
     let position_index =
         get_position_index(&user.perp_positions, user.orders[order_index].market_index)?;
     let existing_base_asset_amount = user.perp_positions[position_index].base_asset_amount;
     let should_cancel_reduce_only =
         should_cancel_reduce_only_order(&user.orders[order_index], existing_base_asset_amount)?;
+    let should_cancel_max_slippage = should_cancel_max_slippage_order(
+        &user.orders[order_index],
+        valid_oracle_price,
+        slot,
+        order_tick_size,
+    )?;
 
-    if should_expire_order || should_cancel_reduce_only {
+    if should_expire_order || should_cancel_reduce_only || should_cancel_max_slippage {
         let filler_reward = {
             let mut market = perp_market_map.get_ref_mut(&market_index)?;
             pay_keeper_flat_reward_for_perps(
@@ -1054,8 +1766,10 @@ pub fn fill_perp_order(
 
         let explanation = if should_expire_order {
             OrderActionExplanation::OrderExpired
-        } else {
+        } else if should_cancel_reduce_only {
             OrderActionExplanation::ReduceOnlyOrderIncreasedPosition
+        } else {
+            OrderActionExplanation::MaxSlippageExceeded
         };
 
         cancel_order(
@@ -1076,6 +1790,14 @@ pub fn fill_perp_order(
         return Ok(0);
     }
 
+    if user.orders[order_index].is_waiting_for_twap_slice() {
+        msg!(
+            "Twap order waiting for next slice at {}",
+            user.orders[order_index].twap_next_slice_ts
+        );
+        return Ok(0);
+    }
+
     let (base_asset_amount, quote_asset_amount) = fulfill_perp_order(
         user,
         order_index,
@@ -1120,8 +1842,14 @@ pub fn fill_perp_order(
     let base_asset_amount_after = user.perp_positions[position_index].base_asset_amount;
     let should_cancel_reduce_only =
         should_cancel_reduce_only_order(&user.orders[order_index], base_asset_amount_after)?;
+    let should_cancel_max_slippage = should_cancel_max_slippage_order(
+        &user.orders[order_index],
+        valid_oracle_price,
+        slot,
+        order_tick_size,
+    )?;
 
-    if should_cancel_reduce_only {
+    if should_cancel_reduce_only || should_cancel_max_slippage {
         let filler_reward = {
             let mut market = perp_market_map.get_ref_mut(&market_index)?;
             pay_keeper_flat_reward_for_perps(
@@ -1133,7 +1861,11 @@ pub fn fill_perp_order(
             )?
         };
 
-        let explanation = OrderActionExplanation::ReduceOnlyOrderIncreasedPosition;
+        let explanation = if should_cancel_reduce_only {
+            OrderActionExplanation::ReduceOnlyOrderIncreasedPosition
+        } else {
+            OrderActionExplanation::MaxSlippageExceeded
+        };
 
         cancel_order(
             order_index,
@@ -1258,25 +1990,63 @@ fn get_maker_orders_info(
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
     makers_and_referrer: &UserMap,
+    perp_order_book_index: Option<&PerpOrderBookIndex>,
     taker_key: &Pubkey,
     taker_order: &Order,
+    taker_authority: &Pubkey,
+    taker_self_trade_prevention_mode: SelfTradePreventionMode,
     filler: &mut Option<&mut User>,
     filler_key: &Pubkey,
     filler_reward: u64,
     oracle_price: i64,
     jit_maker_order_id: Option<u32>,
+    fill_mode: FillMode,
     now: i64,
     slot: u64,
-) -> DriftResult<Vec<(Pubkey, usize, u64)>> {
+) -> DriftResult<(Vec<(Pubkey, usize, u64)>, u64)> {
     let maker_direction = taker_order.direction.opposite();
 
     let mut maker_orders_info = Vec::with_capacity(16);
 
-    for (maker_key, user_account_loader) in makers_and_referrer.0.iter() {
+    // amount the taker order is reduced by for crossing makers from the same authority
+    let mut taker_self_trade_decrement = 0_u64;
+    let taker_price = {
+        let market = perp_market_map.get_ref(&taker_order.market_index)?;
+        taker_order.get_limit_price(Some(oracle_price), None, slot, market.amm.order_tick_size)?
+    };
+
+    // makers with crossing orders in the order book index go first, best price then time priority,
+    // so equal priced orders from different makers fill in the order they were placed. The index only
+    // orders the makers passed in, an instruction can't load accounts it wasn't given, so fillers
+    // read the index off chain to pick which makers to pass
+    let indexed_makers = match perp_order_book_index {
+        Some(perp_order_book_index) => perp_order_book_index.get_crossing_makers(
+            taker_order.direction,
+            taker_price.unwrap_or(match taker_order.direction {
+                PositionDirection::Long => u64::MAX,
+                PositionDirection::Short => 0,
+            }),
+            makers_and_referrer.0.len(),
+        ),
+        None => vec![],
+    };
+    let maker_keys = indexed_makers
+        .iter()
+        .filter(|maker_key| makers_and_referrer.0.contains_key(maker_key))
+        .chain(
+            makers_and_referrer
+                .0
+                .keys()
+                .filter(|maker_key| !indexed_makers.contains(maker_key)),
+        );
+
+    for maker_key in maker_keys {
         if maker_key == taker_key {
             continue;
         }
 
+        let user_account_loader = makers_and_referrer.0.get(maker_key).safe_unwrap()?;
+
         let mut maker = load_mut!(user_account_loader)?;
 
         if maker.is_being_liquidated() || maker.is_bankrupt() {
@@ -1300,9 +2070,11 @@ fn get_maker_orders_info(
 
         maker.update_last_active_slot(slot);
 
-        settle_funding_payment(&mut maker, maker_key, &mut market, now)?;
+        // settle lp position so close position orders size to the whole position
+        controller::lp::settle_funding_payment_then_lp(&mut maker, maker_key, &mut market, now)?;
 
         let initial_margin_ratio = market.margin_ratio_initial;
+        let order_step_size = market.amm.order_step_size;
 
         drop(market);
 
@@ -1311,7 +2083,10 @@ fn get_maker_orders_info(
             let maker_order_price = *maker_order_price;
 
             let maker_order = &maker.orders[maker_order_index];
-            if !is_maker_for_taker(maker_order, taker_order, slot)? {
+            // a signed quote is placed in the same slot as the taker order and only fills against it
+            let is_signed_quote = fill_mode == FillMode::SignedQuote
+                && jit_maker_order_id == Some(maker_order.order_id);
+            if !is_signed_quote && !is_maker_for_taker(maker_order, taker_order, slot)? {
                 continue;
             }
 
@@ -1326,6 +2101,48 @@ fn get_maker_orders_info(
                 }
             }
 
+            let is_self_trade = taker_self_trade_prevention_mode != SelfTradePreventionMode::None
+                && &maker.authority == taker_authority
+                && taker_price.map_or(true, |taker_price| {
+                    do_orders_cross(maker_direction, maker_order_price, taker_price)
+                });
+
+            if is_self_trade {
+                let taker_base_asset_amount_unfilled = taker_order
+                    .get_base_asset_amount_unfilled(None)?
+                    .saturating_sub(taker_self_trade_decrement);
+
+                if taker_base_asset_amount_unfilled == 0 {
+                    continue;
+                }
+
+                let (taker_decrement, maker_decrement) = calculate_self_trade_prevention_decrements(
+                    taker_self_trade_prevention_mode,
+                    taker_base_asset_amount_unfilled,
+                    maker_order.get_base_asset_amount_unfilled(None)?,
+                );
+
+                taker_self_trade_decrement =
+                    taker_self_trade_decrement.safe_add(taker_decrement)?;
+
+                if maker_decrement > 0 {
+                    apply_self_trade_prevention_decrement(
+                        maker_order_index,
+                        maker.deref_mut(),
+                        maker_key,
+                        maker_decrement,
+                        perp_market_map,
+                        spot_market_map,
+                        oracle_map,
+                        now,
+                        slot,
+                        filler_key,
+                    )?;
+                }
+
+                continue;
+            }
+
             let breaches_oracle_price_limits = {
                 limit_price_breaches_maker_oracle_price_bands(
                     maker_order_price,
@@ -1337,6 +2154,12 @@ fn get_maker_orders_info(
 
             let should_expire_order = should_expire_order(&maker, maker_order_index, now)?;
 
+            update_close_position_order_size(
+                maker.deref_mut(),
+                maker_order_index,
+                order_step_size,
+            )?;
+
             let existing_base_asset_amount = maker
                 .get_perp_position(maker.orders[maker_order_index].market_index)?
                 .base_asset_amount;
@@ -1395,7 +2218,7 @@ fn get_maker_orders_info(
         }
     }
 
-    Ok(maker_orders_info)
+    Ok((maker_orders_info, taker_self_trade_decrement))
 }
 
 #[inline(always)]
@@ -1405,13 +2228,11 @@ fn insert_maker_order_info(
     direction: PositionDirection,
 ) {
     let price = maker_order_info.2;
-    let index = match maker_orders_info.binary_search_by(|item| match direction {
-        PositionDirection::Short => item.2.cmp(&price),
-        PositionDirection::Long => price.cmp(&item.2),
-    }) {
-        Ok(index) => index,
-        Err(index) => index,
-    };
+    // behind orders at the same price
+    let index = maker_orders_info.partition_point(|item| match direction {
+        PositionDirection::Short => item.2 <= price,
+        PositionDirection::Long => item.2 >= price,
+    });
 
     if index < maker_orders_info.capacity() {
         maker_orders_info.insert(index, maker_order_info);
@@ -1514,9 +2335,12 @@ fn fulfill_perp_order(
         return Ok((0, 0));
     }
 
+    let user_order = user.orders[user_order_index];
+
     let mut base_asset_amount = 0_u64;
     let mut quote_asset_amount = 0_u64;
     let mut makers_filled: BTreeMap<Pubkey, bool> = BTreeMap::new();
+    let mut linked_maker_orders_filled: Vec<(Pubkey, Order)> = vec![];
     for fulfillment_method in fulfillment_methods.iter() {
         if user.orders[user_order_index].status != OrderStatus::Open {
             break;
@@ -1554,6 +2378,7 @@ fn fulfill_perp_order(
                         None,
                         *maker_price,
                         AMMLiquiditySplit::Shared,
+                        fill_mode,
                     )?;
 
                 (fill_base_asset_amount, fill_quote_asset_amount)
@@ -1573,6 +2398,8 @@ fn fulfill_perp_order(
                     Some(&maker),
                 )?;
 
+                let maker_order = maker.orders[*maker_order_index as usize];
+
                 let (fill_base_asset_amount, fill_quote_asset_amount) =
                     fulfill_perp_order_with_match(
                         market.deref_mut(),
@@ -1592,14 +2419,20 @@ fn fulfill_perp_order(
                         reserve_price_before,
                         valid_oracle_price,
                         limit_price,
+                        None,
                         now,
                         slot,
                         fee_structure,
                         oracle_map,
+                        fill_mode,
                     )?;
 
                 if fill_base_asset_amount != 0 {
                     makers_filled.insert(*maker_key, true);
+
+                    if maker_order.link_type != OrderLinkType::None {
+                        linked_maker_orders_filled.push((*maker_key, maker_order));
+                    }
                 }
 
                 (fill_base_asset_amount, fill_quote_asset_amount)
@@ -1621,14 +2454,43 @@ fn fulfill_perp_order(
         quote_asset_amount
     )?;
 
-    let taker_margin_calculation =
-        calculate_margin_requirement_and_total_collateral_and_liability_info(
+    if base_asset_amount != 0 {
+        update_linked_orders(
             user,
+            user_key,
+            &user_order,
             perp_market_map,
             spot_market_map,
             oracle_map,
-            MarginContext::standard(if user_order_position_decreasing {
-                MarginRequirementType::Maintenance
+            now,
+            slot,
+            Some(filler_key),
+        )?;
+    }
+
+    for (maker_key, maker_order) in linked_maker_orders_filled {
+        let mut maker = makers_and_referrer.get_ref_mut(&maker_key)?;
+        update_linked_orders(
+            &mut maker,
+            &maker_key,
+            &maker_order,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(filler_key),
+        )?;
+    }
+
+    let taker_margin_calculation =
+        calculate_margin_requirement_and_total_collateral_and_liability_info(
+            user,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            MarginContext::standard(if user_order_position_decreasing {
+                MarginRequirementType::Maintenance
             } else {
                 MarginRequirementType::Fill
             }),
@@ -1729,6 +2591,7 @@ pub fn fulfill_perp_order_with_amm(
     override_base_asset_amount: Option<u64>,
     override_fill_price: Option<u64>,
     liquidity_split: AMMLiquiditySplit,
+    fill_mode: FillMode,
 ) -> DriftResult<(u64, u64)> {
     let position_index = get_position_index(&user.perp_positions, market.market_index)?;
     let existing_base_asset_amount = user.perp_positions[position_index].base_asset_amount;
@@ -1806,13 +2669,19 @@ pub fn fulfill_perp_order_with_amm(
             fill_price,
         )?;
 
-    if let Some(limit_price) = limit_price {
+    let max_slippage_price = user.orders[order_index].get_max_slippage_price(
+        oracle_map.get_price_data(&market.amm.oracle)?.price,
+        market.amm.order_tick_size,
+    )?;
+
+    if let Some(limit_price) = limit_price.or(max_slippage_price) {
         validate_fill_price(
             quote_asset_amount,
             base_asset_amount,
             BASE_PRECISION_U64,
             order_direction,
             limit_price,
+            max_slippage_price,
             !order_post_only,
         )?;
     }
@@ -1934,6 +2803,7 @@ pub fn fulfill_perp_order_with_amm(
         &mut user.orders[order_index],
         base_asset_amount,
         quote_asset_amount,
+        slot,
     )?;
 
     decrease_open_bids_and_asks(
@@ -1947,6 +2817,9 @@ pub fn fulfill_perp_order_with_amm(
 
     let fill_record_id = get_then_update_id!(market, next_fill_record_id);
     let order_action_explanation = match (override_base_asset_amount, override_fill_price) {
+        _ if fill_mode == FillMode::BatchAuction => {
+            OrderActionExplanation::OrderFilledWithBatchAuction
+        }
         (Some(_), Some(_)) => liquidity_split.get_order_action_explanation(),
         _ => OrderActionExplanation::OrderFilledWithAMM,
     };
@@ -2002,10 +2875,12 @@ pub fn fulfill_perp_order_with_match(
     reserve_price_before: u64,
     valid_oracle_price: Option<i64>,
     taker_limit_price: Option<u64>,
+    override_base_asset_amount: Option<u64>,
     now: i64,
     slot: u64,
     fee_structure: &FeeStructure,
     oracle_map: &mut OracleMap,
+    fill_mode: FillMode,
 ) -> DriftResult<(u64, u64)> {
     if !are_orders_same_market_but_different_sides(
         &maker.orders[maker_order_index],
@@ -2038,13 +2913,23 @@ pub fn fulfill_perp_order_with_match(
         .base_asset_amount;
     let taker_base_asset_amount = taker.orders[taker_order_index]
         .get_base_asset_amount_unfilled(Some(taker_existing_position))?;
+    let taker_base_asset_amount = match override_base_asset_amount {
+        Some(override_base_asset_amount) => taker_base_asset_amount.min(override_base_asset_amount),
+        None => taker_base_asset_amount,
+    };
 
-    let maker_price = maker.orders[maker_order_index].force_get_limit_price(
+    let maker_limit_price = maker.orders[maker_order_index].force_get_limit_price(
         Some(oracle_price),
         None,
         slot,
         market.amm.order_tick_size,
     )?;
+    // batch auctions fill both sides at the clearing price, passed as the taker limit price
+    let maker_price = if fill_mode == FillMode::BatchAuction {
+        taker_price
+    } else {
+        maker_limit_price
+    };
     let maker_direction = maker.orders[maker_order_index].direction;
     let maker_existing_position = maker
         .get_perp_position(market.market_index)?
@@ -2052,12 +2937,12 @@ pub fn fulfill_perp_order_with_match(
     let maker_base_asset_amount = maker.orders[maker_order_index]
         .get_base_asset_amount_unfilled(Some(maker_existing_position))?;
 
-    let orders_cross = do_orders_cross(maker_direction, maker_price, taker_price);
+    let orders_cross = do_orders_cross(maker_direction, maker_limit_price, taker_price);
 
     if !orders_cross {
         msg!(
             "orders dont cross. maker price {} taker price {}",
-            maker_price,
+            maker_limit_price,
             taker_price
         );
         return Ok((0_u64, 0_u64));
@@ -2087,16 +2972,21 @@ pub fn fulfill_perp_order_with_match(
     let mut total_quote_asset_amount = 0_u64;
     let mut total_base_asset_amount = 0_u64;
 
-    let (jit_base_asset_amount, amm_liquidity_split) = calculate_amm_jit_liquidity(
-        market,
-        taker_direction,
-        maker_price,
-        valid_oracle_price,
-        base_asset_amount,
-        taker_base_asset_amount,
-        maker_base_asset_amount,
-        taker.orders[taker_order_index].has_limit_price(slot)?,
-    )?;
+    let (jit_base_asset_amount, amm_liquidity_split) = if fill_mode == FillMode::BatchAuction {
+        // the amm takes its share of a batch at the clearing price instead
+        (0, AMMLiquiditySplit::ProtocolOwned)
+    } else {
+        calculate_amm_jit_liquidity(
+            market,
+            taker_direction,
+            maker_price,
+            valid_oracle_price,
+            base_asset_amount,
+            taker_base_asset_amount,
+            maker_base_asset_amount,
+            taker.orders[taker_order_index].has_limit_price(slot)?,
+        )?
+    };
 
     if jit_base_asset_amount > 0 {
         let (base_asset_amount_filled_by_amm, quote_asset_amount_filled_by_amm) =
@@ -2120,6 +3010,7 @@ pub fn fulfill_perp_order_with_match(
                 Some(jit_base_asset_amount),
                 Some(maker_price), // match the makers price
                 amm_liquidity_split,
+                fill_mode,
             )?;
 
         total_base_asset_amount = base_asset_amount_filled_by_amm;
@@ -2132,6 +3023,11 @@ pub fn fulfill_perp_order_with_match(
 
     let taker_base_asset_amount = taker.orders[taker_order_index]
         .get_base_asset_amount_unfilled(Some(taker_existing_position))?;
+    let taker_base_asset_amount = match override_base_asset_amount {
+        Some(override_base_asset_amount) => taker_base_asset_amount
+            .min(override_base_asset_amount.saturating_sub(total_base_asset_amount)),
+        None => taker_base_asset_amount,
+    };
 
     let (base_asset_amount_fulfilled, quote_asset_amount) = calculate_fill_for_matched_orders(
         maker_base_asset_amount,
@@ -2147,6 +3043,8 @@ pub fn fulfill_perp_order_with_match(
         BASE_PRECISION_U64,
         taker_direction,
         taker_price,
+        taker.orders[taker_order_index]
+            .get_max_slippage_price(oracle_price, market.amm.order_tick_size)?,
         true,
     )?;
 
@@ -2156,6 +3054,8 @@ pub fn fulfill_perp_order_with_match(
         BASE_PRECISION_U64,
         maker_direction,
         maker_price,
+        maker.orders[maker_order_index]
+            .get_max_slippage_price(oracle_price, market.amm.order_tick_size)?,
         false,
     )?;
 
@@ -2306,6 +3206,7 @@ pub fn fulfill_perp_order_with_match(
         &mut taker.orders[taker_order_index],
         base_asset_amount_fulfilled,
         quote_asset_amount,
+        slot,
     )?;
 
     decrease_open_bids_and_asks(
@@ -2318,6 +3219,7 @@ pub fn fulfill_perp_order_with_match(
         &mut maker.orders[maker_order_index],
         base_asset_amount_fulfilled,
         quote_asset_amount,
+        slot,
     )?;
 
     decrease_open_bids_and_asks(
@@ -2327,7 +3229,11 @@ pub fn fulfill_perp_order_with_match(
     )?;
 
     let fill_record_id = get_then_update_id!(market, next_fill_record_id);
-    let order_action_explanation = if maker.orders[maker_order_index].is_jit_maker() {
+    let order_action_explanation = if fill_mode == FillMode::SignedQuote {
+        OrderActionExplanation::SignedQuote
+    } else if fill_mode == FillMode::BatchAuction {
+        OrderActionExplanation::OrderFilledWithBatchAuction
+    } else if maker.orders[maker_order_index].is_jit_maker() {
         OrderActionExplanation::OrderFilledWithMatchJit
     } else {
         OrderActionExplanation::OrderFilledWithMatch
@@ -2372,181 +3278,1347 @@ pub fn fulfill_perp_order_with_match(
     Ok((total_base_asset_amount, total_quote_asset_amount))
 }
 
-pub fn update_order_after_fill(
-    order: &mut Order,
-    base_asset_amount: u64,
-    quote_asset_amount: u64,
-) -> DriftResult {
-    order.base_asset_amount_filled = order.base_asset_amount_filled.safe_add(base_asset_amount)?;
-
-    order.quote_asset_amount_filled = order
-        .quote_asset_amount_filled
-        .safe_add(quote_asset_amount)?;
+/// An order taking part in a batch auction
+struct BatchAuctionParticipant {
+    user_key: Pubkey,
+    authority: Pubkey,
+    order_index: usize,
+    order_slot: u64,
+    post_only: bool,
+    /// base_asset_amount is what's left to fill of the order's share of the batch
+    order: BatchAuctionOrder,
+}
 
-    if order.get_base_asset_amount_unfilled(None)? == 0 {
-        order.status = OrderStatus::Filled;
+impl BatchAuctionParticipant {
+    /// Base left to fill this batch, capped by what is left on the order (e.g. reduce only orders
+    /// shrink as the position closes and filled orders are reset)
+    fn update_after_fill(
+        &mut self,
+        user: &User,
+        market_index: u16,
+        base_asset_amount_filled: u64,
+    ) -> DriftResult {
+        let existing_base_asset_amount = user.get_perp_position(market_index)?.base_asset_amount;
+        let base_asset_amount_unfilled = user.orders[self.order_index]
+            .get_base_asset_amount_unfilled(Some(existing_base_asset_amount))?;
+
+        self.order.base_asset_amount = self
+            .order
+            .base_asset_amount
+            .saturating_sub(base_asset_amount_filled)
+            .min(base_asset_amount_unfilled);
+
+        Ok(())
     }
-
-    Ok(())
 }
 
-#[allow(clippy::type_complexity)]
-fn get_taker_and_maker_for_order_record(
-    user_key: &Pubkey,
-    user_order: &Order,
-) -> (Option<Pubkey>, Option<Order>, Option<Pubkey>, Option<Order>) {
-    if user_order.post_only {
-        (None, None, Some(*user_key), Some(*user_order))
-    } else {
-        (Some(*user_key), Some(*user_order), None, None)
-    }
+/// Market values a batch auction clears with, read before any fill
+struct BatchAuctionMarketInfo {
+    reserve_price_before: u64,
+    oracle_price: i64,
+    oracle_twap_5min: i64,
+    valid_oracle_price: Option<i64>,
+    amm_is_available: bool,
+    tick_size: u64,
+    step_size: u64,
+    margin_ratio_initial: u32,
 }
 
-pub fn trigger_order(
-    order_id: u32,
+/// Clears the batch of limit orders collected since the last clear for a batch auction market.
+/// The batch is every order in the market's order book index, so every user with an indexed order
+/// must be passed in, at most PERP_ORDER_BOOK_INDEX_MAX_USERS. Orders dropped from the index sit the
+/// batch out. Users below initial margin have their risk increasing orders canceled instead
+/// of blocking the batch. Every order that crosses the clearing price fills at the clearing price:
+/// better priced orders first and orders at the last price level pro rata, with the amm taking the
+/// imbalance between bids and asks
+pub fn clear_perp_batch_auction(
+    market_index: u16,
     state: &State,
-    user: &AccountLoader<User>,
-    spot_market_map: &SpotMarketMap,
     perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    users: &UserMap,
+    users_stats: &UserStatsMap,
     filler: &AccountLoader<User>,
+    filler_stats: &AccountLoader<UserStats>,
     clock: &Clock,
 ) -> DriftResult {
     let now = clock.unix_timestamp;
     let slot = clock.slot;
-
     let filler_key = filler.key();
-    let user_key = user.key();
-    let user = &mut load_mut!(user)?;
-
-    let order_index = user
-        .orders
-        .iter()
-        .position(|order| order.order_id == order_id)
-        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
 
-    let (order_status, market_index, market_type) =
-        get_struct_values!(user.orders[order_index], status, market_index, market_type);
+    let market_info =
+        get_batch_auction_market_info(market_index, state, perp_market_map, oracle_map, now, slot)?;
 
-    validate!(
-        order_status == OrderStatus::Open,
-        ErrorCode::OrderNotOpen,
-        "Order not open"
-    )?;
+    if is_oracle_too_divergent_with_twap_5min(
+        market_info.oracle_price,
+        market_info.oracle_twap_5min,
+        state
+            .oracle_guard_rails
+            .max_oracle_twap_5min_percent_divergence()
+            .cast()?,
+    )? {
+        // the batch stays open and clears once the oracle is back in line
+        msg!(
+            "Batch auction for market {} not cleared, oracle too divergent from twap",
+            market_index
+        );
+        return Ok(());
+    }
 
-    validate!(
-        user.orders[order_index].must_be_triggered(),
-        ErrorCode::OrderNotTriggerable,
-        "Order is not triggerable"
-    )?;
+    let mut perp_order_book_index = match perp_order_book_index_map.get_ref_mut(&market_index)? {
+        Some(perp_order_book_index) => perp_order_book_index,
+        None => {
+            msg!(
+                "Batch auction for market {} needs the market's order book index",
+                market_index
+            );
+            return Err(ErrorCode::InvalidBatchAuction);
+        }
+    };
 
-    validate!(
-        !user.orders[order_index].triggered(),
-        ErrorCode::OrderNotTriggerable,
-        "Order is already triggered"
-    )?;
+    for user_key in perp_order_book_index.get_users() {
+        validate!(
+            users.0.contains_key(&user_key),
+            ErrorCode::InvalidBatchAuction,
+            "Batch auction for market {} missing user {} with an order in the batch",
+            market_index,
+            user_key
+        )?;
+    }
 
-    validate!(
-        market_type == MarketType::Perp,
-        ErrorCode::InvalidOrderMarketType,
-        "Order must be a perp order"
+    let user_keys: Vec<Pubkey> = users.0.keys().copied().collect();
+    sync_batch_auction_order_book_index(
+        market_index,
+        &mut perp_order_book_index,
+        &user_keys,
+        users,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        now,
+        slot,
+        &filler_key,
     )?;
 
-    validate_user_not_being_liquidated(
-        user,
+    let base_asset_amounts_before = prepare_batch_auction_users(
+        market_index,
+        &mut perp_order_book_index,
+        users,
         perp_market_map,
         spot_market_map,
         oracle_map,
-        state.liquidation_margin_buffer_ratio,
+        now,
+        slot,
+        &filler_key,
     )?;
 
-    validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
-
-    let mut perp_market = perp_market_map.get_ref_mut(&market_index)?;
-    let oracle_price_data = &oracle_map.get_price_data(&perp_market.amm.oracle)?;
-
-    let oracle_validity = oracle::oracle_validity(
-        perp_market
-            .amm
-            .historical_oracle_data
-            .last_oracle_price_twap,
-        oracle_price_data,
-        &state.oracle_guard_rails.validity,
+    let (bids, asks) = get_batch_auction_participants(
+        &perp_order_book_index,
+        users,
+        &base_asset_amounts_before,
+        now,
+        slot,
     )?;
-    let is_oracle_valid =
-        is_oracle_valid_for_action(oracle_validity, Some(DriftAction::TriggerOrder))?;
-
-    validate!(is_oracle_valid, ErrorCode::InvalidOracle)?;
 
-    let oracle_price = oracle_price_data.price;
+    let orders: Vec<BatchAuctionOrder> = bids
+        .iter()
+        .chain(asks.iter())
+        .map(|participant| participant.order)
+        .collect();
 
-    let can_trigger = order_satisfies_trigger_condition(
-        &user.orders[order_index],
-        oracle_price.unsigned_abs().cast()?,
-    )?;
-    validate!(can_trigger, ErrorCode::OrderDidNotSatisfyTriggerCondition)?;
+    let clearing = {
+        let market = perp_market_map.get_ref(&market_index)?;
+        let amm = if market_info.amm_is_available {
+            Some(&market.amm)
+        } else {
+            None
+        };
 
-    let worst_case_base_asset_amount_before = user
-        .get_perp_position(market_index)?
-        .worst_case_base_asset_amount()?;
+        match calculate_batch_auction_clearing_price(&orders, amm, market_info.tick_size)? {
+            Some((clearing_price, _)) => {
+                let (amm_bid_liquidity, amm_ask_liquidity) = match amm {
+                    Some(amm) => calculate_amm_batch_auction_liquidity(amm, clearing_price)?,
+                    None => (0, 0),
+                };
+                Some((clearing_price, amm_bid_liquidity, amm_ask_liquidity))
+            }
+            None => None,
+        }
+    };
 
-    {
-        let direction = user.orders[order_index].direction;
-        let base_asset_amount = user.orders[order_index].base_asset_amount;
+    let users_filled = match clearing {
+        Some((clearing_price, amm_bid_liquidity, amm_ask_liquidity)) => {
+            for direction in [PositionDirection::Long, PositionDirection::Short] {
+                validate_fill_price_within_price_bands(
+                    clearing_price,
+                    direction,
+                    market_info.oracle_price,
+                    market_info.oracle_twap_5min,
+                    market_info.margin_ratio_initial,
+                    state
+                        .oracle_guard_rails
+                        .max_oracle_twap_5min_percent_divergence(),
+                )?;
+            }
 
-        user.orders[order_index].trigger_condition =
-            match user.orders[order_index].trigger_condition {
-                OrderTriggerCondition::Above => OrderTriggerCondition::TriggeredAbove,
-                OrderTriggerCondition::Below => OrderTriggerCondition::TriggeredBelow,
-                _ => {
-                    return Err(print_error!(ErrorCode::InvalidTriggerOrderCondition)());
-                }
-            };
+            msg!(
+                "Batch auction for market {} clears at {}",
+                market_index,
+                clearing_price
+            );
 
-        user.orders[order_index].slot = slot;
-        let order_type = user.orders[order_index].order_type;
-        if let OrderType::TriggerMarket = order_type {
-            user.orders[order_index].auction_duration = state.min_perp_auction_duration;
-            let (auction_start_price, auction_end_price) =
-                calculate_auction_prices(oracle_price_data, direction, 0)?;
-            user.orders[order_index].auction_start_price = auction_start_price;
-            user.orders[order_index].auction_end_price = auction_end_price;
+            fill_batch_auction_participants(
+                market_index,
+                state,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                users,
+                users_stats,
+                filler,
+                filler_stats,
+                bids,
+                asks,
+                clearing_price,
+                amm_bid_liquidity,
+                amm_ask_liquidity,
+                &market_info,
+                now,
+                slot,
+            )?
+        }
+        None => {
+            msg!("Batch auction for market {} did not cross", market_index);
+            BTreeSet::new()
         }
-
-        let user_position = user.get_perp_position_mut(market_index)?;
-        increase_open_bids_and_asks(user_position, &direction, base_asset_amount)?;
-    }
-
-    let is_filler_taker = user_key == filler_key;
-    let mut filler = if !is_filler_taker {
-        Some(load_mut!(filler)?)
-    } else {
-        None
     };
 
-    let filler_reward = pay_keeper_flat_reward_for_perps(
-        user,
-        filler.as_deref_mut(),
-        &mut perp_market,
-        state.perp_fee_structure.flat_filler_fee,
+    validate_batch_auction_fills(
+        market_index,
+        &users_filled,
+        &base_asset_amounts_before,
+        users,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
         slot,
     )?;
 
-    let order_action_record = get_order_action_record(
-        now,
-        OrderAction::Trigger,
-        OrderActionExplanation::None,
+    // fills, cancels and activated linked orders all change the batch
+    sync_batch_auction_order_book_index(
         market_index,
-        Some(filler_key),
-        None,
-        Some(filler_reward),
-        None,
-        None,
-        Some(filler_reward),
-        None,
-        None,
-        None,
-        None,
+        &mut perp_order_book_index,
+        &user_keys,
+        users,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        now,
+        slot,
+        &filler_key,
+    )?;
+
+    if !users_filled.is_empty() {
+        {
+            let market = perp_market_map.get_ref(&market_index)?;
+
+            let open_interest = market.get_open_interest();
+            let max_open_interest = market.amm.max_open_interest;
+
+            validate!(
+                max_open_interest == 0 || max_open_interest > open_interest,
+                ErrorCode::MaxOpenInterest,
+                "open interest ({}) > max open interest ({})",
+                open_interest,
+                max_open_interest
+            )?;
+        }
+
+        {
+            let market = &mut perp_market_map.get_ref_mut(&market_index)?;
+            let funding_paused =
+                state.funding_paused()? || matches!(market.status, MarketStatus::FundingPaused);
+
+            controller::funding::update_funding_rate(
+                market_index,
+                market,
+                oracle_map,
+                now,
+                &state.oracle_guard_rails,
+                funding_paused,
+                Some(market_info.reserve_price_before),
+            )?;
+        }
+
+        if !users.0.contains_key(&filler_key) {
+            load_mut!(filler)?.update_last_active_slot(slot);
+        }
+    }
+
+    perp_market_map
+        .get_ref_mut(&market_index)?
+        .last_batch_auction_clear_slot = slot;
+
+    Ok(())
+}
+
+fn get_batch_auction_market_info(
+    market_index: u16,
+    state: &State,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+) -> DriftResult<BatchAuctionMarketInfo> {
+    let market = &perp_market_map.get_ref(&market_index)?;
+
+    validate!(
+        market.is_batch_auction_market(),
+        ErrorCode::InvalidBatchAuction,
+        "Market {} is not a batch auction market",
+        market_index
+    )?;
+
+    validate!(
+        market.can_clear_batch_auction(slot)?,
+        ErrorCode::BatchAuctionNotReady,
+        "Batch auction can not clear before slot {}",
+        market
+            .last_batch_auction_clear_slot
+            .safe_add(market.batch_auction_duration.cast()?)?
+    )?;
+
+    validate!(
+        matches!(
+            market.status,
+            MarketStatus::Active
+                | MarketStatus::FundingPaused
+                | MarketStatus::ReduceOnly
+                | MarketStatus::WithdrawPaused
+        ),
+        ErrorCode::MarketFillOrderPaused,
+        "Market unavailable for fills"
+    )?;
+
+    validation::perp_market::validate_perp_market(market)?;
+    validate!(
+        market.is_active(now)?,
+        ErrorCode::MarketFillOrderPaused,
+        "Market is in settlement mode",
+    )?;
+
+    let oracle_price_data = &oracle_map.get_price_data(&market.amm.oracle)?;
+    let oracle_validity = oracle::oracle_validity(
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+        oracle_price_data,
+        &state.oracle_guard_rails.validity,
+    )?;
+    let is_oracle_valid =
+        is_oracle_valid_for_action(oracle_validity, Some(DriftAction::FillOrderAmm))?;
+
+    let oracle_price = oracle_price_data.price;
+    let valid_oracle_price = if is_oracle_valid || oracle_validity == OracleValidity::StaleForAMM {
+        Some(oracle_price)
+    } else {
+        None
+    };
+
+    Ok(BatchAuctionMarketInfo {
+        reserve_price_before: market.amm.reserve_price()?,
+        oracle_price,
+        oracle_twap_5min: market
+            .amm
+            .historical_oracle_data
+            .last_oracle_price_twap_5min,
+        valid_oracle_price,
+        amm_is_available: is_oracle_valid && !state.amm_paused()?,
+        tick_size: market.amm.order_tick_size,
+        step_size: market.amm.order_step_size,
+        margin_ratio_initial: market.margin_ratio_initial,
+    })
+}
+
+/// Syncs a batch auction market's order book index with the users' orders. Orders that no longer fit
+/// in the index are canceled, so the index always holds the whole batch
+fn sync_batch_auction_order_book_index(
+    market_index: u16,
+    perp_order_book_index: &mut PerpOrderBookIndex,
+    user_keys: &[Pubkey],
+    users: &UserMap,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: &Pubkey,
+) -> DriftResult {
+    for user_key in user_keys.iter() {
+        perp_order_book_index.update_user_orders(user_key, &*users.get_ref(user_key)?)?;
+    }
+
+    for user_key in user_keys.iter() {
+        let mut user = users.get_ref_mut(user_key)?;
+        for order_index in 0..user.orders.len() {
+            let order = user.orders[order_index];
+            if !is_indexable_order(&order, market_index)
+                || perp_order_book_index.contains_order(user_key, order.order_id)
+            {
+                continue;
+            }
+
+            cancel_order(
+                order_index,
+                &mut user,
+                user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                OrderActionExplanation::OrderBookIndexFull,
+                Some(filler_key),
+                0,
+                false,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Settles funding for the users in the batch and cancels the risk increasing orders of users below
+/// initial margin. Returns the position of every user taking part before the batch fills
+fn prepare_batch_auction_users(
+    market_index: u16,
+    perp_order_book_index: &mut PerpOrderBookIndex,
+    users: &UserMap,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: &Pubkey,
+) -> DriftResult<BTreeMap<Pubkey, i64>> {
+    let mut base_asset_amounts_before: BTreeMap<Pubkey, i64> = BTreeMap::new();
+    for user_key in perp_order_book_index.get_users() {
+        let mut user = users.get_ref_mut(&user_key)?;
+        if user.is_being_liquidated() {
+            continue;
+        }
+
+        {
+            let mut market = perp_market_map.get_ref_mut(&market_index)?;
+            controller::lp::settle_funding_payment_then_lp(&mut user, &user_key, &mut market, now)?;
+        }
+
+        if !meets_initial_margin_requirement(&user, perp_market_map, spot_market_map, oracle_map)? {
+            msg!(
+                "user ({}) below initial margin, canceling risk increasing orders in batch",
+                user_key
+            );
+
+            for order_index in 0..user.orders.len() {
+                let order = user.orders[order_index];
+                if order.reduce_only
+                    || !perp_order_book_index.contains_order(&user_key, order.order_id)
+                {
+                    continue;
+                }
+
+                cancel_order(
+                    order_index,
+                    &mut user,
+                    &user_key,
+                    perp_market_map,
+                    spot_market_map,
+                    oracle_map,
+                    now,
+                    slot,
+                    OrderActionExplanation::InsufficientFreeCollateral,
+                    Some(filler_key),
+                    0,
+                    false,
+                )?;
+            }
+
+            perp_order_book_index.update_user_orders(&user_key, &user)?;
+        }
+
+        base_asset_amounts_before.insert(
+            user_key,
+            user.get_perp_position(market_index)?.base_asset_amount,
+        );
+    }
+
+    Ok(base_asset_amounts_before)
+}
+
+/// Bids and asks in the batch in price then time priority, as held by the order book index. Orders
+/// placed in the clearing slot wait for the next batch
+fn get_batch_auction_participants(
+    perp_order_book_index: &PerpOrderBookIndex,
+    users: &UserMap,
+    base_asset_amounts_before: &BTreeMap<Pubkey, i64>,
+    now: i64,
+    slot: u64,
+) -> DriftResult<(Vec<BatchAuctionParticipant>, Vec<BatchAuctionParticipant>)> {
+    let mut bids: Vec<BatchAuctionParticipant> = vec![];
+    let mut asks: Vec<BatchAuctionParticipant> = vec![];
+
+    for direction in [PositionDirection::Long, PositionDirection::Short] {
+        for entry in perp_order_book_index.get_orders(direction) {
+            // users being liquidated sit the batch out
+            let existing_base_asset_amount = match base_asset_amounts_before.get(&entry.user) {
+                Some(base_asset_amount) => *base_asset_amount,
+                None => continue,
+            };
+
+            let user = users.get_ref(&entry.user)?;
+            let order_index = user.get_order_index(entry.order_id)?;
+            let order = &user.orders[order_index];
+            if order.slot >= slot || (order.max_ts != 0 && now > order.max_ts) {
+                continue;
+            }
+
+            let base_asset_amount =
+                order.get_base_asset_amount_unfilled(Some(existing_base_asset_amount))?;
+            if base_asset_amount == 0 {
+                continue;
+            }
+
+            let participant = BatchAuctionParticipant {
+                user_key: entry.user,
+                authority: user.authority,
+                order_index,
+                order_slot: order.slot,
+                post_only: order.post_only,
+                order: BatchAuctionOrder {
+                    direction,
+                    price: order.price,
+                    base_asset_amount,
+                },
+            };
+
+            match direction {
+                PositionDirection::Long => bids.push(participant),
+                PositionDirection::Short => asks.push(participant),
+            }
+        }
+    }
+
+    Ok((bids, asks))
+}
+
+/// Fills the orders crossing the clearing price at the clearing price. Each side is split between its
+/// orders, then bids match asks and what's left fills against the amm. When two orders match, a post
+/// only order is the maker, otherwise the earlier order. Returns the users with fills
+fn fill_batch_auction_participants(
+    market_index: u16,
+    state: &State,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    users: &UserMap,
+    users_stats: &UserStatsMap,
+    filler: &AccountLoader<User>,
+    filler_stats: &AccountLoader<UserStats>,
+    mut bids: Vec<BatchAuctionParticipant>,
+    mut asks: Vec<BatchAuctionParticipant>,
+    clearing_price: u64,
+    amm_bid_liquidity: u64,
+    amm_ask_liquidity: u64,
+    market_info: &BatchAuctionMarketInfo,
+    now: i64,
+    slot: u64,
+) -> DriftResult<BTreeSet<Pubkey>> {
+    let filler_key = filler.key();
+
+    bids.retain(|bid| do_orders_cross(PositionDirection::Long, bid.order.price, clearing_price));
+    asks.retain(|ask| do_orders_cross(PositionDirection::Short, ask.order.price, clearing_price));
+
+    let bids_base_asset_amount = bids.iter().try_fold(0_u64, |total, bid| {
+        total.safe_add(bid.order.base_asset_amount)
+    })?;
+    let asks_base_asset_amount = asks.iter().try_fold(0_u64, |total, ask| {
+        total.safe_add(ask.order.base_asset_amount)
+    })?;
+    let (bids_filled, asks_filled) = calculate_batch_auction_fill_amounts(
+        bids_base_asset_amount,
+        asks_base_asset_amount,
+        amm_bid_liquidity,
+        amm_ask_liquidity,
+    )?;
+
+    for (participants, base_asset_amount_filled) in
+        [(&mut bids, bids_filled), (&mut asks, asks_filled)]
+    {
+        let orders: Vec<BatchAuctionOrder> = participants
+            .iter()
+            .map(|participant| participant.order)
+            .collect();
+        let allocations = calculate_batch_auction_fill_allocations(
+            &orders,
+            base_asset_amount_filled,
+            market_info.step_size,
+        )?;
+
+        for (participant, allocation) in participants.iter_mut().zip(allocations) {
+            participant.order.base_asset_amount = allocation;
+        }
+    }
+
+    let (mut filler, mut filler_stats) = if users.0.contains_key(&filler_key) {
+        (None, None)
+    } else {
+        let filler = load_mut!(filler)?;
+        if users_stats.0.contains_key(&filler.authority) {
+            (None, None)
+        } else {
+            (Some(filler), Some(load_mut!(filler_stats)?))
+        }
+    };
+
+    let mut users_filled: BTreeSet<Pubkey> = BTreeSet::new();
+    let mut linked_orders_filled: Vec<(Pubkey, Order)> = vec![];
+    {
+        let mut market = perp_market_map.get_ref_mut(&market_index)?;
+
+        for bid in bids.iter_mut() {
+            for ask in asks.iter_mut() {
+                if bid.order.base_asset_amount == 0 {
+                    break;
+                }
+
+                if ask.order.base_asset_amount == 0 || ask.authority == bid.authority {
+                    continue;
+                }
+
+                let bid_is_maker = if bid.post_only != ask.post_only {
+                    bid.post_only
+                } else {
+                    bid.order_slot < ask.order_slot
+                };
+
+                let (taker, maker) = if bid_is_maker {
+                    (&mut *ask, &mut *bid)
+                } else {
+                    (&mut *bid, &mut *ask)
+                };
+
+                let mut taker_user = users.get_ref_mut(&taker.user_key)?;
+                let mut taker_stats = users_stats.get_ref_mut(&taker.authority)?;
+                let mut maker_user = users.get_ref_mut(&maker.user_key)?;
+                let mut maker_stats = Some(users_stats.get_ref_mut(&maker.authority)?);
+                let taker_order = taker_user.orders[taker.order_index];
+                let maker_order = maker_user.orders[maker.order_index];
+
+                let (base_asset_amount, quote_asset_amount) = fulfill_perp_order_with_match(
+                    market.deref_mut(),
+                    &mut taker_user,
+                    &mut taker_stats,
+                    taker.order_index,
+                    &taker.user_key,
+                    &mut maker_user,
+                    &mut maker_stats.as_deref_mut(),
+                    maker.order_index,
+                    &maker.user_key,
+                    &mut filler.as_deref_mut(),
+                    &mut filler_stats.as_deref_mut(),
+                    &filler_key,
+                    &mut None,
+                    &mut None,
+                    market_info.reserve_price_before,
+                    market_info.valid_oracle_price,
+                    Some(clearing_price),
+                    Some(
+                        taker
+                            .order
+                            .base_asset_amount
+                            .min(maker.order.base_asset_amount),
+                    ),
+                    now,
+                    slot,
+                    &state.perp_fee_structure,
+                    oracle_map,
+                    FillMode::BatchAuction,
+                )?;
+
+                taker.update_after_fill(&taker_user, market_index, base_asset_amount)?;
+                maker.update_after_fill(&maker_user, market_index, base_asset_amount)?;
+
+                if base_asset_amount != 0 {
+                    market
+                        .amm
+                        .update_volume_24h(quote_asset_amount, taker_order.direction, now)?;
+
+                    for (user_key, filled_order) in
+                        [(taker.user_key, taker_order), (maker.user_key, maker_order)]
+                    {
+                        users_filled.insert(user_key);
+                        if filled_order.link_type != OrderLinkType::None {
+                            linked_orders_filled.push((user_key, filled_order));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (participants, mut amm_liquidity) in [
+            (&mut bids, amm_ask_liquidity),
+            (&mut asks, amm_bid_liquidity),
+        ] {
+            for participant in participants.iter_mut() {
+                let amm_base_asset_amount = participant.order.base_asset_amount.min(amm_liquidity);
+                if amm_base_asset_amount == 0 {
+                    continue;
+                }
+
+                let mut user = users.get_ref_mut(&participant.user_key)?;
+                let mut user_stats = users_stats.get_ref_mut(&participant.authority)?;
+                let user_order = user.orders[participant.order_index];
+
+                let (base_asset_amount, quote_asset_amount) = fulfill_perp_order_with_amm(
+                    &mut user,
+                    &mut user_stats,
+                    participant.order_index,
+                    market.deref_mut(),
+                    oracle_map,
+                    market_info.reserve_price_before,
+                    now,
+                    slot,
+                    &participant.user_key,
+                    &filler_key,
+                    &mut filler.as_deref_mut(),
+                    &mut filler_stats.as_deref_mut(),
+                    &mut None,
+                    &mut None,
+                    &state.perp_fee_structure,
+                    Some(participant.order.price),
+                    Some(amm_base_asset_amount),
+                    Some(clearing_price),
+                    AMMLiquiditySplit::Shared,
+                    FillMode::BatchAuction,
+                )?;
+
+                amm_liquidity = amm_liquidity.safe_sub(base_asset_amount)?;
+                participant.update_after_fill(&user, market_index, base_asset_amount)?;
+
+                if base_asset_amount != 0 {
+                    market
+                        .amm
+                        .update_volume_24h(quote_asset_amount, user_order.direction, now)?;
+
+                    users_filled.insert(participant.user_key);
+                    if user_order.link_type != OrderLinkType::None {
+                        linked_orders_filled.push((participant.user_key, user_order));
+                    }
+                }
+            }
+        }
+    }
+
+    for (user_key, order) in linked_orders_filled {
+        let mut user = users.get_ref_mut(&user_key)?;
+        update_linked_orders(
+            &mut user,
+            &user_key,
+            &order,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(&filler_key),
+        )?;
+    }
+
+    Ok(users_filled)
+}
+
+/// Users that met initial margin with their orders open can still breach the fill requirement
+/// filling away from the oracle price, in which case the whole batch reverts
+fn validate_batch_auction_fills(
+    market_index: u16,
+    users_filled: &BTreeSet<Pubkey>,
+    base_asset_amounts_before: &BTreeMap<Pubkey, i64>,
+    users: &UserMap,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    slot: u64,
+) -> DriftResult {
+    for user_key in users_filled.iter() {
+        let mut user = users.get_ref_mut(user_key)?;
+
+        let base_asset_amount_before = *base_asset_amounts_before.get(user_key).safe_unwrap()?;
+        let base_asset_amount_after = user.get_perp_position(market_index)?.base_asset_amount;
+        let position_decreasing = base_asset_amount_after.unsigned_abs()
+            <= base_asset_amount_before.unsigned_abs()
+            && base_asset_amount_after.signum() * base_asset_amount_before.signum() >= 0;
+
+        let margin_calculation =
+            calculate_margin_requirement_and_total_collateral_and_liability_info(
+                &user,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                MarginContext::standard(if position_decreasing {
+                    MarginRequirementType::Maintenance
+                } else {
+                    MarginRequirementType::Fill
+                }),
+            )?;
+
+        if !margin_calculation.meets_margin_requirement() {
+            msg!(
+                "user ({}) breached fill requirements (margin requirement {}) (total_collateral {})",
+                user_key,
+                margin_calculation.margin_requirement,
+                margin_calculation.total_collateral
+            );
+            return Err(ErrorCode::InsufficientCollateral);
+        }
+
+        user.update_last_active_slot(slot);
+    }
+
+    Ok(())
+}
+
+/// Reduces an order by the self trade prevention decrement, canceling it if nothing would be left.
+/// Twap and iceberg orders are always canceled. Returns true if the order was canceled
+fn apply_self_trade_prevention_decrement(
+    order_index: usize,
+    user: &mut User,
+    user_key: &Pubkey,
+    decrement: u64,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: &Pubkey,
+) -> DriftResult<bool> {
+    let order = user.orders[order_index];
+    if decrement >= order.get_base_asset_amount_unfilled(None)?
+        || order.is_twap()
+        || order.is_iceberg()
+    {
+        cancel_order(
+            order_index,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::SelfTradePrevention,
+            Some(filler_key),
+            0,
+            false,
+        )?;
+
+        return Ok(true);
+    }
+
+    user.orders[order_index].base_asset_amount = order.base_asset_amount.safe_sub(decrement)?;
+
+    if !order.must_be_triggered() || order.triggered() {
+        if order.market_type == MarketType::Perp {
+            let position_index = get_position_index(&user.perp_positions, order.market_index)?;
+            decrease_open_bids_and_asks(
+                &mut user.perp_positions[position_index],
+                &order.direction,
+                decrement,
+            )?;
+        } else {
+            let spot_position_index = user.get_spot_position_index(order.market_index)?;
+            decrease_spot_open_bids_and_asks(
+                &mut user.spot_positions[spot_position_index],
+                &order.direction,
+                decrement,
+            )?;
+        }
+    }
+
+    msg!(
+        "Self trade prevention reduced order {} by {}",
+        order.order_id,
+        decrement
+    );
+
+    Ok(false)
+}
+
+/// Starts the next twap slice, auctioned from the current oracle price like a new market order.
+/// The slice is added to base_asset_amount so the order's fills stay cumulative
+fn release_twap_slice(
+    order: &mut Order,
+    oracle_price_data: &OraclePriceData,
+    tick_size: u64,
+    auction_duration: u8,
+    slot: u64,
+) -> DriftResult {
+    let (auction_start_price, auction_end_price) =
+        calculate_auction_prices(oracle_price_data, order.direction, order.price)?;
+
+    let slice_base_asset_amount = calculate_twap_slice_base_asset_amount(
+        order.hidden_base_asset_amount,
+        order.slice_base_asset_amount,
+        order.twap_slices_remaining,
+    );
+    order.base_asset_amount = order.base_asset_amount.safe_add(slice_base_asset_amount)?;
+    order.hidden_base_asset_amount = order
+        .hidden_base_asset_amount
+        .safe_sub(slice_base_asset_amount)?;
+    order.twap_slices_remaining = order.twap_slices_remaining.safe_sub(1)?;
+    order.slot = slot;
+    order.auction_start_price =
+        standardize_price_i64(auction_start_price, tick_size.cast()?, order.direction)?;
+    order.auction_end_price =
+        standardize_price_i64(auction_end_price, tick_size.cast()?, order.direction)?;
+    order.auction_duration = auction_duration;
+    order.twap_next_slice_ts = order
+        .twap_next_slice_ts
+        .safe_add(order.twap_interval.cast()?)?;
+
+    msg!(
+        "Released twap slice of {} for order {}, next slice at {}",
+        slice_base_asset_amount,
+        order.order_id,
+        order.twap_next_slice_ts
+    );
+
+    Ok(())
+}
+
+/// Resets a close position order's unfilled size to the user's current position. The lp position must
+/// be settled first so the size includes the lp's share. Orders that no longer reduce the position
+/// are left for the reduce only check to cancel
+fn update_close_position_order_size(
+    user: &mut User,
+    order_index: usize,
+    order_step_size: u64,
+) -> DriftResult {
+    let order = user.orders[order_index];
+    if !order.close_position {
+        return Ok(());
+    }
+
+    let position_index = get_position_index(&user.perp_positions, order.market_index)?;
+    let base_asset_amount_unfilled = calculate_close_position_base_asset_amount(
+        user.perp_positions[position_index].base_asset_amount,
+        order.direction,
+        order_step_size,
+    )?;
+    let existing_base_asset_amount_unfilled = order.get_base_asset_amount_unfilled(None)?;
+
+    if base_asset_amount_unfilled == 0
+        || base_asset_amount_unfilled == existing_base_asset_amount_unfilled
+    {
+        return Ok(());
+    }
+
+    // open bids and asks only include trigger orders once triggered
+    if !order.must_be_triggered() || order.triggered() {
+        let position = &mut user.perp_positions[position_index];
+        decrease_open_bids_and_asks(
+            position,
+            &order.direction,
+            existing_base_asset_amount_unfilled,
+        )?;
+        increase_open_bids_and_asks(position, &order.direction, base_asset_amount_unfilled)?;
+    }
+
+    user.orders[order_index].base_asset_amount = order
+        .base_asset_amount_filled
+        .safe_add(base_asset_amount_unfilled)?;
+
+    Ok(())
+}
+
+/// Called after a linked order is filled or triggered. For one cancels other, the other orders with
+/// the same link id are canceled. For a parent, its pending children are activated
+fn update_linked_orders(
+    user: &mut User,
+    user_key: &Pubkey,
+    order: &Order,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: Option<&Pubkey>,
+) -> DriftResult {
+    match order.link_type {
+        OrderLinkType::OneCancelsOther => {
+            for linked_order_index in 0..user.orders.len() {
+                let linked_order = &user.orders[linked_order_index];
+                if linked_order.status != OrderStatus::Open
+                    || linked_order.link_type != OrderLinkType::OneCancelsOther
+                    || !linked_order.is_linked_to(order)
+                {
+                    continue;
+                }
+
+                cancel_order(
+                    linked_order_index,
+                    user,
+                    user_key,
+                    perp_market_map,
+                    spot_market_map,
+                    oracle_map,
+                    now,
+                    slot,
+                    OrderActionExplanation::CanceledByLinkedOrder,
+                    filler_key,
+                    0,
+                    false,
+                )?;
+            }
+        }
+        OrderLinkType::Parent => {
+            for linked_order in user.orders.iter_mut() {
+                if linked_order.status == OrderStatus::Open
+                    && linked_order.is_pending_linked_order()
+                    && linked_order.is_linked_to(order)
+                {
+                    linked_order.link_type = OrderLinkType::OneCancelsOther;
+                }
+            }
+        }
+        OrderLinkType::None | OrderLinkType::PendingChild => {}
+    }
+
+    Ok(())
+}
+
+pub fn update_order_after_fill(
+    order: &mut Order,
+    base_asset_amount: u64,
+    quote_asset_amount: u64,
+    slot: u64,
+) -> DriftResult {
+    order.base_asset_amount_filled = order.base_asset_amount_filled.safe_add(base_asset_amount)?;
+
+    order.quote_asset_amount_filled = order
+        .quote_asset_amount_filled
+        .safe_add(quote_asset_amount)?;
+
+    if order.is_iceberg() && order.base_asset_amount_filled == order.base_asset_amount {
+        refill_iceberg_order(order, slot)?;
+    }
+
+    if order.get_base_asset_amount_unfilled(None)? == 0 {
+        order.status = OrderStatus::Filled;
+    }
+
+    Ok(())
+}
+
+/// Displays the next slice of an iceberg order once the displayed amount is filled. The slice is added
+/// to base_asset_amount so fills stay cumulative. The order keeps its price but takes the current slot,
+/// so it goes to the back of the queue
+fn refill_iceberg_order(order: &mut Order, slot: u64) -> DriftResult {
+    let display_base_asset_amount = order
+        .slice_base_asset_amount
+        .min(order.hidden_base_asset_amount);
+
+    if display_base_asset_amount == 0 {
+        return Ok(());
+    }
+
+    order.base_asset_amount = order
+        .base_asset_amount
+        .safe_add(display_base_asset_amount)?;
+    order.hidden_base_asset_amount = order
+        .hidden_base_asset_amount
+        .safe_sub(display_base_asset_amount)?;
+    order.slot = slot;
+
+    msg!(
+        "Refilled iceberg order {} with {}, {} still hidden",
+        order.order_id,
+        display_base_asset_amount,
+        order.hidden_base_asset_amount
+    );
+
+    Ok(())
+}
+
+#[allow(clippy::type_complexity)]
+fn get_taker_and_maker_for_order_record(
+    user_key: &Pubkey,
+    user_order: &Order,
+) -> (Option<Pubkey>, Option<Order>, Option<Pubkey>, Option<Order>) {
+    if user_order.post_only {
+        (None, None, Some(*user_key), Some(*user_order))
+    } else {
+        (Some(*user_key), Some(*user_order), None, None)
+    }
+}
+
+/// Price an untriggered order's trigger condition is checked against when it doesn't use its own
+/// market's oracle. Returns None if the order uses its own market's oracle
+pub fn get_trigger_reference_price(
+    order: &Order,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+) -> DriftResult<Option<u64>> {
+    let (trigger_price_source, trigger_market_index) = order.get_trigger_price_source();
+
+    let trigger_reference_price = match trigger_price_source {
+        TriggerPriceSource::Oracle => return Ok(None),
+        TriggerPriceSource::PerpOracle => {
+            let perp_market = perp_market_map.get_ref(&trigger_market_index)?;
+            let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
+                &perp_market.amm.oracle,
+                perp_market
+                    .amm
+                    .historical_oracle_data
+                    .last_oracle_price_twap,
+            )?;
+
+            validate!(
+                is_oracle_valid_for_action(oracle_validity, Some(DriftAction::TriggerOrder))?,
+                ErrorCode::InvalidOracle,
+                "OracleValidity for perp marketIndex={} invalid for TriggerOrder",
+                trigger_market_index
+            )?;
+
+            oracle_price_data.price.unsigned_abs()
+        }
+        TriggerPriceSource::SpotOracle => {
+            let spot_market = spot_market_map.get_ref(&trigger_market_index)?;
+            let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
+                &spot_market.oracle,
+                spot_market.historical_oracle_data.last_oracle_price_twap,
+            )?;
+
+            validate!(
+                is_oracle_valid_for_action(oracle_validity, Some(DriftAction::TriggerOrder))?,
+                ErrorCode::InvalidOracle,
+                "OracleValidity for spot marketIndex={} invalid for TriggerOrder",
+                trigger_market_index
+            )?;
+
+            oracle_price_data.price.unsigned_abs()
+        }
+        TriggerPriceSource::PerpMarkPrice => {
+            perp_market_map
+                .get_ref(&trigger_market_index)?
+                .amm
+                .last_mark_price_twap_5min
+        }
+        TriggerPriceSource::PerpMarkPriceTwap => {
+            perp_market_map
+                .get_ref(&trigger_market_index)?
+                .amm
+                .last_mark_price_twap
+        }
+    };
+
+    Ok(Some(trigger_reference_price))
+}
+
+pub fn trigger_order(
+    order_id: u32,
+    state: &State,
+    user: &AccountLoader<User>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.order_id == order_id)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    let (order_status, market_index, market_type) =
+        get_struct_values!(user.orders[order_index], status, market_index, market_type);
+
+    validate!(
+        order_status == OrderStatus::Open,
+        ErrorCode::OrderNotOpen,
+        "Order not open"
+    )?;
+
+    validate!(
+        user.orders[order_index].must_be_triggered(),
+        ErrorCode::OrderNotTriggerable,
+        "Order is not triggerable"
+    )?;
+
+    validate!(
+        !user.orders[order_index].triggered(),
+        ErrorCode::OrderNotTriggerable,
+        "Order is already triggered"
+    )?;
+
+    validate!(
+        !user.orders[order_index].is_pending_linked_order(),
+        ErrorCode::LinkedOrderNotActive,
+        "Order must wait for its parent order to fill"
+    )?;
+
+    validate!(
+        market_type == MarketType::Perp,
+        ErrorCode::InvalidOrderMarketType,
+        "Order must be a perp order"
+    )?;
+
+    validate_user_not_being_liquidated(
+        user,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        state.liquidation_margin_buffer_ratio,
+    )?;
+
+    validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
+
+    let trigger_reference_price = get_trigger_reference_price(
+        &user.orders[order_index],
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+    )?;
+
+    let mut perp_market = perp_market_map.get_ref_mut(&market_index)?;
+    let is_batch_auction_market = perp_market.is_batch_auction_market();
+    let oracle_price_data = &oracle_map.get_price_data(&perp_market.amm.oracle)?;
+
+    let oracle_validity = oracle::oracle_validity(
+        perp_market
+            .amm
+            .historical_oracle_data
+            .last_oracle_price_twap,
+        oracle_price_data,
+        &state.oracle_guard_rails.validity,
+    )?;
+    let is_oracle_valid =
+        is_oracle_valid_for_action(oracle_validity, Some(DriftAction::TriggerOrder))?;
+
+    validate!(is_oracle_valid, ErrorCode::InvalidOracle)?;
+
+    let oracle_price = oracle_price_data.price;
+    let trigger_reference_price = trigger_reference_price.unwrap_or(oracle_price.unsigned_abs());
+
+    let trailing_stop_updated = if user.orders[order_index].is_trailing_stop() {
+        let trigger_price_before = user.orders[order_index].trigger_price;
+        let trigger_price = calculate_trailing_stop_trigger_price(
+            &user.orders[order_index],
+            trigger_reference_price,
+            perp_market.amm.order_tick_size,
+        )?;
+        user.orders[order_index].trigger_price = trigger_price;
+        trigger_price != trigger_price_before
+    } else {
+        false
+    };
+
+    let can_trigger =
+        order_satisfies_trigger_condition(&user.orders[order_index], trigger_reference_price)?;
+
+    if !can_trigger && trailing_stop_updated {
+        msg!(
+            "Trailing stop order {} trigger price updated to {}",
+            order_id,
+            user.orders[order_index].trigger_price
+        );
+        return Ok(());
+    }
+
+    validate!(can_trigger, ErrorCode::OrderDidNotSatisfyTriggerCondition)?;
+
+    if user.orders[order_index].close_position {
+        // settle lp position so the order sizes to the whole position
+        controller::lp::settle_funding_payment_then_lp(user, &user_key, &mut perp_market, now)?;
+    }
+
+    update_close_position_order_size(user, order_index, perp_market.amm.order_step_size)?;
+
+    let worst_case_base_asset_amount_before = user
+        .get_perp_position(market_index)?
+        .worst_case_base_asset_amount()?;
+
+    {
+        let direction = user.orders[order_index].direction;
+        let base_asset_amount = user.orders[order_index].base_asset_amount;
+
+        user.orders[order_index].trigger_condition =
+            match user.orders[order_index].trigger_condition {
+                OrderTriggerCondition::Above => OrderTriggerCondition::TriggeredAbove,
+                OrderTriggerCondition::Below => OrderTriggerCondition::TriggeredBelow,
+                _ => {
+                    return Err(print_error!(ErrorCode::InvalidTriggerOrderCondition)());
+                }
+            };
+
+        user.orders[order_index].slot = slot;
+        if user.orders[order_index].is_trailing_stop() {
+            // once triggered, the order behaves like a regular trigger order
+            user.orders[order_index].trailing_stop_type = TrailingStopType::None;
+            user.orders[order_index].trailing_stop_offset = 0;
+        }
+        let order_type = user.orders[order_index].order_type;
+        if let OrderType::TriggerMarket = order_type {
+            user.orders[order_index].auction_duration = state.min_perp_auction_duration;
+            let (auction_start_price, auction_end_price) =
+                calculate_auction_prices(oracle_price_data, direction, 0)?;
+            user.orders[order_index].auction_start_price = auction_start_price;
+            user.orders[order_index].auction_end_price = auction_end_price;
+        }
+
+        let user_position = user.get_perp_position_mut(market_index)?;
+        increase_open_bids_and_asks(user_position, &direction, base_asset_amount)?;
+    }
+
+    let is_filler_taker = user_key == filler_key;
+    let mut filler = if !is_filler_taker {
+        Some(load_mut!(filler)?)
+    } else {
+        None
+    };
+
+    let filler_reward = pay_keeper_flat_reward_for_perps(
+        user,
+        filler.as_deref_mut(),
+        &mut perp_market,
+        state.perp_fee_structure.flat_filler_fee,
+        slot,
+    )?;
+
+    let order_action_record = get_order_action_record(
+        now,
+        OrderAction::Trigger,
+        OrderActionExplanation::None,
+        market_index,
+        Some(filler_key),
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        None,
+        None,
         Some(user_key),
         Some(user.orders[order_index]),
         None,
@@ -2587,8 +4659,33 @@ pub fn trigger_order(
         }
     }
 
+    let order = user.orders[order_index];
+    if order.status == OrderStatus::Open && order.link_type == OrderLinkType::OneCancelsOther {
+        update_linked_orders(
+            user,
+            &user_key,
+            &order,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(&filler_key),
+        )?;
+    }
+
     user.update_last_active_slot(slot);
 
+    perp_order_book_index_map.update_user_orders(&user_key, user)?;
+
+    if is_batch_auction_market {
+        validate_batch_auction_order_indexed(
+            perp_order_book_index_map,
+            &user_key,
+            &user.orders[order_index],
+        )?;
+    }
+
     Ok(())
 }
 
@@ -2598,6 +4695,7 @@ pub fn force_cancel_orders(
     spot_market_map: &SpotMarketMap,
     perp_market_map: &PerpMarketMap,
     oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
     filler: &AccountLoader<User>,
     clock: &Clock,
 ) -> DriftResult {
@@ -2650,26 +4748,100 @@ pub fn force_cancel_orders(
                     continue;
                 }
 
-                state.spot_fee_structure.flat_filler_fee
-            }
-            MarketType::Perp => {
-                let base_asset_amount = user.get_perp_position(market_index)?.base_asset_amount;
-                let is_position_reducing = is_order_position_reducing(
-                    &user.orders[order_index].direction,
-                    user.orders[order_index]
-                        .get_base_asset_amount_unfilled(Some(base_asset_amount))?,
-                    base_asset_amount,
-                )?;
-                if is_position_reducing {
-                    continue;
-                }
+                state.spot_fee_structure.flat_filler_fee
+            }
+            MarketType::Perp => {
+                let base_asset_amount = user.get_perp_position(market_index)?.base_asset_amount;
+                let is_position_reducing = is_order_position_reducing(
+                    &user.orders[order_index].direction,
+                    user.orders[order_index]
+                        .get_base_asset_amount_unfilled(Some(base_asset_amount))?,
+                    base_asset_amount,
+                )?;
+                if is_position_reducing {
+                    continue;
+                }
+
+                state.perp_fee_structure.flat_filler_fee
+            }
+        };
+
+        total_fee = total_fee.safe_add(fee)?;
+
+        cancel_order(
+            order_index,
+            user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::InsufficientFreeCollateral,
+            Some(&filler_key),
+            fee,
+            false,
+        )?;
+    }
+
+    pay_keeper_flat_reward_for_spot(
+        user,
+        Some(filler),
+        spot_market_map.get_quote_spot_market_mut()?.deref_mut(),
+        total_fee,
+    )?;
+
+    user.update_last_active_slot(slot);
+
+    perp_order_book_index_map.update_user_orders(&user_key, user)?;
+
+    Ok(())
+}
+
+/// Dead man's switch. Any keeper can cancel all of a user's open orders once the user's heartbeat has
+/// expired, earning the flat filler fee for each order canceled
+pub fn cancel_orders_on_expired_heartbeat(
+    state: &State,
+    user: &AccountLoader<User>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+    let filler = &mut load_mut!(filler)?;
+
+    validate!(
+        user.is_heartbeat_expired(now)?,
+        ErrorCode::HeartbeatNotExpired,
+        "last heartbeat ts = {} heartbeat timeout = {} now = {}",
+        user.last_heartbeat_ts,
+        user.heartbeat_timeout,
+        now
+    )?;
+
+    let mut total_spot_fee = 0_u64;
+
+    for order_index in 0..user.orders.len() {
+        if user.orders[order_index].status != OrderStatus::Open {
+            continue;
+        }
+
+        let market_index = user.orders[order_index].market_index;
+        let market_type = user.orders[order_index].market_type;
 
-                state.perp_fee_structure.flat_filler_fee
-            }
+        let fee = match market_type {
+            MarketType::Spot => state.spot_fee_structure.flat_filler_fee,
+            MarketType::Perp => state.perp_fee_structure.flat_filler_fee,
         };
 
-        total_fee = total_fee.safe_add(fee)?;
-
         cancel_order(
             order_index,
             user,
@@ -2679,22 +4851,41 @@ pub fn force_cancel_orders(
             oracle_map,
             now,
             slot,
-            OrderActionExplanation::InsufficientFreeCollateral,
+            OrderActionExplanation::HeartbeatExpired,
             Some(&filler_key),
             fee,
             false,
         )?;
+
+        match market_type {
+            MarketType::Spot => {
+                total_spot_fee = total_spot_fee.safe_add(fee)?;
+            }
+            MarketType::Perp => {
+                pay_keeper_flat_reward_for_perps(
+                    user,
+                    Some(filler),
+                    perp_market_map.get_ref_mut(&market_index)?.deref_mut(),
+                    fee,
+                    slot,
+                )?;
+            }
+        }
     }
 
-    pay_keeper_flat_reward_for_spot(
-        user,
-        Some(filler),
-        spot_market_map.get_quote_spot_market_mut()?.deref_mut(),
-        total_fee,
-    )?;
+    if total_spot_fee > 0 {
+        pay_keeper_flat_reward_for_spot(
+            user,
+            Some(filler),
+            spot_market_map.get_quote_spot_market_mut()?.deref_mut(),
+            total_spot_fee,
+        )?;
+    }
 
     user.update_last_active_slot(slot);
 
+    perp_order_book_index_map.update_user_orders(&user_key, user)?;
+
     Ok(())
 }
 
@@ -2851,6 +5042,18 @@ pub fn place_spot_order(
         }
     }
 
+    if params.client_order_id != 0
+        && user
+            .get_order_index_by_client_order_id(params.client_order_id)
+            .is_ok()
+    {
+        msg!(
+            "client_order_id is already in use {}",
+            params.client_order_id
+        );
+        return Err(ErrorCode::ClientOrderIdAlreadyInUse);
+    }
+
     let market_index = params.market_index;
     let spot_market = &spot_market_map.get_ref(&market_index)?;
     let force_reduce_only = spot_market.is_reduce_only();
@@ -2935,7 +5138,13 @@ pub fn place_spot_order(
         "must be spot order"
     )?;
 
-    let new_order = Order {
+    validate!(
+        !params.close_position,
+        ErrorCode::InvalidClosePositionOrder,
+        "close position orders are perp only"
+    )?;
+
+    let mut new_order = Order {
         status: OrderStatus::Open,
         order_type: params.order_type,
         market_type: params.market_type,
@@ -2963,15 +5172,104 @@ pub fn place_spot_order(
         auction_end_price,
         auction_duration,
         max_ts,
-        padding: [0; 3],
+        trailing_stop_type: params.trailing_stop_type,
+        link_id: params.link_id,
+        link_type: params.link_type,
+        client_order_id: params.client_order_id,
+        twap_next_slice_ts: 0,
+        hidden_base_asset_amount: 0,
+        slice_base_asset_amount: 0,
+        trailing_stop_offset: params.trailing_stop_offset,
+        twap_interval: 0,
+        max_slippage_bps: params.max_slippage_bps.unwrap_or(0),
+        twap_slices_remaining: 0,
+        trigger_market_index: params.trigger_market_index,
+        close_position: false,
+        trigger_price_source: params.trigger_price_source,
+        padding: [0; 16],
     };
 
+    if params.trigger_price_source != TriggerPriceSource::Oracle {
+        validate!(
+            new_order.must_be_triggered(),
+            ErrorCode::InvalidOrderTrigger,
+            "Only trigger orders can have a trigger price source"
+        )?;
+
+        // fails if the trigger market doesn't exist or its price can't be used
+        get_trigger_reference_price(&new_order, perp_market_map, spot_market_map, oracle_map)?;
+    }
+
+    if new_order.is_trailing_stop() {
+        let trigger_reference_price = match get_trigger_reference_price(
+            &new_order,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+        )? {
+            Some(trigger_reference_price) => trigger_reference_price,
+            None => oracle_price_data.price.unsigned_abs(),
+        };
+        new_order.trigger_price = calculate_trailing_stop_trigger_price(
+            &new_order,
+            trigger_reference_price,
+            spot_market.order_tick_size,
+        )?;
+    }
+
+    if new_order.is_twap() {
+        let (slice_base_asset_amount, next_slice_ts) = calculate_twap_order_schedule(
+            order_base_asset_amount,
+            params.twap_slice_count,
+            params.twap_interval,
+            step_size,
+            now,
+        )?;
+        let first_slice_base_asset_amount = calculate_twap_slice_base_asset_amount(
+            order_base_asset_amount,
+            slice_base_asset_amount,
+            params.twap_slice_count,
+        );
+        new_order.base_asset_amount = first_slice_base_asset_amount;
+        new_order.hidden_base_asset_amount =
+            order_base_asset_amount.safe_sub(first_slice_base_asset_amount)?;
+        new_order.slice_base_asset_amount = slice_base_asset_amount;
+        new_order.twap_next_slice_ts = next_slice_ts;
+        new_order.twap_interval = params.twap_interval;
+        new_order.twap_slices_remaining = params.twap_slice_count.safe_sub(1)?;
+    }
+
+    validate!(
+        !(new_order.order_type == OrderType::Limit && new_order.trigger_price != 0),
+        ErrorCode::InvalidOrderTrigger,
+        "Limit order should not have trigger price"
+    )?;
+
+    if params.display_base_asset_amount != 0 {
+        validate!(
+            new_order.order_type == OrderType::Limit,
+            ErrorCode::InvalidIcebergOrder,
+            "Only limit orders can be iceberg orders"
+        )?;
+
+        let (display_base_asset_amount, hidden_base_asset_amount) = calculate_iceberg_order_sizes(
+            order_base_asset_amount,
+            params.display_base_asset_amount,
+            step_size,
+        )?;
+        new_order.base_asset_amount = display_base_asset_amount;
+        new_order.hidden_base_asset_amount = hidden_base_asset_amount;
+        new_order.slice_base_asset_amount = display_base_asset_amount;
+    }
+
     validate_spot_order(
         &new_order,
         spot_market.order_step_size,
         spot_market.min_order_size,
     )?;
 
+    validate_linked_order(&new_order, &user.orders)?;
+
     let risk_increasing = is_new_order_risk_increasing(
         &new_order,
         signed_token_amount.cast()?,
@@ -3045,6 +5343,7 @@ pub fn place_spot_order(
     emit_stack::<_, { OrderRecord::SIZE }>(order_record)?;
 
     user.update_last_active_slot(slot);
+    user.update_last_heartbeat_ts(now);
 
     Ok(())
 }
@@ -3114,6 +5413,12 @@ pub fn fill_spot_order(
         "Order must be triggered first"
     )?;
 
+    validate!(
+        !user.orders[order_index].is_pending_linked_order(),
+        ErrorCode::LinkedOrderNotActive,
+        "Order must wait for its parent order to fill"
+    )?;
+
     if user.is_bankrupt() {
         msg!("User is bankrupt");
         return Ok(0);
@@ -3133,6 +5438,17 @@ pub fn fill_spot_order(
         }
     }
 
+    if should_release_twap_slice(&user.orders[order_index], now)? {
+        let spot_market = spot_market_map.get_ref(&order_market_index)?;
+        release_twap_slice(
+            &mut user.orders[order_index],
+            oracle_map.get_price_data(&spot_market.oracle)?,
+            spot_market.order_tick_size,
+            state.default_spot_auction_duration,
+            slot,
+        )?;
+    }
+
     let is_filler_taker = user_key == filler_key;
     let is_filler_maker = maker.map_or(false, |maker| maker.key() == filler_key);
     let (mut filler, mut filler_stats) = if !is_filler_maker && !is_filler_taker {
@@ -3146,22 +5462,43 @@ pub fn fill_spot_order(
         (None, None)
     };
 
-    let (mut maker, mut maker_stats, maker_key, maker_order_index) = get_spot_maker_order(
-        perp_market_map,
-        spot_market_map,
-        oracle_map,
-        maker,
-        maker_stats,
-        maker_order_id,
-        &user_key,
-        &user.authority,
-        &user.orders[order_index],
-        &mut filler.as_deref_mut(),
-        &filler_key,
-        state.spot_fee_structure.flat_filler_fee,
-        now,
-        slot,
-    )?;
+    let (mut maker, mut maker_stats, maker_key, maker_order_index, taker_self_trade_decrement) =
+        get_spot_maker_order(
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            maker,
+            maker_stats,
+            maker_order_id,
+            &user_key,
+            &user.authority,
+            &user.orders[order_index],
+            user.self_trade_prevention_mode,
+            &mut filler.as_deref_mut(),
+            &filler_key,
+            state.spot_fee_structure.flat_filler_fee,
+            now,
+            slot,
+        )?;
+
+    if taker_self_trade_decrement > 0 {
+        let taker_order_canceled = apply_self_trade_prevention_decrement(
+            order_index,
+            user,
+            &user_key,
+            taker_self_trade_decrement,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            &filler_key,
+        )?;
+
+        if taker_order_canceled {
+            return Ok(0);
+        }
+    }
 
     {
         let mut quote_market = spot_market_map.get_quote_spot_market_mut()?;
@@ -3206,7 +5543,18 @@ pub fn fill_spot_order(
         false
     };
 
-    if should_expire_order || should_cancel_reduce_only {
+    let should_cancel_max_slippage = {
+        let spot_market = spot_market_map.get_ref(&user.orders[order_index].market_index)?;
+        let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?.price;
+        should_cancel_max_slippage_order(
+            &user.orders[order_index],
+            Some(oracle_price),
+            slot,
+            spot_market.order_tick_size,
+        )?
+    };
+
+    if should_expire_order || should_cancel_reduce_only || should_cancel_max_slippage {
         let filler_reward = {
             let mut quote_market = spot_market_map.get_quote_spot_market_mut()?;
             pay_keeper_flat_reward_for_spot(
@@ -3219,8 +5567,10 @@ pub fn fill_spot_order(
 
         let explanation = if should_expire_order {
             OrderActionExplanation::OrderExpired
-        } else {
+        } else if should_cancel_reduce_only {
             OrderActionExplanation::ReduceOnlyOrderIncreasedPosition
+        } else {
+            OrderActionExplanation::MaxSlippageExceeded
         };
 
         cancel_order(
@@ -3240,6 +5590,14 @@ pub fn fill_spot_order(
         return Ok(0);
     }
 
+    if user.orders[order_index].is_waiting_for_twap_slice() {
+        msg!(
+            "Twap order waiting for next slice at {}",
+            user.orders[order_index].twap_next_slice_ts
+        );
+        return Ok(0);
+    }
+
     let (base_asset_amount, quote_asset_amount) = fulfill_spot_order(
         user,
         order_index,
@@ -3298,6 +5656,17 @@ pub fn fill_spot_order(
         false
     };
 
+    let should_cancel_max_slippage = {
+        let spot_market = spot_market_map.get_ref(&order_market_index)?;
+        let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?.price;
+        should_cancel_max_slippage_order(
+            &user.orders[order_index],
+            Some(oracle_price),
+            slot,
+            spot_market.order_tick_size,
+        )?
+    };
+
     let should_cancel_for_no_borrow_liquidity = if is_open {
         let market_index = user.orders[order_index].market_index;
         let base_market = spot_market_map.get_ref(&market_index)?;
@@ -3309,7 +5678,10 @@ pub fn fill_spot_order(
         false
     };
 
-    if should_cancel_reduce_only || should_cancel_for_no_borrow_liquidity {
+    if should_cancel_reduce_only
+        || should_cancel_max_slippage
+        || should_cancel_for_no_borrow_liquidity
+    {
         let filler_reward = {
             let mut quote_market = spot_market_map.get_quote_spot_market_mut()?;
             pay_keeper_flat_reward_for_spot(
@@ -3322,6 +5694,8 @@ pub fn fill_spot_order(
 
         let explanation = if should_cancel_reduce_only {
             OrderActionExplanation::ReduceOnlyOrderIncreasedPosition
+        } else if should_cancel_max_slippage {
+            OrderActionExplanation::MaxSlippageExceeded
         } else {
             OrderActionExplanation::NoBorrowLiquidity
         };
@@ -3362,6 +5736,7 @@ fn get_spot_maker_order<'a>(
     taker_key: &Pubkey,
     taker_authority: &Pubkey,
     taker_order: &Order,
+    taker_self_trade_prevention_mode: SelfTradePreventionMode,
     filler: &mut Option<&mut User>,
     filler_key: &Pubkey,
     filler_reward: u64,
@@ -3372,14 +5747,15 @@ fn get_spot_maker_order<'a>(
     Option<RefMut<'a, UserStats>>,
     Option<Pubkey>,
     Option<usize>,
+    u64,
 )> {
     if maker.is_none() || maker_stats.is_none() {
-        return Ok((None, None, None, None));
+        return Ok((None, None, None, None, 0));
     }
 
     let maker = maker.safe_unwrap()?;
     if &maker.key() == taker_key {
-        return Ok((None, None, None, None));
+        return Ok((None, None, None, None, 0));
     }
 
     let maker_key = maker.key();
@@ -3406,18 +5782,18 @@ fn get_spot_maker_order<'a>(
         Ok(order_index) => order_index,
         Err(_) => {
             msg!("Maker has no order id {}", maker_order_id);
-            return Ok((None, None, None, None));
+            return Ok((None, None, None, None, 0));
         }
     };
 
     {
         let maker_order = &maker.orders[maker_order_index];
         if !is_maker_for_taker(maker_order, taker_order, slot)? {
-            return Ok((None, None, None, None));
+            return Ok((None, None, None, None, 0));
         }
 
         if maker.is_being_liquidated() || maker.is_bankrupt() {
-            return Ok((None, None, None, None));
+            return Ok((None, None, None, None, 0));
         }
 
         validate!(
@@ -3426,6 +5802,12 @@ fn get_spot_maker_order<'a>(
             "Maker order not triggered"
         )?;
 
+        validate!(
+            !maker_order.is_pending_linked_order(),
+            ErrorCode::LinkedOrderNotActive,
+            "Maker order waiting for its parent order to fill"
+        )?;
+
         validate!(
             maker_order.market_type == MarketType::Spot,
             ErrorCode::InvalidOrderMarketType,
@@ -3433,6 +5815,58 @@ fn get_spot_maker_order<'a>(
         )?
     }
 
+    if taker_self_trade_prevention_mode != SelfTradePreventionMode::None
+        && &maker.authority == taker_authority
+    {
+        let (maker_price, taker_price) = {
+            let spot_market =
+                spot_market_map.get_ref(&maker.orders[maker_order_index].market_index)?;
+            let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?.price;
+            (
+                maker.orders[maker_order_index].force_get_limit_price(
+                    Some(oracle_price),
+                    None,
+                    slot,
+                    spot_market.order_tick_size,
+                )?,
+                taker_order.get_limit_price(
+                    Some(oracle_price),
+                    None,
+                    slot,
+                    spot_market.order_tick_size,
+                )?,
+            )
+        };
+
+        let maker_direction = maker.orders[maker_order_index].direction;
+        if taker_price.map_or(true, |taker_price| {
+            do_orders_cross(maker_direction, maker_price, taker_price)
+        }) {
+            let (taker_decrement, maker_decrement) = calculate_self_trade_prevention_decrements(
+                taker_self_trade_prevention_mode,
+                taker_order.get_base_asset_amount_unfilled(None)?,
+                maker.orders[maker_order_index].get_base_asset_amount_unfilled(None)?,
+            );
+
+            if maker_decrement > 0 {
+                apply_self_trade_prevention_decrement(
+                    maker_order_index,
+                    maker.deref_mut(),
+                    &maker_key,
+                    maker_decrement,
+                    perp_market_map,
+                    spot_market_map,
+                    oracle_map,
+                    now,
+                    slot,
+                    filler_key,
+                )?;
+            }
+
+            return Ok((None, None, None, None, taker_decrement));
+        }
+    }
+
     let spot_market = spot_market_map.get_ref(&maker.orders[maker_order_index].market_index)?;
     let breaches_oracle_price_limits = {
         let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?;
@@ -3495,7 +5929,7 @@ fn get_spot_maker_order<'a>(
             false,
         )?;
 
-        return Ok((None, None, None, None));
+        return Ok((None, None, None, None, 0));
     }
 
     Ok((
@@ -3503,6 +5937,7 @@ fn get_spot_maker_order<'a>(
         maker_stats,
         Some(maker_key),
         Some(maker_order_index),
+        0,
     ))
 }
 
@@ -3545,33 +5980,44 @@ fn fulfill_spot_order(
         .force_get_spot_position_mut(base_market_index)?
         .get_signed_token_amount(&base_market)?;
 
+    let user_order = user.orders[user_order_index];
+    let maker_order = match (maker.as_deref(), maker_order_index) {
+        (Some(maker), Some(maker_order_index)) => Some(maker.orders[maker_order_index]),
+        _ => None,
+    };
+
     let mut base_asset_amount = 0_u64;
     let mut quote_asset_amount = 0_u64;
+    let mut maker_base_asset_amount = 0_u64;
     for fulfillment_method in fulfillment_methods.iter() {
         if user.orders[user_order_index].status != OrderStatus::Open {
             break;
         }
 
         let (base_filled, quote_filled) = match fulfillment_method {
-            SpotFulfillmentMethod::Match => fulfill_spot_order_with_match(
-                &mut base_market,
-                &mut quote_market,
-                user,
-                user_stats,
-                user_order_index,
-                user_key,
-                maker.as_deref_mut().safe_unwrap()?,
-                maker_stats,
-                maker_order_index.safe_unwrap()?,
-                maker_key.safe_unwrap()?,
-                filler.as_deref_mut(),
-                filler_stats.as_deref_mut(),
-                filler_key,
-                now,
-                slot,
-                oracle_map,
-                fee_structure,
-            )?,
+            SpotFulfillmentMethod::Match => {
+                let (base_filled, quote_filled) = fulfill_spot_order_with_match(
+                    &mut base_market,
+                    &mut quote_market,
+                    user,
+                    user_stats,
+                    user_order_index,
+                    user_key,
+                    maker.as_deref_mut().safe_unwrap()?,
+                    maker_stats,
+                    maker_order_index.safe_unwrap()?,
+                    maker_key.safe_unwrap()?,
+                    filler.as_deref_mut(),
+                    filler_stats.as_deref_mut(),
+                    filler_key,
+                    now,
+                    slot,
+                    oracle_map,
+                    fee_structure,
+                )?;
+                maker_base_asset_amount = maker_base_asset_amount.safe_add(base_filled)?;
+                (base_filled, quote_filled)
+            }
             SpotFulfillmentMethod::ExternalMarket => fulfill_spot_order_with_external_market(
                 &mut base_market,
                 &mut quote_market,
@@ -3658,6 +6104,36 @@ fn fulfill_spot_order(
     drop(base_market);
     drop(quote_market);
 
+    if base_asset_amount != 0 {
+        update_linked_orders(
+            user,
+            user_key,
+            &user_order,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(filler_key),
+        )?;
+    }
+
+    if let (Some(maker), Some(maker_order)) = (maker.as_deref_mut(), maker_order) {
+        if maker_base_asset_amount != 0 {
+            update_linked_orders(
+                maker,
+                maker_key.safe_unwrap()?,
+                &maker_order,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                Some(filler_key),
+            )?;
+        }
+    }
+
     let taker_margin_calculation =
         calculate_margin_requirement_and_total_collateral_and_liability_info(
             user,
@@ -3837,6 +6313,8 @@ pub fn fulfill_spot_order_with_match(
         base_precision,
         taker_direction,
         taker_price,
+        taker.orders[taker_order_index]
+            .get_max_slippage_price(oracle_price, base_market.order_tick_size)?,
         true,
     )?;
     validate_fill_price(
@@ -3845,6 +6323,8 @@ pub fn fulfill_spot_order_with_match(
         base_precision,
         maker_direction,
         maker_price,
+        maker.orders[maker_order_index]
+            .get_max_slippage_price(oracle_price, base_market.order_tick_size)?,
         false,
     )?;
 
@@ -3904,6 +6384,7 @@ pub fn fulfill_spot_order_with_match(
         &mut taker.orders[taker_order_index],
         base_asset_amount,
         quote_asset_amount,
+        slot,
     )?;
 
     let taker_order_direction = taker.orders[taker_order_index].direction;
@@ -3947,6 +6428,7 @@ pub fn fulfill_spot_order_with_match(
         &mut maker.orders[maker_order_index],
         base_asset_amount,
         quote_asset_amount,
+        slot,
     )?;
 
     let maker_order_direction = maker.orders[maker_order_index].direction;
@@ -4188,6 +6670,8 @@ pub fn fulfill_spot_order_with_external_market(
         base_market.get_precision(),
         order_direction,
         taker_price,
+        taker.orders[taker_order_index]
+            .get_max_slippage_price(oracle_price, base_market.order_tick_size)?,
         true,
     )?;
 
@@ -4274,6 +6758,7 @@ pub fn fulfill_spot_order_with_external_market(
         &mut taker.orders[taker_order_index],
         base_asset_amount_filled,
         quote_asset_amount_filled,
+        slot,
     )?;
 
     let taker_order_direction = taker.orders[taker_order_index].direction;
@@ -4394,6 +6879,12 @@ pub fn trigger_spot_order(
         "Order is already triggered"
     )?;
 
+    validate!(
+        !user.orders[order_index].is_pending_linked_order(),
+        ErrorCode::LinkedOrderNotActive,
+        "Order must wait for its parent order to fill"
+    )?;
+
     validate!(
         market_type == MarketType::Spot,
         ErrorCode::InvalidOrderMarketType,
@@ -4410,6 +6901,13 @@ pub fn trigger_spot_order(
 
     validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
 
+    let trigger_reference_price = get_trigger_reference_price(
+        &user.orders[order_index],
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+    )?;
+
     let spot_market = spot_market_map.get_ref(&market_index)?;
     let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
         &spot_market.oracle,
@@ -4422,6 +6920,7 @@ pub fn trigger_spot_order(
                 .historical_oracle_data
                 .last_oracle_price_twap_5min,
         ),
+        confidence_offset: 0,
     };
 
     validate!(
@@ -4432,11 +6931,33 @@ pub fn trigger_spot_order(
     )?;
 
     let oracle_price = oracle_price_data.price;
+    let trigger_reference_price = trigger_reference_price.unwrap_or(oracle_price.unsigned_abs());
+
+    let trailing_stop_updated = if user.orders[order_index].is_trailing_stop() {
+        let trigger_price_before = user.orders[order_index].trigger_price;
+        let trigger_price = calculate_trailing_stop_trigger_price(
+            &user.orders[order_index],
+            trigger_reference_price,
+            spot_market.order_tick_size,
+        )?;
+        user.orders[order_index].trigger_price = trigger_price;
+        trigger_price != trigger_price_before
+    } else {
+        false
+    };
+
+    let can_trigger =
+        order_satisfies_trigger_condition(&user.orders[order_index], trigger_reference_price)?;
+
+    if !can_trigger && trailing_stop_updated {
+        msg!(
+            "Trailing stop order {} trigger price updated to {}",
+            order_id,
+            user.orders[order_index].trigger_price
+        );
+        return Ok(());
+    }
 
-    let can_trigger = order_satisfies_trigger_condition(
-        &user.orders[order_index],
-        oracle_price.unsigned_abs().cast()?,
-    )?;
     validate!(can_trigger, ErrorCode::OrderDidNotSatisfyTriggerCondition)?;
 
     let position_index = user.get_spot_position_index(market_index)?;
@@ -4464,6 +6985,11 @@ pub fn trigger_spot_order(
                 }
             };
         user.orders[order_index].slot = slot;
+        if user.orders[order_index].is_trailing_stop() {
+            // once triggered, the order behaves like a regular trigger order
+            user.orders[order_index].trailing_stop_type = TrailingStopType::None;
+            user.orders[order_index].trailing_stop_offset = 0;
+        }
         let order_type = user.orders[order_index].order_type;
         if let OrderType::TriggerMarket = order_type {
             user.orders[order_index].auction_duration = state.default_spot_auction_duration;
@@ -4554,6 +7080,21 @@ pub fn trigger_spot_order(
         }
     }
 
+    let order = user.orders[order_index];
+    if order.status == OrderStatus::Open && order.link_type == OrderLinkType::OneCancelsOther {
+        update_linked_orders(
+            user,
+            &user_key,
+            &order,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(&filler_key),
+        )?;
+    }
+
     user.update_last_active_slot(slot);
 
     Ok(())
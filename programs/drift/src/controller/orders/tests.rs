@@ -38,6 +38,7 @@ pub mod fulfill_order_with_maker_order {
         PRICE_PRECISION_U64, QUOTE_PRECISION_I64, QUOTE_PRECISION_U64,
     };
     use crate::math::oracle::OracleValidity;
+    use crate::state::fill_mode::FillMode;
     use crate::state::perp_market::{PerpMarket, AMM};
     use crate::state::user::{Order, OrderType, PerpPosition, User, UserStats};
 
@@ -126,10 +127,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -245,10 +248,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -364,10 +369,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -483,10 +490,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -602,10 +611,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -687,10 +698,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -773,10 +786,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -859,10 +874,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -945,10 +962,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1051,10 +1070,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1160,10 +1181,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1276,10 +1299,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1393,10 +1418,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1534,10 +1561,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1650,10 +1679,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1765,10 +1796,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             Some(oracle_price),
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut oracle_map,
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -1905,10 +1938,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             Some(oracle_price),
             taker_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut oracle_map,
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -2038,10 +2073,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut oracle_map,
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -2177,10 +2214,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut oracle_map,
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -2304,10 +2343,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -2430,10 +2471,12 @@ pub mod fulfill_order_with_maker_order {
             0,
             None,
             taker_limit_price,
+            None,
             now,
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            FillMode::Fill,
         )
         .unwrap();
 
@@ -4003,7 +4046,7 @@ pub mod fulfill_order {
 
         let mut oracle_map = get_oracle_map();
 
-        let mut taker_orders = [Order::default(); 32];
+        let mut taker_orders = [Order::default(); 48];
         taker_orders[0] = Order {
             market_index: 0,
             status: OrderStatus::Open,
@@ -4030,7 +4073,7 @@ pub mod fulfill_order {
         };
 
         // Taker has sol order and position at index 0, btc at index 1
-        let mut taker_positions = [PerpPosition::default(); 8];
+        let mut taker_positions = [PerpPosition::default(); 16];
         taker_positions[0] = PerpPosition {
             market_index: 0,
             open_orders: 1,
@@ -4060,7 +4103,7 @@ pub mod fulfill_order {
         let maker_key = Pubkey::default();
         let maker_authority =
             Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); 48];
         maker_orders[0] = Order {
             market_index: 1,
             post_only: true,
@@ -4080,7 +4123,7 @@ pub mod fulfill_order {
             ..Order::default()
         };
 
-        let mut maker_positions = [PerpPosition::default(); 8];
+        let mut maker_positions = [PerpPosition::default(); 16];
         maker_positions[0] = PerpPosition {
             market_index: 1,
             open_orders: 1,
@@ -4234,6 +4277,7 @@ pub mod fulfill_order {
 }
 
 pub mod fill_order {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use std::str::FromStr;
 
     use anchor_lang::prelude::{AccountLoader, Clock};
@@ -4264,6 +4308,7 @@ pub mod fill_order {
     use crate::error::ErrorCode;
     use crate::state::fill_mode::FillMode;
     use crate::state::user_map::{UserMap, UserStatsMap};
+    use crate::validation::order::validate_fill_or_kill_order_filled;
 
     #[test]
     fn maker_order_canceled_for_breaching_oracle_price_band() {
@@ -4453,6 +4498,7 @@ pub mod fill_order {
             &spot_market_map,
             &market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             &filler_account_loader,
             &filler_stats_account_loader,
             &makers_and_referrers,
@@ -4661,6 +4707,7 @@ pub mod fill_order {
             &spot_market_map,
             &market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             &filler_account_loader,
             &filler_stats_account_loader,
             &makers_and_referrers,
@@ -4674,6 +4721,208 @@ pub mod fill_order {
         assert_eq!(base_asset_amount, 1000000000);
     }
 
+    /// Taker market order still in its auction, so only the maker can fill it
+    fn fill_taker_order_against_maker(
+        taker_base_asset_amount: u64,
+        maker_base_asset_amount: u64,
+    ) -> u64 {
+        let clock = Clock {
+            slot: 56,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::AmmPaused,
+            ..PerpMarket::default()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let user_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut user = User {
+            authority: user_key,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                market_type: MarketType::Perp,
+                base_asset_amount: taker_base_asset_amount,
+                slot: clock.slot,
+                auction_start_price: 100 * PRICE_PRECISION_I64,
+                auction_end_price: 100 * PRICE_PRECISION_I64,
+                auction_duration: 10,
+                price: 100 * PRICE_PRECISION_U64,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: taker_base_asset_amount as i64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, &user_key, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        create_anchor_account_info!(UserStats::default(), UserStats, user_stats_account_info);
+        let user_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&user_stats_account_info).unwrap();
+
+        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        let maker_authority =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let mut maker = User {
+            authority: maker_authority,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                market_type: MarketType::Perp,
+                direction: PositionDirection::Short,
+                base_asset_amount: maker_base_asset_amount,
+                slot: 0,
+                price: 100 * PRICE_PRECISION_U64,
+                post_only: true,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_asks: -(maker_base_asset_amount as i64),
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+
+        let mut maker_stats = UserStats {
+            authority: maker_authority,
+            ..UserStats::default()
+        };
+        create_anchor_account_info!(maker_stats, UserStats, maker_stats_account_info);
+        let maker_and_referrer_stats = UserStatsMap::load_one(&maker_stats_account_info).unwrap();
+
+        let state = State {
+            min_perp_auction_duration: 1,
+            default_market_order_time_in_force: 10,
+            ..State::default()
+        };
+
+        // place_and_take fills with the taker as the filler
+        fill_perp_order(
+            1,
+            &state,
+            &user_account_loader,
+            &user_stats_account_loader,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &user_account_loader.clone(),
+            &user_stats_account_loader.clone(),
+            &makers_and_referrers,
+            &maker_and_referrer_stats,
+            None,
+            &clock,
+            FillMode::PlaceAndTake,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn fill_or_kill_full_fill() {
+        let base_asset_amount_filled =
+            fill_taker_order_against_maker(BASE_PRECISION_U64, BASE_PRECISION_U64);
+        assert_eq!(base_asset_amount_filled, BASE_PRECISION_U64);
+
+        assert!(
+            validate_fill_or_kill_order_filled(base_asset_amount_filled, BASE_PRECISION_U64)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn fill_or_kill_partial_fill() {
+        let base_asset_amount_filled =
+            fill_taker_order_against_maker(2 * BASE_PRECISION_U64, BASE_PRECISION_U64);
+        assert_eq!(base_asset_amount_filled, BASE_PRECISION_U64);
+
+        assert_eq!(
+            validate_fill_or_kill_order_filled(base_asset_amount_filled, 2 * BASE_PRECISION_U64),
+            Err(ErrorCode::FillOrKillOrderNotFilled)
+        );
+    }
+
     #[test]
     fn expire_order() {
         let mut market = PerpMarket {
@@ -4790,6 +5039,7 @@ pub mod fill_order {
             &spot_market_map,
             &market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             &filler_account_loader,
             &filler_stats_account_loader,
             &UserMap::empty(),
@@ -4958,6 +5208,7 @@ pub mod fill_order {
             &spot_market_map,
             &market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             &filler_account_loader,
             &filler_stats_account_loader,
             &UserMap::empty(),
@@ -4989,7 +5240,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_fulfilled_start_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5019,7 +5270,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5114,7 +5365,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_fulfilled_middle_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 161 * SPOT_BALANCE_PRECISION_U64,
@@ -5144,7 +5395,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5239,7 +5490,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn short_taker_order_fulfilled_start_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5265,7 +5516,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5364,7 +5615,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn short_taker_order_fulfilled_middle_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5390,7 +5641,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5489,7 +5740,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_auction_price_does_not_satisfy_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5519,7 +5770,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5588,7 +5839,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn short_taker_order_auction_price_does_not_satisfy_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5614,7 +5865,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5687,7 +5938,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_taker_same_direction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5713,7 +5964,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5786,7 +6037,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_taker_different_market_index() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5816,7 +6067,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -5885,7 +6136,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_bigger_than_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -5915,7 +6166,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6022,7 +6273,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn long_taker_order_smaller_than_maker() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -6052,7 +6303,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6159,7 +6410,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn double_dutch_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -6189,7 +6440,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6286,7 +6537,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_bid_crosses_maker_ask() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -6314,7 +6565,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6409,7 +6660,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_ask_crosses_maker_bid() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6433,7 +6684,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -6532,7 +6783,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn zero_price_market_order_cant_match() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -6561,7 +6812,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6630,7 +6881,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_short_selling_base_no_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6654,7 +6905,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -6727,7 +6978,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_short_selling_base_small_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6751,7 +7002,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -6827,7 +7078,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_short_selling_quote_no_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6851,7 +7102,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 0,
@@ -6924,7 +7175,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_short_selling_quote_little_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -6948,7 +7199,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 0,
@@ -7023,7 +7274,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_short_selling_quote_no_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 0,
@@ -7051,7 +7302,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -7122,7 +7373,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn taker_short_selling_quote_little_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 0,
@@ -7150,7 +7401,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -7221,7 +7472,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_short_selling_base_no_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -7249,7 +7500,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -7318,7 +7569,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn maker_short_selling_base_little_borrow_liquidity() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -7346,7 +7597,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -7417,7 +7668,7 @@ pub mod fulfill_spot_order_with_match {
 
     #[test]
     fn max_utilization() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -7445,7 +7696,7 @@ pub mod fulfill_spot_order_with_match {
             ..User::default()
         };
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -7627,7 +7878,7 @@ pub mod fulfill_spot_order {
     //     )
     //     .unwrap();
     //
-    //     let mut taker_spot_positions = [SpotPosition::default(); 8];
+    //     let mut taker_spot_positions = [SpotPosition::default(); 16];
     //     taker_spot_positions[0] = SpotPosition {
     //         market_index: 0,
     //         scaled_balance: SPOT_BALANCE_PRECISION_U64,
@@ -7668,7 +7919,7 @@ pub mod fulfill_spot_order {
     //     let taker_stats_account_loader: AccountLoader<UserStats> =
     //         AccountLoader::try_from(&taker_stats_account_info).unwrap();
     //
-    //     let mut maker_spot_positions = [SpotPosition::default(); 8];
+    //     let mut maker_spot_positions = [SpotPosition::default(); 16];
     //     maker_spot_positions[1] = SpotPosition {
     //         market_index: 1,
     //         balance_type: SpotBalanceType::Deposit,
@@ -7816,7 +8067,7 @@ pub mod fulfill_spot_order {
         )
         .unwrap();
 
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -7835,7 +8086,7 @@ pub mod fulfill_spot_order {
             open_bids: LAMPORTS_PER_SOL_I64,
             ..SpotPosition::default()
         };
-        let mut taker_orders = [Order::default(); 32];
+        let mut taker_orders = [Order::default(); 48];
         taker_orders[0] = Order {
             order_id: 1,
             market_index: 1,
@@ -7880,7 +8131,7 @@ pub mod fulfill_spot_order {
         let taker_stats_account_loader: AccountLoader<UserStats> =
             AccountLoader::try_from(&taker_stats_account_info).unwrap();
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 2,
             balance_type: SpotBalanceType::Deposit,
@@ -7897,7 +8148,7 @@ pub mod fulfill_spot_order {
             open_asks: -LAMPORTS_PER_SOL_I64,
             ..SpotPosition::default()
         };
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); 48];
         maker_orders[0] = Order {
             order_id: 2,
             market_index: 2,
@@ -8070,7 +8321,7 @@ pub mod fulfill_spot_order {
         )
         .unwrap();
 
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -8083,7 +8334,7 @@ pub mod fulfill_spot_order {
             open_bids: LAMPORTS_PER_SOL_I64,
             ..SpotPosition::default()
         };
-        let mut taker_orders = [Order::default(); 32];
+        let mut taker_orders = [Order::default(); 48];
         taker_orders[0] = Order {
             order_id: 1,
             market_index: 1,
@@ -8113,7 +8364,7 @@ pub mod fulfill_spot_order {
         let taker_stats_account_loader: AccountLoader<UserStats> =
             AccountLoader::try_from(&taker_stats_account_info).unwrap();
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[2] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -8122,7 +8373,7 @@ pub mod fulfill_spot_order {
             open_asks: -LAMPORTS_PER_SOL_I64,
             ..SpotPosition::default()
         };
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); 48];
         maker_orders[1] = Order {
             order_id: 1,
             market_index: 1,
@@ -8256,7 +8507,7 @@ pub mod fill_spot_order {
         )
         .unwrap();
 
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        let mut taker_spot_positions = [SpotPosition::default(); 16];
         taker_spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
@@ -8298,7 +8549,7 @@ pub mod fill_spot_order {
         let taker_stats_account_loader: AccountLoader<UserStats> =
             AccountLoader::try_from(&taker_stats_account_info).unwrap();
 
-        let mut maker_spot_positions = [SpotPosition::default(); 8];
+        let mut maker_spot_positions = [SpotPosition::default(); 16];
         maker_spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -8372,6 +8623,7 @@ pub mod fill_spot_order {
 }
 
 pub mod force_cancel_orders {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use std::str::FromStr;
 
     use anchor_lang::prelude::{AccountLoader, Clock};
@@ -8509,7 +8761,7 @@ pub mod force_cancel_orders {
         )
         .unwrap();
 
-        let mut orders = [Order::default(); 32];
+        let mut orders = [Order::default(); 48];
         orders[0] = Order {
             market_index: 0,
             order_id: 1,
@@ -8610,6 +8862,7 @@ pub mod force_cancel_orders {
             &spot_market_map,
             &market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             &filler_account_loader,
             &clock,
         )
@@ -8626,69 +8879,306 @@ pub mod force_cancel_orders {
     }
 }
 
-pub mod insert_maker_order_info {
-    use crate::controller::orders::insert_maker_order_info;
-    use crate::controller::position::PositionDirection;
-    use solana_program::pubkey::Pubkey;
-
-    #[test]
-    fn bids() {
-        let mut bids = Vec::with_capacity(3);
-        bids.push((Pubkey::default(), 1, 10));
-        bids.push((Pubkey::default(), 0, 1));
-        let maker_direction = PositionDirection::Long;
-
-        insert_maker_order_info(&mut bids, (Pubkey::default(), 2, 100), maker_direction);
-
-        assert_eq!(
-            bids,
-            vec![
-                (Pubkey::default(), 2, 100),
-                (Pubkey::default(), 1, 10),
-                (Pubkey::default(), 0, 1),
-            ]
-        );
-    }
-
-    #[test]
-    fn asks() {
-        let mut asks = Vec::with_capacity(3);
-        asks.push((Pubkey::default(), 0, 1));
-        asks.push((Pubkey::default(), 1, 10));
-        let maker_direction = PositionDirection::Short;
-
-        insert_maker_order_info(&mut asks, (Pubkey::default(), 2, 100), maker_direction);
-
-        assert_eq!(
-            asks,
-            vec![
-                (Pubkey::default(), 0, 1),
-                (Pubkey::default(), 1, 10),
-                (Pubkey::default(), 2, 100)
-            ]
-        );
-    }
-}
-
-pub mod get_maker_orders_info {
+pub mod cancel_orders_on_expired_heartbeat {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use std::str::FromStr;
 
     use anchor_lang::prelude::{AccountLoader, Clock};
 
-    use crate::controller::orders::get_maker_orders_info;
+    use crate::controller::orders::cancel_orders_on_expired_heartbeat;
     use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
     use crate::math::constants::{
-        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
-        PRICE_PRECISION_I64, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION_U64,
-        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, LAMPORTS_PER_SOL_I64,
+        LAMPORTS_PER_SOL_U64, PEG_PRECISION, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION,
+        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::HistoricalOracleData;
+    use crate::state::oracle::OracleSource;
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::state::State;
+    use crate::state::user::{MarketType, OrderStatus, OrderType, SpotPosition, User};
+    use crate::test_utils::*;
+    use crate::test_utils::{
+        create_account_info, get_positions, get_pyth_price, get_spot_positions,
+    };
+
+    use super::*;
+
+    #[test]
+    fn cancel_orders_after_timeout() {
+        let mut clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 160,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+
+        let mut sol_spot_market = SpotMarket {
+            market_index: 1,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            oracle: oracle_price_key,
+            ..SpotMarket::default_base_market()
+        };
+        create_anchor_account_info!(sol_spot_market, SpotMarket, sol_spot_market_account_info);
+
+        let spot_market_map = SpotMarketMap::load_multiple(
+            vec![
+                &usdc_spot_market_account_info,
+                &sol_spot_market_account_info,
+            ],
+            true,
+        )
+        .unwrap();
+
+        let mut orders = [Order::default(); 48];
+        orders[0] = Order {
+            market_index: 0,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 99 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        orders[1] = Order {
+            market_index: 1,
+            order_id: 2,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Spot,
+            direction: PositionDirection::Short,
+            base_asset_amount: LAMPORTS_PER_SOL_U64,
+            price: 101 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+
+        let mut user = User {
+            orders,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 1,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: SPOT_BALANCE_PRECISION_U64,
+                open_orders: 1,
+                open_asks: -LAMPORTS_PER_SOL_I64,
+                ..SpotPosition::default()
+            }),
+            heartbeat_timeout: 60,
+            last_heartbeat_ts: 100,
+            open_orders: 2,
+            has_open_order: true,
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        let filler_key = Pubkey::new_unique();
+        create_anchor_account_info!(User::default(), &filler_key, User, filler_account_info);
+        let filler_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&filler_account_info).unwrap();
+
+        let state = State::default();
+
+        // heartbeat hasn't expired yet
+        assert!(cancel_orders_on_expired_heartbeat(
+            &state,
+            &user_account_loader,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &filler_account_loader,
+            &clock,
+        )
+        .is_err());
+
+        clock.unix_timestamp = 161;
+
+        cancel_orders_on_expired_heartbeat(
+            &state,
+            &user_account_loader,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &filler_account_loader,
+            &clock,
+        )
+        .unwrap();
+
+        let user = user_account_loader.load().unwrap();
+        assert_eq!(user.orders[0], Order::default());
+        assert_eq!(user.orders[1], Order::default());
+        assert_eq!(user.open_orders, 0);
+        assert_eq!(user.perp_positions[0].quote_asset_amount, -10000);
+
+        let filler = filler_account_loader.load().unwrap();
+        assert_eq!(filler.perp_positions[0].quote_asset_amount, 10000);
+        assert_eq!(filler.spot_positions[0].scaled_balance, 10000000);
+        assert_eq!(
+            filler.spot_positions[0].balance_type,
+            SpotBalanceType::Deposit
+        );
+    }
+}
+
+pub mod insert_maker_order_info {
+    use crate::controller::orders::insert_maker_order_info;
+    use crate::controller::position::PositionDirection;
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn bids() {
+        let mut bids = Vec::with_capacity(3);
+        bids.push((Pubkey::default(), 1, 10));
+        bids.push((Pubkey::default(), 0, 1));
+        let maker_direction = PositionDirection::Long;
+
+        insert_maker_order_info(&mut bids, (Pubkey::default(), 2, 100), maker_direction);
+
+        assert_eq!(
+            bids,
+            vec![
+                (Pubkey::default(), 2, 100),
+                (Pubkey::default(), 1, 10),
+                (Pubkey::default(), 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn asks() {
+        let mut asks = Vec::with_capacity(3);
+        asks.push((Pubkey::default(), 0, 1));
+        asks.push((Pubkey::default(), 1, 10));
+        let maker_direction = PositionDirection::Short;
+
+        insert_maker_order_info(&mut asks, (Pubkey::default(), 2, 100), maker_direction);
+
+        assert_eq!(
+            asks,
+            vec![
+                (Pubkey::default(), 0, 1),
+                (Pubkey::default(), 1, 10),
+                (Pubkey::default(), 2, 100)
+            ]
+        );
+    }
+
+    #[test]
+    fn same_price_keeps_insertion_order() {
+        let first_maker = Pubkey::new_unique();
+        let second_maker = Pubkey::new_unique();
+        let mut asks = Vec::with_capacity(3);
+        asks.push((first_maker, 0, 10));
+        let maker_direction = PositionDirection::Short;
+
+        insert_maker_order_info(&mut asks, (second_maker, 0, 10), maker_direction);
+        insert_maker_order_info(&mut asks, (first_maker, 1, 1), maker_direction);
+
+        assert_eq!(
+            asks,
+            vec![
+                (first_maker, 1, 1),
+                (first_maker, 0, 10),
+                (second_maker, 0, 10)
+            ]
+        );
+    }
+}
+
+pub mod get_maker_orders_info {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountLoader, Clock};
+
+    use crate::controller::orders::get_maker_orders_info;
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, AMM_RESERVE_PRECISION_I128, BASE_PRECISION_I128, BASE_PRECISION_I64,
+        BASE_PRECISION_U64, PEG_PRECISION, PRICE_PRECISION_I64, PRICE_PRECISION_U64,
+        QUOTE_PRECISION_I128, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
     };
+    use crate::state::fill_mode::FillMode;
     use crate::state::oracle::HistoricalOracleData;
     use crate::state::oracle::OracleSource;
+    use crate::state::order_book_index::PerpOrderBookIndex;
     use crate::state::perp_market::{PerpMarket, AMM};
     use crate::state::perp_market_map::PerpMarketMap;
     use crate::state::spot_market::{SpotBalanceType, SpotMarket};
     use crate::state::spot_market_map::SpotMarketMap;
-    use crate::state::user::{OrderStatus, OrderType, SpotPosition, User};
+    use crate::state::user::{OrderStatus, OrderType, SelfTradePreventionMode, SpotPosition, User};
     use crate::state::user_map::UserMap;
     use crate::test_utils::*;
     use crate::test_utils::{
@@ -8820,7 +9310,7 @@ pub mod get_maker_orders_info {
             ..User::default()
         };
 
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); 48];
         maker_orders[0] = Order {
             market_index: 0,
             order_id: 1,
@@ -8870,18 +9360,22 @@ pub mod get_maker_orders_info {
         let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
         let mut filler = User::default();
 
-        let maker_order_price_and_indexes = get_maker_orders_info(
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
             &market_map,
             &spot_market_map,
             &mut oracle_map,
             &makers_and_referrers,
+            None,
             &taker_key,
             &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
             &mut Some(&mut filler),
             &filler_key,
             0,
             oracle_price,
             None,
+            FillMode::Fill,
             clock.unix_timestamp,
             clock.slot,
         )
@@ -9014,7 +9508,7 @@ pub mod get_maker_orders_info {
             ..User::default()
         };
 
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); 48];
         maker_orders[0] = Order {
             market_index: 0,
             order_id: 1,
@@ -9065,18 +9559,22 @@ pub mod get_maker_orders_info {
         let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
         let mut filler = User::default();
 
-        let maker_order_price_and_indexes = get_maker_orders_info(
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
             &market_map,
             &spot_market_map,
             &mut oracle_map,
             &makers_and_referrers,
+            None,
             &taker_key,
             &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
             &mut Some(&mut filler),
             &filler_key,
             0,
             oracle_price,
             None,
+            FillMode::Fill,
             clock.unix_timestamp,
             clock.slot,
         )
@@ -9210,7 +9708,7 @@ pub mod get_maker_orders_info {
             ..User::default()
         };
 
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); 48];
         maker_orders[0] = Order {
             market_index: 0,
             order_id: 1,
@@ -9249,18 +9747,22 @@ pub mod get_maker_orders_info {
         let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
         let mut filler = User::default();
 
-        let maker_order_price_and_indexes = get_maker_orders_info(
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
             &market_map,
             &spot_market_map,
             &mut oracle_map,
             &makers_and_referrers,
+            None,
             &taker_key,
             &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
             &mut Some(&mut filler),
             &filler_key,
             0,
             oracle_price,
             None,
+            FillMode::Fill,
             clock.unix_timestamp,
             clock.slot,
         )
@@ -9496,18 +9998,22 @@ pub mod get_maker_orders_info {
         let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
         let mut filler = User::default();
 
-        let maker_order_price_and_indexes = get_maker_orders_info(
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
             &market_map,
             &spot_market_map,
             &mut oracle_map,
             &makers_and_referrers,
+            None,
             &taker_key,
             &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
             &mut Some(&mut filler),
             &filler_key,
             0,
             oracle_price,
             None,
+            FillMode::Fill,
             clock.unix_timestamp,
             clock.slot,
         )
@@ -9525,7 +10031,7 @@ pub mod get_maker_orders_info {
     }
 
     #[test]
-    fn jit_maker_order_id() {
+    fn makers_at_same_price_follow_order_book_index() {
         let clock = Clock {
             slot: 6,
             epoch_start_timestamp: 0,
@@ -9653,6 +10159,7 @@ pub mod get_maker_orders_info {
                     order_id: 1,
                     status: OrderStatus::Open,
                     order_type: OrderType::Limit,
+                    post_only: true,
                     direction: PositionDirection::Short,
                     base_asset_amount: BASE_PRECISION_U64,
                     slot: 0,
@@ -9664,10 +10171,11 @@ pub mod get_maker_orders_info {
                     order_id: 2,
                     status: OrderStatus::Open,
                     order_type: OrderType::Limit,
+                    post_only: true,
                     direction: PositionDirection::Short,
                     base_asset_amount: BASE_PRECISION_U64,
                     slot: 0,
-                    price: 102 * PRICE_PRECISION_U64,
+                    price: 101 * PRICE_PRECISION_U64,
                     ..Order::default()
                 }
             ),
@@ -9693,23 +10201,91 @@ pub mod get_maker_orders_info {
             first_maker_account_info
         );
 
-        let makers_and_referrers = UserMap::load_one(&first_maker_account_info).unwrap();
+        let mut second_maker = User {
+            orders: get_orders!(
+                Order {
+                    market_index: 0,
+                    order_id: 1,
+                    status: OrderStatus::Open,
+                    order_type: OrderType::Limit,
+                    post_only: true,
+                    direction: PositionDirection::Short,
+                    base_asset_amount: BASE_PRECISION_U64,
+                    slot: 0,
+                    price: 100 * PRICE_PRECISION_U64,
+                    ..Order::default()
+                },
+                Order {
+                    market_index: 0,
+                    order_id: 2,
+                    status: OrderStatus::Open,
+                    order_type: OrderType::Limit,
+                    post_only: true,
+                    direction: PositionDirection::Short,
+                    base_asset_amount: BASE_PRECISION_U64,
+                    slot: 0,
+                    price: 101 * PRICE_PRECISION_U64,
+                    ..Order::default()
+                }
+            ),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 2,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        let second_maker_key =
+            Pubkey::from_str("My11111111111111111111111111111111111111112").unwrap();
+        create_anchor_account_info!(
+            second_maker,
+            &second_maker_key,
+            User,
+            second_maker_account_info
+        );
+
+        let mut makers_and_referrers = UserMap::load_one(&first_maker_account_info).unwrap();
+        makers_and_referrers
+            .insert(
+                second_maker_key,
+                AccountLoader::try_from(&second_maker_account_info).unwrap(),
+            )
+            .unwrap();
+
+        // the second maker's keys sort first in the user map, but the first maker rested first
+        let mut perp_order_book_index = PerpOrderBookIndex::default();
+        perp_order_book_index
+            .update_user_orders(&first_maker_key, &first_maker)
+            .unwrap();
+        perp_order_book_index
+            .update_user_orders(&second_maker_key, &second_maker)
+            .unwrap();
 
         let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
         let mut filler = User::default();
 
-        let maker_order_price_and_indexes = get_maker_orders_info(
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
             &market_map,
             &spot_market_map,
             &mut oracle_map,
             &makers_and_referrers,
+            Some(&perp_order_book_index),
             &taker_key,
             &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
             &mut Some(&mut filler),
             &filler_key,
             0,
             oracle_price,
-            Some(2),
+            None,
+            FillMode::Fill,
             clock.unix_timestamp,
             clock.slot,
         )
@@ -9717,12 +10293,17 @@ pub mod get_maker_orders_info {
 
         assert_eq!(
             maker_order_price_and_indexes,
-            vec![(first_maker_key, 1, 102000000),],
+            vec![
+                (first_maker_key, 0, 100000000),
+                (second_maker_key, 0, 100000000),
+                (first_maker_key, 1, 101000000),
+                (second_maker_key, 1, 101000000),
+            ],
         );
     }
 
     #[test]
-    fn two_makers_with_max_orders() {
+    fn jit_maker_order_id() {
         let clock = Clock {
             slot: 6,
             epoch_start_timestamp: 0,
@@ -9844,17 +10425,30 @@ pub mod get_maker_orders_info {
         };
 
         let mut first_maker = User {
-            orders: [Order {
-                market_index: 0,
-                order_id: 1,
-                status: OrderStatus::Open,
-                order_type: OrderType::Limit,
-                direction: PositionDirection::Short,
-                base_asset_amount: BASE_PRECISION_U64,
-                slot: 0,
-                price: 100 * PRICE_PRECISION_U64,
-                ..Order::default()
-            }; 32],
+            orders: get_orders!(
+                Order {
+                    market_index: 0,
+                    order_id: 1,
+                    status: OrderStatus::Open,
+                    order_type: OrderType::Limit,
+                    direction: PositionDirection::Short,
+                    base_asset_amount: BASE_PRECISION_U64,
+                    slot: 0,
+                    price: 100 * PRICE_PRECISION_U64,
+                    ..Order::default()
+                },
+                Order {
+                    market_index: 0,
+                    order_id: 2,
+                    status: OrderStatus::Open,
+                    order_type: OrderType::Limit,
+                    direction: PositionDirection::Short,
+                    base_asset_amount: BASE_PRECISION_U64,
+                    slot: 0,
+                    price: 102 * PRICE_PRECISION_U64,
+                    ..Order::default()
+                }
+            ),
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 open_orders: 2,
@@ -9877,21 +10471,146 @@ pub mod get_maker_orders_info {
             first_maker_account_info
         );
 
-        let mut second_maker = User {
-            orders: [Order {
+        let makers_and_referrers = UserMap::load_one(&first_maker_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut filler = User::default();
+
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &makers_and_referrers,
+            None,
+            &taker_key,
+            &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
+            &mut Some(&mut filler),
+            &filler_key,
+            0,
+            oracle_price,
+            Some(2),
+            FillMode::Fill,
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+
+        assert_eq!(
+            maker_order_price_and_indexes,
+            vec![(first_maker_key, 1, 102000000),],
+        );
+    }
+
+    #[test]
+    fn signed_quote_maker_order_placed_in_same_slot() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut pyth_price = get_pyth_price(100, 6);
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            pyth_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                base_spread: 0,
+                long_spread: 0,
+                short_spread: 0,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: pyth_price.twap as i64,
+                    last_oracle_price_twap_5min: pyth_price.twap as i64,
+                    last_oracle_price: pyth_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Initialized,
+            ..PerpMarket::default()
+        };
+        market.status = MarketStatus::Active;
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        let (new_ask_base_asset_reserve, new_ask_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Long,
+            )
+            .unwrap();
+        let (new_bid_base_asset_reserve, new_bid_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Short,
+            )
+            .unwrap();
+        market.amm.ask_base_asset_reserve = new_ask_base_asset_reserve;
+        market.amm.bid_base_asset_reserve = new_bid_base_asset_reserve;
+        market.amm.ask_quote_asset_reserve = new_ask_quote_asset_reserve;
+        market.amm.bid_quote_asset_reserve = new_bid_quote_asset_reserve;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let taker_key = Pubkey::default();
+        let taker_authority =
+            Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let user = User {
+            authority: taker_authority,
+            orders: get_orders(Order {
                 market_index: 0,
                 order_id: 1,
                 status: OrderStatus::Open,
                 order_type: OrderType::Limit,
-                direction: PositionDirection::Short,
+                direction: PositionDirection::Long,
                 base_asset_amount: BASE_PRECISION_U64,
-                slot: 0,
+                slot: 6,
                 price: 101 * PRICE_PRECISION_U64,
+                immediate_or_cancel: true,
                 ..Order::default()
-            }; 32],
+            }),
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
-                open_orders: 2,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
             spot_positions: get_spot_positions(SpotPosition {
@@ -9902,43 +10621,2291 @@ pub mod get_maker_orders_info {
             }),
             ..User::default()
         };
-        let second_maker_key =
-            Pubkey::from_str("My11111111111111111111111111111111111111112").unwrap();
-        create_anchor_account_info!(
-            second_maker,
-            &second_maker_key,
-            User,
-            second_maker_account_info
-        );
 
-        let mut makers_and_referrers = UserMap::load_one(&first_maker_account_info).unwrap();
-        makers_and_referrers
-            .insert(
-                second_maker_key,
-                AccountLoader::try_from(&second_maker_account_info).unwrap(),
-            )
-            .unwrap();
-
-        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
-        let mut filler = User::default();
-
-        let maker_order_price_and_indexes = get_maker_orders_info(
-            &market_map,
-            &spot_market_map,
-            &mut oracle_map,
-            &makers_and_referrers,
-            &taker_key,
+        let mut maker = User {
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 6,
+                price: 100 * PRICE_PRECISION_U64,
+                immediate_or_cancel: true,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+
+        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut filler = User::default();
+
+        // both orders were placed in the current slot
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &makers_and_referrers,
+            None,
+            &taker_key,
             &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
             &mut Some(&mut filler),
             &filler_key,
             0,
             oracle_price,
+            Some(1),
+            FillMode::Fill,
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+
+        assert_eq!(maker_order_price_and_indexes, vec![]);
+
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &makers_and_referrers,
             None,
+            &taker_key,
+            &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
+            &mut Some(&mut filler),
+            &filler_key,
+            0,
+            oracle_price,
+            Some(1),
+            FillMode::SignedQuote,
             clock.unix_timestamp,
             clock.slot,
         )
         .unwrap();
 
-        assert_eq!(maker_order_price_and_indexes.len(), 64);
+        assert_eq!(
+            maker_order_price_and_indexes,
+            vec![(maker_key, 0, 100 * PRICE_PRECISION_U64)]
+        );
+    }
+
+    #[test]
+    fn two_makers_with_max_orders() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut pyth_price = get_pyth_price(100, 6);
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            pyth_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                base_spread: 0,
+                long_spread: 0,
+                short_spread: 0,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: pyth_price.twap as i64,
+                    last_oracle_price_twap_5min: pyth_price.twap as i64,
+                    last_oracle_price: pyth_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Initialized,
+            ..PerpMarket::default()
+        };
+        market.status = MarketStatus::Active;
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        let (new_ask_base_asset_reserve, new_ask_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Long,
+            )
+            .unwrap();
+        let (new_bid_base_asset_reserve, new_bid_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Short,
+            )
+            .unwrap();
+        market.amm.ask_base_asset_reserve = new_ask_base_asset_reserve;
+        market.amm.bid_base_asset_reserve = new_bid_base_asset_reserve;
+        market.amm.ask_quote_asset_reserve = new_ask_quote_asset_reserve;
+        market.amm.bid_quote_asset_reserve = new_bid_quote_asset_reserve;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let taker_key = Pubkey::default();
+        let taker_authority =
+            Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let user = User {
+            authority: taker_authority,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                auction_start_price: 0,
+                auction_end_price: 50 * PRICE_PRECISION_I64,
+                auction_duration: 5,
+                price: 100 * PRICE_PRECISION_U64,
+                max_ts: 1,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let mut first_maker = User {
+            orders: [Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                price: 100 * PRICE_PRECISION_U64,
+                ..Order::default()
+            }; 64],
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 2,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        let first_maker_key =
+            Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        create_anchor_account_info!(
+            first_maker,
+            &first_maker_key,
+            User,
+            first_maker_account_info
+        );
+
+        let mut second_maker = User {
+            orders: [Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                price: 101 * PRICE_PRECISION_U64,
+                ..Order::default()
+            }; 64],
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 2,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        let second_maker_key =
+            Pubkey::from_str("My11111111111111111111111111111111111111112").unwrap();
+        create_anchor_account_info!(
+            second_maker,
+            &second_maker_key,
+            User,
+            second_maker_account_info
+        );
+
+        let mut makers_and_referrers = UserMap::load_one(&first_maker_account_info).unwrap();
+        makers_and_referrers
+            .insert(
+                second_maker_key,
+                AccountLoader::try_from(&second_maker_account_info).unwrap(),
+            )
+            .unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut filler = User::default();
+
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &makers_and_referrers,
+            None,
+            &taker_key,
+            &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
+            &mut Some(&mut filler),
+            &filler_key,
+            0,
+            oracle_price,
+            None,
+            FillMode::Fill,
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+
+        assert_eq!(maker_order_price_and_indexes.len(), 96);
+    }
+
+    #[test]
+    fn self_trade_prevention_decrement_and_cancel() {
+        let clock = Clock {
+            slot: 56,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut pyth_price = get_pyth_price(100, 6);
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            pyth_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: pyth_price.twap as i64,
+                    last_oracle_price_twap_5min: pyth_price.twap as i64,
+                    last_oracle_price: pyth_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let taker_key = Pubkey::default();
+        let authority = Pubkey::from_str("My11111111111111111111111111111111111111112").unwrap();
+        let user = User {
+            authority,
+            self_trade_prevention_mode: SelfTradePreventionMode::DecrementAndCancel,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                base_asset_amount: 3 * BASE_PRECISION_U64 / 2,
+                slot: 0,
+                price: 102 * PRICE_PRECISION_U64,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: 3 * BASE_PRECISION_I64 / 2,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let maker_order = Order {
+            market_index: 0,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            slot: 0,
+            post_only: true,
+            ..Order::default()
+        };
+        let mut maker_orders = [Order::default(); 48];
+        maker_orders[0] = Order {
+            order_id: 1,
+            base_asset_amount: BASE_PRECISION_U64 / 2,
+            price: 100 * PRICE_PRECISION_U64,
+            ..maker_order
+        };
+        maker_orders[1] = Order {
+            order_id: 2,
+            base_asset_amount: 2 * BASE_PRECISION_U64,
+            price: 101 * PRICE_PRECISION_U64,
+            ..maker_order
+        };
+        // doesn't cross the taker
+        maker_orders[2] = Order {
+            order_id: 3,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 103 * PRICE_PRECISION_U64,
+            ..maker_order
+        };
+
+        let mut maker = User {
+            authority,
+            orders: maker_orders,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 3,
+                open_asks: -7 * BASE_PRECISION_I64 / 2,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+
+        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut filler = User::default();
+
+        let (maker_order_price_and_indexes, taker_self_trade_decrement) = get_maker_orders_info(
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &makers_and_referrers,
+            None,
+            &taker_key,
+            &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
+            &mut Some(&mut filler),
+            &filler_key,
+            0,
+            oracle_price,
+            None,
+            FillMode::Fill,
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+
+        assert_eq!(
+            maker_order_price_and_indexes,
+            vec![(maker_key, 2, 103 * PRICE_PRECISION_U64)]
+        );
+        assert_eq!(taker_self_trade_decrement, 3 * BASE_PRECISION_U64 / 2);
+
+        let maker = makers_and_referrers.get_ref_mut(&maker_key).unwrap();
+        // smaller maker order canceled, larger one reduced by the rest of the taker
+        assert_eq!(maker.orders[0], Order::default());
+        assert_eq!(maker.orders[1].base_asset_amount, BASE_PRECISION_U64);
+        assert_eq!(maker.perp_positions[0].open_orders, 2);
+        assert_eq!(maker.perp_positions[0].open_asks, -2 * BASE_PRECISION_I64);
+    }
+
+    #[test]
+    fn close_position_maker_order_includes_settled_lp_position() {
+        let clock = Clock {
+            slot: 56,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut pyth_price = get_pyth_price(100, 6);
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            pyth_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        // the lp is short 1 against users it hasn't settled with
+        let lp_shares = 10 * AMM_RESERVE_PRECISION;
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                base_spread: 0,
+                long_spread: 0,
+                short_spread: 0,
+                base_asset_amount_long: 3 * BASE_PRECISION_I128,
+                base_asset_amount_short: -2 * BASE_PRECISION_I128,
+                base_asset_amount_with_unsettled_lp: BASE_PRECISION_I128,
+                user_lp_shares: lp_shares,
+                base_asset_amount_per_lp: -(BASE_PRECISION_I128 * AMM_RESERVE_PRECISION_I128)
+                    / lp_shares as i128,
+                quote_asset_amount_per_lp: (100
+                    * QUOTE_PRECISION_I128
+                    * AMM_RESERVE_PRECISION_I128)
+                    / lp_shares as i128,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: pyth_price.twap as i64,
+                    last_oracle_price_twap_5min: pyth_price.twap as i64,
+                    last_oracle_price: pyth_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            number_of_users_with_base: 1,
+            number_of_users: 1,
+            ..PerpMarket::default()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let taker_key = Pubkey::default();
+        let taker_authority =
+            Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let user = User {
+            authority: taker_authority,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                auction_start_price: 0,
+                auction_end_price: 100 * PRICE_PRECISION_I64,
+                auction_duration: 5,
+                price: 100 * PRICE_PRECISION_U64,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+
+        // close position order placed for the 2 long before the lp took on its short
+        let mut maker = User {
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: 2 * BASE_PRECISION_U64,
+                slot: 0,
+                price: 100 * PRICE_PRECISION_U64,
+                post_only: true,
+                reduce_only: true,
+                close_position: true,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: 2 * BASE_PRECISION_I64,
+                quote_asset_amount: -200 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -200 * QUOTE_PRECISION_I64,
+                quote_break_even_amount: -200 * QUOTE_PRECISION_I64,
+                lp_shares: lp_shares as u64,
+                open_orders: 1,
+                open_asks: -2 * BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+
+        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut filler = User::default();
+
+        let (maker_order_price_and_indexes, _) = get_maker_orders_info(
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &makers_and_referrers,
+            None,
+            &taker_key,
+            &user.orders[0],
+            &user.authority,
+            user.self_trade_prevention_mode,
+            &mut Some(&mut filler),
+            &filler_key,
+            0,
+            oracle_price,
+            None,
+            FillMode::Fill,
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+
+        assert_eq!(
+            maker_order_price_and_indexes,
+            vec![(maker_key, 0, 100 * PRICE_PRECISION_U64)]
+        );
+
+        // lp short settled into the position before the order was resized
+        let maker = makers_and_referrers.get_ref(&maker_key).unwrap();
+        assert_eq!(
+            maker.perp_positions[0].base_asset_amount,
+            BASE_PRECISION_I64
+        );
+        assert_eq!(maker.orders[0].base_asset_amount, BASE_PRECISION_U64);
+        assert_eq!(maker.perp_positions[0].open_asks, -BASE_PRECISION_I64);
+    }
+}
+
+pub mod linked_orders {
+    use std::str::FromStr;
+
+    use crate::controller::orders::{cancel_order, update_linked_orders};
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::events::OrderActionExplanation;
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::SpotMarket;
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{
+        MarketType, OrderLinkType, OrderStatus, OrderTriggerCondition, OrderType, User,
+    };
+    use crate::test_utils::*;
+    use crate::test_utils::{create_account_info, get_positions, get_pyth_price};
+
+    use super::*;
+
+    fn get_linked_orders() -> [Order; 48] {
+        let mut orders = [Order::default(); 48];
+        // entry
+        orders[0] = Order {
+            market_index: 0,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 100 * PRICE_PRECISION_U64,
+            link_id: 1,
+            link_type: OrderLinkType::Parent,
+            ..Order::default()
+        };
+        // take profit
+        orders[1] = Order {
+            market_index: 0,
+            order_id: 2,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 110 * PRICE_PRECISION_U64,
+            reduce_only: true,
+            link_id: 1,
+            link_type: OrderLinkType::PendingChild,
+            ..Order::default()
+        };
+        // stop loss
+        orders[2] = Order {
+            market_index: 0,
+            order_id: 3,
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            trigger_price: 90 * PRICE_PRECISION_U64,
+            trigger_condition: OrderTriggerCondition::Below,
+            reduce_only: true,
+            link_id: 1,
+            link_type: OrderLinkType::PendingChild,
+            ..Order::default()
+        };
+        // not linked
+        orders[3] = Order {
+            market_index: 0,
+            order_id: 4,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 95 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        orders
+    }
+
+    #[test]
+    fn parent_fill_activates_children_and_child_fill_cancels_sibling() {
+        let slot = 6;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_multiple(vec![&usdc_spot_market_account_info], true).unwrap();
+
+        let mut user = User {
+            orders: get_linked_orders(),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 4,
+                open_bids: 2 * BASE_PRECISION_I64,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+        let user_key = Pubkey::default();
+
+        // children can't be matched before parent fills
+        assert!(user.orders[1].is_pending_linked_order());
+
+        let parent_order = user.orders[0];
+        update_linked_orders(
+            &mut user,
+            &user_key,
+            &parent_order,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            0,
+            slot,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(user.orders[0].link_type, OrderLinkType::Parent);
+        assert_eq!(user.orders[1].link_type, OrderLinkType::OneCancelsOther);
+        assert_eq!(user.orders[2].link_type, OrderLinkType::OneCancelsOther);
+        assert_eq!(user.orders[3].link_type, OrderLinkType::None);
+
+        // take profit fills, stop loss is canceled
+        let take_profit_order = user.orders[1];
+        update_linked_orders(
+            &mut user,
+            &user_key,
+            &take_profit_order,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            0,
+            slot,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(user.orders[0].status, OrderStatus::Open);
+        assert_eq!(user.orders[1].status, OrderStatus::Open);
+        assert_eq!(user.orders[2], Order::default());
+        assert_eq!(user.orders[3].status, OrderStatus::Open);
+        assert_eq!(user.perp_positions[0].open_orders, 3);
+        // untriggered stop loss wasn't counted in open asks
+        assert_eq!(user.perp_positions[0].open_asks, -BASE_PRECISION_I64);
+    }
+
+    #[test]
+    fn canceling_parent_cancels_pending_children() {
+        let slot = 6;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_multiple(vec![&usdc_spot_market_account_info], true).unwrap();
+
+        let mut user = User {
+            orders: get_linked_orders(),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 4,
+                open_bids: 2 * BASE_PRECISION_I64,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+        let user_key = Pubkey::default();
+
+        cancel_order(
+            0,
+            &mut user,
+            &user_key,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            0,
+            slot,
+            OrderActionExplanation::None,
+            None,
+            0,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(user.orders[0], Order::default());
+        assert_eq!(user.orders[1], Order::default());
+        assert_eq!(user.orders[2], Order::default());
+        assert_eq!(user.orders[3].status, OrderStatus::Open);
+        assert_eq!(user.perp_positions[0].open_orders, 1);
+        assert_eq!(user.perp_positions[0].open_bids, BASE_PRECISION_I64);
+        assert_eq!(user.perp_positions[0].open_asks, 0);
+    }
+}
+
+pub mod get_trigger_reference_price {
+    use std::str::FromStr;
+
+    use crate::controller::orders::get_trigger_reference_price;
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_U64, PEG_PRECISION, PRICE_PRECISION_U64,
+    };
+    use crate::state::oracle::HistoricalOracleData;
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{
+        MarketType, OrderStatus, OrderTriggerCondition, OrderType, TriggerPriceSource,
+    };
+    use crate::test_utils::*;
+    use crate::test_utils::{create_account_info, get_pyth_price};
+
+    use super::*;
+
+    #[test]
+    fn cross_market() {
+        let slot = 6;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            market_index: 1,
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 101 * PEG_PRECISION,
+                oracle: oracle_price_key,
+                last_mark_price_twap: 98 * PRICE_PRECISION_U64,
+                last_mark_price_twap_5min: 99 * PRICE_PRECISION_U64,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let spot_market_map = SpotMarketMap::load_multiple(vec![], true).unwrap();
+
+        // sell perp market 0 if perp market 1 trades below 97
+        let mut order = Order {
+            market_index: 0,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            trigger_price: 97 * PRICE_PRECISION_U64,
+            trigger_condition: OrderTriggerCondition::Below,
+            ..Order::default()
+        };
+
+        let trigger_reference_price = get_trigger_reference_price(
+            &order,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+        )
+        .unwrap();
+        assert_eq!(trigger_reference_price, None);
+
+        order.trigger_price_source = TriggerPriceSource::PerpOracle;
+        order.trigger_market_index = 1;
+        let trigger_reference_price = get_trigger_reference_price(
+            &order,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+        )
+        .unwrap();
+        assert_eq!(trigger_reference_price, Some(100 * PRICE_PRECISION_U64));
+
+        // 5 minute mark twap, not the reserve price of 101
+        order.trigger_price_source = TriggerPriceSource::PerpMarkPrice;
+        order.trigger_market_index = 1;
+        let trigger_reference_price = get_trigger_reference_price(
+            &order,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+        )
+        .unwrap();
+        assert_eq!(trigger_reference_price, Some(99 * PRICE_PRECISION_U64));
+
+        order.trigger_price_source = TriggerPriceSource::PerpMarkPriceTwap;
+        order.trigger_market_index = 1;
+        let trigger_reference_price = get_trigger_reference_price(
+            &order,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+        )
+        .unwrap();
+        assert_eq!(trigger_reference_price, Some(98 * PRICE_PRECISION_U64));
+
+        // market isn't loaded
+        order.trigger_price_source = TriggerPriceSource::SpotOracle;
+        order.trigger_market_index = 1;
+        assert!(get_trigger_reference_price(
+            &order,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+        )
+        .is_err());
+    }
+}
+
+pub mod update_order_after_fill {
+    use crate::controller::orders::update_order_after_fill;
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::state::user::{OrderStatus, OrderType};
+
+    use super::*;
+
+    #[test]
+    fn iceberg_refill() {
+        let mut order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            slot: 1,
+            base_asset_amount: 2 * BASE_PRECISION_U64,
+            hidden_base_asset_amount: 3 * BASE_PRECISION_U64,
+            slice_base_asset_amount: 2 * BASE_PRECISION_U64,
+            ..Order::default()
+        };
+
+        // partial fill of displayed amount
+        update_order_after_fill(&mut order, BASE_PRECISION_U64, 100, 2).unwrap();
+        assert_eq!(order.base_asset_amount_filled, BASE_PRECISION_U64);
+        assert_eq!(order.slot, 1);
+
+        // displayed amount filled, next slice shown and order goes to back of queue
+        update_order_after_fill(&mut order, BASE_PRECISION_U64, 100, 3).unwrap();
+        assert_eq!(order.status, OrderStatus::Open);
+        assert_eq!(order.base_asset_amount, 4 * BASE_PRECISION_U64);
+        assert_eq!(order.base_asset_amount_filled, 2 * BASE_PRECISION_U64);
+        assert_eq!(order.quote_asset_amount_filled, 200);
+        assert_eq!(order.hidden_base_asset_amount, BASE_PRECISION_U64);
+        assert_eq!(order.slot, 3);
+        assert_eq!(
+            order.get_base_asset_amount_unfilled(None).unwrap(),
+            3 * BASE_PRECISION_U64
+        );
+
+        // last slice is smaller than display amount
+        update_order_after_fill(&mut order, 2 * BASE_PRECISION_U64, 200, 4).unwrap();
+        assert_eq!(order.base_asset_amount, 5 * BASE_PRECISION_U64);
+        assert_eq!(order.base_asset_amount_filled, 4 * BASE_PRECISION_U64);
+        assert_eq!(order.hidden_base_asset_amount, 0);
+        assert_eq!(order.slot, 4);
+
+        update_order_after_fill(&mut order, BASE_PRECISION_U64, 100, 5).unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.base_asset_amount_filled, 5 * BASE_PRECISION_U64);
+        assert_eq!(order.quote_asset_amount_filled, 500);
+        assert_eq!(order.slot, 4);
+    }
+}
+
+pub mod release_twap_slice {
+    use crate::controller::orders::{release_twap_slice, update_order_after_fill};
+    use crate::math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64};
+    use crate::state::oracle::OraclePriceData;
+    use crate::state::user::{OrderStatus, OrderType};
+
+    use super::*;
+
+    #[test]
+    fn fills_stay_cumulative() {
+        let oracle_price_data = OraclePriceData {
+            price: 100 * PRICE_PRECISION_I64,
+            confidence: 0,
+            delay: 0,
+            has_sufficient_number_of_data_points: true,
+        };
+        let tick_size = PRICE_PRECISION_U64 / 100;
+
+        // 10 in 3 slices: 3.3, 3.3, 3.4
+        let mut order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Twap,
+            slot: 1,
+            price: 110 * PRICE_PRECISION_U64,
+            base_asset_amount: 33 * BASE_PRECISION_U64 / 10,
+            hidden_base_asset_amount: 67 * BASE_PRECISION_U64 / 10,
+            slice_base_asset_amount: 33 * BASE_PRECISION_U64 / 10,
+            twap_next_slice_ts: 1060,
+            twap_interval: 60,
+            twap_slices_remaining: 2,
+            ..Order::default()
+        };
+
+        update_order_after_fill(
+            &mut order,
+            33 * BASE_PRECISION_U64 / 10,
+            330 * PRICE_PRECISION_U64,
+            2,
+        )
+        .unwrap();
+        assert_eq!(order.status, OrderStatus::Open);
+        assert!(order.is_waiting_for_twap_slice());
+
+        release_twap_slice(&mut order, &oracle_price_data, tick_size, 10, 3).unwrap();
+        assert_eq!(order.base_asset_amount, 66 * BASE_PRECISION_U64 / 10);
+        assert_eq!(order.base_asset_amount_filled, 33 * BASE_PRECISION_U64 / 10);
+        assert_eq!(order.quote_asset_amount_filled, 330 * PRICE_PRECISION_U64);
+        assert_eq!(order.hidden_base_asset_amount, 34 * BASE_PRECISION_U64 / 10);
+        assert_eq!(order.twap_slices_remaining, 1);
+        assert_eq!(order.twap_next_slice_ts, 1120);
+        assert_eq!(order.slot, 3);
+        assert_eq!(order.auction_duration, 10);
+        assert_eq!(
+            order.get_base_asset_amount_unfilled(None).unwrap(),
+            67 * BASE_PRECISION_U64 / 10
+        );
+
+        update_order_after_fill(
+            &mut order,
+            33 * BASE_PRECISION_U64 / 10,
+            330 * PRICE_PRECISION_U64,
+            4,
+        )
+        .unwrap();
+
+        // last slice includes the remainder
+        release_twap_slice(&mut order, &oracle_price_data, tick_size, 10, 5).unwrap();
+        assert_eq!(order.base_asset_amount, 10 * BASE_PRECISION_U64);
+        assert_eq!(order.base_asset_amount_filled, 66 * BASE_PRECISION_U64 / 10);
+        assert_eq!(order.hidden_base_asset_amount, 0);
+        assert_eq!(order.twap_slices_remaining, 0);
+
+        update_order_after_fill(
+            &mut order,
+            34 * BASE_PRECISION_U64 / 10,
+            340 * PRICE_PRECISION_U64,
+            6,
+        )
+        .unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.base_asset_amount_filled, 10 * BASE_PRECISION_U64);
+        assert_eq!(order.quote_asset_amount_filled, 1000 * PRICE_PRECISION_U64);
+    }
+}
+
+pub mod modify_orders {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountLoader, Clock};
+
+    use crate::controller::orders::modify_orders;
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::HistoricalOracleData;
+    use crate::state::oracle::OracleSource;
+    use crate::state::order_params::{
+        BatchModifyOrderParams, ModifyOrderParams, ModifyOrderPolicy, OrderParams,
+    };
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::state::State;
+    use crate::state::user::{MarketType, OrderStatus, OrderType, SpotPosition, User};
+    use crate::test_utils::*;
+    use crate::test_utils::{
+        create_account_info, get_positions, get_pyth_price, get_spot_positions,
+    };
+
+    use super::*;
+
+    #[test]
+    fn modify_skip_and_fail() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: 10000 * SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_one(&usdc_spot_market_account_info, true).unwrap();
+
+        let mut orders = [Order::default(); 48];
+        orders[0] = Order {
+            market_index: 0,
+            order_id: 1,
+            user_order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 99 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        orders[1] = Order {
+            market_index: 0,
+            order_id: 2,
+            user_order_id: 2,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 101 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+
+        let mut user = User {
+            orders,
+            next_order_id: 3,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 2,
+                open_bids: BASE_PRECISION_I64,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 10000 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        let state = State {
+            min_perp_auction_duration: 1,
+            default_market_order_time_in_force: 10,
+            ..State::default()
+        };
+
+        let params = vec![
+            BatchModifyOrderParams {
+                order_id: Some(1),
+                modify_order_params: ModifyOrderParams {
+                    price: Some(98 * PRICE_PRECISION_U64),
+                    ..ModifyOrderParams::default()
+                },
+                ..BatchModifyOrderParams::default()
+            },
+            // not found
+            BatchModifyOrderParams {
+                order_id: Some(99),
+                ..BatchModifyOrderParams::default()
+            },
+        ];
+
+        modify_orders(
+            &params,
+            &user_account_loader,
+            &state,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &clock,
+        )
+        .unwrap();
+
+        {
+            let user = user_account_loader.load().unwrap();
+            assert_eq!(user.orders[0].order_id, 3);
+            assert_eq!(user.orders[0].price, 98 * PRICE_PRECISION_U64);
+            assert_eq!(user.orders[0].user_order_id, 1);
+            assert_eq!(user.orders[1].order_id, 2);
+            assert_eq!(user.next_order_id, 4);
+            assert_eq!(user.perp_positions[0].open_orders, 2);
+            assert_eq!(user.perp_positions[0].open_bids, BASE_PRECISION_I64);
+            assert_eq!(user.perp_positions[0].open_asks, -BASE_PRECISION_I64);
+        }
+
+        // a replacement that can't be placed fails the whole batch
+        let params = vec![
+            BatchModifyOrderParams {
+                order_id: Some(3),
+                modify_order_params: ModifyOrderParams {
+                    price: Some(97 * PRICE_PRECISION_U64),
+                    ..ModifyOrderParams::default()
+                },
+                ..BatchModifyOrderParams::default()
+            },
+            // replacement has no price
+            BatchModifyOrderParams {
+                user_order_id: 2,
+                replacement_order_params: Some(OrderParams {
+                    order_type: OrderType::Limit,
+                    market_type: MarketType::Perp,
+                    direction: PositionDirection::Short,
+                    base_asset_amount: BASE_PRECISION_U64,
+                    price: 0,
+                    ..OrderParams::default()
+                }),
+                ..BatchModifyOrderParams::default()
+            },
+        ];
+
+        assert!(modify_orders(
+            &params,
+            &user_account_loader,
+            &state,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &clock,
+        )
+        .is_err());
+
+        // must modify fails the whole batch
+        let params = vec![BatchModifyOrderParams {
+            order_id: Some(99),
+            modify_order_params: ModifyOrderParams {
+                policy: Some(ModifyOrderPolicy::MustModify),
+                ..ModifyOrderParams::default()
+            },
+            ..BatchModifyOrderParams::default()
+        }];
+
+        assert!(modify_orders(
+            &params,
+            &user_account_loader,
+            &state,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &clock,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn invalid_entry_fails_before_any_order_is_modified() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: 10000 * SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_one(&usdc_spot_market_account_info, true).unwrap();
+
+        let mut orders = [Order::default(); 48];
+        orders[0] = Order {
+            market_index: 0,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 99 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        orders[1] = Order {
+            market_index: 0,
+            order_id: 2,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 98 * PRICE_PRECISION_U64,
+            slice_base_asset_amount: BASE_PRECISION_U64 / 2,
+            ..Order::default()
+        };
+
+        let mut user = User {
+            orders,
+            next_order_id: 3,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 2,
+                open_bids: 2 * BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 10000 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        let state = State {
+            min_perp_auction_duration: 1,
+            default_market_order_time_in_force: 10,
+            ..State::default()
+        };
+
+        // the second entry is an iceberg, which can't be modified
+        let params = vec![
+            BatchModifyOrderParams {
+                order_id: Some(1),
+                modify_order_params: ModifyOrderParams {
+                    price: Some(97 * PRICE_PRECISION_U64),
+                    ..ModifyOrderParams::default()
+                },
+                ..BatchModifyOrderParams::default()
+            },
+            BatchModifyOrderParams {
+                order_id: Some(2),
+                modify_order_params: ModifyOrderParams {
+                    price: Some(97 * PRICE_PRECISION_U64),
+                    ..ModifyOrderParams::default()
+                },
+                ..BatchModifyOrderParams::default()
+            },
+        ];
+
+        assert!(modify_orders(
+            &params,
+            &user_account_loader,
+            &state,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &clock,
+        )
+        .is_err());
+
+        let user = user_account_loader.load().unwrap();
+        assert_eq!(user.orders[0].order_id, 1);
+        assert_eq!(user.orders[0].price, 99 * PRICE_PRECISION_U64);
+        assert_eq!(user.next_order_id, 3);
+        assert_eq!(user.perp_positions[0].open_orders, 2);
+    }
+
+    #[test]
+    fn modify_max_entries() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: 10000 * SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_one(&usdc_spot_market_account_info, true).unwrap();
+
+        let order_count = 32_u32;
+        let mut orders = [Order::default(); 48];
+        for (i, order) in orders.iter_mut().take(order_count as usize).enumerate() {
+            *order = Order {
+                market_index: 0,
+                order_id: i as u32 + 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                market_type: MarketType::Perp,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION_U64,
+                price: 90 * PRICE_PRECISION_U64,
+                ..Order::default()
+            };
+        }
+
+        let mut user = User {
+            orders,
+            next_order_id: order_count + 1,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: order_count as u8,
+                open_bids: order_count as i64 * BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 10000 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        let state = State {
+            min_perp_auction_duration: 1,
+            default_market_order_time_in_force: 10,
+            ..State::default()
+        };
+
+        let params: Vec<BatchModifyOrderParams> = (1..=order_count)
+            .map(|order_id| BatchModifyOrderParams {
+                order_id: Some(order_id),
+                modify_order_params: ModifyOrderParams {
+                    price: Some(91 * PRICE_PRECISION_U64),
+                    ..ModifyOrderParams::default()
+                },
+                ..BatchModifyOrderParams::default()
+            })
+            .collect();
+
+        modify_orders(
+            &params,
+            &user_account_loader,
+            &state,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
+            &clock,
+        )
+        .unwrap();
+
+        let user = user_account_loader.load().unwrap();
+        assert_eq!(user.next_order_id, 2 * order_count + 1);
+        for order in user.orders.iter().take(order_count as usize) {
+            assert_eq!(order.status, OrderStatus::Open);
+            assert!(order.order_id > order_count);
+            assert_eq!(order.price, 91 * PRICE_PRECISION_U64);
+        }
+        assert_eq!(user.perp_positions[0].open_orders, order_count as u8);
+        assert_eq!(
+            user.perp_positions[0].open_bids,
+            order_count as i64 * BASE_PRECISION_I64
+        );
+    }
+}
+
+pub mod clear_perp_batch_auction {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountInfo, AccountLoader, Clock};
+
+    use crate::controller::orders::clear_perp_batch_auction;
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::error::{DriftResult, ErrorCode};
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION,
+        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::HistoricalOracleData;
+    use crate::state::oracle::OracleSource;
+    use crate::state::order_book_index::{PerpOrderBookIndex, PERP_ORDER_BOOK_INDEX_MAX_USERS};
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::state::{ExchangeStatus, State};
+    use crate::state::user::{MarketType, OrderStatus, OrderType, SpotPosition, User, UserStats};
+    use crate::state::user_map::{UserMap, UserStatsMap};
+    use crate::test_utils::*;
+    use crate::test_utils::{
+        create_account_info, get_positions, get_pyth_price, get_spot_positions,
+    };
+
+    use super::*;
+
+    /// direction, price, base asset amount and usdc deposit of a user's batch order
+    type BatchOrder = (PositionDirection, u64, u64, u64);
+
+    fn batch_auction_user_key(index: usize) -> Pubkey {
+        Pubkey::new_from_array([index as u8 + 1; 32])
+    }
+
+    fn batch_auction_user(
+        authority: Pubkey,
+        (direction, price, base_asset_amount, deposit): BatchOrder,
+    ) -> User {
+        let (open_bids, open_asks) = match direction {
+            PositionDirection::Long => (base_asset_amount as i64, 0),
+            PositionDirection::Short => (0, -(base_asset_amount as i64)),
+        };
+
+        User {
+            authority,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                market_type: MarketType::Perp,
+                direction,
+                base_asset_amount,
+                price: price * PRICE_PRECISION_U64,
+                slot: 0,
+                ..Order::default()
+            }),
+            next_order_id: 2,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids,
+                open_asks,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: deposit * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        }
+    }
+
+    /// Clears a batch of one order per user, indexed in order, with the amm paused. Only the first
+    /// users_passed users are passed to the clear
+    fn clear_batch(
+        orders: &[BatchOrder],
+        oracle_twap_5min: i64,
+        users_passed: usize,
+    ) -> (DriftResult, Vec<User>, PerpMarket, PerpOrderBookIndex) {
+        let clock = Clock {
+            slot: 20,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_twap_5min,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            batch_auction_duration: 10,
+            ..PerpMarket::default()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: 100000 * SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_one(&usdc_spot_market_account_info, true).unwrap();
+
+        let user_keys: Vec<Pubkey> = (0..orders.len()).map(batch_auction_user_key).collect();
+        let authorities: Vec<Pubkey> = orders.iter().map(|_| Pubkey::new_unique()).collect();
+        let mut users: Vec<User> = authorities
+            .iter()
+            .zip(orders.iter())
+            .map(|(authority, order)| batch_auction_user(*authority, *order))
+            .collect();
+
+        let mut perp_order_book_index = PerpOrderBookIndex::default();
+        for (user_key, user) in user_keys.iter().zip(users.iter()) {
+            perp_order_book_index
+                .update_user_orders(user_key, user)
+                .unwrap();
+        }
+        create_anchor_account_info!(
+            perp_order_book_index,
+            PerpOrderBookIndex,
+            perp_order_book_index_account_info
+        );
+        let mut perp_order_book_index_map = PerpOrderBookIndexMap::empty();
+        perp_order_book_index_map.0.insert(
+            0,
+            AccountLoader::try_from(&perp_order_book_index_account_info).unwrap(),
+        );
+
+        let user_owner = User::owner();
+        let mut user_lamports = vec![0_u64; users.len()];
+        let mut user_data: Vec<_> = users.iter_mut().map(get_anchor_account_bytes).collect();
+        let user_account_infos: Vec<AccountInfo> = user_keys
+            .iter()
+            .zip(user_lamports.iter_mut())
+            .zip(user_data.iter_mut())
+            .map(|((user_key, lamports), data)| {
+                create_account_info(user_key, true, lamports, &mut data[..], &user_owner)
+            })
+            .collect();
+
+        let mut user_stats: Vec<UserStats> = authorities
+            .iter()
+            .map(|authority| UserStats {
+                authority: *authority,
+                ..UserStats::default()
+            })
+            .collect();
+        let user_stats_key = Pubkey::default();
+        let user_stats_owner = UserStats::owner();
+        let mut user_stats_lamports = vec![0_u64; user_stats.len()];
+        let mut user_stats_data: Vec<_> = user_stats
+            .iter_mut()
+            .map(get_anchor_account_bytes)
+            .collect();
+        let user_stats_account_infos: Vec<AccountInfo> = user_stats_lamports
+            .iter_mut()
+            .zip(user_stats_data.iter_mut())
+            .map(|(lamports, data)| {
+                create_account_info(
+                    &user_stats_key,
+                    true,
+                    lamports,
+                    &mut data[..],
+                    &user_stats_owner,
+                )
+            })
+            .collect();
+
+        let mut user_map = UserMap::empty();
+        let mut user_stats_map = UserStatsMap::empty();
+        for (((user_key, authority), user_account_info), user_stats_account_info) in user_keys
+            .iter()
+            .zip(authorities.iter())
+            .zip(user_account_infos.iter())
+            .zip(user_stats_account_infos.iter())
+            .take(users_passed)
+        {
+            user_map
+                .insert(
+                    *user_key,
+                    AccountLoader::try_from(user_account_info).unwrap(),
+                )
+                .unwrap();
+            user_stats_map
+                .insert(
+                    *authority,
+                    AccountLoader::try_from(user_stats_account_info).unwrap(),
+                )
+                .unwrap();
+        }
+
+        let filler_key = batch_auction_user_key(orders.len());
+        let mut filler = User {
+            authority: Pubkey::new_unique(),
+            ..User::default()
+        };
+        create_anchor_account_info!(filler, &filler_key, User, filler_account_info);
+        let filler_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&filler_account_info).unwrap();
+        create_anchor_account_info!(UserStats::default(), UserStats, filler_stats_account_info);
+        let filler_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&filler_stats_account_info).unwrap();
+
+        let state = State {
+            exchange_status: ExchangeStatus::AmmPaused as u8,
+            ..State::default()
+        };
+
+        let result = clear_perp_batch_auction(
+            0,
+            &state,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &perp_order_book_index_map,
+            &user_map,
+            &user_stats_map,
+            &filler_account_loader,
+            &filler_stats_account_loader,
+            &clock,
+        );
+
+        let users = user_account_infos
+            .iter()
+            .map(|account_info| {
+                *AccountLoader::<User>::try_from(account_info)
+                    .unwrap()
+                    .load()
+                    .unwrap()
+            })
+            .collect();
+        let market = *market_map.get_ref(&0).unwrap();
+        let perp_order_book_index = *perp_order_book_index_map.get_ref_mut(&0).unwrap().unwrap();
+
+        (result, users, market, perp_order_book_index)
+    }
+
+    #[test]
+    fn clears_at_uniform_price() {
+        let (result, users, market, perp_order_book_index) = clear_batch(
+            &[
+                (PositionDirection::Long, 101, 2 * BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 99, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 100, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Long, 98, BASE_PRECISION_U64, 10000),
+            ],
+            100 * PRICE_PRECISION_I64,
+            4,
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(market.last_batch_auction_clear_slot, 20);
+
+        // the 2 bid at 101 needs both asks, so everyone fills at 100
+        assert_eq!(
+            users[0].perp_positions[0].base_asset_amount,
+            2 * BASE_PRECISION_I64
+        );
+        assert_eq!(
+            users[0].perp_positions[0].quote_entry_amount,
+            -200 * QUOTE_PRECISION_I64
+        );
+        assert_eq!(
+            users[1].perp_positions[0].base_asset_amount,
+            -BASE_PRECISION_I64
+        );
+        assert_eq!(
+            users[1].perp_positions[0].quote_entry_amount,
+            100 * QUOTE_PRECISION_I64
+        );
+        assert_eq!(
+            users[2].perp_positions[0].base_asset_amount,
+            -BASE_PRECISION_I64
+        );
+        assert_eq!(users[3].perp_positions[0].base_asset_amount, 0);
+
+        // only the bid below the clearing price is left in the index
+        assert_eq!(
+            perp_order_book_index.get_users(),
+            vec![batch_auction_user_key(3)]
+        );
+    }
+
+    #[test]
+    fn rationed_side_fills_pro_rata() {
+        let (result, users, _, perp_order_book_index) = clear_batch(
+            &[
+                (PositionDirection::Long, 100, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 100, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 100, 3 * BASE_PRECISION_U64, 10000),
+                (PositionDirection::Long, 98, BASE_PRECISION_U64, 10000),
+            ],
+            100 * PRICE_PRECISION_I64,
+            4,
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            users[0].perp_positions[0].base_asset_amount,
+            BASE_PRECISION_I64
+        );
+        // the earlier ask doesn't take the whole bid, both asks fill a quarter of their size
+        assert_eq!(
+            users[1].perp_positions[0].base_asset_amount,
+            -BASE_PRECISION_I64 / 4
+        );
+        assert_eq!(
+            users[2].perp_positions[0].base_asset_amount,
+            -3 * BASE_PRECISION_I64 / 4
+        );
+
+        let asks = perp_order_book_index.get_orders(PositionDirection::Short);
+        assert_eq!(asks.len(), 2);
+        assert_eq!(asks[0].base_asset_amount, 3 * BASE_PRECISION_U64 / 4);
+        assert_eq!(asks[1].base_asset_amount, 9 * BASE_PRECISION_U64 / 4);
+    }
+
+    #[test]
+    fn user_below_margin_is_canceled() {
+        let (result, users, market, perp_order_book_index) = clear_batch(
+            &[
+                (PositionDirection::Long, 101, 2 * BASE_PRECISION_U64, 1),
+                (PositionDirection::Short, 99, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 100, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Long, 100, BASE_PRECISION_U64, 10000),
+            ],
+            100 * PRICE_PRECISION_I64,
+            4,
+        );
+
+        // the batch clears without the first user instead of reverting
+        assert_eq!(result, Ok(()));
+        assert_eq!(market.last_batch_auction_clear_slot, 20);
+        assert_eq!(users[0].perp_positions[0].base_asset_amount, 0);
+        assert_eq!(users[0].perp_positions[0].open_orders, 0);
+        assert_eq!(users[0].orders[0].status, OrderStatus::Init);
+
+        assert_eq!(
+            users[3].perp_positions[0].base_asset_amount,
+            BASE_PRECISION_I64
+        );
+        assert_eq!(
+            users[3].perp_positions[0].quote_entry_amount,
+            -99 * QUOTE_PRECISION_I64
+        );
+        assert_eq!(
+            users[1].perp_positions[0].base_asset_amount,
+            -BASE_PRECISION_I64
+        );
+        assert_eq!(users[2].perp_positions[0].base_asset_amount, 0);
+        assert_eq!(
+            perp_order_book_index.get_users(),
+            vec![batch_auction_user_key(2)]
+        );
+    }
+
+    #[test]
+    fn oracle_divergence_keeps_batch_open() {
+        let (result, users, market, perp_order_book_index) = clear_batch(
+            &[
+                (PositionDirection::Long, 101, 2 * BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 99, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 100, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Long, 98, BASE_PRECISION_U64, 10000),
+            ],
+            50 * PRICE_PRECISION_I64,
+            4,
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(market.last_batch_auction_clear_slot, 0);
+        for user in users.iter() {
+            assert_eq!(user.perp_positions[0].base_asset_amount, 0);
+            assert_eq!(user.orders[0].status, OrderStatus::Open);
+        }
+        assert_eq!(perp_order_book_index.get_users().len(), 4);
+    }
+
+    #[test]
+    fn missing_user_in_batch() {
+        let (result, users, market, _) = clear_batch(
+            &[
+                (PositionDirection::Long, 101, 2 * BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 99, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Short, 100, BASE_PRECISION_U64, 10000),
+                (PositionDirection::Long, 98, BASE_PRECISION_U64, 10000),
+            ],
+            100 * PRICE_PRECISION_I64,
+            3,
+        );
+
+        assert_eq!(result, Err(ErrorCode::InvalidBatchAuction));
+        assert_eq!(market.last_batch_auction_clear_slot, 0);
+        assert_eq!(users[0].perp_positions[0].base_asset_amount, 0);
+    }
+
+    #[test]
+    fn clears_max_users() {
+        // every user the index holds, bids and asks crossing
+        let orders: Vec<BatchOrder> = (0..PERP_ORDER_BOOK_INDEX_MAX_USERS)
+            .map(|i| {
+                if i % 2 == 0 {
+                    (PositionDirection::Long, 101, BASE_PRECISION_U64, 10000)
+                } else {
+                    (PositionDirection::Short, 99, BASE_PRECISION_U64, 10000)
+                }
+            })
+            .collect();
+
+        let (result, users, market, perp_order_book_index) =
+            clear_batch(&orders, 100 * PRICE_PRECISION_I64, orders.len());
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(market.last_batch_auction_clear_slot, 20);
+        for (i, user) in users.iter().enumerate() {
+            let expected_base_asset_amount = if i % 2 == 0 {
+                BASE_PRECISION_I64
+            } else {
+                -BASE_PRECISION_I64
+            };
+            assert_eq!(
+                user.perp_positions[0].base_asset_amount,
+                expected_base_asset_amount
+            );
+        }
+        assert!(perp_order_book_index.get_users().is_empty());
     }
 }
@@ -21,6 +21,7 @@ use crate::state::events::{OrderActionExplanation, SettlePnlExplanation, SettleP
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::MarketStatus;
 use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
 use crate::state::spot_market::{SpotBalance, SpotBalanceType};
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::State;
@@ -237,6 +238,7 @@ pub fn settle_expired_position(
         now,
     )?;
 
+    // the market is expired, its order book index is no longer used
     cancel_orders(
         user,
         user_key,
@@ -244,12 +246,14 @@ pub fn settle_expired_position(
         perp_market_map,
         spot_market_map,
         oracle_map,
+        &PerpOrderBookIndexMap::empty(),
         now,
         slot,
         OrderActionExplanation::MarketExpired,
         Some(MarketType::Perp),
         Some(perp_market_index),
         None,
+        None,
     )?;
 
     let position_index = match get_position_index(&user.perp_positions, perp_market_index) {
@@ -10,6 +10,7 @@ fn get_user_keys() -> (Pubkey, Pubkey, Pubkey) {
 
 #[cfg(test)]
 pub mod delisting_test {
+    use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
     use std::str::FromStr;
 
     use anchor_lang::prelude::Clock;
@@ -2346,7 +2347,7 @@ pub mod delisting_test {
             let (perp_margin_requirement, weighted_pnl, _) = calculate_perp_position_value_and_pnl(
                 &shorter.perp_positions[0],
                 &market,
-                oracle_price_data,
+                &StrictOraclePrice::test(oracle_price_data.price),
                 &strict_quote_price,
                 MarginRequirementType::Initial,
                 0,
@@ -2407,6 +2408,7 @@ pub mod delisting_test {
                 &market_map,
                 &spot_market_map,
                 &mut oracle_map,
+                &PerpOrderBookIndexMap::empty(),
                 clock.slot,
                 clock.unix_timestamp,
                 &state,
@@ -2425,7 +2427,7 @@ pub mod delisting_test {
                     calculate_perp_position_value_and_pnl(
                         &shorter.perp_positions[0],
                         &market,
-                        oracle_price_data,
+                        &StrictOraclePrice::test(oracle_price_data.price),
                         &strict_quote_price,
                         MarginRequirementType::Initial,
                         0,
@@ -2486,6 +2488,7 @@ pub mod delisting_test {
                 &market_map,
                 &spot_market_map,
                 &mut oracle_map,
+                &PerpOrderBookIndexMap::empty(),
                 clock.unix_timestamp,
                 clock.slot,
                 10,
@@ -2511,7 +2514,7 @@ pub mod delisting_test {
                     calculate_perp_position_value_and_pnl(
                         &shorter.perp_positions[0],
                         &market,
-                        oracle_price_data,
+                        &StrictOraclePrice::test(oracle_price_data.price),
                         &strict_quote_price,
                         MarginRequirementType::Initial,
                         0,
@@ -2576,6 +2579,7 @@ pub mod delisting_test {
                 &market_map,
                 &spot_market_map,
                 &mut oracle_map,
+                &PerpOrderBookIndexMap::empty(),
                 clock.unix_timestamp,
                 clock.slot,
                 10,
@@ -2601,7 +2605,7 @@ pub mod delisting_test {
                     calculate_perp_position_value_and_pnl(
                         &shorter.perp_positions[0],
                         &market,
-                        oracle_price_data,
+                        &StrictOraclePrice::test(oracle_price_data.price),
                         &strict_quote_price,
                         MarginRequirementType::Initial,
                         0,
@@ -10,10 +10,11 @@ use crate::error::ErrorCode;
 use crate::error::*;
 use crate::load_mut;
 use crate::math::amm;
+use crate::math::amm_spread;
 use crate::math::bn;
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    K_BPS_UPDATE_SCALE, MAX_SQRT_K, QUOTE_PRECISION, QUOTE_SPOT_MARKET_INDEX,
+    K_BPS_UPDATE_SCALE, MAX_SQRT_K, ONE_HOUR, QUOTE_PRECISION, QUOTE_SPOT_MARKET_INDEX,
 };
 use crate::math::cp_curve;
 use crate::math::cp_curve::get_update_k_result;
@@ -26,6 +27,7 @@ use crate::math::spot_balance::get_token_amount;
 
 use crate::state::oracle::OraclePriceData;
 use crate::state::oracle_map::OracleMap;
+use crate::state::oracle_price_history::OraclePriceHistory;
 use crate::state::perp_market::{MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_market::SpotBalanceType;
@@ -136,6 +138,55 @@ pub fn update_amm(
     Ok(cost_of_update)
 }
 
+pub fn update_oracle_price_history(
+    oracle_price_history: &mut OraclePriceHistory,
+    market: &mut PerpMarket,
+    oracle_price_data: &OraclePriceData,
+    guard_rails: &OracleGuardRails,
+    now: i64,
+) -> DriftResult<bool> {
+    validate!(
+        oracle_price_history.perp_market_index == market.market_index,
+        ErrorCode::InvalidOraclePriceHistory,
+        "oracle price history market index {} != perp market index {}",
+        oracle_price_history.perp_market_index,
+        market.market_index
+    )?;
+
+    if oracle_price_history.oracle != market.amm.oracle {
+        msg!(
+            "perp market {} oracle changed, resetting oracle price history",
+            market.market_index
+        );
+        oracle_price_history.reset(market.amm.oracle);
+    }
+
+    let oracle_validity = oracle::oracle_validity(
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+        oracle_price_data,
+        &guard_rails.validity,
+    )?;
+
+    if !is_oracle_valid_for_action(oracle_validity, Some(DriftAction::UpdateTwap))? {
+        return Ok(false);
+    }
+
+    let appended = oracle_price_history.append(oracle_price_data, now)?;
+
+    if appended {
+        let price_history_std = oracle_price_history.get_price_std(now, ONE_HOUR)?;
+        market.amm.oracle_std = amm_spread::calculate_oracle_std_with_price_history(
+            market.amm.oracle_std,
+            price_history_std,
+        );
+
+        let reserve_price = market.amm.reserve_price()?;
+        update_spreads(&mut market.amm, reserve_price)?;
+    }
+
+    Ok(appended)
+}
+
 pub fn _update_amm(
     market: &mut PerpMarket,
     oracle_price_data: &OraclePriceData,
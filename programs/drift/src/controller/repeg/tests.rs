@@ -1,6 +1,6 @@
 use crate::controller::repeg::*;
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, PEG_PRECISION, PRICE_PRECISION, PRICE_PRECISION_I64,
+    AMM_RESERVE_PRECISION, ONE_HOUR, PEG_PRECISION, PRICE_PRECISION, PRICE_PRECISION_I64,
     PRICE_PRECISION_U64, QUOTE_PRECISION,
 };
 use crate::math::oracle::OracleValidity;
@@ -532,3 +532,221 @@ pub fn update_amm_larg_conf_w_neg_tfmd_test() {
     assert_eq!((oracle_price_data.price as u64) > bid, true);
     assert_eq!((oracle_price_data.price as u64) < ask, true);
 }
+
+#[test]
+fn update_oracle_price_history_test() {
+    let oracle = Pubkey::new_unique();
+    let mut market = PerpMarket {
+        market_index: 1,
+        amm: AMM {
+            oracle,
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            base_spread: 250,
+            max_spread: 55500,
+            curve_update_intensity: 100,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: 100 * PRICE_PRECISION_I64,
+                ..HistoricalOracleData::default()
+            },
+            ..AMM::default()
+        },
+        ..PerpMarket::default()
+    };
+    let guard_rails = OracleGuardRails::default();
+
+    let oracle_price_data = OraclePriceData {
+        price: 101 * PRICE_PRECISION_I64,
+        confidence: PRICE_PRECISION_U64 / 10,
+        delay: 1,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    // history belongs to another market
+    let mut oracle_price_history = OraclePriceHistory::default();
+    assert!(update_oracle_price_history(
+        &mut oracle_price_history,
+        &mut market,
+        &oracle_price_data,
+        &guard_rails,
+        0
+    )
+    .is_err());
+
+    // oracle changed, samples from the old oracle are dropped
+    let mut oracle_price_history = OraclePriceHistory {
+        perp_market_index: 1,
+        oracle: Pubkey::new_unique(),
+        ..OraclePriceHistory::default()
+    };
+    oracle_price_history.append(&oracle_price_data, 0).unwrap();
+
+    let updated = update_oracle_price_history(
+        &mut oracle_price_history,
+        &mut market,
+        &oracle_price_data,
+        &guard_rails,
+        10,
+    )
+    .unwrap();
+    assert!(updated);
+    assert_eq!(oracle_price_history.oracle, oracle);
+    assert_eq!(oracle_price_history.len, 1);
+    assert_eq!(oracle_price_history.get_sample(0).unwrap().ts, 10);
+
+    // invalid oracle isn't sampled
+    let invalid_oracle_price_data = OraclePriceData {
+        price: 0,
+        ..oracle_price_data
+    };
+    let updated = update_oracle_price_history(
+        &mut oracle_price_history,
+        &mut market,
+        &invalid_oracle_price_data,
+        &guard_rails,
+        20,
+    )
+    .unwrap();
+    assert!(!updated);
+    assert_eq!(oracle_price_history.len, 1);
+
+    // oracle std from the ewma is raised to the std of the sampled prices and spreads widen
+    let (long_spread_before, short_spread_before) =
+        (market.amm.long_spread, market.amm.short_spread);
+    let moved_oracle_price_data = OraclePriceData {
+        price: 111 * PRICE_PRECISION_I64,
+        ..oracle_price_data
+    };
+    let updated = update_oracle_price_history(
+        &mut oracle_price_history,
+        &mut market,
+        &moved_oracle_price_data,
+        &guard_rails,
+        10 + ONE_HOUR,
+    )
+    .unwrap();
+    assert!(updated);
+    assert_eq!(
+        market.amm.oracle_std,
+        oracle_price_history
+            .get_price_std(10 + ONE_HOUR, ONE_HOUR)
+            .unwrap()
+            .unwrap()
+    );
+    assert_eq!(market.amm.oracle_std, 7071067);
+    assert!(market.amm.long_spread > long_spread_before);
+    assert!(market.amm.short_spread > short_spread_before);
+}
+
+#[test]
+pub fn update_amm_inventory_skew_test() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 65 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 63015384615,
+            terminal_quote_asset_reserve: 64 * AMM_RESERVE_PRECISION,
+            sqrt_k: 64 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 19_400 * PEG_PRECISION,
+            base_asset_amount_with_amm: -(AMM_RESERVE_PRECISION as i128),
+            mark_std: PRICE_PRECISION as u64,
+            last_mark_price_twap_ts: 0,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: 19_400 * PRICE_PRECISION_I64,
+                ..HistoricalOracleData::default()
+            },
+            base_spread: 250,
+            curve_update_intensity: 100,
+            max_spread: 55500,
+            concentration_coef: 31020710,
+            total_exchange_fee: QUOTE_PRECISION,
+            total_fee_minus_distributions: (1000 * QUOTE_PRECISION) as i128,
+            ..AMM::default()
+        },
+        status: MarketStatus::Initialized,
+        contract_tier: ContractTier::B,
+        margin_ratio_initial: 555,
+        ..PerpMarket::default()
+    };
+    let (_, new_terminal_base_reserve) = amm::calculate_terminal_reserves(&market.amm).unwrap();
+    let (min_base_asset_reserve, max_base_asset_reserve) =
+        amm::calculate_bid_ask_bounds(market.amm.concentration_coef, new_terminal_base_reserve)
+            .unwrap();
+    market.amm.min_base_asset_reserve = min_base_asset_reserve;
+    market.amm.max_base_asset_reserve = max_base_asset_reserve;
+
+    let state = State {
+        oracle_guard_rails: OracleGuardRails {
+            price_divergence: PriceDivergenceGuardRails {
+                mark_oracle_percent_divergence: 1,
+                oracle_twap_5min_percent_divergence: 10,
+            },
+            validity: ValidityGuardRails {
+                slots_before_stale_for_amm: 10,     // 5s
+                slots_before_stale_for_margin: 120, // 60s
+                confidence_interval_max_size: 1000,
+                too_volatile_ratio: 5,
+            },
+        },
+        ..State::default()
+    };
+
+    let now = 10000;
+    let slot = 81680085;
+    let oracle_price_data = OraclePriceData {
+        price: (19_400 * PRICE_PRECISION) as i64,
+        confidence: 0,
+        delay: 2,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    assert_eq!(market.amm.reserve_price().unwrap(), 18807668638);
+
+    // without skew the reserves re-center on the oracle
+    let mut unskewed_market = market;
+    _update_amm(&mut unskewed_market, &oracle_price_data, &state, now, slot).unwrap();
+
+    assert_eq!(unskewed_market.amm.peg_multiplier, 20010986328);
+    assert_eq!(unskewed_market.amm.reserve_price().unwrap(), 19399999999);
+    assert_eq!(unskewed_market.amm.inventory_skew, 0);
+
+    // amm is long, reserves re-center below the oracle
+    let mut skewed_market = market;
+    skewed_market.amm.max_inventory_skew = 100_000;
+    let reference_price = skewed_market
+        .amm
+        .reference_price(oracle_price_data.price)
+        .unwrap();
+    assert_eq!(reference_price, 19369192800);
+
+    let cost = _update_amm(&mut skewed_market, &oracle_price_data, &state, now, slot).unwrap();
+
+    assert_eq!(
+        skewed_market.amm.peg_multiplier,
+        calculate_peg_from_target_price(
+            market.amm.quote_asset_reserve,
+            market.amm.base_asset_reserve,
+            reference_price as u64
+        )
+        .unwrap()
+    );
+    assert_eq!(skewed_market.amm.peg_multiplier, 19979208881);
+    assert_eq!(skewed_market.amm.reserve_price().unwrap(), 19369192799);
+    assert_eq!(
+        skewed_market.amm.base_asset_reserve,
+        market.amm.base_asset_reserve
+    );
+    assert_eq!(
+        skewed_market.amm.quote_asset_reserve,
+        market.amm.quote_asset_reserve
+    );
+    assert_eq!(skewed_market.amm.inventory_skew, -1588);
+
+    // moving the peg up with users short is revenue for the amm
+    assert!(cost < 0);
+    assert_eq!(
+        skewed_market.amm.total_fee_minus_distributions,
+        market.amm.total_fee_minus_distributions - cost
+    );
+}
@@ -141,7 +141,7 @@ fn test_daily_withdraw_limits() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); 16];
     spot_positions[0] = SpotPosition {
         market_index: 0,
         balance_type: SpotBalanceType::Deposit,
@@ -149,8 +149,8 @@ fn test_daily_withdraw_limits() {
         ..SpotPosition::default()
     };
     let mut user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); 48],
+        perp_positions: [PerpPosition::default(); 16],
         spot_positions,
         ..User::default()
     };
@@ -454,7 +454,7 @@ fn test_check_withdraw_limits() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); 16];
     spot_positions[0] = SpotPosition {
         market_index: 0,
         balance_type: SpotBalanceType::Deposit,
@@ -468,8 +468,8 @@ fn test_check_withdraw_limits() {
         ..SpotPosition::default()
     };
     let user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); 48],
+        perp_positions: [PerpPosition::default(); 16],
         spot_positions,
         ..User::default()
     };
@@ -674,7 +674,7 @@ fn test_check_withdraw_limits_above_optimal_utilization() {
     assert_eq!(valid_withdraw, false);
 
     // with mock user doing no borrowing, success!
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); 16];
     spot_positions[0] = SpotPosition {
         market_index: 0,
         balance_type: SpotBalanceType::Deposit,
@@ -688,8 +688,8 @@ fn test_check_withdraw_limits_above_optimal_utilization() {
         ..SpotPosition::default()
     };
     let user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); 48],
+        perp_positions: [PerpPosition::default(); 16],
         spot_positions,
         ..User::default()
     };
@@ -796,7 +796,7 @@ fn check_fee_collection() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); 16];
     spot_positions[1] = SpotPosition {
         market_index: 1,
         balance_type: SpotBalanceType::Deposit,
@@ -804,8 +804,8 @@ fn check_fee_collection() {
         ..SpotPosition::default()
     };
     let mut user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); 48],
+        perp_positions: [PerpPosition::default(); 16],
         spot_positions,
         ..User::default()
     };
@@ -1161,7 +1161,7 @@ fn check_fee_collection_larger_nums() {
         Vec::from([&spot_market_account_info, &sol_spot_market_account_info]);
     let _spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); 16];
     spot_positions[0] = SpotPosition {
         market_index: 1,
         balance_type: SpotBalanceType::Deposit,
@@ -1169,8 +1169,8 @@ fn check_fee_collection_larger_nums() {
         ..SpotPosition::default()
     };
     let mut user = User {
-        orders: [Order::default(); 32],
-        perp_positions: [PerpPosition::default(); 8],
+        orders: [Order::default(); 48],
+        perp_positions: [PerpPosition::default(); 16],
         spot_positions,
         ..User::default()
     };
@@ -1451,7 +1451,7 @@ fn attempt_borrow_with_massive_upnl() {
     let spot_market_map = SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
     // user has 100 sol
-    let mut spot_positions = [SpotPosition::default(); 8];
+    let mut spot_positions = [SpotPosition::default(); 16];
     spot_positions[0] = SpotPosition {
         market_index: 1,
         balance_type: SpotBalanceType::Deposit,
@@ -1460,7 +1460,7 @@ fn attempt_borrow_with_massive_upnl() {
     };
 
     let user = User {
-        orders: [Order::default(); 32],
+        orders: [Order::default(); 48],
         perp_positions: get_positions(PerpPosition {
             market_index: 0,
             base_asset_amount: 1000 * BASE_PRECISION_I64,
@@ -90,7 +90,7 @@ mod update_spot_position_balance {
     fn transfer_fail() {
         let mut user = User::default();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -517,6 +517,54 @@ pub enum ErrorCode {
     UserReduceOnly,
     #[msg("InvalidMarginCalculation")]
     InvalidMarginCalculation,
+    #[msg("InvalidOraclePriceHistory")]
+    InvalidOraclePriceHistory,
+    #[msg("InvalidLinkedOrder")]
+    InvalidLinkedOrder,
+    #[msg("LinkedOrderNotActive")]
+    LinkedOrderNotActive,
+    #[msg("InvalidTwapOrder")]
+    InvalidTwapOrder,
+    #[msg("InvalidScaleOrderParams")]
+    InvalidScaleOrderParams,
+    #[msg("InvalidOrderFillOrKill")]
+    InvalidOrderFillOrKill,
+    #[msg("FillOrKillOrderNotFilled")]
+    FillOrKillOrderNotFilled,
+    #[msg("HeartbeatNotExpired")]
+    HeartbeatNotExpired,
+    #[msg("InvalidIcebergOrder")]
+    InvalidIcebergOrder,
+    #[msg("InvalidSignedQuote")]
+    InvalidSignedQuote,
+    #[msg("SignedQuoteExpired")]
+    SignedQuoteExpired,
+    #[msg("SignedQuoteNonceAlreadyUsed")]
+    SignedQuoteNonceAlreadyUsed,
+    #[msg("InvalidEd25519Instruction")]
+    InvalidEd25519Instruction,
+    #[msg("InvalidSignedOrder")]
+    InvalidSignedOrder,
+    #[msg("SignedOrderExpired")]
+    SignedOrderExpired,
+    #[msg("SignedOrderNonceAlreadyUsed")]
+    SignedOrderNonceAlreadyUsed,
+    #[msg("InvalidBatchAuction")]
+    InvalidBatchAuction,
+    #[msg("BatchAuctionNotReady")]
+    BatchAuctionNotReady,
+    #[msg("InvalidPerpOrderBookIndex")]
+    InvalidPerpOrderBookIndex,
+    #[msg("InvalidUserMigration")]
+    InvalidUserMigration,
+    #[msg("InvalidClosePositionOrder")]
+    InvalidClosePositionOrder,
+    #[msg("InvalidOrderMaxSlippage")]
+    InvalidOrderMaxSlippage,
+    #[msg("ClientOrderIdAlreadyInUse")]
+    ClientOrderIdAlreadyInUse,
+    #[msg("UserNotMigrated")]
+    UserNotMigrated,
 }
 
 #[macro_export]
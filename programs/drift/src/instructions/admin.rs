@@ -27,6 +27,9 @@ use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
 use crate::math::{amm, bn, oracle};
 use crate::math_error;
+use crate::state::composite_oracle::{
+    get_composite_oracle_price, CompositeOracle, CompositeOracleMode,
+};
 use crate::state::events::CurveRecord;
 use crate::state::fulfillment_params::phoenix::PhoenixMarketContext;
 use crate::state::fulfillment_params::phoenix::PhoenixV1FulfillmentConfig;
@@ -34,12 +37,18 @@ use crate::state::fulfillment_params::serum::SerumContext;
 use crate::state::fulfillment_params::serum::SerumV3FulfillmentConfig;
 use crate::state::insurance_fund_stake::ProtocolIfSharesTransferConfig;
 use crate::state::oracle::{
-    get_oracle_price, get_pyth_price, HistoricalIndexData, HistoricalOracleData, OraclePriceData,
+    get_oracle_feed_id, get_oracle_price, get_pyth_price, get_pyth_pull_price, get_pyth_pull_twap,
+    get_switchboard_price, HistoricalIndexData, HistoricalOracleData, OraclePriceData,
     OracleSource,
 };
+use crate::state::oracle_price_history::OraclePriceHistory;
+use crate::state::order_book_index::PerpOrderBookIndex;
 use crate::state::perp_market::{
     ContractTier, ContractType, InsuranceClaim, MarketStatus, PerpMarket, PoolBalance, AMM,
 };
+use crate::state::prelaunch_oracle::{
+    get_prelaunch_oracle_price, get_prelaunch_oracle_twap, PrelaunchOracle, PrelaunchOracleParams,
+};
 use crate::state::spot_market::{
     AssetTier, InsuranceFund, SpotBalanceType, SpotFulfillmentConfigStatus, SpotMarket,
 };
@@ -269,7 +278,9 @@ pub fn handle_initialize_spot_market(
         flash_loan_initial_token_amount: 0,
         total_swap_fee: 0,
         scale_initial_asset_weight_start: 0,
-        padding: [0; 48],
+        margin_confidence_multiplier: 0,
+        oracle_feed_id: get_oracle_feed_id(&oracle_source, &ctx.accounts.oracle)?,
+        padding: [0; 14],
         insurance_fund: InsuranceFund {
             vault: *ctx.accounts.insurance_fund_vault.to_account_info().key,
             unstaking_period: THIRTEEN_DAY,
@@ -564,8 +575,38 @@ pub fn handle_initialize_perp_market(
             (oracle_price, oracle_delay, QUOTE_PRECISION_I64)
         }
         OracleSource::Switchboard => {
-            msg!("Switchboard oracle cant be used for perp market");
-            return Err(ErrorCode::InvalidOracle.into());
+            let OraclePriceData {
+                price: oracle_price,
+                delay: oracle_delay,
+                ..
+            } = get_switchboard_price(&ctx.accounts.oracle, clock_slot)?;
+            (oracle_price, oracle_delay, oracle_price)
+        }
+        OracleSource::PythPull => {
+            let OraclePriceData {
+                price: oracle_price,
+                delay: oracle_delay,
+                ..
+            } = get_pyth_pull_price(&ctx.accounts.oracle, clock_slot, 1)?;
+            let last_oracle_price_twap = get_pyth_pull_twap(&ctx.accounts.oracle, 1)?;
+            (oracle_price, oracle_delay, last_oracle_price_twap)
+        }
+        OracleSource::Prelaunch => {
+            let OraclePriceData {
+                price: oracle_price,
+                delay: oracle_delay,
+                ..
+            } = get_prelaunch_oracle_price(&ctx.accounts.oracle, clock_slot)?;
+            let last_oracle_price_twap = get_prelaunch_oracle_twap(&ctx.accounts.oracle)?;
+            (oracle_price, oracle_delay, last_oracle_price_twap)
+        }
+        OracleSource::Composite => {
+            let OraclePriceData {
+                price: oracle_price,
+                delay: oracle_delay,
+                ..
+            } = get_composite_oracle_price(&ctx.accounts.oracle, clock_slot)?;
+            (oracle_price, oracle_delay, oracle_price)
         }
         OracleSource::QuoteAsset => {
             msg!("Quote asset oracle cant be used for perp market");
@@ -633,7 +674,10 @@ pub fn handle_initialize_perp_market(
         padding1: 0,
         quote_spot_market_index: 0,
         fee_adjustment: 0,
-        padding: [0; 46],
+        margin_confidence_multiplier: 0,
+        batch_auction_duration: 0,
+        last_batch_auction_clear_slot: 0,
+        oracle_feed_id: get_oracle_feed_id(&oracle_source, &ctx.accounts.oracle)?,
         amm: AMM {
             oracle: *ctx.accounts.oracle.key,
             oracle_source,
@@ -725,7 +769,9 @@ pub fn handle_initialize_perp_market(
             padding1: 0,
             padding2: 0,
             total_fee_earned_per_lp: 0,
-            padding: [0; 32],
+            max_inventory_skew: 0,
+            inventory_skew: 0,
+            padding: [0; 24],
         },
     };
 
@@ -785,6 +831,12 @@ pub fn handle_update_spot_market_oracle(
     let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
     let clock = Clock::get()?;
 
+    validate!(
+        ctx.accounts.oracle.key == &oracle,
+        ErrorCode::InvalidOracle,
+        "oracle account must be the new oracle"
+    )?;
+
     // Verify oracle is readable
     let OraclePriceData {
         price: _oracle_price,
@@ -794,6 +846,7 @@ pub fn handle_update_spot_market_oracle(
 
     spot_market.oracle = oracle;
     spot_market.oracle_source = oracle_source;
+    spot_market.oracle_feed_id = get_oracle_feed_id(&oracle_source, &ctx.accounts.oracle)?;
     Ok(())
 }
 
@@ -1711,6 +1764,23 @@ pub fn handle_update_spot_market_scale_initial_asset_weight_start(
     Ok(())
 }
 
+#[access_control(
+    spot_market_valid(&ctx.accounts.spot_market)
+)]
+pub fn handle_update_spot_market_margin_confidence_multiplier(
+    ctx: Context<AdminUpdateSpotMarket>,
+    margin_confidence_multiplier: u16,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    msg!(
+        "spot_market.margin_confidence_multiplier: {:?} -> {:?}",
+        spot_market.margin_confidence_multiplier,
+        margin_confidence_multiplier
+    );
+    spot_market.margin_confidence_multiplier = margin_confidence_multiplier;
+    Ok(())
+}
+
 #[access_control(
     spot_market_valid(&ctx.accounts.spot_market)
 )]
@@ -1972,6 +2042,12 @@ pub fn handle_update_perp_market_oracle(
     let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
     let clock = Clock::get()?;
 
+    validate!(
+        ctx.accounts.oracle.key == &oracle,
+        ErrorCode::InvalidOracle,
+        "oracle account must be the new oracle"
+    )?;
+
     // Verify oracle is readable
     let OraclePriceData {
         price: _oracle_price,
@@ -1981,6 +2057,7 @@ pub fn handle_update_perp_market_oracle(
 
     perp_market.amm.oracle = oracle;
     perp_market.amm.oracle_source = oracle_source;
+    perp_market.oracle_feed_id = get_oracle_feed_id(&oracle_source, &ctx.accounts.oracle)?;
 
     Ok(())
 }
@@ -2043,6 +2120,25 @@ pub fn handle_update_perp_market_max_spread(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_max_inventory_skew(
+    ctx: Context<AdminUpdatePerpMarket>,
+    max_inventory_skew: u32,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    validate!(
+        max_inventory_skew <= perp_market.amm.max_spread,
+        ErrorCode::DefaultError,
+        "invalid max_inventory_skew > max_spread",
+    )?;
+
+    perp_market.amm.max_inventory_skew = max_inventory_skew;
+
+    Ok(())
+}
+
 #[access_control(
     perp_market_valid(&ctx.accounts.perp_market)
 )]
@@ -2176,6 +2272,40 @@ pub fn handle_update_perp_market_fee_adjustment(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_margin_confidence_multiplier(
+    ctx: Context<AdminUpdatePerpMarket>,
+    margin_confidence_multiplier: u16,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.margin_confidence_multiplier: {:?} -> {:?}",
+        perp_market.margin_confidence_multiplier,
+        margin_confidence_multiplier
+    );
+    perp_market.margin_confidence_multiplier = margin_confidence_multiplier;
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_batch_auction_duration(
+    ctx: Context<AdminUpdatePerpMarket>,
+    batch_auction_duration: u32,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.batch_auction_duration: {:?} -> {:?}",
+        perp_market.batch_auction_duration,
+        batch_auction_duration
+    );
+    perp_market.batch_auction_duration = batch_auction_duration;
+    Ok(())
+}
+
 pub fn handle_update_admin(ctx: Context<AdminUpdateState>, admin: Pubkey) -> Result<()> {
     ctx.accounts.state.admin = admin;
     Ok(())
@@ -2309,6 +2439,101 @@ pub fn handle_update_protocol_if_shares_transfer_config(
     Ok(())
 }
 
+pub fn handle_initialize_prelaunch_oracle(
+    ctx: Context<InitializePrelaunchOracle>,
+    params: PrelaunchOracleParams,
+) -> Result<()> {
+    let mut oracle = ctx.accounts.prelaunch_oracle.load_init()?;
+    let clock = Clock::get()?;
+
+    validate!(
+        params.price.is_some(),
+        ErrorCode::DefaultError,
+        "prelaunch oracle must be initialized with a price"
+    )?;
+
+    oracle.perp_market_index = params.perp_market_index;
+    oracle.update_params(&params, clock.slot)?;
+
+    Ok(())
+}
+
+pub fn handle_update_prelaunch_oracle_params(
+    ctx: Context<UpdatePrelaunchOracleParams>,
+    params: PrelaunchOracleParams,
+) -> Result<()> {
+    let mut oracle = ctx.accounts.prelaunch_oracle.load_mut()?;
+    let clock = Clock::get()?;
+
+    validate!(
+        oracle.perp_market_index == params.perp_market_index,
+        ErrorCode::DefaultError,
+        "perp_market_index={} does not match prelaunch oracle perp_market_index={}",
+        params.perp_market_index,
+        oracle.perp_market_index
+    )?;
+
+    oracle.update_params(&params, clock.slot)?;
+
+    Ok(())
+}
+
+pub fn handle_initialize_composite_oracle(
+    ctx: Context<InitializeCompositeOracle>,
+    oracles: [Pubkey; 4],
+    oracle_sources: [OracleSource; 4],
+    mode: CompositeOracleMode,
+    min_valid_oracles: u8,
+) -> Result<()> {
+    let mut composite_oracle = ctx.accounts.composite_oracle.load_init()?;
+
+    composite_oracle.oracles = oracles;
+    composite_oracle.oracle_sources = oracle_sources;
+    composite_oracle.mode = mode;
+    composite_oracle.min_valid_oracles = min_valid_oracles;
+
+    composite_oracle.validate()?;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_initialize_oracle_price_history(
+    ctx: Context<InitializeOraclePriceHistory>,
+    min_sample_interval: i64,
+) -> Result<()> {
+    let perp_market = load!(ctx.accounts.perp_market)?;
+    let mut oracle_price_history = ctx.accounts.oracle_price_history.load_init()?;
+
+    validate!(
+        min_sample_interval >= 0,
+        ErrorCode::DefaultError,
+        "min_sample_interval must be non-negative"
+    )?;
+
+    oracle_price_history.perp_market_index = perp_market.market_index;
+    oracle_price_history.oracle = perp_market.amm.oracle;
+    oracle_price_history.min_sample_interval = min_sample_interval;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_initialize_perp_order_book_index(
+    ctx: Context<InitializePerpOrderBookIndex>,
+) -> Result<()> {
+    let perp_market = load!(ctx.accounts.perp_market)?;
+    let mut perp_order_book_index = ctx.accounts.perp_order_book_index.load_init()?;
+
+    perp_order_book_index.perp_market_index = perp_market.market_index;
+
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct Initialize<'info> {
     #[account(mut)]
@@ -2740,3 +2965,101 @@ pub struct UpdateProtocolIfSharesTransferConfig<'info> {
     )]
     pub state: Box<Account<'info, State>>,
 }
+
+#[derive(Accounts)]
+pub struct InitializeCompositeOracle<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        space = CompositeOracle::SIZE,
+        payer = admin
+    )]
+    pub composite_oracle: AccountLoader<'info, CompositeOracle>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: PrelaunchOracleParams,)]
+pub struct InitializePrelaunchOracle<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        seeds = [b"prelaunch_oracle".as_ref(), params.perp_market_index.to_le_bytes().as_ref()],
+        space = PrelaunchOracle::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub prelaunch_oracle: AccountLoader<'info, PrelaunchOracle>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: PrelaunchOracleParams,)]
+pub struct UpdatePrelaunchOracleParams<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"prelaunch_oracle".as_ref(), params.perp_market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub prelaunch_oracle: AccountLoader<'info, PrelaunchOracle>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeOraclePriceHistory<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        init,
+        seeds = [b"oracle_price_history".as_ref(), perp_market.load()?.market_index.to_le_bytes().as_ref()],
+        space = OraclePriceHistory::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub oracle_price_history: AccountLoader<'info, OraclePriceHistory>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePerpOrderBookIndex<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        init,
+        seeds = [b"perp_order_book_index".as_ref(), perp_market.load()?.market_index.to_le_bytes().as_ref()],
+        space = PerpOrderBookIndex::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub perp_order_book_index: AccountLoader<'info, PerpOrderBookIndex>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
@@ -2,26 +2,45 @@ use anchor_lang::accounts::account::Account;
 use anchor_lang::accounts::account_loader::AccountLoader;
 use anchor_lang::accounts::signer::Signer;
 use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::ToAccountInfo;
 
 use crate::error::ErrorCode;
+use crate::state::oracle::{validate_pyth_pull_feed_id, OracleSource};
 use crate::state::perp_market::{MarketStatus, PerpMarket};
+use crate::state::prelaunch_oracle::get_prelaunch_oracle_market_index;
 use crate::state::spot_market::SpotMarket;
 use crate::state::state::{ExchangeStatus, State};
-use crate::state::user::{User, UserStats};
+use crate::state::user::{validate_user_migrated, User, UserStats};
 use crate::validate;
 use solana_program::msg;
 
+pub fn is_user_migrated(user: &AccountLoader<User>) -> anchor_lang::Result<bool> {
+    validate_user_migrated(&user.to_account_info())?;
+    Ok(true)
+}
+
 pub fn can_sign_for_user(user: &AccountLoader<User>, signer: &Signer) -> anchor_lang::Result<bool> {
+    validate_user_migrated(&user.to_account_info())?;
     user.load().map(|user| {
         user.authority.eq(signer.key)
             || (user.delegate.eq(signer.key) && !user.delegate.eq(&Pubkey::default()))
     })
 }
 
+/// has_one = authority for users, which loads the user before any constraint could check it's migrated
+pub fn is_authority_for_user(
+    user: &AccountLoader<User>,
+    authority: &Signer,
+) -> anchor_lang::Result<bool> {
+    validate_user_migrated(&user.to_account_info())?;
+    user.load().map(|user| user.authority.eq(authority.key))
+}
+
 pub fn is_stats_for_user(
     user: &AccountLoader<User>,
     user_stats: &AccountLoader<UserStats>,
 ) -> anchor_lang::Result<bool> {
+    validate_user_migrated(&user.to_account_info())?;
     let user = user.load()?;
     let user_stats = user_stats.load()?;
     Ok(user_stats.authority.eq(&user.authority))
@@ -45,11 +64,17 @@ pub fn valid_oracle_for_spot_market(
     oracle: &AccountInfo,
     market: &AccountLoader<SpotMarket>,
 ) -> anchor_lang::Result<()> {
+    let market = market.load()?;
     validate!(
-        market.load()?.oracle.eq(oracle.key),
+        market.oracle.eq(oracle.key),
         ErrorCode::InvalidOracle,
         "not valid_oracle_for_spot_market"
     )?;
+
+    if market.oracle_source == OracleSource::PythPull {
+        validate_pyth_pull_feed_id(oracle, &market.oracle_feed_id)?;
+    }
+
     Ok(())
 }
 
@@ -57,11 +82,26 @@ pub fn valid_oracle_for_perp_market(
     oracle: &AccountInfo,
     market: &AccountLoader<PerpMarket>,
 ) -> anchor_lang::Result<()> {
+    let market = market.load()?;
     validate!(
-        market.load()?.amm.oracle.eq(oracle.key),
+        market.amm.oracle.eq(oracle.key),
         ErrorCode::InvalidOracle,
         "not valid_oracle_for_perp_market"
     )?;
+
+    if market.amm.oracle_source == OracleSource::PythPull {
+        validate_pyth_pull_feed_id(oracle, &market.oracle_feed_id)?;
+    }
+
+    if market.amm.oracle_source == OracleSource::Prelaunch {
+        validate!(
+            get_prelaunch_oracle_market_index(oracle)? == market.market_index,
+            ErrorCode::InvalidOracle,
+            "prelaunch oracle not for perp market {}",
+            market.market_index
+        )?;
+    }
+
     Ok(())
 }
 
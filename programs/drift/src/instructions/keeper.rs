@@ -1,39 +1,55 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions;
 use anchor_spl::token::{Token, TokenAccount};
 
 use crate::error::ErrorCode;
 use crate::instructions::constraints::*;
 use crate::instructions::optional_accounts::{
-    get_maker_and_maker_stats, get_referrer_and_referrer_stats, load_maps, AccountMaps,
+    get_maker_and_maker_stats, get_oracle_price_histories, get_referrer_and_referrer_stats,
+    load_maps, AccountMaps,
 };
+use crate::math::casting::Cast;
 use crate::math::constants::QUOTE_SPOT_MARKET_INDEX;
 use crate::math::insurance::if_shares_to_vault_amount;
 use crate::math::margin::calculate_user_equity;
 use crate::math::orders::{estimate_price_from_side, find_bids_and_asks_from_users};
 use crate::math::spot_withdraw::validate_spot_market_vault_amount;
+use crate::state::composite_oracle::CompositeOracle;
 use crate::state::fill_mode::FillMode;
 use crate::state::fulfillment_params::drift::MatchFulfillmentParams;
 use crate::state::fulfillment_params::phoenix::PhoenixFulfillmentParams;
 use crate::state::fulfillment_params::serum::SerumFulfillmentParams;
 use crate::state::insurance_fund_stake::InsuranceFundStake;
 use crate::state::oracle_map::OracleMap;
+use crate::state::order_book_index::PerpOrderBookIndex;
+use crate::state::order_params::{PlaceOrderOptions, PostOnlyParam, SignedOrderParamsMessage};
 use crate::state::perp_market::{MarketStatus, PerpMarket};
 use crate::state::perp_market_map::{
     get_market_set_for_user_positions, get_market_set_from_list, get_writable_perp_market_set,
     MarketSet, PerpMarketMap,
 };
+use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
+use crate::state::prelaunch_oracle::PrelaunchOracle;
 use crate::state::spot_fulfillment_params::SpotFulfillmentParams;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::{
     get_writable_spot_market_set, get_writable_spot_market_set_from_many,
 };
 use crate::state::state::State;
-use crate::state::user::{MarketType, OrderStatus, User, UserStats};
+use crate::state::user::{
+    validate_user_migrated, MarketType, OrderLinkType, OrderStatus, User, UserStats,
+};
 use crate::state::user_map::load_user_maps;
+use crate::validation::order::{
+    validate_fill_or_kill_order_filled, validate_fill_or_kill_order_params,
+};
+use crate::validation::sig_verification::{
+    get_preceding_ed25519_signer_and_message, validate_signed_order_params_message,
+};
 use crate::validation::user::validate_user_is_idle;
 use crate::{controller, load, math};
 use crate::{load_mut, QUOTE_PRECISION_U64};
-use crate::{validate, QUOTE_PRECISION_I128};
+use crate::{print_error, validate, QUOTE_PRECISION_I128};
 
 #[access_control(
     fill_not_paused(&ctx.accounts.state)
@@ -86,6 +102,7 @@ fn fill_order(ctx: Context<FillOrder>, order_id: u32, market_index: u16) -> Resu
 
     let (makers_and_referrer, makers_and_referrer_stats) =
         load_user_maps(remaining_accounts_iter, true)?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::repeg::update_amm(
         market_index,
@@ -103,6 +120,145 @@ fn fill_order(ctx: Context<FillOrder>, order_id: u32, market_index: u16) -> Resu
         &spot_market_map,
         &perp_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
+        &ctx.accounts.filler,
+        &ctx.accounts.filler_stats,
+        &makers_and_referrer,
+        &makers_and_referrer_stats,
+        None,
+        clock,
+        FillMode::Fill,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    fill_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_signed_taker_perp_order<'info>(
+    ctx: Context<PlaceSignedTakerOrder>,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    // the user's signature is verified by the ed25519 instruction right before this one
+    let signed_message = {
+        let (signer, message) =
+            get_preceding_ed25519_signer_and_message(ctx.accounts.instructions.as_ref())?;
+        let signed_message = SignedOrderParamsMessage::try_from_slice(&message)
+            .or(Err(ErrorCode::InvalidSignedOrder))?;
+
+        validate_signed_order_params_message(
+            &signed_message,
+            &signer,
+            &ctx.accounts.user.key(),
+            &*load!(ctx.accounts.user)?,
+            clock.slot,
+        )?;
+
+        signed_message
+    };
+
+    load_mut!(ctx.accounts.user_stats)?.use_signed_order_nonce(signed_message.nonce)?;
+
+    let mut params = signed_message.signed_order_params;
+
+    if params.post_only != PostOnlyParam::None {
+        msg!("post_only cant be used for signed taker orders");
+        return Err(print_error!(ErrorCode::InvalidOrderPostOnly)().into());
+    }
+
+    validate_fill_or_kill_order_params(&params, true)?;
+
+    // a keeper placing the order can't keep the user's heartbeat alive
+    let update_heartbeat = can_sign_for_user(&ctx.accounts.user, &ctx.accounts.authority)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &get_writable_perp_market_set(params.market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let (makers_and_referrer, makers_and_referrer_stats) =
+        load_user_maps(remaining_accounts_iter, true)?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    controller::repeg::update_amm(
+        params.market_index,
+        &perp_market_map,
+        &mut oracle_map,
+        state,
+        clock,
+    )?;
+
+    // take profit and stop loss are placed as pending children of the signed order
+    let trigger_order_params = if signed_message.has_trigger_orders() {
+        let link_id = load!(ctx.accounts.user)?.get_unused_link_id()?;
+        params.link_id = link_id;
+        params.link_type = OrderLinkType::Parent;
+        signed_message.get_trigger_order_params(link_id)
+    } else {
+        vec![]
+    };
+
+    controller::orders::place_perp_order(
+        state,
+        &ctx.accounts.user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+        params,
+        PlaceOrderOptions {
+            update_heartbeat,
+            ..PlaceOrderOptions::default()
+        },
+    )?;
+
+    let (order_id, order_base_asset_amount) = {
+        let user = load!(ctx.accounts.user)?;
+        let order_id = user.get_last_order_id();
+        let order_base_asset_amount = user
+            .get_order(order_id)
+            .map_or(0, |order| order.base_asset_amount);
+        (order_id, order_base_asset_amount)
+    };
+
+    for trigger_order_params in trigger_order_params {
+        controller::orders::place_perp_order(
+            state,
+            &ctx.accounts.user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &perp_order_book_index_map,
+            clock,
+            trigger_order_params,
+            PlaceOrderOptions {
+                update_heartbeat,
+                ..PlaceOrderOptions::default()
+            },
+        )?;
+    }
+
+    let base_asset_amount_filled = controller::orders::fill_perp_order(
+        order_id,
+        state,
+        &ctx.accounts.user,
+        &ctx.accounts.user_stats,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
         &ctx.accounts.filler,
         &ctx.accounts.filler_stats,
         &makers_and_referrer,
@@ -112,6 +268,97 @@ fn fill_order(ctx: Context<FillOrder>, order_id: u32, market_index: u16) -> Resu
         FillMode::Fill,
     )?;
 
+    if params.fill_or_kill {
+        validate_fill_or_kill_order_filled(base_asset_amount_filled, order_base_asset_amount)?;
+    }
+
+    let order_exists = load!(ctx.accounts.user)?
+        .orders
+        .iter()
+        .any(|order| order.order_id == order_id);
+
+    if params.immediate_or_cancel && order_exists {
+        controller::orders::cancel_order_by_order_id(
+            order_id,
+            &ctx.accounts.user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &perp_order_book_index_map,
+            clock,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[access_control(
+    fill_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_clear_perp_batch_auction<'info>(
+    ctx: Context<ClearPerpBatchAuction>,
+    market_index: u16,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    // every user with an order in the market's order book index, which holds the whole batch
+    let (users, users_stats) = load_user_maps(remaining_accounts_iter, true)?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    controller::repeg::update_amm(
+        market_index,
+        &perp_market_map,
+        &mut oracle_map,
+        state,
+        clock,
+    )?;
+
+    controller::orders::clear_perp_batch_auction(
+        market_index,
+        state,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        &users,
+        &users_stats,
+        &ctx.accounts.filler,
+        &ctx.accounts.filler_stats,
+        clock,
+    )?;
+
+    Ok(())
+}
+
+/// Resyncs the index with the orders of the users passed in remaining accounts, for users whose
+/// orders changed in an instruction the index wasn't passed to. Admin only, instructions that change
+/// orders sync the indexes passed to them
+pub fn handle_update_perp_order_book_index<'info>(
+    ctx: Context<UpdatePerpOrderBookIndex>,
+) -> Result<()> {
+    let perp_order_book_index = &mut load_mut!(ctx.accounts.perp_order_book_index)?;
+
+    for account_info in ctx.remaining_accounts.iter() {
+        validate_user_migrated(account_info)?;
+        let user: AccountLoader<User> =
+            AccountLoader::try_from(account_info).or(Err(ErrorCode::CouldNotLoadUserData))?;
+        perp_order_book_index.update_user_orders(account_info.key, &*load!(user)?)?;
+    }
+
     Ok(())
 }
 
@@ -276,17 +523,19 @@ fn fill_spot_order<'info>(
     exchange_not_paused(&ctx.accounts.state)
 )]
 pub fn handle_trigger_order<'info>(ctx: Context<TriggerOrder>, order_id: u32) -> Result<()> {
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         Clock::get()?.slot,
         None,
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     let market_type = match load!(ctx.accounts.user)?.get_order(order_id) {
         Some(order) => order.market_type,
@@ -304,6 +553,7 @@ pub fn handle_trigger_order<'info>(ctx: Context<TriggerOrder>, order_id: u32) ->
             &spot_market_map,
             &perp_market_map,
             &mut oracle_map,
+            &perp_order_book_index_map,
             &ctx.accounts.filler,
             &Clock::get()?,
         )?,
@@ -326,17 +576,19 @@ pub fn handle_trigger_order<'info>(ctx: Context<TriggerOrder>, order_id: u32) ->
     exchange_not_paused(&ctx.accounts.state)
 )]
 pub fn handle_force_cancel_orders<'info>(ctx: Context<ForceCancelOrder>) -> Result<()> {
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         Clock::get()?.slot,
         None,
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::orders::force_cancel_orders(
         &ctx.accounts.state,
@@ -344,6 +596,41 @@ pub fn handle_force_cancel_orders<'info>(ctx: Context<ForceCancelOrder>) -> Resu
         &spot_market_map,
         &perp_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
+        &ctx.accounts.filler,
+        &Clock::get()?,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_cancel_orders_on_expired_heartbeat<'info>(
+    ctx: Context<ForceCancelOrder>,
+) -> Result<()> {
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        Clock::get()?.slot,
+        None,
+    )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    controller::orders::cancel_orders_on_expired_heartbeat(
+        &ctx.accounts.state,
+        &ctx.accounts.user,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
         &ctx.accounts.filler,
         &Clock::get()?,
     )?;
@@ -603,17 +890,19 @@ pub fn handle_liquidate_perp(
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
     let liquidator_stats = &mut load_mut!(ctx.accounts.liquidator_stats)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &get_writable_perp_market_set(market_index),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::liquidation::liquidate_perp(
         market_index,
@@ -628,6 +917,7 @@ pub fn handle_liquidate_perp(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         slot,
         now,
         state,
@@ -661,17 +951,19 @@ pub fn handle_liquidate_spot(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set_from_many(vec![asset_market_index, liability_market_index]),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::liquidation::liquidate_spot(
         asset_market_index,
@@ -685,6 +977,7 @@ pub fn handle_liquidate_spot(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         now,
         clock.slot,
         state,
@@ -718,17 +1011,19 @@ pub fn handle_liquidate_borrow_for_perp_pnl(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(spot_market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::liquidation::liquidate_borrow_for_perp_pnl(
         perp_market_index,
@@ -742,6 +1037,7 @@ pub fn handle_liquidate_borrow_for_perp_pnl(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         now,
         clock.slot,
         state.liquidation_margin_buffer_ratio,
@@ -777,17 +1073,19 @@ pub fn handle_liquidate_perp_pnl_for_deposit(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(spot_market_index),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::liquidation::liquidate_perp_pnl_for_deposit(
         perp_market_index,
@@ -801,6 +1099,7 @@ pub fn handle_liquidate_perp_pnl_for_deposit(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         now,
         clock.slot,
         state.liquidation_margin_buffer_ratio,
@@ -1167,6 +1466,17 @@ pub fn handle_update_funding_rate(
     let oracle_price_data = &oracle_map.get_price_data(&perp_market.amm.oracle)?;
     controller::repeg::_update_amm(perp_market, oracle_price_data, state, now, clock_slot)?;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    for oracle_price_history in get_oracle_price_histories(remaining_accounts_iter)? {
+        controller::repeg::update_oracle_price_history(
+            &mut load_mut!(oracle_price_history)?,
+            perp_market,
+            oracle_price_data,
+            &state.oracle_guard_rails,
+            now,
+        )?;
+    }
+
     validate!(
         matches!(perp_market.status, MarketStatus::Active),
         ErrorCode::MarketActionPaused,
@@ -1413,9 +1723,81 @@ pub fn handle_update_amms(ctx: Context<UpdateAMM>, market_indexes: [u16; 5]) ->
         &get_market_set_from_list(market_indexes),
         remaining_accounts_iter,
     )?;
+    oracle_map.validate_perp_market_pyth_pull_feed_ids(market_map)?;
 
     controller::repeg::update_amms(market_map, oracle_map, state, &clock)?;
 
+    // optional oracle price histories after the markets
+    for oracle_price_history in get_oracle_price_histories(remaining_accounts_iter)? {
+        let oracle_price_history = &mut load_mut!(oracle_price_history)?;
+        let mut market = market_map.get_ref_mut(&oracle_price_history.perp_market_index)?;
+        let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+        controller::repeg::update_oracle_price_history(
+            oracle_price_history,
+            &mut market,
+            oracle_price_data,
+            &state.oracle_guard_rails,
+            clock.unix_timestamp,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_prelaunch_oracle(ctx: Context<UpdatePrelaunchOracle>) -> Result<()> {
+    let clock = Clock::get()?;
+    let perp_market = load!(ctx.accounts.perp_market)?;
+    let oracle = &mut load_mut!(ctx.accounts.prelaunch_oracle)?;
+
+    validate!(
+        perp_market.amm.oracle == ctx.accounts.prelaunch_oracle.key(),
+        ErrorCode::InvalidOracle,
+        "prelaunch oracle is not the perp market oracle"
+    )?;
+
+    let last_mark_price_twap = perp_market.amm.last_mark_price_twap.cast::<i64>()?;
+    oracle.update_price(last_mark_price_twap, clock.slot)?;
+
+    Ok(())
+}
+
+pub fn handle_set_prelaunch_oracle_price(
+    ctx: Context<SetPrelaunchOraclePrice>,
+    price: i64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let oracle = &mut load_mut!(ctx.accounts.prelaunch_oracle)?;
+
+    oracle.update_price(price, clock.slot)?;
+
+    Ok(())
+}
+
+pub fn handle_update_composite_oracle(ctx: Context<UpdateCompositeOracle>) -> Result<()> {
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+    let composite_oracle = &mut load_mut!(ctx.accounts.composite_oracle)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let oracle_map = OracleMap::load(
+        remaining_accounts_iter,
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let oracle_price_data = match oracle_map.get_live_composite_price_data(composite_oracle)? {
+        Some(oracle_price_data) => oracle_price_data,
+        None => {
+            msg!("Must pass every underlying oracle of the composite oracle");
+            return Err(ErrorCode::OracleNotFound.into());
+        }
+    };
+
+    composite_oracle.update_cached_price_data(&oracle_price_data, clock.slot)?;
+
     Ok(())
 }
 
@@ -1455,13 +1837,72 @@ pub struct FillOrder<'info> {
         constraint = is_stats_for_user(&filler, &filler_stats)?
     )]
     pub filler_stats: AccountLoader<'info, UserStats>,
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&user, &user_stats)?
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+}
+
+#[derive(Accounts)]
+pub struct ClearPerpBatchAuction<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&filler, &authority)?
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&filler, &filler_stats)?
+    )]
+    pub filler_stats: AccountLoader<'info, UserStats>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePerpOrderBookIndex<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
     #[account(mut)]
+    pub perp_order_book_index: AccountLoader<'info, PerpOrderBookIndex>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceSignedTakerOrder<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&filler, &authority)?
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&filler, &filler_stats)?
+    )]
+    pub filler_stats: AccountLoader<'info, UserStats>,
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
     #[account(
         mut,
         constraint = is_stats_for_user(&user, &user_stats)?
     )]
     pub user_stats: AccountLoader<'info, UserStats>,
+    /// CHECK: fixed instructions sysvar account
+    #[account(address = instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
@@ -1489,7 +1930,10 @@ pub struct TriggerOrder<'info> {
         constraint = can_sign_for_user(&filler, &authority)?
     )]
     pub filler: AccountLoader<'info, User>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
 }
 
@@ -1502,7 +1946,10 @@ pub struct ForceCancelOrder<'info> {
         constraint = can_sign_for_user(&filler, &authority)?
     )]
     pub filler: AccountLoader<'info, User>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
 }
 
@@ -1515,14 +1962,20 @@ pub struct UpdateUserIdle<'info> {
         constraint = can_sign_for_user(&filler, &authority)?
     )]
     pub filler: AccountLoader<'info, User>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
 }
 
 #[derive(Accounts)]
 pub struct SettlePNL<'info> {
     pub state: Box<Account<'info, State>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
     pub authority: Signer<'info>,
     #[account(
@@ -1535,14 +1988,20 @@ pub struct SettlePNL<'info> {
 #[derive(Accounts)]
 pub struct SettleFunding<'info> {
     pub state: Box<Account<'info, State>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
 }
 
 #[derive(Accounts)]
 pub struct SettleLP<'info> {
     pub state: Box<Account<'info, State>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
 }
 
@@ -1560,7 +2019,10 @@ pub struct LiquidatePerp<'info> {
         constraint = is_stats_for_user(&liquidator, &liquidator_stats)?
     )]
     pub liquidator_stats: AccountLoader<'info, UserStats>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
     #[account(
         mut,
@@ -1583,7 +2045,10 @@ pub struct LiquidateSpot<'info> {
         constraint = is_stats_for_user(&liquidator, &liquidator_stats)?
     )]
     pub liquidator_stats: AccountLoader<'info, UserStats>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
     #[account(
         mut,
@@ -1606,7 +2071,10 @@ pub struct LiquidateBorrowForPerpPnl<'info> {
         constraint = is_stats_for_user(&liquidator, &liquidator_stats)?
     )]
     pub liquidator_stats: AccountLoader<'info, UserStats>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
     #[account(
         mut,
@@ -1629,7 +2097,10 @@ pub struct LiquidatePerpPnlForDeposit<'info> {
         constraint = is_stats_for_user(&liquidator, &liquidator_stats)?
     )]
     pub liquidator_stats: AccountLoader<'info, UserStats>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
     #[account(
         mut,
@@ -1653,7 +2124,10 @@ pub struct ResolveBankruptcy<'info> {
         constraint = is_stats_for_user(&liquidator, &liquidator_stats)?
     )]
     pub liquidator_stats: AccountLoader<'info, UserStats>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
     #[account(
         mut,
@@ -1775,6 +2249,35 @@ pub struct UpdatePerpBidAskTwap<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct UpdatePrelaunchOracle<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        mut,
+        seeds = [b"prelaunch_oracle".as_ref(), perp_market.load()?.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub prelaunch_oracle: AccountLoader<'info, PrelaunchOracle>,
+}
+
+#[derive(Accounts)]
+pub struct SetPrelaunchOraclePrice<'info> {
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub prelaunch_oracle: AccountLoader<'info, PrelaunchOracle>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCompositeOracle<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub composite_oracle: AccountLoader<'info, CompositeOracle>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateUserQuoteAssetInsuranceStake<'info> {
     pub state: Box<Account<'info, State>>,
@@ -2,6 +2,7 @@ use crate::error::{DriftResult, ErrorCode};
 
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::oracle_map::OracleMap;
+use crate::state::oracle_price_history::OraclePriceHistory;
 use crate::state::perp_market_map::{MarketSet, PerpMarketMap};
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::OracleGuardRails;
@@ -36,6 +37,9 @@ pub fn load_maps<'a, 'b>(
     let spot_market_map = SpotMarketMap::load(writable_spot_markets, account_info_iter)?;
     let perp_market_map = PerpMarketMap::load(writable_perp_markets, account_info_iter)?;
 
+    oracle_map.validate_perp_market_pyth_pull_feed_ids(&perp_market_map)?;
+    oracle_map.validate_spot_market_pyth_pull_feed_ids(&spot_market_map)?;
+
     Ok(AccountMaps {
         perp_market_map,
         spot_market_map,
@@ -54,6 +58,13 @@ pub fn get_maker_and_maker_stats<'a>(
         ErrorCode::MakerMustBeWritable
     )?;
 
+    validate!(
+        maker_account_info.data_len() >= User::SIZE,
+        ErrorCode::UserNotMigrated,
+        "maker {} has not been migrated, resize it with migrate_user",
+        maker_account_info.key
+    )?;
+
     let maker: AccountLoader<User> =
         AccountLoader::try_from(maker_account_info).or(Err(ErrorCode::CouldNotDeserializeMaker))?;
 
@@ -91,7 +102,7 @@ pub fn get_referrer_and_referrer_stats<'a>(
         ErrorCode::CouldNotDeserializeReferrer
     })?;
 
-    if data.len() < User::SIZE {
+    if data.len() < 8 {
         return Ok((None, None));
     }
 
@@ -101,6 +112,14 @@ pub fn get_referrer_and_referrer_stats<'a>(
         return Ok((None, None));
     }
 
+    if data.len() < User::SIZE {
+        msg!(
+            "referrer {} has not been migrated, resize it with migrate_user",
+            referrer_account_info.key
+        );
+        return Err(ErrorCode::UserNotMigrated);
+    }
+
     let referrer_account_info = next_account_info(account_info_iter).safe_unwrap()?;
 
     validate!(
@@ -165,3 +184,40 @@ pub fn get_whitelist_token<'a>(
 
     Ok(whitelist_token)
 }
+
+pub fn get_oracle_price_histories<'a>(
+    account_info_iter: &mut Peekable<Iter<AccountInfo<'a>>>,
+) -> DriftResult<Vec<AccountLoader<'a, OraclePriceHistory>>> {
+    let mut oracle_price_histories = Vec::new();
+
+    let oracle_price_history_discriminator: [u8; 8] = OraclePriceHistory::discriminator();
+    while let Some(account_info) = account_info_iter.peek() {
+        let data = account_info
+            .try_borrow_data()
+            .or(Err(ErrorCode::InvalidOraclePriceHistory))?;
+
+        if data.len() < OraclePriceHistory::SIZE {
+            break;
+        }
+
+        let account_discriminator = array_ref![data, 0, 8];
+        if account_discriminator != &oracle_price_history_discriminator {
+            break;
+        }
+
+        let account_info = account_info_iter.next().safe_unwrap()?;
+
+        validate!(
+            account_info.is_writable,
+            ErrorCode::InvalidOraclePriceHistory,
+            "oracle price history must be writable"
+        )?;
+
+        let oracle_price_history: AccountLoader<OraclePriceHistory> =
+            AccountLoader::try_from(account_info).or(Err(ErrorCode::InvalidOraclePriceHistory))?;
+
+        oracle_price_histories.push(oracle_price_history);
+    }
+
+    Ok(oracle_price_histories)
+}
@@ -9,7 +9,7 @@ use crate::controller::spot_position::{
     charge_withdraw_fee, update_spot_balances_and_cumulative_deposits,
     update_spot_balances_and_cumulative_deposits_with_limits,
 };
-use crate::error::ErrorCode;
+use crate::error::{DriftResult, ErrorCode};
 use crate::ids::{
     jupiter_mainnet_3, jupiter_mainnet_4, jupiter_mainnet_6, marinade_mainnet, serum_program,
 };
@@ -44,22 +44,34 @@ use crate::state::fulfillment_params::drift::MatchFulfillmentParams;
 use crate::state::fulfillment_params::phoenix::PhoenixFulfillmentParams;
 use crate::state::fulfillment_params::serum::SerumFulfillmentParams;
 use crate::state::oracle::StrictOraclePrice;
+use crate::state::oracle_map::OracleMap;
 use crate::state::order_params::{
-    ModifyOrderParams, OrderParams, PlaceOrderOptions, PostOnlyParam,
+    BatchModifyOrderParams, ClientOrderIdPrefix, ModifyOrderParams, OrderParams, PlaceOrderOptions,
+    PostOnlyParam, ScaleOrderParams, SignedQuote,
 };
 use crate::state::perp_market::MarketStatus;
-use crate::state::perp_market_map::{get_writable_perp_market_set, MarketSet};
+use crate::state::perp_market_map::{get_writable_perp_market_set, MarketSet, PerpMarketMap};
+use crate::state::perp_order_book_index_map::PerpOrderBookIndexMap;
 use crate::state::spot_fulfillment_params::SpotFulfillmentParams;
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::{
-    get_writable_spot_market_set, get_writable_spot_market_set_from_many,
+    get_writable_spot_market_set, get_writable_spot_market_set_from_many, SpotMarketMap,
 };
 use crate::state::state::State;
 use crate::state::traits::Size;
-use crate::state::user::{MarketType, OrderType, ReferrerName, User, UserStats};
+use crate::state::user::{
+    migrate_legacy_user_data, MarketType, OrderType, ReferrerName, SelfTradePreventionMode, User,
+    UserStats, LEGACY_USER_SIZE,
+};
 use crate::state::user_map::load_user_maps;
 use crate::validate;
+use crate::validation::order::{
+    validate_fill_or_kill_order_filled, validate_fill_or_kill_order_params,
+};
+use crate::validation::sig_verification::{
+    get_preceding_ed25519_signer_and_message, validate_signed_quote,
+};
 use crate::validation::user::validate_user_deletion;
 use crate::validation::whitelist::validate_whitelist_token;
 use crate::{controller, math};
@@ -745,29 +757,34 @@ pub fn handle_place_perp_order(ctx: Context<PlaceOrder>, params: OrderParams) ->
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     if params.immediate_or_cancel {
         msg!("immediate_or_cancel order must be in place_and_make or place_and_take");
         return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
     }
 
+    validate_fill_or_kill_order_params(&params, false)?;
+
     controller::orders::place_perp_order(
         &ctx.accounts.state,
         &ctx.accounts.user,
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         clock,
         params,
         PlaceOrderOptions::default(),
@@ -783,17 +800,19 @@ pub fn handle_cancel_order(ctx: Context<CancelOrder>, order_id: Option<u32>) ->
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     let order_id = match order_id {
         Some(order_id) => order_id,
@@ -806,6 +825,7 @@ pub fn handle_cancel_order(ctx: Context<CancelOrder>, order_id: Option<u32>) ->
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         clock,
     )?;
 
@@ -819,17 +839,19 @@ pub fn handle_cancel_order_by_user_id(ctx: Context<CancelOrder>, user_order_id:
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::orders::cancel_order_by_user_order_id(
         user_order_id,
@@ -837,6 +859,44 @@ pub fn handle_cancel_order_by_user_id(ctx: Context<CancelOrder>, user_order_id:
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_cancel_order_by_client_order_id(
+    ctx: Context<CancelOrder>,
+    client_order_id: u64,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    controller::orders::cancel_order_by_client_order_id(
+        client_order_id,
+        &ctx.accounts.user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
         clock,
     )?;
 
@@ -850,17 +910,19 @@ pub fn handle_cancel_orders_by_ids(ctx: Context<CancelOrder>, order_ids: Vec<u32
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     for order_id in order_ids {
         controller::orders::cancel_order_by_order_id(
@@ -869,6 +931,7 @@ pub fn handle_cancel_orders_by_ids(ctx: Context<CancelOrder>, order_ids: Vec<u32
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &perp_order_book_index_map,
             clock,
         )?;
     }
@@ -888,17 +951,19 @@ pub fn handle_cancel_orders(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     let user_key = ctx.accounts.user.key();
     let mut user = load_mut!(ctx.accounts.user)?;
@@ -910,14 +975,70 @@ pub fn handle_cancel_orders(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         clock.unix_timestamp,
         clock.slot,
         OrderActionExplanation::None,
         market_type,
         market_index,
         direction,
+        None,
     )?;
 
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_cancel_orders_by_client_order_id_prefix(
+    ctx: Context<CancelOrder>,
+    client_order_id_prefix: ClientOrderIdPrefix,
+    market_type: Option<MarketType>,
+    market_index: Option<u16>,
+    direction: Option<PositionDirection>,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    let user_key = ctx.accounts.user.key();
+    let mut user = load_mut!(ctx.accounts.user)?;
+
+    cancel_orders(
+        &mut user,
+        &user_key,
+        None,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        clock.unix_timestamp,
+        clock.slot,
+        OrderActionExplanation::None,
+        market_type,
+        market_index,
+        direction,
+        Some(client_order_id_prefix),
+    )?;
+
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+
     Ok(())
 }
 
@@ -932,17 +1053,19 @@ pub fn handle_modify_order(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     let order_id = match order_id {
         Some(order_id) => order_id,
@@ -957,6 +1080,7 @@ pub fn handle_modify_order(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         clock,
     )?;
 
@@ -974,17 +1098,19 @@ pub fn handle_modify_order_by_user_order_id(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     controller::orders::modify_order(
         ModifyOrderId::UserOrderId(user_order_id),
@@ -994,6 +1120,91 @@ pub fn handle_modify_order_by_user_order_id(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_modify_order_by_client_order_id(
+    ctx: Context<CancelOrder>,
+    client_order_id: u64,
+    modify_order_params: ModifyOrderParams,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    controller::orders::modify_order(
+        ModifyOrderId::ClientOrderId(client_order_id),
+        modify_order_params,
+        &ctx.accounts.user,
+        state,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_modify_orders(
+    ctx: Context<CancelOrder>,
+    params: Vec<BatchModifyOrderParams>,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    validate!(
+        params.len() <= 32,
+        ErrorCode::DefaultError,
+        "max 32 modify order params"
+    )?;
+
+    controller::orders::modify_orders(
+        &params,
+        &ctx.accounts.user,
+        state,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
         clock,
     )?;
 
@@ -1007,17 +1218,19 @@ pub fn handle_place_orders(ctx: Context<PlaceOrder>, params: Vec<OrderParams>) -
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     validate!(
         params.len() <= 32,
@@ -1025,6 +1238,103 @@ pub fn handle_place_orders(ctx: Context<PlaceOrder>, params: Vec<OrderParams>) -
         "max 32 order params"
     )?;
 
+    place_orders(
+        state,
+        &ctx.accounts.user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+        &params,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_scale_orders(ctx: Context<PlaceOrder>, params: ScaleOrderParams) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
+
+    let (step_size, tick_size) = match params.market_type {
+        MarketType::Perp => {
+            let perp_market = perp_market_map.get_ref(&params.market_index)?;
+            (
+                perp_market.amm.order_step_size,
+                perp_market.amm.order_tick_size,
+            )
+        }
+        MarketType::Spot => {
+            let spot_market = spot_market_map.get_ref(&params.market_index)?;
+            (spot_market.order_step_size, spot_market.order_tick_size)
+        }
+    };
+
+    let order_params = params.get_order_params(step_size, tick_size)?;
+
+    if params.cancel_existing_orders {
+        let user_key = ctx.accounts.user.key();
+        let mut user = load_mut!(ctx.accounts.user)?;
+
+        cancel_orders(
+            &mut user,
+            &user_key,
+            None,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &perp_order_book_index_map,
+            clock.unix_timestamp,
+            clock.slot,
+            OrderActionExplanation::None,
+            Some(params.market_type),
+            Some(params.market_index),
+            Some(params.direction),
+            None,
+        )?;
+    }
+
+    place_orders(
+        state,
+        &ctx.accounts.user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+        &order_params,
+    )?;
+
+    Ok(())
+}
+
+fn place_orders(
+    state: &State,
+    user: &AccountLoader<User>,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    perp_order_book_index_map: &PerpOrderBookIndexMap,
+    clock: &Clock,
+    params: &[OrderParams],
+) -> DriftResult {
     let num_orders = params.len();
     for (i, params) in params.iter().enumerate() {
         validate!(
@@ -1033,31 +1343,35 @@ pub fn handle_place_orders(ctx: Context<PlaceOrder>, params: Vec<OrderParams>) -
             "immediate_or_cancel order must be in place_and_make or place_and_take"
         )?;
 
+        validate_fill_or_kill_order_params(params, false)?;
+
         // only enforce margin on last order and only try to expire on first order
         let options = PlaceOrderOptions {
             enforce_margin_check: i == num_orders - 1,
             try_expire_orders: i == 0,
             risk_increasing: false,
+            update_heartbeat: true,
         };
 
         if params.market_type == MarketType::Perp {
             controller::orders::place_perp_order(
-                &ctx.accounts.state,
-                &ctx.accounts.user,
-                &perp_market_map,
-                &spot_market_map,
-                &mut oracle_map,
+                state,
+                user,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                perp_order_book_index_map,
                 clock,
                 *params,
                 options,
             )?;
         } else {
             controller::orders::place_spot_order(
-                &ctx.accounts.state,
-                &ctx.accounts.user,
-                &perp_market_map,
-                &spot_market_map,
-                &mut oracle_map,
+                state,
+                user,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
                 clock,
                 *params,
                 options,
@@ -1097,10 +1411,14 @@ pub fn handle_place_and_take_perp_order<'info>(
         return Err(print_error!(ErrorCode::InvalidOrderPostOnly)().into());
     }
 
+    validate_fill_or_kill_order_params(&params, true)?;
+
     let (makers_and_referrer, makers_and_referrer_stats) =
         load_user_maps(remaining_accounts_iter, true)?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
 
     let is_immediate_or_cancel = params.immediate_or_cancel;
+    let is_fill_or_kill = params.fill_or_kill;
 
     controller::repeg::update_amm(
         params.market_index,
@@ -1116,15 +1434,23 @@ pub fn handle_place_and_take_perp_order<'info>(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         &Clock::get()?,
         params,
         PlaceOrderOptions::default(),
     )?;
 
     let user = &mut ctx.accounts.user;
-    let order_id = load!(user)?.get_last_order_id();
+    let (order_id, order_base_asset_amount) = {
+        let user = load!(user)?;
+        let order_id = user.get_last_order_id();
+        let order_base_asset_amount = user
+            .get_order(order_id)
+            .map_or(0, |order| order.base_asset_amount);
+        (order_id, order_base_asset_amount)
+    };
 
-    controller::orders::fill_perp_order(
+    let base_asset_amount_filled = controller::orders::fill_perp_order(
         order_id,
         &ctx.accounts.state,
         user,
@@ -1132,6 +1458,7 @@ pub fn handle_place_and_take_perp_order<'info>(
         &spot_market_map,
         &perp_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         &user.clone(),
         &ctx.accounts.user_stats.clone(),
         &makers_and_referrer,
@@ -1141,6 +1468,10 @@ pub fn handle_place_and_take_perp_order<'info>(
         FillMode::PlaceAndTake,
     )?;
 
+    if is_fill_or_kill {
+        validate_fill_or_kill_order_filled(base_asset_amount_filled, order_base_asset_amount)?;
+    }
+
     let order_exists = load!(ctx.accounts.user)?
         .orders
         .iter()
@@ -1153,6 +1484,7 @@ pub fn handle_place_and_take_perp_order<'info>(
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &perp_order_book_index_map,
             &Clock::get()?,
         )?;
     }
@@ -1192,6 +1524,8 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
         return Err(print_error!(ErrorCode::InvalidOrderIOCPostOnly)().into());
     }
 
+    validate_fill_or_kill_order_params(&params, false)?;
+
     controller::repeg::update_amm(
         params.market_index,
         &perp_market_map,
@@ -1206,6 +1540,7 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
         &perp_market_map,
         &spot_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         clock,
         params,
         PlaceOrderOptions::default(),
@@ -1219,6 +1554,7 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
 
     let (mut makers_and_referrer, mut makers_and_referrer_stats) =
         load_user_maps(remaining_accounts_iter, true)?;
+    let perp_order_book_index_map = PerpOrderBookIndexMap::load(remaining_accounts_iter)?;
     makers_and_referrer.insert(ctx.accounts.user.key(), ctx.accounts.user.clone())?;
     makers_and_referrer_stats.insert(authority, ctx.accounts.user_stats.clone())?;
 
@@ -1230,6 +1566,7 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
         &spot_market_map,
         &perp_market_map,
         &mut oracle_map,
+        &perp_order_book_index_map,
         &ctx.accounts.user.clone(),
         &ctx.accounts.user_stats.clone(),
         &makers_and_referrer,
@@ -1251,6 +1588,7 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &perp_order_book_index_map,
             clock,
         )?;
     }
@@ -1258,6 +1596,175 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
     Ok(())
 }
 
+#[access_control(
+    fill_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_and_take_signed_quote_perp_order<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, PlaceAndTakeSignedQuote<'info>>,
+    params: OrderParams,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    // the maker's signature is verified by the ed25519 instruction right before this one
+    let quote = {
+        let (signer, message) =
+            get_preceding_ed25519_signer_and_message(ctx.accounts.instructions.as_ref())?;
+        let quote = SignedQuote::try_from_slice(&message).or(Err(ErrorCode::InvalidSignedQuote))?;
+
+        validate_signed_quote(
+            &quote,
+            &signer,
+            &ctx.accounts.maker.key(),
+            &*load!(ctx.accounts.maker)?,
+            &ctx.accounts.user.key(),
+            &params,
+            clock.unix_timestamp,
+        )?;
+
+        quote
+    };
+
+    load_mut!(ctx.accounts.maker_stats)?.use_quote_nonce(quote.nonce)?;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &get_writable_perp_market_set(params.market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    if !params.immediate_or_cancel || params.post_only != PostOnlyParam::None {
+        msg!("signed quote taker order must be immediate or cancel and not post only");
+        return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
+    }
+
+    if params.order_type == OrderType::Twap {
+        msg!("signed quote taker order cant be a twap order");
+        return Err(print_error!(ErrorCode::InvalidTwapOrder)().into());
+    }
+
+    if perp_market_map
+        .get_ref(&params.market_index)?
+        .is_batch_auction_market()
+    {
+        msg!("signed quotes cant fill in batch auction markets");
+        return Err(print_error!(ErrorCode::InvalidBatchAuction)().into());
+    }
+
+    let is_fill_or_kill = params.fill_or_kill;
+
+    // the quote and taker order don't outlive the instruction, so neither is indexed
+    let perp_order_book_index_map = PerpOrderBookIndexMap::empty();
+
+    controller::repeg::update_amm(
+        params.market_index,
+        &perp_market_map,
+        &mut oracle_map,
+        state,
+        clock,
+    )?;
+
+    controller::orders::place_perp_order(
+        state,
+        &ctx.accounts.maker,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+        quote.get_maker_order_params(),
+        PlaceOrderOptions {
+            // a taker holding the quote can't keep the maker's heartbeat alive
+            update_heartbeat: can_sign_for_user(&ctx.accounts.maker, &ctx.accounts.authority)?,
+            ..PlaceOrderOptions::default()
+        },
+    )?;
+
+    let (maker_order_id, maker_authority) = {
+        let maker = load!(ctx.accounts.maker)?;
+        (maker.get_last_order_id(), maker.authority)
+    };
+
+    controller::orders::place_perp_order(
+        state,
+        &ctx.accounts.user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        clock,
+        params,
+        PlaceOrderOptions::default(),
+    )?;
+
+    let (order_id, order_base_asset_amount) = {
+        let user = load!(ctx.accounts.user)?;
+        let order_id = user.get_last_order_id();
+        let order_base_asset_amount = user
+            .get_order(order_id)
+            .map_or(0, |order| order.base_asset_amount);
+        (order_id, order_base_asset_amount)
+    };
+
+    let (mut makers_and_referrer, mut makers_and_referrer_stats) =
+        load_user_maps(remaining_accounts_iter, true)?;
+    makers_and_referrer.insert(ctx.accounts.maker.key(), ctx.accounts.maker.clone())?;
+    makers_and_referrer_stats.insert(maker_authority, ctx.accounts.maker_stats.clone())?;
+
+    let base_asset_amount_filled = controller::orders::fill_perp_order(
+        order_id,
+        state,
+        &ctx.accounts.user,
+        &ctx.accounts.user_stats,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &perp_order_book_index_map,
+        &ctx.accounts.user.clone(),
+        &ctx.accounts.user_stats.clone(),
+        &makers_and_referrer,
+        &makers_and_referrer_stats,
+        Some(maker_order_id),
+        clock,
+        FillMode::SignedQuote,
+    )?;
+
+    if is_fill_or_kill {
+        validate_fill_or_kill_order_filled(base_asset_amount_filled, order_base_asset_amount)?;
+    }
+
+    for (user, order_id) in [
+        (&ctx.accounts.user, order_id),
+        (&ctx.accounts.maker, maker_order_id),
+    ] {
+        let order_exists = load!(user)?
+            .orders
+            .iter()
+            .any(|order| order.order_id == order_id);
+
+        if order_exists {
+            controller::orders::cancel_order_by_order_id(
+                order_id,
+                user,
+                &perp_market_map,
+                &spot_market_map,
+                &mut oracle_map,
+                &perp_order_book_index_map,
+                clock,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
 pub fn handle_place_spot_order(ctx: Context<PlaceOrder>, params: OrderParams) -> Result<()> {
     let AccountMaps {
         perp_market_map,
@@ -1276,6 +1783,8 @@ pub fn handle_place_spot_order(ctx: Context<PlaceOrder>, params: OrderParams) ->
         return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
     }
 
+    validate_fill_or_kill_order_params(&params, false)?;
+
     controller::orders::place_spot_order(
         &ctx.accounts.state,
         &ctx.accounts.user,
@@ -1320,6 +1829,8 @@ pub fn handle_place_and_take_spot_order<'info>(
         return Err(print_error!(ErrorCode::InvalidOrderPostOnly)().into());
     }
 
+    validate_fill_or_kill_order_params(&params, true)?;
+
     let (maker, maker_stats) = match maker_order_id {
         Some(_) => {
             let (user, user_stats) = get_maker_and_maker_stats(remaining_accounts_iter)?;
@@ -1331,6 +1842,7 @@ pub fn handle_place_and_take_spot_order<'info>(
     let (_referrer, _referrer_stats) = get_referrer_and_referrer_stats(remaining_accounts_iter)?;
 
     let is_immediate_or_cancel = params.immediate_or_cancel;
+    let is_fill_or_kill = params.fill_or_kill;
 
     let mut fulfillment_params: Box<dyn SpotFulfillmentParams> = match fulfillment_type {
         SpotFulfillmentType::SerumV3 => {
@@ -1377,9 +1889,16 @@ pub fn handle_place_and_take_spot_order<'info>(
     )?;
 
     let user = &mut ctx.accounts.user;
-    let order_id = load!(user)?.get_last_order_id();
+    let (order_id, order_base_asset_amount) = {
+        let user = load!(user)?;
+        let order_id = user.get_last_order_id();
+        let order_base_asset_amount = user
+            .get_order(order_id)
+            .map_or(0, |order| order.base_asset_amount);
+        (order_id, order_base_asset_amount)
+    };
 
-    controller::orders::fill_spot_order(
+    let base_asset_amount_filled = controller::orders::fill_spot_order(
         order_id,
         &ctx.accounts.state,
         user,
@@ -1396,6 +1915,10 @@ pub fn handle_place_and_take_spot_order<'info>(
         fulfillment_params.as_mut(),
     )?;
 
+    if is_fill_or_kill {
+        validate_fill_or_kill_order_filled(base_asset_amount_filled, order_base_asset_amount)?;
+    }
+
     let order_exists = load!(ctx.accounts.user)?
         .orders
         .iter()
@@ -1408,6 +1931,7 @@ pub fn handle_place_and_take_spot_order<'info>(
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             &clock,
         )?;
     }
@@ -1454,6 +1978,8 @@ pub fn handle_place_and_make_spot_order<'info>(
         return Err(print_error!(ErrorCode::InvalidOrderIOCPostOnly)().into());
     }
 
+    validate_fill_or_kill_order_params(&params, false)?;
+
     let market_index = params.market_index;
 
     let mut fulfillment_params: Box<dyn SpotFulfillmentParams> = match fulfillment_type {
@@ -1531,6 +2057,7 @@ pub fn handle_place_and_make_spot_order<'info>(
             &perp_market_map,
             &spot_market_map,
             &mut oracle_map,
+            &PerpOrderBookIndexMap::empty(),
             clock,
         )?;
     }
@@ -1794,6 +2321,35 @@ pub fn handle_update_user_delegate(
     Ok(())
 }
 
+pub fn handle_update_user_self_trade_prevention_mode(
+    ctx: Context<UpdateUser>,
+    _sub_account_id: u16,
+    self_trade_prevention_mode: SelfTradePreventionMode,
+) -> Result<()> {
+    let mut user = load_mut!(ctx.accounts.user)?;
+    user.self_trade_prevention_mode = self_trade_prevention_mode;
+    Ok(())
+}
+
+pub fn handle_update_user_heartbeat_timeout(
+    ctx: Context<UpdateUser>,
+    _sub_account_id: u16,
+    heartbeat_timeout: u32,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let mut user = load_mut!(ctx.accounts.user)?;
+    user.heartbeat_timeout = heartbeat_timeout;
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+    Ok(())
+}
+
+pub fn handle_heartbeat(ctx: Context<Heartbeat>) -> Result<()> {
+    let clock = Clock::get()?;
+    let mut user = load_mut!(ctx.accounts.user)?;
+    user.update_last_heartbeat_ts(clock.unix_timestamp);
+    Ok(())
+}
+
 pub fn handle_update_user_reduce_only(
     ctx: Context<UpdateUser>,
     _sub_account_id: u16,
@@ -1821,6 +2377,41 @@ pub fn handle_delete_user(ctx: Context<DeleteUser>) -> Result<()> {
     Ok(())
 }
 
+/// Resizes a user account created before users could hold 16 spot positions, 16 perp positions and
+/// 48 orders. Anyone can migrate a user and pays the extra rent
+pub fn handle_migrate_user(ctx: Context<MigrateUser>) -> Result<()> {
+    let user = &ctx.accounts.user;
+
+    validate!(
+        user.data_len() == LEGACY_USER_SIZE,
+        ErrorCode::InvalidUserMigration,
+        "user account has size {}, only legacy accounts of size {} can be migrated",
+        user.data_len(),
+        LEGACY_USER_SIZE
+    )?;
+
+    let lamports_needed = Rent::get()?
+        .minimum_balance(User::SIZE)
+        .saturating_sub(user.lamports());
+    if lamports_needed > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: user.to_account_info(),
+                },
+            ),
+            lamports_needed,
+        )?;
+    }
+
+    user.realloc(User::SIZE, true)?;
+    migrate_legacy_user_data(&mut user.try_borrow_mut_data()?)?;
+
+    Ok(())
+}
+
 #[access_control(
     deposit_not_paused(&ctx.accounts.state)
 )]
@@ -1999,7 +2590,7 @@ pub struct Withdraw<'info> {
     pub state: Box<Account<'info, State>>,
     #[account(
         mut,
-        has_one = authority,
+        constraint = is_authority_for_user(&user, &authority)?,
     )]
     pub user: AccountLoader<'info, User>,
     #[account(
@@ -2032,12 +2623,12 @@ pub struct Withdraw<'info> {
 pub struct TransferDeposit<'info> {
     #[account(
         mut,
-        has_one = authority,
+        constraint = is_authority_for_user(&from_user, &authority)?,
     )]
     pub from_user: AccountLoader<'info, User>,
     #[account(
         mut,
-        has_one = authority,
+        constraint = is_authority_for_user(&to_user, &authority)?,
     )]
     pub to_user: AccountLoader<'info, User>,
     #[account(
@@ -2076,6 +2667,16 @@ pub struct CancelOrder<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct Heartbeat<'info> {
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct PlaceAndTake<'info> {
     pub state: Box<Account<'info, State>>,
@@ -2105,7 +2706,10 @@ pub struct PlaceAndMake<'info> {
         constraint = is_stats_for_user(&user, &user_stats)?
     )]
     pub user_stats: AccountLoader<'info, UserStats>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&taker)?
+    )]
     pub taker: AccountLoader<'info, User>,
     #[account(
         mut,
@@ -2115,6 +2719,35 @@ pub struct PlaceAndMake<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct PlaceAndTakeSignedQuote<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&user, &user_stats)?
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    #[account(
+        mut,
+        constraint = is_user_migrated(&maker)?
+    )]
+    pub maker: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        constraint = is_stats_for_user(&maker, &maker_stats)?
+    )]
+    pub maker_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    /// CHECK: fixed instructions sysvar account
+    #[account(address = instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
 #[derive(Accounts)]
 pub struct AddRemoveLiquidity<'info> {
     pub state: Box<Account<'info, State>>,
@@ -2129,7 +2762,10 @@ pub struct AddRemoveLiquidity<'info> {
 #[derive(Accounts)]
 pub struct RemoveLiquidityInExpiredMarket<'info> {
     pub state: Box<Account<'info, State>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = is_user_migrated(&user)?
+    )]
     pub user: AccountLoader<'info, User>,
 }
 
@@ -2142,16 +2778,30 @@ pub struct UpdateUser<'info> {
         mut,
         seeds = [b"user", authority.key.as_ref(), sub_account_id.to_le_bytes().as_ref()],
         bump,
+        constraint = is_user_migrated(&user)?
     )]
     pub user: AccountLoader<'info, User>,
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct MigrateUser<'info> {
+    /// CHECK: legacy user accounts cant be loaded with the current layout, checked in handler
+    #[account(
+        mut,
+        owner = crate::id()
+    )]
+    pub user: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct DeleteUser<'info> {
     #[account(
         mut,
-        has_one = authority,
+        constraint = is_authority_for_user(&user, &authority)?,
         close = authority
     )]
     pub user: AccountLoader<'info, User>,
@@ -11,13 +11,17 @@ use math::{bn, constants::*};
 use state::oracle::OracleSource;
 
 use crate::controller::position::PositionDirection;
-use crate::state::order_params::{ModifyOrderParams, OrderParams};
+use crate::state::composite_oracle::CompositeOracleMode;
+use crate::state::order_params::{
+    BatchModifyOrderParams, ClientOrderIdPrefix, ModifyOrderParams, OrderParams, ScaleOrderParams,
+};
 use crate::state::perp_market::{ContractTier, MarketStatus};
+use crate::state::prelaunch_oracle::PrelaunchOracleParams;
 use crate::state::spot_market::AssetTier;
 use crate::state::spot_market::SpotFulfillmentConfigStatus;
 use crate::state::state::FeeStructure;
 use crate::state::state::*;
-use crate::state::user::MarketType;
+use crate::state::user::{MarketType, SelfTradePreventionMode};
 
 pub mod controller;
 pub mod error;
@@ -100,6 +104,13 @@ pub mod drift {
         handle_cancel_order_by_user_id(ctx, user_order_id)
     }
 
+    pub fn cancel_order_by_client_order_id(
+        ctx: Context<CancelOrder>,
+        client_order_id: u64,
+    ) -> Result<()> {
+        handle_cancel_order_by_client_order_id(ctx, client_order_id)
+    }
+
     pub fn cancel_orders(
         ctx: Context<CancelOrder>,
         market_type: Option<MarketType>,
@@ -109,6 +120,22 @@ pub mod drift {
         handle_cancel_orders(ctx, market_type, market_index, direction)
     }
 
+    pub fn cancel_orders_by_client_order_id_prefix(
+        ctx: Context<CancelOrder>,
+        client_order_id_prefix: ClientOrderIdPrefix,
+        market_type: Option<MarketType>,
+        market_index: Option<u16>,
+        direction: Option<PositionDirection>,
+    ) -> Result<()> {
+        handle_cancel_orders_by_client_order_id_prefix(
+            ctx,
+            client_order_id_prefix,
+            market_type,
+            market_index,
+            direction,
+        )
+    }
+
     pub fn cancel_orders_by_ids(ctx: Context<CancelOrder>, order_ids: Vec<u32>) -> Result<()> {
         handle_cancel_orders_by_ids(ctx, order_ids)
     }
@@ -129,6 +156,21 @@ pub mod drift {
         handle_modify_order_by_user_order_id(ctx, user_order_id, modify_order_params)
     }
 
+    pub fn modify_order_by_client_order_id(
+        ctx: Context<CancelOrder>,
+        client_order_id: u64,
+        modify_order_params: ModifyOrderParams,
+    ) -> Result<()> {
+        handle_modify_order_by_client_order_id(ctx, client_order_id, modify_order_params)
+    }
+
+    pub fn modify_orders(
+        ctx: Context<CancelOrder>,
+        params: Vec<BatchModifyOrderParams>,
+    ) -> Result<()> {
+        handle_modify_orders(ctx, params)
+    }
+
     pub fn place_and_take_perp_order(
         ctx: Context<PlaceAndTake>,
         params: OrderParams,
@@ -145,6 +187,13 @@ pub mod drift {
         handle_place_and_make_perp_order(ctx, params, taker_order_id)
     }
 
+    pub fn place_and_take_signed_quote_perp_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, PlaceAndTakeSignedQuote<'info>>,
+        params: OrderParams,
+    ) -> Result<()> {
+        handle_place_and_take_signed_quote_perp_order(ctx, params)
+    }
+
     pub fn place_spot_order(ctx: Context<PlaceOrder>, params: OrderParams) -> Result<()> {
         handle_place_spot_order(ctx, params)
     }
@@ -181,6 +230,10 @@ pub mod drift {
         handle_place_orders(ctx, params)
     }
 
+    pub fn place_scale_orders(ctx: Context<PlaceOrder>, params: ScaleOrderParams) -> Result<()> {
+        handle_place_scale_orders(ctx, params)
+    }
+
     pub fn begin_swap(
         ctx: Context<Swap>,
         in_market_index: u16,
@@ -270,10 +323,38 @@ pub mod drift {
         handle_update_user_reduce_only(ctx, _sub_account_id, reduce_only)
     }
 
+    pub fn update_user_self_trade_prevention_mode(
+        ctx: Context<UpdateUser>,
+        _sub_account_id: u16,
+        self_trade_prevention_mode: SelfTradePreventionMode,
+    ) -> Result<()> {
+        handle_update_user_self_trade_prevention_mode(
+            ctx,
+            _sub_account_id,
+            self_trade_prevention_mode,
+        )
+    }
+
+    pub fn update_user_heartbeat_timeout(
+        ctx: Context<UpdateUser>,
+        _sub_account_id: u16,
+        heartbeat_timeout: u32,
+    ) -> Result<()> {
+        handle_update_user_heartbeat_timeout(ctx, _sub_account_id, heartbeat_timeout)
+    }
+
+    pub fn heartbeat(ctx: Context<Heartbeat>) -> Result<()> {
+        handle_heartbeat(ctx)
+    }
+
     pub fn delete_user(ctx: Context<DeleteUser>) -> Result<()> {
         handle_delete_user(ctx)
     }
 
+    pub fn migrate_user(ctx: Context<MigrateUser>) -> Result<()> {
+        handle_migrate_user(ctx)
+    }
+
     // Keeper Instructions
 
     pub fn fill_perp_order(
@@ -284,6 +365,21 @@ pub mod drift {
         handle_fill_perp_order(ctx, order_id)
     }
 
+    pub fn place_signed_taker_perp_order(ctx: Context<PlaceSignedTakerOrder>) -> Result<()> {
+        handle_place_signed_taker_perp_order(ctx)
+    }
+
+    pub fn clear_perp_batch_auction(
+        ctx: Context<ClearPerpBatchAuction>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_clear_perp_batch_auction(ctx, market_index)
+    }
+
+    pub fn update_perp_order_book_index(ctx: Context<UpdatePerpOrderBookIndex>) -> Result<()> {
+        handle_update_perp_order_book_index(ctx)
+    }
+
     pub fn revert_fill(ctx: Context<RevertFill>) -> Result<()> {
         handle_revert_fill(ctx)
     }
@@ -305,6 +401,10 @@ pub mod drift {
         handle_force_cancel_orders(ctx)
     }
 
+    pub fn cancel_orders_on_expired_heartbeat(ctx: Context<ForceCancelOrder>) -> Result<()> {
+        handle_cancel_orders_on_expired_heartbeat(ctx)
+    }
+
     pub fn update_user_idle(ctx: Context<UpdateUserIdle>) -> Result<()> {
         handle_update_user_idle(ctx)
     }
@@ -446,6 +546,21 @@ pub mod drift {
         handle_update_amms(ctx, market_indexes)
     }
 
+    pub fn update_composite_oracle(ctx: Context<UpdateCompositeOracle>) -> Result<()> {
+        handle_update_composite_oracle(ctx)
+    }
+
+    pub fn update_prelaunch_oracle(ctx: Context<UpdatePrelaunchOracle>) -> Result<()> {
+        handle_update_prelaunch_oracle(ctx)
+    }
+
+    pub fn set_prelaunch_oracle_price(
+        ctx: Context<SetPrelaunchOraclePrice>,
+        price: i64,
+    ) -> Result<()> {
+        handle_set_prelaunch_oracle_price(ctx, price)
+    }
+
     pub fn update_spot_market_expiry(
         ctx: Context<AdminUpdateSpotMarket>,
         expiry_ts: i64,
@@ -796,6 +911,13 @@ pub mod drift {
         )
     }
 
+    pub fn update_spot_market_margin_confidence_multiplier(
+        ctx: Context<AdminUpdateSpotMarket>,
+        margin_confidence_multiplier: u16,
+    ) -> Result<()> {
+        handle_update_spot_market_margin_confidence_multiplier(ctx, margin_confidence_multiplier)
+    }
+
     pub fn update_spot_market_oracle(
         ctx: Context<AdminUpdateSpotMarketOracle>,
         oracle: Pubkey,
@@ -983,6 +1105,13 @@ pub mod drift {
         handle_update_perp_market_max_spread(ctx, max_spread)
     }
 
+    pub fn update_perp_market_max_inventory_skew(
+        ctx: Context<AdminUpdatePerpMarket>,
+        max_inventory_skew: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_max_inventory_skew(ctx, max_inventory_skew)
+    }
+
     pub fn update_perp_market_step_size_and_tick_size(
         ctx: Context<AdminUpdatePerpMarket>,
         step_size: u64,
@@ -1033,6 +1162,20 @@ pub mod drift {
         handle_update_perp_market_fee_adjustment(ctx, fee_adjustment)
     }
 
+    pub fn update_perp_market_margin_confidence_multiplier(
+        ctx: Context<AdminUpdatePerpMarket>,
+        margin_confidence_multiplier: u16,
+    ) -> Result<()> {
+        handle_update_perp_market_margin_confidence_multiplier(ctx, margin_confidence_multiplier)
+    }
+
+    pub fn update_perp_market_batch_auction_duration(
+        ctx: Context<AdminUpdatePerpMarket>,
+        batch_auction_duration: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_batch_auction_duration(ctx, batch_auction_duration)
+    }
+
     pub fn update_admin(ctx: Context<AdminUpdateState>, admin: Pubkey) -> Result<()> {
         handle_update_admin(ctx, admin)
     }
@@ -1080,6 +1223,43 @@ pub mod drift {
         handle_admin_remove_insurance_fund_stake(ctx, market_index, amount)
     }
 
+    pub fn initialize_prelaunch_oracle(
+        ctx: Context<InitializePrelaunchOracle>,
+        params: PrelaunchOracleParams,
+    ) -> Result<()> {
+        handle_initialize_prelaunch_oracle(ctx, params)
+    }
+
+    pub fn update_prelaunch_oracle_params(
+        ctx: Context<UpdatePrelaunchOracleParams>,
+        params: PrelaunchOracleParams,
+    ) -> Result<()> {
+        handle_update_prelaunch_oracle_params(ctx, params)
+    }
+
+    pub fn initialize_composite_oracle(
+        ctx: Context<InitializeCompositeOracle>,
+        oracles: [Pubkey; 4],
+        oracle_sources: [OracleSource; 4],
+        mode: CompositeOracleMode,
+        min_valid_oracles: u8,
+    ) -> Result<()> {
+        handle_initialize_composite_oracle(ctx, oracles, oracle_sources, mode, min_valid_oracles)
+    }
+
+    pub fn initialize_oracle_price_history(
+        ctx: Context<InitializeOraclePriceHistory>,
+        min_sample_interval: i64,
+    ) -> Result<()> {
+        handle_initialize_oracle_price_history(ctx, min_sample_interval)
+    }
+
+    pub fn initialize_perp_order_book_index(
+        ctx: Context<InitializePerpOrderBookIndex>,
+    ) -> Result<()> {
+        handle_initialize_perp_order_book_index(ctx)
+    }
+
     pub fn initialize_protocol_if_shares_transfer_config(
         ctx: Context<InitializeProtocolIfSharesTransferConfig>,
     ) -> Result<()> {
@@ -195,6 +195,73 @@ pub fn calculate_inventory_liquidity_ratio(
     Ok(amm_inventory_pct)
 }
 
+/// Skew of the reference price vs the oracle toward unloading the amm's inventory. Scales with the
+/// inventory vs the liquidity available to unload it, up to max_inventory_skew at full imbalance.
+/// Positive (quote above the oracle) when users are net long and the amm is short
+pub fn calculate_inventory_skew(
+    base_asset_amount_with_amm: i128,
+    base_asset_reserve: u128,
+    min_base_asset_reserve: u128,
+    max_base_asset_reserve: u128,
+    max_inventory_skew: u32,
+) -> DriftResult<i64> {
+    if base_asset_amount_with_amm == 0 || max_inventory_skew == 0 {
+        return Ok(0);
+    }
+
+    let amm_inventory_pct = calculate_inventory_liquidity_ratio(
+        base_asset_amount_with_amm,
+        base_asset_reserve,
+        min_base_asset_reserve,
+        max_base_asset_reserve,
+    )?;
+
+    let inventory_skew = amm_inventory_pct
+        .safe_mul(max_inventory_skew.cast()?)?
+        .safe_div(PERCENTAGE_PRECISION_I128)?
+        .cast::<i64>()?;
+
+    if base_asset_amount_with_amm > 0 {
+        Ok(inventory_skew)
+    } else {
+        Ok(-inventory_skew)
+    }
+}
+
+pub fn calculate_reference_price(oracle_price: i64, inventory_skew: i64) -> DriftResult<i64> {
+    oracle_price
+        .cast::<i128>()?
+        .safe_mul(BID_ASK_SPREAD_PRECISION_I128.safe_add(inventory_skew.cast()?)?)?
+        .safe_div(BID_ASK_SPREAD_PRECISION_I128)?
+        .cast()
+}
+
+/// Oracle std for the vol spread. The std of the sampled oracle prices is used when it's above the
+/// ewma so spreads widen before the ewma catches up to a move the history already shows
+pub fn calculate_oracle_std_with_price_history(
+    oracle_std: u64,
+    price_history_std: Option<u64>,
+) -> u64 {
+    match price_history_std {
+        Some(price_history_std) => oracle_std.max(price_history_std),
+        None => oracle_std,
+    }
+}
+
+/// Reserve price vs reference price spread, in place of the oracle spread for the oracle retreat when
+/// the amm quotes around an inventory skewed reference price
+pub fn calculate_reference_reserve_price_spread_pct(
+    reserve_price: u64,
+    reference_price: i64,
+) -> DriftResult<i64> {
+    reserve_price
+        .cast::<i128>()?
+        .safe_sub(reference_price.cast()?)?
+        .safe_mul(BID_ASK_SPREAD_PRECISION_I128)?
+        .safe_div(reserve_price.cast::<i128>()?)?
+        .cast()
+}
+
 pub fn calculate_spread_inventory_scale(
     base_asset_amount_with_amm: i128,
     base_asset_reserve: u128,
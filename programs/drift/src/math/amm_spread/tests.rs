@@ -3,7 +3,8 @@ mod test {
     use crate::math::amm_spread::*;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BID_ASK_SPREAD_PRECISION,
-        BID_ASK_SPREAD_PRECISION_I64, QUOTE_PRECISION, QUOTE_PRECISION_I128,
+        BID_ASK_SPREAD_PRECISION_I64, PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION,
+        QUOTE_PRECISION_I128,
     };
 
     #[test]
@@ -1228,4 +1229,92 @@ mod test {
         assert_eq!(long_spread, 4390);
         assert_eq!(short_spread, 43110);
     }
+
+    #[test]
+    fn calculate_inventory_skew_tests() {
+        let base_asset_reserve = AMM_RESERVE_PRECISION * 10;
+        let min_base_asset_reserve = 0_u128;
+        let max_base_asset_reserve = AMM_RESERVE_PRECISION * 100000;
+        let max_inventory_skew = 10000; // 1%
+
+        // balanced or disabled
+        let inventory_skew = calculate_inventory_skew(
+            0,
+            base_asset_reserve,
+            min_base_asset_reserve,
+            max_base_asset_reserve,
+            max_inventory_skew,
+        )
+        .unwrap();
+        assert_eq!(inventory_skew, 0);
+
+        let inventory_skew = calculate_inventory_skew(
+            AMM_RESERVE_PRECISION as i128,
+            base_asset_reserve,
+            min_base_asset_reserve,
+            max_base_asset_reserve,
+            0,
+        )
+        .unwrap();
+        assert_eq!(inventory_skew, 0);
+
+        // users long 10% of the liquidity available to unload, amm quotes 10% of the max skew above the oracle
+        let inventory_skew = calculate_inventory_skew(
+            AMM_RESERVE_PRECISION as i128,
+            base_asset_reserve,
+            min_base_asset_reserve,
+            max_base_asset_reserve,
+            max_inventory_skew,
+        )
+        .unwrap();
+        assert_eq!(inventory_skew, 1000);
+
+        // users short, amm quotes below the oracle
+        let inventory_skew = calculate_inventory_skew(
+            -(AMM_RESERVE_PRECISION as i128),
+            base_asset_reserve,
+            min_base_asset_reserve,
+            max_base_asset_reserve,
+            max_inventory_skew,
+        )
+        .unwrap();
+        assert_eq!(inventory_skew, -1000);
+
+        // capped at max skew
+        let inventory_skew = calculate_inventory_skew(
+            AMM_RESERVE_PRECISION as i128 * 20,
+            base_asset_reserve,
+            min_base_asset_reserve,
+            max_base_asset_reserve,
+            max_inventory_skew,
+        )
+        .unwrap();
+        assert_eq!(inventory_skew, max_inventory_skew as i64);
+    }
+
+    #[test]
+    fn calculate_reference_price_tests() {
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+
+        let reference_price = calculate_reference_price(oracle_price, 0).unwrap();
+        assert_eq!(reference_price, oracle_price);
+
+        let reference_price = calculate_reference_price(oracle_price, 1000).unwrap();
+        assert_eq!(reference_price, 100_100_000);
+
+        let reference_price = calculate_reference_price(oracle_price, -1000).unwrap();
+        assert_eq!(reference_price, 99_900_000);
+
+        // reserve price above the reference
+        let spread_pct =
+            calculate_reference_reserve_price_spread_pct(101 * PRICE_PRECISION_U64, oracle_price)
+                .unwrap();
+        assert_eq!(spread_pct, 9900);
+
+        // reserve price below the reference
+        let spread_pct =
+            calculate_reference_reserve_price_spread_pct(99 * PRICE_PRECISION_U64, oracle_price)
+                .unwrap();
+        assert_eq!(spread_pct, -10101);
+    }
 }
@@ -0,0 +1,228 @@
+use std::cmp::{max, min};
+
+use crate::controller::position::PositionDirection;
+use crate::error::DriftResult;
+use crate::math::amm::calculate_amm_available_liquidity;
+use crate::math::amm_spread::calculate_base_asset_amount_to_trade_to_price;
+use crate::math::casting::Cast;
+use crate::math::orders::standardize_base_asset_amount;
+use crate::math::safe_math::SafeMath;
+use crate::state::perp_market::AMM;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchAuctionOrder {
+    pub direction: PositionDirection,
+    pub price: u64,
+    pub base_asset_amount: u64,
+}
+
+/// Base asset amount the amm can buy before its bid falls to price and sell before its ask rises to price
+pub fn calculate_amm_batch_auction_liquidity(amm: &AMM, price: u64) -> DriftResult<(u64, u64)> {
+    let bid_liquidity = calculate_amm_liquidity_to_price(amm, price, PositionDirection::Short)?;
+    let ask_liquidity = calculate_amm_liquidity_to_price(amm, price, PositionDirection::Long)?;
+
+    Ok((bid_liquidity, ask_liquidity))
+}
+
+fn calculate_amm_liquidity_to_price(
+    amm: &AMM,
+    price: u64,
+    taker_direction: PositionDirection,
+) -> DriftResult<u64> {
+    let (base_asset_amount, direction) =
+        calculate_base_asset_amount_to_trade_to_price(amm, price, taker_direction)?;
+
+    if direction != taker_direction {
+        return Ok(0);
+    }
+
+    standardize_base_asset_amount(
+        min(
+            base_asset_amount,
+            calculate_amm_available_liquidity(amm, &taker_direction)?,
+        ),
+        amm.order_step_size,
+    )
+}
+
+/// Base asset amount bid at or above price and asked at or below price
+pub fn calculate_batch_auction_bids_and_asks(
+    orders: &[BatchAuctionOrder],
+    amm: Option<&AMM>,
+    price: u64,
+) -> DriftResult<(u64, u64)> {
+    let (mut bids, mut asks) = match amm {
+        Some(amm) => calculate_amm_batch_auction_liquidity(amm, price)?,
+        None => (0, 0),
+    };
+
+    for order in orders.iter() {
+        match order.direction {
+            PositionDirection::Long if order.price >= price => {
+                bids = bids.safe_add(order.base_asset_amount)?;
+            }
+            PositionDirection::Short if order.price <= price => {
+                asks = asks.safe_add(order.base_asset_amount)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok((bids, asks))
+}
+
+/// Uniform price a batch clears at and the base asset amount matched at that price. Bids only fall
+/// and asks only rise with the price, so the price matching the most base is the lowest tick where
+/// bids no longer exceed asks, or the tick below it
+pub fn calculate_batch_auction_clearing_price(
+    orders: &[BatchAuctionOrder],
+    amm: Option<&AMM>,
+    tick_size: u64,
+) -> DriftResult<Option<(u64, u64)>> {
+    let (mut min_price, mut max_price) = match (
+        orders.iter().map(|order| order.price).min(),
+        orders.iter().map(|order| order.price).max(),
+    ) {
+        (Some(min_price), Some(max_price)) => (min_price, max_price),
+        _ => return Ok(None),
+    };
+
+    if let Some(amm) = amm {
+        let (bid_price, ask_price) = amm.bid_ask_price(amm.reserve_price()?)?;
+        min_price = min(min_price, bid_price);
+        max_price = max(max_price, ask_price);
+    }
+
+    let min_price = max(
+        min_price.safe_div(tick_size)?.safe_mul(tick_size)?,
+        tick_size,
+    );
+    let ticks = max_price
+        .saturating_sub(min_price)
+        .safe_div_ceil(tick_size)?;
+
+    let (mut low, mut high) = (0_u64, ticks);
+    while low < high {
+        let mid = low.safe_add(high.safe_sub(low)?.safe_div(2)?)?;
+        let price = min_price.safe_add(mid.safe_mul(tick_size)?)?;
+        let (bids, asks) = calculate_batch_auction_bids_and_asks(orders, amm, price)?;
+        if bids <= asks {
+            high = mid;
+        } else {
+            low = mid.safe_add(1)?;
+        }
+    }
+
+    let price = min_price.safe_add(low.safe_mul(tick_size)?)?;
+    let (bids, asks) = calculate_batch_auction_bids_and_asks(orders, amm, price)?;
+    let mut clearing_price = price;
+    let mut base_asset_amount = min(bids, asks);
+
+    if low > 0 {
+        let lower_price = price.safe_sub(tick_size)?;
+        let (bids, asks) = calculate_batch_auction_bids_and_asks(orders, amm, lower_price)?;
+        if min(bids, asks) > base_asset_amount {
+            clearing_price = lower_price;
+            base_asset_amount = min(bids, asks);
+        }
+    }
+
+    if base_asset_amount == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some((clearing_price, base_asset_amount)))
+}
+
+/// Base asset amount filled on the bid and ask side of a batch. Orders on one side match orders on
+/// the other, the amm takes the imbalance up to its liquidity
+pub fn calculate_batch_auction_fill_amounts(
+    bids: u64,
+    asks: u64,
+    amm_bid_liquidity: u64,
+    amm_ask_liquidity: u64,
+) -> DriftResult<(u64, u64)> {
+    let bids_filled = min(bids, asks.safe_add(amm_ask_liquidity)?);
+    let asks_filled = min(asks, bids.safe_add(amm_bid_liquidity)?);
+
+    Ok((bids_filled, asks_filled))
+}
+
+/// Splits the base asset amount filled on one side of a batch between its orders. Better priced
+/// orders fill first, orders at the price level that can't fully fill share what is left pro rata.
+/// Rounding leftovers go to the earliest orders, so orders must be in time priority
+pub fn calculate_batch_auction_fill_allocations(
+    orders: &[BatchAuctionOrder],
+    base_asset_amount: u64,
+    step_size: u64,
+) -> DriftResult<Vec<u64>> {
+    let mut allocations = vec![0_u64; orders.len()];
+
+    let mut prices: Vec<u64> = orders.iter().map(|order| order.price).collect();
+    prices.sort_unstable();
+    prices.dedup();
+    if matches!(
+        orders.first().map(|order| order.direction),
+        Some(PositionDirection::Long)
+    ) {
+        prices.reverse();
+    }
+
+    let mut remaining = base_asset_amount;
+    for price in prices {
+        if remaining == 0 {
+            break;
+        }
+
+        let level_total = orders
+            .iter()
+            .filter(|order| order.price == price)
+            .try_fold(0_u64, |total, order| {
+                total.safe_add(order.base_asset_amount)
+            })?;
+
+        if level_total <= remaining {
+            for (allocation, order) in allocations.iter_mut().zip(orders.iter()) {
+                if order.price == price {
+                    *allocation = order.base_asset_amount;
+                }
+            }
+            remaining = remaining.safe_sub(level_total)?;
+            continue;
+        }
+
+        let mut allocated = 0_u64;
+        for (allocation, order) in allocations.iter_mut().zip(orders.iter()) {
+            if order.price == price {
+                let pro_rata = order
+                    .base_asset_amount
+                    .cast::<u128>()?
+                    .safe_mul(remaining.cast()?)?
+                    .safe_div(level_total.cast()?)?
+                    .cast::<u64>()?;
+                *allocation = standardize_base_asset_amount(pro_rata, step_size)?;
+                allocated = allocated.safe_add(*allocation)?;
+            }
+        }
+
+        let mut leftover = remaining.safe_sub(allocated)?;
+        for (allocation, order) in allocations.iter_mut().zip(orders.iter()) {
+            if leftover == 0 {
+                break;
+            }
+
+            if order.price == price {
+                let extra = min(order.base_asset_amount.safe_sub(*allocation)?, leftover);
+                *allocation = allocation.safe_add(extra)?;
+                leftover = leftover.safe_sub(extra)?;
+            }
+        }
+
+        remaining = 0;
+    }
+
+    Ok(allocations)
+}
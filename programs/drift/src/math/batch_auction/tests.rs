@@ -0,0 +1,250 @@
+mod calculate_batch_auction_clearing_price {
+    use crate::controller::position::PositionDirection;
+    use crate::math::batch_auction::{
+        calculate_amm_batch_auction_liquidity, calculate_batch_auction_clearing_price,
+        BatchAuctionOrder,
+    };
+    use crate::math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+    use crate::state::perp_market::AMM;
+
+    const TICK_SIZE: u64 = PRICE_PRECISION_U64 / 100;
+
+    fn order(
+        direction: PositionDirection,
+        price: u64,
+        base_asset_amount: u64,
+    ) -> BatchAuctionOrder {
+        BatchAuctionOrder {
+            direction,
+            price,
+            base_asset_amount,
+        }
+    }
+
+    #[test]
+    fn orders_only() {
+        let orders = [
+            order(
+                PositionDirection::Long,
+                101 * PRICE_PRECISION_U64,
+                5 * BASE_PRECISION_U64,
+            ),
+            order(
+                PositionDirection::Long,
+                100 * PRICE_PRECISION_U64,
+                5 * BASE_PRECISION_U64,
+            ),
+            order(
+                PositionDirection::Short,
+                99 * PRICE_PRECISION_U64,
+                4 * BASE_PRECISION_U64,
+            ),
+            order(
+                PositionDirection::Short,
+                100 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2,
+                4 * BASE_PRECISION_U64,
+            ),
+        ];
+
+        // below 100.5 only 4 is asked, at 100.5 the 5 bid above 100 all match
+        let clearing = calculate_batch_auction_clearing_price(&orders, None, TICK_SIZE).unwrap();
+        assert_eq!(
+            clearing,
+            Some((
+                100 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2,
+                5 * BASE_PRECISION_U64
+            ))
+        );
+    }
+
+    #[test]
+    fn excess_demand_clears_at_marginal_bid() {
+        let orders = [
+            order(
+                PositionDirection::Long,
+                101 * PRICE_PRECISION_U64,
+                10 * BASE_PRECISION_U64,
+            ),
+            order(
+                PositionDirection::Short,
+                99 * PRICE_PRECISION_U64,
+                3 * BASE_PRECISION_U64,
+            ),
+            order(
+                PositionDirection::Short,
+                100 * PRICE_PRECISION_U64,
+                3 * BASE_PRECISION_U64,
+            ),
+        ];
+
+        let clearing = calculate_batch_auction_clearing_price(&orders, None, TICK_SIZE).unwrap();
+        assert_eq!(
+            clearing,
+            Some((101 * PRICE_PRECISION_U64, 6 * BASE_PRECISION_U64))
+        );
+    }
+
+    #[test]
+    fn no_cross() {
+        let orders = [
+            order(
+                PositionDirection::Long,
+                99 * PRICE_PRECISION_U64,
+                5 * BASE_PRECISION_U64,
+            ),
+            order(
+                PositionDirection::Short,
+                100 * PRICE_PRECISION_U64,
+                5 * BASE_PRECISION_U64,
+            ),
+        ];
+        let clearing = calculate_batch_auction_clearing_price(&orders, None, TICK_SIZE).unwrap();
+        assert_eq!(clearing, None);
+
+        let clearing =
+            calculate_batch_auction_clearing_price(&orders[..1], None, TICK_SIZE).unwrap();
+        assert_eq!(clearing, None);
+
+        let clearing = calculate_batch_auction_clearing_price(&[], None, TICK_SIZE).unwrap();
+        assert_eq!(clearing, None);
+    }
+
+    #[test]
+    fn amm_fills_imbalance() {
+        let amm = AMM::default_test();
+        let tick_size = amm.order_tick_size;
+
+        // amm price is 1, the bid is willing to pay up to 1.1
+        let orders = [order(
+            PositionDirection::Long,
+            PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 10,
+            2 * BASE_PRECISION_U64,
+        )];
+
+        let (clearing_price, base_asset_amount) =
+            calculate_batch_auction_clearing_price(&orders, Some(&amm), tick_size)
+                .unwrap()
+                .unwrap();
+        assert_eq!(base_asset_amount, 2 * BASE_PRECISION_U64);
+        assert!(clearing_price > PRICE_PRECISION_U64);
+        assert!(clearing_price < orders[0].price);
+
+        // clears at the lowest price the amm sells the full bid at
+        let (_, ask_liquidity) =
+            calculate_amm_batch_auction_liquidity(&amm, clearing_price).unwrap();
+        assert!(ask_liquidity >= 2 * BASE_PRECISION_U64);
+        let (_, ask_liquidity) =
+            calculate_amm_batch_auction_liquidity(&amm, clearing_price - tick_size).unwrap();
+        assert!(ask_liquidity < 2 * BASE_PRECISION_U64);
+
+        // amm doesnt bid above its price
+        let (bid_liquidity, _) =
+            calculate_amm_batch_auction_liquidity(&amm, clearing_price).unwrap();
+        assert_eq!(bid_liquidity, 0);
+    }
+}
+
+mod calculate_batch_auction_fill_allocations {
+    use crate::controller::position::PositionDirection;
+    use crate::math::batch_auction::{
+        calculate_batch_auction_fill_allocations, calculate_batch_auction_fill_amounts,
+        BatchAuctionOrder,
+    };
+    use crate::math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+
+    const STEP_SIZE: u64 = BASE_PRECISION_U64 / 10;
+
+    fn bid(price: u64, base_asset_amount: u64) -> BatchAuctionOrder {
+        BatchAuctionOrder {
+            direction: PositionDirection::Long,
+            price: price * PRICE_PRECISION_U64,
+            base_asset_amount,
+        }
+    }
+
+    #[test]
+    fn better_price_fills_first() {
+        let orders = [
+            bid(100, 2 * BASE_PRECISION_U64),
+            bid(101, 2 * BASE_PRECISION_U64),
+            bid(100, 6 * BASE_PRECISION_U64),
+        ];
+
+        let allocations =
+            calculate_batch_auction_fill_allocations(&orders, 4 * BASE_PRECISION_U64, STEP_SIZE)
+                .unwrap();
+
+        // 2 left for the 8 bid at 100
+        assert_eq!(
+            allocations,
+            vec![
+                BASE_PRECISION_U64 / 2,
+                2 * BASE_PRECISION_U64,
+                3 * BASE_PRECISION_U64 / 2
+            ]
+        );
+    }
+
+    #[test]
+    fn rounding_leftover_goes_to_earliest() {
+        let orders = [
+            bid(100, BASE_PRECISION_U64),
+            bid(100, BASE_PRECISION_U64),
+            bid(100, BASE_PRECISION_U64),
+        ];
+
+        let allocations =
+            calculate_batch_auction_fill_allocations(&orders, BASE_PRECISION_U64, STEP_SIZE)
+                .unwrap();
+
+        // 0.333 each rounds down to 0.3, the 0.1 left goes to the first order
+        assert_eq!(
+            allocations,
+            vec![
+                4 * BASE_PRECISION_U64 / 10,
+                3 * BASE_PRECISION_U64 / 10,
+                3 * BASE_PRECISION_U64 / 10
+            ]
+        );
+        assert_eq!(allocations.iter().sum::<u64>(), BASE_PRECISION_U64);
+    }
+
+    #[test]
+    fn asks_fill_lowest_price_first() {
+        let orders = [
+            BatchAuctionOrder {
+                direction: PositionDirection::Short,
+                price: 101 * PRICE_PRECISION_U64,
+                base_asset_amount: BASE_PRECISION_U64,
+            },
+            BatchAuctionOrder {
+                direction: PositionDirection::Short,
+                price: 99 * PRICE_PRECISION_U64,
+                base_asset_amount: BASE_PRECISION_U64,
+            },
+        ];
+
+        let allocations =
+            calculate_batch_auction_fill_allocations(&orders, BASE_PRECISION_U64, STEP_SIZE)
+                .unwrap();
+
+        assert_eq!(allocations, vec![0, BASE_PRECISION_U64]);
+    }
+
+    #[test]
+    fn amm_takes_imbalance() {
+        // 5 bid, 3 asked and the amm can sell 1
+        let fill_amounts = calculate_batch_auction_fill_amounts(
+            5 * BASE_PRECISION_U64,
+            3 * BASE_PRECISION_U64,
+            0,
+            BASE_PRECISION_U64,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fill_amounts,
+            (4 * BASE_PRECISION_U64, 3 * BASE_PRECISION_U64)
+        );
+    }
+}
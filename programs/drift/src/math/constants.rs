@@ -6,9 +6,9 @@ pub const LAMPORTS_PER_SOL_I64: i64 = LAMPORTS_PER_SOL as i64;
 pub const QUOTE_SPOT_MARKET_INDEX: u16 = 0;
 
 // USER ACCOUNT CONSTANTS
-pub const MAX_SPOT_POSITIONS: u8 = 8;
-pub const MAX_PERP_POSITIONS: u8 = 8;
-pub const MAX_OPEN_ORDERS: u8 = 32;
+pub const MAX_SPOT_POSITIONS: u8 = 16;
+pub const MAX_PERP_POSITIONS: u8 = 16;
+pub const MAX_OPEN_ORDERS: u8 = 48;
 
 // PRECISIONS
 pub const AMM_RESERVE_PRECISION: u128 = 1_000_000_000; //expo = -9;
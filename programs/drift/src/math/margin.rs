@@ -20,7 +20,7 @@ use crate::math::spot_balance::{get_strict_token_value, get_token_value};
 
 use crate::math::safe_math::SafeMath;
 use crate::state::margin_calculation::{MarginCalculation, MarginContext, MarketIdentifier};
-use crate::state::oracle::{OraclePriceData, StrictOraclePrice};
+use crate::state::oracle::StrictOraclePrice;
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::{ContractTier, MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
@@ -99,15 +99,17 @@ pub fn calculate_size_discount_asset_weight(
 pub fn calculate_perp_position_value_and_pnl(
     market_position: &PerpPosition,
     market: &PerpMarket,
-    oracle_price_data: &OraclePriceData,
+    strict_oracle_price: &StrictOraclePrice,
     strict_quote_price: &StrictOraclePrice,
     margin_requirement_type: MarginRequirementType,
     user_custom_margin_ratio: u32,
 ) -> DriftResult<(u128, i128, u128)> {
-    let valuation_price = if market.status == MarketStatus::Settlement {
+    let is_settlement = market.status == MarketStatus::Settlement;
+
+    let valuation_price = if is_settlement {
         market.expiry_price
     } else {
-        oracle_price_data.price
+        strict_oracle_price.current
     };
 
     // the funding must be calculated before calculated the unrealized pnl w simulated lp position
@@ -122,8 +124,21 @@ pub fn calculate_perp_position_value_and_pnl(
 
     let market_position = market_position.simulate_settled_lp_position(market, valuation_price)?;
 
-    let (_, unrealized_pnl) =
-        calculate_base_asset_value_and_pnl_with_oracle_price(&market_position, valuation_price)?;
+    // value pnl at the strict price least favorable to the position
+    let pnl_valuation_price = if is_settlement {
+        valuation_price
+    } else {
+        match market_position.base_asset_amount.cmp(&0) {
+            Ordering::Greater => strict_oracle_price.min(),
+            Ordering::Less => strict_oracle_price.max(),
+            Ordering::Equal => valuation_price,
+        }
+    };
+
+    let (_, unrealized_pnl) = calculate_base_asset_value_and_pnl_with_oracle_price(
+        &market_position,
+        pnl_valuation_price,
+    )?;
 
     let total_unrealized_pnl = unrealized_pnl.safe_add(unrealized_funding.cast()?)?;
 
@@ -131,7 +146,11 @@ pub fn calculate_perp_position_value_and_pnl(
 
     let worse_case_base_asset_value = calculate_base_asset_value_with_oracle_price(
         worst_case_base_asset_amount,
-        valuation_price,
+        if is_settlement {
+            valuation_price
+        } else {
+            strict_oracle_price.max()
+        },
     )?;
 
     // for calculating the perps value, since it's a liability, use the large of twap and quote oracle price
@@ -144,7 +163,7 @@ pub fn calculate_perp_position_value_and_pnl(
         margin_requirement_type,
     )?);
 
-    let mut margin_requirement = if market.status == MarketStatus::Settlement {
+    let mut margin_requirement = if is_settlement {
         0
     } else {
         worse_case_base_asset_value
@@ -196,6 +215,23 @@ pub fn calculate_perp_position_value_and_pnl(
     ))
 }
 
+/// k * confidence, k being the market's margin_confidence_multiplier
+pub fn calculate_confidence_offset(
+    confidence: u64,
+    margin_confidence_multiplier: u16,
+) -> DriftResult<i64> {
+    if margin_confidence_multiplier == 0 {
+        return Ok(0);
+    }
+
+    confidence
+        .cast::<u128>()?
+        .safe_mul(margin_confidence_multiplier.cast()?)?
+        .safe_div(MARGIN_PRECISION_U128)?
+        .min(i64::MAX.cast()?)
+        .cast()
+}
+
 pub fn calculate_user_safest_position_tiers(
     user: &User,
     perp_market_map: &PerpMarketMap,
@@ -256,13 +292,23 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
             Some(DriftAction::MarginCalc),
         )?);
 
+        let confidence_offset = if calculation.context.confidence_adjusted {
+            calculate_confidence_offset(
+                oracle_price_data.confidence,
+                spot_market.margin_confidence_multiplier,
+            )?
+        } else {
+            0
+        };
+
         let strict_oracle_price = StrictOraclePrice::new(
             oracle_price_data.price,
             spot_market
                 .historical_oracle_data
                 .last_oracle_price_twap_5min,
             calculation.context.strict,
-        );
+        )
+        .with_confidence_offset(confidence_offset);
         strict_oracle_price.validate()?;
 
         if spot_market.market_index == 0 {
@@ -421,13 +467,23 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
             Some(DriftAction::MarginCalc),
         )?);
 
+        let quote_confidence_offset = if calculation.context.confidence_adjusted {
+            calculate_confidence_offset(
+                quote_oracle_price_data.confidence,
+                quote_spot_market.margin_confidence_multiplier,
+            )?
+        } else {
+            0
+        };
+
         let strict_quote_price = StrictOraclePrice::new(
             quote_oracle_price_data.price,
             quote_spot_market
                 .historical_oracle_data
                 .last_oracle_price_twap_5min,
             calculation.context.strict,
-        );
+        )
+        .with_confidence_offset(quote_confidence_offset);
         drop(quote_spot_market);
 
         let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
@@ -440,11 +496,23 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
             Some(DriftAction::MarginCalc),
         )?);
 
+        let confidence_offset = if calculation.context.confidence_adjusted {
+            calculate_confidence_offset(
+                oracle_price_data.confidence,
+                market.margin_confidence_multiplier,
+            )?
+        } else {
+            0
+        };
+
+        let strict_oracle_price = StrictOraclePrice::new(oracle_price_data.price, 0, false)
+            .with_confidence_offset(confidence_offset);
+
         let (perp_margin_requirement, weighted_pnl, worst_case_base_asset_value) =
             calculate_perp_position_value_and_pnl(
                 market_position,
                 market,
-                oracle_price_data,
+                &strict_oracle_price,
                 &strict_quote_price,
                 context.margin_type,
                 user_custom_margin_ratio,
@@ -481,7 +549,9 @@ pub fn meets_withdraw_margin_requirement(
     margin_requirement_type: MarginRequirementType,
 ) -> DriftResult<bool> {
     let strict = margin_requirement_type == MarginRequirementType::Initial;
-    let context = MarginContext::standard(margin_requirement_type).strict(strict);
+    let context = MarginContext::standard(margin_requirement_type)
+        .strict(strict)
+        .confidence_adjusted(strict);
 
     let calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
         user,
@@ -530,7 +600,9 @@ pub fn meets_place_order_margin_requirement(
     } else {
         MarginRequirementType::Maintenance
     };
-    let context = MarginContext::standard(margin_type).strict(true);
+    let context = MarginContext::standard(margin_type)
+        .strict(true)
+        .confidence_adjusted(risk_increasing);
 
     let calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
         user,
@@ -5,10 +5,12 @@ mod test {
     use crate::amm::calculate_swap_output;
     use crate::controller::amm::SwapDirection;
     use crate::math::constants::{
-        AMM_RESERVE_PRECISION, PRICE_PRECISION, PRICE_PRECISION_U64, QUOTE_PRECISION,
-        QUOTE_PRECISION_I64, SPOT_IMF_PRECISION,
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, MARGIN_PRECISION, PRICE_PRECISION,
+        PRICE_PRECISION_U64, QUOTE_PRECISION, QUOTE_PRECISION_I64, SPOT_IMF_PRECISION,
+    };
+    use crate::math::margin::{
+        calculate_confidence_offset, calculate_perp_position_value_and_pnl, MarginRequirementType,
     };
-    use crate::math::margin::{calculate_perp_position_value_and_pnl, MarginRequirementType};
     use crate::math::position::calculate_base_asset_value_and_pnl_with_oracle_price;
     use crate::state::oracle::{OraclePriceData, StrictOraclePrice};
     use crate::state::perp_market::{ContractTier, PerpMarket, AMM};
@@ -236,7 +238,7 @@ mod test {
         let (pmr, upnl, _) = calculate_perp_position_value_and_pnl(
             &market_position,
             &market,
-            &oracle_price_data,
+            &StrictOraclePrice::test(oracle_price_data.price),
             &strict_oracle_price,
             MarginRequirementType::Initial,
             0,
@@ -312,7 +314,7 @@ mod test {
         let (pmr_2, upnl_2, _) = calculate_perp_position_value_and_pnl(
             &market_position,
             &market,
-            &oracle_price_data,
+            &StrictOraclePrice::test(oracle_price_data.price),
             &strict_oracle_price,
             MarginRequirementType::Initial,
             0,
@@ -375,7 +377,7 @@ mod test {
         let (pmr, _, _) = calculate_perp_position_value_and_pnl(
             &position,
             &market,
-            &oracle_price_data,
+            &StrictOraclePrice::test(oracle_price_data.price),
             &strict_oracle_price,
             MarginRequirementType::Initial,
             0,
@@ -398,7 +400,7 @@ mod test {
         let (pmr2, _, _) = calculate_perp_position_value_and_pnl(
             &position,
             &market,
-            &oracle_price_data,
+            &StrictOraclePrice::test(oracle_price_data.price),
             &strict_oracle_price,
             MarginRequirementType::Initial,
             0,
@@ -445,7 +447,7 @@ mod test {
         let (pmr, _, _) = calculate_perp_position_value_and_pnl(
             &position,
             &market,
-            &oracle_price_data,
+            &StrictOraclePrice::test(oracle_price_data.price),
             &strict_oracle_price,
             MarginRequirementType::Initial,
             0,
@@ -468,7 +470,7 @@ mod test {
         let (pmr2, _, _) = calculate_perp_position_value_and_pnl(
             &position,
             &market,
-            &oracle_price_data,
+            &StrictOraclePrice::test(oracle_price_data.price),
             &strict_oracle_price,
             MarginRequirementType::Initial,
             0,
@@ -478,6 +480,114 @@ mod test {
         // larger margin req in more unbalanced market
         assert!(pmr2 > pmr)
     }
+
+    #[test]
+    fn calculate_confidence_offset_test() {
+        assert_eq!(
+            calculate_confidence_offset(PRICE_PRECISION_U64, 0).unwrap(),
+            0
+        );
+        assert_eq!(
+            calculate_confidence_offset(PRICE_PRECISION_U64, 2 * MARGIN_PRECISION as u16).unwrap(),
+            2 * PRICE_PRECISION_I64
+        );
+        assert_eq!(
+            calculate_confidence_offset(PRICE_PRECISION_U64, MARGIN_PRECISION as u16 / 2).unwrap(),
+            PRICE_PRECISION_I64 / 2
+        );
+        // saturates instead of overflowing for the max confidence
+        assert_eq!(
+            calculate_confidence_offset(u64::MAX, 2 * MARGIN_PRECISION as u16).unwrap(),
+            i64::MAX
+        );
+
+        let strict_oracle_price = StrictOraclePrice::new(100 * PRICE_PRECISION_I64, 0, false)
+            .with_confidence_offset(2 * PRICE_PRECISION_I64);
+        assert_eq!(strict_oracle_price.min(), 98 * PRICE_PRECISION_I64);
+        assert_eq!(strict_oracle_price.max(), 102 * PRICE_PRECISION_I64);
+
+        let strict_oracle_price =
+            StrictOraclePrice::new(100 * PRICE_PRECISION_I64, 99 * PRICE_PRECISION_I64, true)
+                .with_confidence_offset(200 * PRICE_PRECISION_I64);
+        assert_eq!(strict_oracle_price.min(), 0);
+        assert_eq!(strict_oracle_price.max(), 300 * PRICE_PRECISION_I64);
+    }
+
+    #[test]
+    fn confidence_adjusted_perp_position_value() {
+        let market = PerpMarket {
+            amm: AMM::default_test(),
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            unrealized_pnl_initial_asset_weight: 10000,
+            unrealized_pnl_maintenance_asset_weight: 10000,
+            ..PerpMarket::default()
+        };
+
+        let strict_oracle_price = StrictOraclePrice::new(100 * PRICE_PRECISION_I64, 0, false)
+            .with_confidence_offset(2 * PRICE_PRECISION_I64);
+        let strict_quote_price = StrictOraclePrice::test(QUOTE_PRECISION_I64);
+
+        // long valued at price - offset, liability at price + offset
+        let long_position = PerpPosition {
+            base_asset_amount: BASE_PRECISION_I64,
+            quote_asset_amount: -100 * QUOTE_PRECISION_I64,
+            ..PerpPosition::default()
+        };
+
+        let (margin_requirement, weighted_pnl, worst_case_base_asset_value) =
+            calculate_perp_position_value_and_pnl(
+                &long_position,
+                &market,
+                &strict_oracle_price,
+                &strict_quote_price,
+                MarginRequirementType::Initial,
+                0,
+            )
+            .unwrap();
+
+        assert_eq!(margin_requirement, 10_200_000);
+        assert_eq!(weighted_pnl, -2_000_000);
+        assert_eq!(worst_case_base_asset_value, 102_000_000);
+
+        // short valued at price + offset
+        let short_position = PerpPosition {
+            base_asset_amount: -BASE_PRECISION_I64,
+            quote_asset_amount: 100 * QUOTE_PRECISION_I64,
+            ..PerpPosition::default()
+        };
+
+        let (margin_requirement, weighted_pnl, worst_case_base_asset_value) =
+            calculate_perp_position_value_and_pnl(
+                &short_position,
+                &market,
+                &strict_oracle_price,
+                &strict_quote_price,
+                MarginRequirementType::Initial,
+                0,
+            )
+            .unwrap();
+
+        assert_eq!(margin_requirement, 10_200_000);
+        assert_eq!(weighted_pnl, -2_000_000);
+        assert_eq!(worst_case_base_asset_value, 102_000_000);
+
+        // no offset, valued at oracle
+        let (margin_requirement, weighted_pnl, worst_case_base_asset_value) =
+            calculate_perp_position_value_and_pnl(
+                &long_position,
+                &market,
+                &StrictOraclePrice::test(100 * PRICE_PRECISION_I64),
+                &strict_quote_price,
+                MarginRequirementType::Initial,
+                0,
+            )
+            .unwrap();
+
+        assert_eq!(margin_requirement, 10_000_000);
+        assert_eq!(weighted_pnl, 0);
+        assert_eq!(worst_case_base_asset_value, 100_000_000);
+    }
 }
 
 #[cfg(test)]
@@ -561,7 +671,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -576,8 +686,8 @@ mod calculate_margin_requirement_and_total_collateral {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -652,7 +762,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -667,8 +777,8 @@ mod calculate_margin_requirement_and_total_collateral {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -746,7 +856,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -761,8 +871,8 @@ mod calculate_margin_requirement_and_total_collateral {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -857,7 +967,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -872,7 +982,7 @@ mod calculate_margin_requirement_and_total_collateral {
         };
 
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: 100 * BASE_PRECISION_I64,
@@ -952,7 +1062,7 @@ mod calculate_margin_requirement_and_total_collateral {
         // doesnt affect maintenance margin requirement
         assert_eq!(maintenance_margin_requirement, 11500000000); // 100 * 100 * .05 + 100 * $100 * 1.1
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -961,7 +1071,7 @@ mod calculate_margin_requirement_and_total_collateral {
         };
 
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             spot_positions,
             max_margin_ratio: MARGIN_PRECISION as u32 / 2, // 2x leverage
             ..User::default()
@@ -1054,7 +1164,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Deposit,
@@ -1063,7 +1173,7 @@ mod calculate_margin_requirement_and_total_collateral {
         };
 
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             spot_positions,
             max_margin_ratio: 2 * MARGIN_PRECISION as u32, // .5x leverage
             ..User::default()
@@ -1175,7 +1285,7 @@ mod calculate_margin_requirement_and_total_collateral {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[1] = SpotPosition {
             market_index: 1,
             balance_type: SpotBalanceType::Borrow,
@@ -1184,7 +1294,7 @@ mod calculate_margin_requirement_and_total_collateral {
         };
 
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             spot_positions,
             max_margin_ratio: 2 * MARGIN_PRECISION as u32, // .5x leverage
             ..User::default()
@@ -1267,6 +1377,117 @@ mod calculate_margin_requirement_and_total_collateral {
         assert_eq!(total_collateral, 0); // todo not 0
         assert_eq!(margin_requirement, 3);
     }
+
+    #[test]
+    pub fn confidence_adjusted_sol_borrow() {
+        let slot = 0_u64;
+
+        let mut sol_oracle_price = get_pyth_price(100, 6);
+        sol_oracle_price.agg.conf = 1_000_000; // $1
+        let sol_oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            sol_oracle_price,
+            &sol_oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let market_map = PerpMarketMap::empty();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            deposit_balance: 10000 * SPOT_BALANCE_PRECISION,
+            liquidator_fee: 0,
+            historical_oracle_data: HistoricalOracleData::default_quote_oracle(),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let mut sol_spot_market = SpotMarket {
+            market_index: 1,
+            oracle_source: OracleSource::Pyth,
+            oracle: sol_oracle_price_key,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 9,
+            initial_asset_weight: 8 * SPOT_WEIGHT_PRECISION / 10,
+            maintenance_asset_weight: 9 * SPOT_WEIGHT_PRECISION / 10,
+            initial_liability_weight: 12 * SPOT_WEIGHT_PRECISION / 10,
+            maintenance_liability_weight: 11 * SPOT_WEIGHT_PRECISION / 10,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 1000,
+            margin_confidence_multiplier: 2 * MARGIN_PRECISION as u16,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(sol_spot_market, SpotMarket, sol_spot_market_account_info);
+        let spot_market_account_infos = Vec::from([
+            &usdc_spot_market_account_info,
+            &sol_spot_market_account_info,
+        ]);
+        let spot_market_map =
+            SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
+
+        let mut spot_positions = [SpotPosition::default(); 16];
+        spot_positions[0] = SpotPosition {
+            market_index: 0,
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: 10000 * SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        };
+        spot_positions[1] = SpotPosition {
+            market_index: 1,
+            balance_type: SpotBalanceType::Borrow,
+            scaled_balance: 10 * SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        };
+        let user = User {
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
+            spot_positions,
+            ..User::default()
+        };
+
+        let MarginCalculation {
+            margin_requirement,
+            total_collateral,
+            ..
+        } = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::standard(MarginRequirementType::Initial),
+        )
+        .unwrap();
+
+        // 10 sol * $100 * 1.2
+        assert_eq!(margin_requirement, 1200000000);
+        assert_eq!(total_collateral, 10000000000);
+
+        let MarginCalculation {
+            margin_requirement,
+            total_collateral,
+            ..
+        } = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::standard(MarginRequirementType::Initial).confidence_adjusted(true),
+        )
+        .unwrap();
+
+        // borrow valued at $100 + 2 * $1 conf
+        assert_eq!(margin_requirement, 1224000000);
+        // usdc market has no multiplier
+        assert_eq!(total_collateral, 10000000000);
+    }
 }
 
 #[cfg(test)]
@@ -1370,7 +1591,7 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1479,7 +1700,7 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1583,7 +1804,7 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1591,8 +1812,8 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -1730,7 +1951,7 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Borrow,
@@ -1738,8 +1959,8 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -1896,7 +2117,7 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
         create_anchor_account_info!(market, PerpMarket, market_account_info);
         let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1904,7 +2125,7 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: BASE_PRECISION_I64,
@@ -2054,7 +2275,7 @@ mod calculate_max_withdrawable_amount {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2069,8 +2290,8 @@ mod calculate_max_withdrawable_amount {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2143,7 +2364,7 @@ mod calculate_max_withdrawable_amount {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Borrow,
@@ -2157,8 +2378,8 @@ mod calculate_max_withdrawable_amount {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2231,7 +2452,7 @@ mod calculate_max_withdrawable_amount {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Borrow,
@@ -2245,8 +2466,8 @@ mod calculate_max_withdrawable_amount {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2340,7 +2561,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2355,8 +2576,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2418,7 +2639,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2433,8 +2654,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2496,7 +2717,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2511,8 +2732,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2574,7 +2795,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2589,8 +2810,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2652,7 +2873,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2667,8 +2888,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2730,7 +2951,7 @@ mod validate_spot_margin_trading {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Borrow,
@@ -2745,8 +2966,8 @@ mod validate_spot_margin_trading {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2861,7 +3082,7 @@ mod calculate_user_equity {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2869,7 +3090,7 @@ mod calculate_user_equity {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: BASE_PRECISION_I64,
@@ -2959,7 +3180,7 @@ mod calculate_user_equity {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2967,7 +3188,7 @@ mod calculate_user_equity {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: BASE_PRECISION_I64,
@@ -3041,7 +3262,7 @@ mod calculate_user_equity {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -3055,8 +3276,8 @@ mod calculate_user_equity {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -7,7 +7,7 @@ use crate::math::constants::{BID_ASK_SPREAD_PRECISION_I128, TEN_BPS_I64};
 use crate::math::orders::calculate_quote_asset_amount_for_maker_order;
 use crate::math::safe_math::SafeMath;
 
-use crate::state::user::Order;
+use crate::state::user::{Order, SelfTradePreventionMode};
 
 #[cfg(test)]
 mod tests;
@@ -59,6 +59,31 @@ pub fn do_orders_cross(
     }
 }
 
+/// Base asset amount the taker and maker orders are reduced by when orders from the same authority
+/// would match. An order reduced by its full unfilled amount is canceled
+pub fn calculate_self_trade_prevention_decrements(
+    self_trade_prevention_mode: SelfTradePreventionMode,
+    taker_base_asset_amount_unfilled: u64,
+    maker_base_asset_amount_unfilled: u64,
+) -> (u64, u64) {
+    match self_trade_prevention_mode {
+        SelfTradePreventionMode::None => (0, 0),
+        SelfTradePreventionMode::CancelMaker => (0, maker_base_asset_amount_unfilled),
+        SelfTradePreventionMode::CancelTaker => (taker_base_asset_amount_unfilled, 0),
+        SelfTradePreventionMode::CancelBoth => (
+            taker_base_asset_amount_unfilled,
+            maker_base_asset_amount_unfilled,
+        ),
+        SelfTradePreventionMode::DecrementAndCancel => {
+            let decrement = min(
+                taker_base_asset_amount_unfilled,
+                maker_base_asset_amount_unfilled,
+            );
+            (decrement, decrement)
+        }
+    }
+}
+
 pub fn calculate_fill_for_matched_orders(
     maker_base_asset_amount: u64,
     maker_price: u64,
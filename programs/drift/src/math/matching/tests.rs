@@ -255,3 +255,41 @@ fn filler_multiplier_maker_short() {
 
     assert_eq!(mult, 2100); // 2.1x
 }
+
+mod calculate_self_trade_prevention_decrements {
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::math::matching::calculate_self_trade_prevention_decrements;
+    use crate::state::user::SelfTradePreventionMode;
+
+    #[test]
+    fn modes() {
+        let taker_base_asset_amount_unfilled = BASE_PRECISION_U64;
+        let maker_base_asset_amount_unfilled = 3 * BASE_PRECISION_U64;
+
+        let decrements = |mode| {
+            calculate_self_trade_prevention_decrements(
+                mode,
+                taker_base_asset_amount_unfilled,
+                maker_base_asset_amount_unfilled,
+            )
+        };
+
+        assert_eq!(decrements(SelfTradePreventionMode::None), (0, 0));
+        assert_eq!(
+            decrements(SelfTradePreventionMode::CancelMaker),
+            (0, 3 * BASE_PRECISION_U64)
+        );
+        assert_eq!(
+            decrements(SelfTradePreventionMode::CancelTaker),
+            (BASE_PRECISION_U64, 0)
+        );
+        assert_eq!(
+            decrements(SelfTradePreventionMode::CancelBoth),
+            (BASE_PRECISION_U64, 3 * BASE_PRECISION_U64)
+        );
+        assert_eq!(
+            decrements(SelfTradePreventionMode::DecrementAndCancel),
+            (BASE_PRECISION_U64, BASE_PRECISION_U64)
+        );
+    }
+}
@@ -3,6 +3,7 @@ pub mod amm_jit;
 pub mod amm_spread;
 pub mod auction;
 pub mod bankruptcy;
+pub mod batch_auction;
 pub mod bn;
 pub mod casting;
 pub mod ceil_div;
@@ -15,7 +15,7 @@ use crate::{
     SPOT_WEIGHT_PRECISION_I128,
 };
 
-use crate::math::constants::MARGIN_PRECISION_U128;
+use crate::math::constants::{MARGIN_PRECISION_U128, ONE_BPS_DENOMINATOR};
 use crate::math::margin::{
     calculate_margin_requirement_and_total_collateral_and_liability_info, MarginRequirementType,
 };
@@ -33,7 +33,8 @@ use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::user::{
-    MarketType, Order, OrderFillSimulation, OrderStatus, OrderTriggerCondition, PerpPosition, User,
+    MarketType, Order, OrderFillSimulation, OrderStatus, OrderTriggerCondition, PerpPosition,
+    TrailingStopType, User,
 };
 use crate::state::user_map::UserMap;
 use crate::validate;
@@ -209,6 +210,29 @@ pub fn standardize_price(
     }
 }
 
+/// Worst price an order can fill at, max_slippage_bps from the oracle price. Rounded towards the
+/// oracle price so the bound is never exceeded
+pub fn calculate_max_slippage_price(
+    oracle_price: i64,
+    direction: PositionDirection,
+    max_slippage_bps: u16,
+    tick_size: u64,
+) -> DriftResult<u64> {
+    let oracle_price = oracle_price.cast::<u64>()?;
+    let slippage = oracle_price
+        .cast::<u128>()?
+        .safe_mul(max_slippage_bps.cast()?)?
+        .safe_div(ONE_BPS_DENOMINATOR.cast()?)?
+        .cast::<u64>()?;
+
+    let price = match direction {
+        PositionDirection::Long => oracle_price.safe_add(slippage)?,
+        PositionDirection::Short => oracle_price.safe_sub(slippage)?,
+    };
+
+    standardize_price(price, tick_size, direction)
+}
+
 pub fn standardize_price_i64(
     price: i64,
     tick_size: i64,
@@ -328,12 +352,74 @@ pub fn should_expire_order(user: &User, user_order_index: usize, now: i64) -> Dr
     Ok(now > order.max_ts)
 }
 
+/// Whether the order's max slippage, rather than its own limit price, keeps it from filling once its
+/// auction is over
+pub fn should_cancel_max_slippage_order(
+    order: &Order,
+    valid_oracle_price: Option<i64>,
+    slot: u64,
+    tick_size: u64,
+) -> DriftResult<bool> {
+    if order.status != OrderStatus::Open
+        || !order.has_max_slippage()
+        || (order.must_be_triggered() && !order.triggered())
+        || !order.is_auction_complete(slot)?
+    {
+        return Ok(false);
+    }
+
+    let oracle_price = match valid_oracle_price {
+        Some(oracle_price) => oracle_price,
+        None => return Ok(false),
+    };
+
+    let max_slippage_price = calculate_max_slippage_price(
+        oracle_price,
+        order.direction,
+        order.max_slippage_bps,
+        tick_size,
+    )?;
+
+    let limit_price = order.get_limit_price(Some(oracle_price), None, slot, tick_size)?;
+
+    let should_cancel = match limit_price {
+        None => true,
+        Some(limit_price) => match order.direction {
+            PositionDirection::Long => limit_price > max_slippage_price,
+            PositionDirection::Short => limit_price < max_slippage_price,
+        },
+    };
+
+    Ok(should_cancel)
+}
+
+/// Size a close position order needs to fully close the position, zero if the order wouldn't
+/// reduce it
+pub fn calculate_close_position_base_asset_amount(
+    existing_base_asset_amount: i64,
+    direction: PositionDirection,
+    step_size: u64,
+) -> DriftResult<u64> {
+    let reduces_position = match direction {
+        PositionDirection::Long => existing_base_asset_amount < 0,
+        PositionDirection::Short => existing_base_asset_amount > 0,
+    };
+
+    if !reduces_position {
+        return Ok(0);
+    }
+
+    standardize_base_asset_amount(existing_base_asset_amount.unsigned_abs(), step_size)
+}
+
 pub fn should_cancel_reduce_only_order(
     order: &Order,
     existing_base_asset_amount: i64,
 ) -> DriftResult<bool> {
+    // twap waiting on its next slice has nothing to fill yet, checked again once the slice is released
     let should_cancel = order.status == OrderStatus::Open
         && order.reduce_only
+        && !order.is_waiting_for_twap_slice()
         && order.get_base_asset_amount_unfilled(Some(existing_base_asset_amount))? == 0;
 
     Ok(should_cancel)
@@ -542,6 +628,135 @@ pub fn order_satisfies_trigger_condition(order: &Order, oracle_price: u64) -> Dr
     }
 }
 
+/// Ratchets a trailing stop's trigger price toward the oracle price. Below triggers only move up and
+/// Above triggers only move down, so the trigger price trails the best oracle price seen.
+/// A trigger price of 0 is treated as unset
+pub fn calculate_trailing_stop_trigger_price(
+    order: &Order,
+    oracle_price: u64,
+    tick_size: u64,
+) -> DriftResult<u64> {
+    let trail = match order.trailing_stop_type {
+        TrailingStopType::FixedOffset => order.trailing_stop_offset.cast::<u64>()?,
+        TrailingStopType::Percentage => oracle_price
+            .cast::<u128>()?
+            .safe_mul(order.trailing_stop_offset.cast()?)?
+            .safe_div(PERCENTAGE_PRECISION)?
+            .cast::<u64>()?,
+        TrailingStopType::None => return Ok(order.trigger_price),
+    };
+
+    match order.trigger_condition {
+        OrderTriggerCondition::Below => {
+            let trailing_price = standardize_price(
+                oracle_price.saturating_sub(trail),
+                tick_size,
+                PositionDirection::Long,
+            )?;
+            Ok(order.trigger_price.max(trailing_price))
+        }
+        OrderTriggerCondition::Above => {
+            let trailing_price = standardize_price(
+                oracle_price.safe_add(trail)?,
+                tick_size,
+                PositionDirection::Short,
+            )?;
+            if order.trigger_price == 0 {
+                Ok(trailing_price)
+            } else {
+                Ok(order.trigger_price.min(trailing_price))
+            }
+        }
+        _ => Err(print_error!(ErrorCode::InvalidTriggerOrderCondition)()),
+    }
+}
+
+/// Splits a twap order's base asset amount into slice_count equal slices, rounded down to the step size.
+/// The first slice is released when the order is placed and another every interval seconds after.
+/// Returns the slice size and the ts the next slice is released
+pub fn calculate_twap_order_schedule(
+    base_asset_amount: u64,
+    slice_count: u16,
+    interval: u32,
+    step_size: u64,
+    now: i64,
+) -> DriftResult<(u64, i64)> {
+    validate!(
+        slice_count > 0,
+        ErrorCode::InvalidTwapOrder,
+        "Twap slice count must be greater than 0"
+    )?;
+
+    validate!(
+        interval > 0,
+        ErrorCode::InvalidTwapOrder,
+        "Twap interval must be greater than 0"
+    )?;
+
+    let slice_base_asset_amount =
+        standardize_base_asset_amount(base_asset_amount.safe_div(slice_count.cast()?)?, step_size)?;
+
+    validate!(
+        slice_base_asset_amount > 0,
+        ErrorCode::InvalidTwapOrder,
+        "Base asset amount ({}) too small to split into {} slices of step size {}",
+        base_asset_amount,
+        slice_count,
+        step_size
+    )?;
+
+    let next_slice_ts = now.safe_add(interval.cast()?)?;
+
+    Ok((slice_base_asset_amount, next_slice_ts))
+}
+
+/// Size of the next twap slice. The last slice releases everything left, so the remainder from rounding
+/// the slices down to the step size isn't dropped
+pub fn calculate_twap_slice_base_asset_amount(
+    base_asset_amount_unreleased: u64,
+    slice_base_asset_amount: u64,
+    slices_remaining: u16,
+) -> u64 {
+    if slices_remaining <= 1 {
+        base_asset_amount_unreleased
+    } else {
+        slice_base_asset_amount.min(base_asset_amount_unreleased)
+    }
+}
+
+/// The next twap slice is released once the current slice is filled and the slice's start ts has passed.
+/// Slices that fall behind schedule are released as soon as the previous slice fills
+pub fn should_release_twap_slice(order: &Order, now: i64) -> DriftResult<bool> {
+    Ok(order.status == OrderStatus::Open
+        && order.is_waiting_for_twap_slice()
+        && order.twap_slices_remaining > 0
+        && now >= order.twap_next_slice_ts)
+}
+
+/// Splits an iceberg order into the displayed base asset amount and the hidden remainder.
+/// The displayed amount is rounded down to the step size
+pub fn calculate_iceberg_order_sizes(
+    base_asset_amount: u64,
+    display_base_asset_amount: u64,
+    step_size: u64,
+) -> DriftResult<(u64, u64)> {
+    let display_base_asset_amount =
+        standardize_base_asset_amount(display_base_asset_amount, step_size)?;
+
+    validate!(
+        display_base_asset_amount > 0 && display_base_asset_amount < base_asset_amount,
+        ErrorCode::InvalidIcebergOrder,
+        "Iceberg display base asset amount ({}) must be at least step size ({}) and less than base asset amount ({})",
+        display_base_asset_amount,
+        step_size,
+        base_asset_amount
+    )?;
+
+    let hidden_base_asset_amount = base_asset_amount.safe_sub(display_base_asset_amount)?;
+
+    Ok((display_base_asset_amount, hidden_base_asset_amount))
+}
+
 pub fn is_new_order_risk_increasing(
     order: &Order,
     position_base_asset_amount: i64,
@@ -552,13 +767,16 @@ pub fn is_new_order_risk_increasing(
         return Ok(false);
     }
 
+    // includes unreleased twap slices and the hidden part of an iceberg
+    let base_asset_amount = order.get_base_asset_amount_unfilled(None)?;
+
     match order.direction {
         PositionDirection::Long => {
             if position_base_asset_amount >= 0 {
                 return Ok(true);
             }
 
-            Ok(position_bids.safe_add(order.base_asset_amount.cast()?)?
+            Ok(position_bids.safe_add(base_asset_amount.cast()?)?
                 > position_base_asset_amount.abs())
         }
         PositionDirection::Short => {
@@ -566,9 +784,7 @@ pub fn is_new_order_risk_increasing(
                 return Ok(true);
             }
 
-            Ok(position_asks
-                .safe_sub(order.base_asset_amount.cast()?)?
-                .abs()
+            Ok(position_asks.safe_sub(base_asset_amount.cast()?)?.abs()
                 > position_base_asset_amount)
         }
     }
@@ -598,6 +814,7 @@ pub fn validate_fill_price(
     base_precision: u64,
     order_direction: PositionDirection,
     order_limit_price: u64,
+    order_max_slippage_price: Option<u64>,
     is_taker: bool,
 ) -> DriftResult {
     let rounded_quote_asset_amount = if is_taker {
@@ -639,6 +856,24 @@ pub fn validate_fill_price(
         return Err(ErrorCode::InvalidOrderFillPrice);
     }
 
+    if let Some(max_slippage_price) = order_max_slippage_price {
+        let breaches_max_slippage = match order_direction {
+            PositionDirection::Long => fill_price > max_slippage_price,
+            PositionDirection::Short => fill_price < max_slippage_price,
+        };
+
+        if breaches_max_slippage {
+            msg!(
+                "{:?} order fill price ({}) breaches max slippage price ({}) is_taker={}",
+                order_direction,
+                fill_price,
+                max_slippage_price,
+                is_taker
+            );
+            return Err(ErrorCode::InvalidOrderFillPrice);
+        }
+    }
+
     Ok(())
 }
 
@@ -738,6 +973,11 @@ pub fn find_maker_orders(
             continue;
         }
 
+        // if order is waiting on its parent order to fill, skip
+        if order.is_pending_linked_order() {
+            continue;
+        }
+
         let limit_price = order.force_get_limit_price(valid_oracle_price, None, slot, tick_size)?;
 
         orders.push((order_index, limit_price));
@@ -765,7 +1005,9 @@ pub fn calculate_max_perp_order_size(
         perp_market_map,
         spot_market_map,
         oracle_map,
-        MarginContext::standard(MarginRequirementType::Initial).strict(true),
+        MarginContext::standard(MarginRequirementType::Initial)
+            .strict(true)
+            .confidence_adjusted(true),
     )?;
 
     let user_custom_margin_ratio = user.max_margin_ratio;
@@ -887,7 +1129,9 @@ pub fn calculate_max_spot_order_size(
         perp_market_map,
         spot_market_map,
         oracle_map,
-        MarginContext::standard(MarginRequirementType::Initial).strict(true),
+        MarginContext::standard(MarginRequirementType::Initial)
+            .strict(true)
+            .confidence_adjusted(true),
     )?;
 
     let user_custom_margin_ratio = user.max_margin_ratio;
@@ -109,6 +109,49 @@ pub mod standardize_base_asset_amount {
     }
 }
 
+pub mod calculate_close_position_base_asset_amount {
+    use crate::controller::position::PositionDirection;
+    use crate::math::orders::calculate_close_position_base_asset_amount;
+
+    #[test]
+    fn reduces_position() {
+        let step_size: u64 = 100000;
+
+        let result =
+            calculate_close_position_base_asset_amount(250001, PositionDirection::Short, step_size)
+                .unwrap();
+        assert_eq!(result, 200000);
+
+        let result =
+            calculate_close_position_base_asset_amount(-300000, PositionDirection::Long, step_size)
+                .unwrap();
+        assert_eq!(result, 300000);
+    }
+
+    #[test]
+    fn doesnt_reduce_position() {
+        let step_size: u64 = 100000;
+
+        let result =
+            calculate_close_position_base_asset_amount(300000, PositionDirection::Long, step_size)
+                .unwrap();
+        assert_eq!(result, 0);
+
+        let result = calculate_close_position_base_asset_amount(
+            -300000,
+            PositionDirection::Short,
+            step_size,
+        )
+        .unwrap();
+        assert_eq!(result, 0);
+
+        let result =
+            calculate_close_position_base_asset_amount(0, PositionDirection::Short, step_size)
+                .unwrap();
+        assert_eq!(result, 0);
+    }
+}
+
 pub mod is_multiple_of_step_size {
     use crate::math::orders::is_multiple_of_step_size;
 
@@ -467,7 +510,7 @@ mod get_max_fill_amounts {
         };
         let quote_market = SpotMarket::default_quote_market();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
@@ -505,7 +548,7 @@ mod get_max_fill_amounts {
         let base_market = SpotMarket::default_base_market();
         let quote_market = SpotMarket::default_quote_market();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
@@ -547,7 +590,7 @@ mod get_max_fill_amounts {
         };
         let quote_market = SpotMarket::default_quote_market();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
@@ -588,7 +631,7 @@ mod get_max_fill_amounts {
             ..SpotMarket::default_quote_market()
         };
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
@@ -626,7 +669,7 @@ mod get_max_fill_amounts {
         let base_market = SpotMarket::default_base_market();
         let quote_market = SpotMarket::default_quote_market();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
@@ -669,7 +712,7 @@ mod get_max_fill_amounts {
             ..SpotMarket::default_quote_market()
         };
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
@@ -744,7 +787,7 @@ mod find_maker_orders {
                 market_type: MarketType::Perp,
                 direction: PositionDirection::Long,
                 ..Order::default()
-            }; 32],
+            }; 48],
             ..User::default()
         };
         let direction = PositionDirection::Long;
@@ -779,7 +822,7 @@ mod find_maker_orders {
                 market_type: MarketType::Perp,
                 direction: PositionDirection::Long,
                 ..Order::default()
-            }; 32],
+            }; 48],
             ..User::default()
         };
         let direction = PositionDirection::Long;
@@ -814,7 +857,7 @@ mod find_maker_orders {
                 direction: PositionDirection::Short,
                 price: PRICE_PRECISION_U64,
                 ..Order::default()
-            }; 32],
+            }; 48],
             ..User::default()
         };
         let direction = PositionDirection::Long;
@@ -849,7 +892,7 @@ mod find_maker_orders {
                 direction: PositionDirection::Long,
                 price: PRICE_PRECISION_U64,
                 ..Order::default()
-            }; 32],
+            }; 48],
             ..User::default()
         };
         let direction = PositionDirection::Long;
@@ -884,7 +927,7 @@ mod find_maker_orders {
                 direction: PositionDirection::Long,
                 price: PRICE_PRECISION_U64,
                 ..Order::default()
-            }; 32],
+            }; 48],
             ..User::default()
         };
         let direction = PositionDirection::Long;
@@ -910,7 +953,7 @@ mod find_maker_orders {
 
     #[test]
     fn only_one_maker_bid() {
-        let mut orders = [Order::default(); 32];
+        let mut orders = [Order::default(); 48];
         orders[0] = Order {
             status: OrderStatus::Open,
             order_type: OrderType::Limit,
@@ -948,7 +991,7 @@ mod find_maker_orders {
 
     #[test]
     fn multiple_maker_bids() {
-        let mut orders = [Order::default(); 32];
+        let mut orders = [Order::default(); 48];
         for (i, order) in orders.iter_mut().enumerate() {
             *order = Order {
                 status: OrderStatus::Open,
@@ -984,7 +1027,7 @@ mod find_maker_orders {
         .unwrap();
 
         let mut expected_orders = vec![];
-        for i in 0..32 {
+        for i in 0..48 {
             expected_orders.push((i, (i as u64 + 1) * PRICE_PRECISION_U64));
         }
 
@@ -993,7 +1036,7 @@ mod find_maker_orders {
 
     #[test]
     fn multiple_asks() {
-        let mut orders = [Order::default(); 32];
+        let mut orders = [Order::default(); 48];
         for (i, order) in orders.iter_mut().enumerate() {
             *order = Order {
                 status: OrderStatus::Open,
@@ -1029,7 +1072,7 @@ mod find_maker_orders {
         .unwrap();
 
         let mut expected_orders = vec![];
-        for i in 0..32 {
+        for i in 0..48 {
             expected_orders.push((i, (i as u64 + 1) * PRICE_PRECISION_U64));
         }
 
@@ -1121,7 +1164,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1134,8 +1177,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -1228,7 +1271,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1242,8 +1285,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -1336,7 +1379,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1349,8 +1392,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -1443,7 +1486,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1457,8 +1500,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -1533,7 +1576,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1546,8 +1589,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             max_margin_ratio: MARGIN_PRECISION / 2, // 50% margin ratio or 2x leverage
             ..User::default()
@@ -1641,7 +1684,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1654,8 +1697,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             max_margin_ratio: MARGIN_PRECISION / 2, // 2x
             ..User::default()
@@ -1750,7 +1793,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1763,8 +1806,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -1858,7 +1901,7 @@ mod calculate_max_spot_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -1871,8 +1914,8 @@ mod calculate_max_spot_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
-            perp_positions: [PerpPosition::default(); 8],
+            orders: [Order::default(); 48],
+            perp_positions: [PerpPosition::default(); 16],
             spot_positions,
             ..User::default()
         };
@@ -2016,7 +2059,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2024,7 +2067,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 ..PerpPosition::default()
@@ -2138,7 +2181,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2146,7 +2189,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: -500000000000,
@@ -2243,7 +2286,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2251,7 +2294,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 ..PerpPosition::default()
@@ -2365,7 +2408,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2373,7 +2416,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 base_asset_amount: 500000000000,
@@ -2470,7 +2513,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2478,7 +2521,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 ..PerpPosition::default()
@@ -2593,7 +2636,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2601,7 +2644,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 ..PerpPosition::default()
@@ -2717,7 +2760,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2725,7 +2768,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 ..PerpPosition::default()
@@ -2840,7 +2883,7 @@ mod calculate_max_perp_order_size {
         let spot_market_map =
             SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
 
-        let mut spot_positions = [SpotPosition::default(); 8];
+        let mut spot_positions = [SpotPosition::default(); 16];
         spot_positions[0] = SpotPosition {
             market_index: 0,
             balance_type: SpotBalanceType::Deposit,
@@ -2848,7 +2891,7 @@ mod calculate_max_perp_order_size {
             ..SpotPosition::default()
         };
         let mut user = User {
-            orders: [Order::default(); 32],
+            orders: [Order::default(); 48],
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 ..PerpPosition::default()
@@ -3415,7 +3458,7 @@ pub mod find_bids_and_asks_from_users {
             ..OraclePriceData::default()
         };
 
-        let mut maker_orders = [Order::default(); 32];
+        let mut maker_orders = [Order::default(); 48];
         for (i, order) in maker_orders.iter_mut().enumerate().take(16) {
             *order = Order {
                 status: OrderStatus::Open,
@@ -3481,3 +3524,467 @@ pub mod find_bids_and_asks_from_users {
         assert_eq!(asks, expected_asks);
     }
 }
+
+mod calculate_trailing_stop_trigger_price {
+    use crate::math::constants::{
+        PERCENTAGE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64,
+    };
+    use crate::math::orders::{
+        calculate_trailing_stop_trigger_price, order_satisfies_trigger_condition,
+    };
+    use crate::state::user::{Order, OrderTriggerCondition, OrderType, TrailingStopType};
+
+    #[test]
+    fn fixed_offset_below() {
+        let mut order = Order {
+            order_type: OrderType::TriggerMarket,
+            trigger_condition: OrderTriggerCondition::Below,
+            trailing_stop_type: TrailingStopType::FixedOffset,
+            trailing_stop_offset: 5 * PRICE_PRECISION_U64 as u32,
+            ..Order::default()
+        };
+        let tick_size = PRICE_PRECISION_U64 / 100;
+
+        // unset trigger price is initialized from oracle
+        order.trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 100 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(order.trigger_price, 95 * PRICE_PRECISION_U64);
+
+        // ratchets up with oracle
+        order.trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 110 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(order.trigger_price, 105 * PRICE_PRECISION_U64);
+
+        // doesn't move down when oracle falls
+        order.trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 107 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(order.trigger_price, 105 * PRICE_PRECISION_U64);
+        assert!(!order_satisfies_trigger_condition(&order, 107 * PRICE_PRECISION_U64).unwrap());
+
+        order.trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 104 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(order.trigger_price, 105 * PRICE_PRECISION_U64);
+        assert!(order_satisfies_trigger_condition(&order, 104 * PRICE_PRECISION_U64).unwrap());
+    }
+
+    #[test]
+    fn percentage_above() {
+        let mut order = Order {
+            order_type: OrderType::TriggerLimit,
+            trigger_condition: OrderTriggerCondition::Above,
+            trailing_stop_type: TrailingStopType::Percentage,
+            trailing_stop_offset: (PERCENTAGE_PRECISION_U64 / 20) as u32, // 5%
+            trigger_price: 120 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        let tick_size = PRICE_PRECISION_U64 / 100;
+
+        // tighter than existing trigger price
+        order.trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 100 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(order.trigger_price, 105 * PRICE_PRECISION_U64);
+
+        // ratchets down with oracle, rounded up to tick size
+        order.trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 90_123_456, tick_size).unwrap();
+        assert_eq!(order.trigger_price, 94_630_000);
+
+        // doesn't move up when oracle rises
+        order.trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 92 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(order.trigger_price, 94_630_000);
+        assert!(!order_satisfies_trigger_condition(&order, 92 * PRICE_PRECISION_U64).unwrap());
+        assert!(order_satisfies_trigger_condition(&order, 95 * PRICE_PRECISION_U64).unwrap());
+    }
+
+    #[test]
+    fn not_trailing_stop() {
+        let order = Order {
+            order_type: OrderType::TriggerMarket,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_price: 95 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+
+        let trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 110 * PRICE_PRECISION_U64, 1).unwrap();
+        assert_eq!(trigger_price, 95 * PRICE_PRECISION_U64);
+    }
+
+    #[test]
+    fn trail_is_not_an_oracle_offset() {
+        let order = Order {
+            order_type: OrderType::TriggerLimit,
+            trigger_condition: OrderTriggerCondition::Below,
+            trailing_stop_type: TrailingStopType::FixedOffset,
+            trailing_stop_offset: 5 * PRICE_PRECISION_U64 as u32,
+            trigger_price: 95 * PRICE_PRECISION_U64,
+            price: 94 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+
+        assert!(!order.has_oracle_price_offset());
+        let limit_price = order
+            .get_limit_price(Some(100 * PRICE_PRECISION_I64), None, 0, 1)
+            .unwrap();
+        assert_eq!(limit_price, Some(94 * PRICE_PRECISION_U64));
+    }
+}
+
+mod twap_order {
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::math::orders::{
+        calculate_twap_order_schedule, calculate_twap_slice_base_asset_amount,
+        should_release_twap_slice,
+    };
+    use crate::state::user::{Order, OrderStatus, OrderType};
+
+    #[test]
+    fn schedule() {
+        let step_size = BASE_PRECISION_U64 / 10;
+        let now = 1000;
+
+        // 10 split into 3 slices of 3.3
+        let (slice_base_asset_amount, next_slice_ts) =
+            calculate_twap_order_schedule(10 * BASE_PRECISION_U64, 3, 60, step_size, now).unwrap();
+        assert_eq!(slice_base_asset_amount, 33 * BASE_PRECISION_U64 / 10);
+        assert_eq!(next_slice_ts, 1060);
+
+        // remainder goes to the last slice
+        let mut unreleased = 10 * BASE_PRECISION_U64;
+        let mut slices = vec![];
+        for slices_remaining in (1..=3).rev() {
+            let slice = calculate_twap_slice_base_asset_amount(
+                unreleased,
+                slice_base_asset_amount,
+                slices_remaining,
+            );
+            unreleased -= slice;
+            slices.push(slice);
+        }
+        assert_eq!(
+            slices,
+            vec![
+                33 * BASE_PRECISION_U64 / 10,
+                33 * BASE_PRECISION_U64 / 10,
+                34 * BASE_PRECISION_U64 / 10
+            ]
+        );
+        assert_eq!(unreleased, 0);
+
+        assert!(calculate_twap_order_schedule(BASE_PRECISION_U64, 0, 60, step_size, now).is_err());
+        assert!(calculate_twap_order_schedule(BASE_PRECISION_U64, 3, 0, step_size, now).is_err());
+        // slices smaller than step size
+        assert!(calculate_twap_order_schedule(step_size, 3, 60, step_size, now).is_err());
+    }
+
+    #[test]
+    fn slices() {
+        let mut order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Twap,
+            base_asset_amount: BASE_PRECISION_U64,
+            hidden_base_asset_amount: 2 * BASE_PRECISION_U64,
+            slice_base_asset_amount: BASE_PRECISION_U64,
+            twap_next_slice_ts: 1060,
+            twap_interval: 60,
+            twap_slices_remaining: 2,
+            ..Order::default()
+        };
+
+        assert_eq!(
+            order.get_base_asset_amount_unfilled(None).unwrap(),
+            3 * BASE_PRECISION_U64
+        );
+        assert_eq!(
+            order.get_base_asset_amount_unfilled(Some(0)).unwrap(),
+            BASE_PRECISION_U64
+        );
+
+        // current slice not filled
+        assert!(!should_release_twap_slice(&order, 1060).unwrap());
+
+        order.base_asset_amount_filled = BASE_PRECISION_U64;
+        assert!(order.is_waiting_for_twap_slice());
+        assert_eq!(
+            order.get_base_asset_amount_unfilled(None).unwrap(),
+            2 * BASE_PRECISION_U64
+        );
+        assert!(!should_release_twap_slice(&order, 1059).unwrap());
+        assert!(should_release_twap_slice(&order, 1060).unwrap());
+        // behind schedule, released as soon as the slice fills
+        assert!(should_release_twap_slice(&order, 1181).unwrap());
+
+        // last slice released and filled
+        order.base_asset_amount = 3 * BASE_PRECISION_U64;
+        order.base_asset_amount_filled = 3 * BASE_PRECISION_U64;
+        order.hidden_base_asset_amount = 0;
+        order.twap_slices_remaining = 0;
+        assert_eq!(order.get_base_asset_amount_unfilled(None).unwrap(), 0);
+        assert!(!should_release_twap_slice(&order, 1180).unwrap());
+    }
+}
+
+mod iceberg_order {
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::math::orders::calculate_iceberg_order_sizes;
+    use crate::state::user::{Order, OrderStatus, OrderType};
+
+    #[test]
+    fn sizes() {
+        let step_size = BASE_PRECISION_U64 / 10;
+
+        // display rounded down to step size
+        let (display_base_asset_amount, hidden_base_asset_amount) = calculate_iceberg_order_sizes(
+            10 * BASE_PRECISION_U64,
+            BASE_PRECISION_U64 + 1,
+            step_size,
+        )
+        .unwrap();
+        assert_eq!(display_base_asset_amount, BASE_PRECISION_U64);
+        assert_eq!(hidden_base_asset_amount, 9 * BASE_PRECISION_U64);
+
+        // displays everything
+        assert!(
+            calculate_iceberg_order_sizes(BASE_PRECISION_U64, BASE_PRECISION_U64, step_size)
+                .is_err()
+        );
+        // display smaller than step size
+        assert!(
+            calculate_iceberg_order_sizes(BASE_PRECISION_U64, step_size - 1, step_size).is_err()
+        );
+    }
+
+    #[test]
+    fn unfilled() {
+        // a limit order with a trigger price isn't an iceberg
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            base_asset_amount: BASE_PRECISION_U64,
+            trigger_price: 4 * BASE_PRECISION_U64,
+            ..Order::default()
+        };
+        assert!(!order.is_iceberg());
+
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            base_asset_amount: BASE_PRECISION_U64,
+            base_asset_amount_filled: BASE_PRECISION_U64 / 2,
+            hidden_base_asset_amount: 4 * BASE_PRECISION_U64,
+            slice_base_asset_amount: BASE_PRECISION_U64,
+            trigger_price: 0,
+            ..Order::default()
+        };
+
+        assert!(order.is_iceberg());
+        assert_eq!(
+            order.get_base_asset_amount_unfilled(None).unwrap(),
+            9 * BASE_PRECISION_U64 / 2
+        );
+        // makers only show the displayed amount
+        assert_eq!(
+            order.get_base_asset_amount_unfilled(Some(0)).unwrap(),
+            BASE_PRECISION_U64 / 2
+        );
+    }
+}
+
+mod max_slippage_order {
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{
+        BASE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION_U64,
+    };
+    use crate::math::orders::{
+        calculate_max_slippage_price, should_cancel_max_slippage_order, validate_fill_price,
+    };
+    use crate::state::user::{Order, OrderStatus, OrderType};
+
+    const TICK_SIZE: u64 = PRICE_PRECISION_U64 / 100;
+
+    #[test]
+    fn max_slippage_price() {
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+
+        let price =
+            calculate_max_slippage_price(oracle_price, PositionDirection::Long, 50, TICK_SIZE)
+                .unwrap();
+        assert_eq!(price, 100 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2);
+
+        let price =
+            calculate_max_slippage_price(oracle_price, PositionDirection::Short, 50, TICK_SIZE)
+                .unwrap();
+        assert_eq!(price, 99 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2);
+
+        // rounded towards the oracle price
+        let oracle_price = 100 * PRICE_PRECISION_I64 + 4000;
+        let price =
+            calculate_max_slippage_price(oracle_price, PositionDirection::Long, 100, TICK_SIZE)
+                .unwrap();
+        assert_eq!(price, 101 * PRICE_PRECISION_U64);
+
+        let price =
+            calculate_max_slippage_price(oracle_price, PositionDirection::Short, 100, TICK_SIZE)
+                .unwrap();
+        assert_eq!(price, 99 * PRICE_PRECISION_U64 + TICK_SIZE);
+    }
+
+    #[test]
+    fn limit_price_ignores_max_slippage() {
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            auction_duration: 10,
+            auction_start_price: 100 * PRICE_PRECISION_I64,
+            auction_end_price: 110 * PRICE_PRECISION_I64,
+            max_slippage_bps: 500,
+            ..Order::default()
+        };
+
+        // auction price past the bound
+        let limit_price = order
+            .get_limit_price(Some(oracle_price), None, 9, TICK_SIZE)
+            .unwrap();
+        assert_eq!(limit_price, Some(109 * PRICE_PRECISION_U64));
+
+        // no limit price once the auction is over
+        let limit_price = order
+            .get_limit_price(Some(oracle_price), None, 11, TICK_SIZE)
+            .unwrap();
+        assert_eq!(limit_price, None);
+
+        // doesn't need the oracle
+        let limit_price = order.get_limit_price(None, None, 9, TICK_SIZE).unwrap();
+        assert_eq!(limit_price, Some(109 * PRICE_PRECISION_U64));
+
+        let max_slippage_price = order
+            .get_max_slippage_price(oracle_price, TICK_SIZE)
+            .unwrap();
+        assert_eq!(max_slippage_price, Some(105 * PRICE_PRECISION_U64));
+
+        let unbounded_order = Order {
+            max_slippage_bps: 0,
+            ..order
+        };
+        let max_slippage_price = unbounded_order
+            .get_max_slippage_price(oracle_price, TICK_SIZE)
+            .unwrap();
+        assert_eq!(max_slippage_price, None);
+    }
+
+    #[test]
+    fn fill_price() {
+        let max_slippage_price = Some(105 * PRICE_PRECISION_U64);
+
+        // within limit price and bound
+        assert!(validate_fill_price(
+            104 * QUOTE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            PositionDirection::Long,
+            110 * PRICE_PRECISION_U64,
+            max_slippage_price,
+            true,
+        )
+        .is_ok());
+
+        // within limit price, past the bound
+        assert!(validate_fill_price(
+            106 * QUOTE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            PositionDirection::Long,
+            110 * PRICE_PRECISION_U64,
+            max_slippage_price,
+            true,
+        )
+        .is_err());
+
+        // no bound
+        assert!(validate_fill_price(
+            106 * QUOTE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            PositionDirection::Long,
+            110 * PRICE_PRECISION_U64,
+            None,
+            true,
+        )
+        .is_ok());
+
+        let max_slippage_price = Some(95 * PRICE_PRECISION_U64);
+
+        // short past the bound
+        assert!(validate_fill_price(
+            94 * QUOTE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64,
+            PositionDirection::Short,
+            90 * PRICE_PRECISION_U64,
+            max_slippage_price,
+            true,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn should_cancel() {
+        let oracle_price = Some(100 * PRICE_PRECISION_I64);
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Market,
+            direction: PositionDirection::Short,
+            auction_duration: 10,
+            auction_start_price: 100 * PRICE_PRECISION_I64,
+            auction_end_price: 90 * PRICE_PRECISION_I64,
+            max_slippage_bps: 500,
+            ..Order::default()
+        };
+
+        // auction not over
+        assert!(!should_cancel_max_slippage_order(&order, oracle_price, 10, TICK_SIZE).unwrap());
+
+        // bound is all that limits the order
+        assert!(should_cancel_max_slippage_order(&order, oracle_price, 11, TICK_SIZE).unwrap());
+
+        // own limit price within the bound
+        let limit_order = Order {
+            price: 96 * PRICE_PRECISION_U64,
+            ..order
+        };
+        assert!(
+            !should_cancel_max_slippage_order(&limit_order, oracle_price, 11, TICK_SIZE).unwrap()
+        );
+
+        // own limit price past the bound
+        let limit_order = Order {
+            price: 94 * PRICE_PRECISION_U64,
+            ..order
+        };
+        assert!(
+            should_cancel_max_slippage_order(&limit_order, oracle_price, 11, TICK_SIZE).unwrap()
+        );
+
+        // no bound
+        let unbounded_order = Order {
+            max_slippage_bps: 0,
+            ..order
+        };
+        assert!(
+            !should_cancel_max_slippage_order(&unbounded_order, oracle_price, 11, TICK_SIZE)
+                .unwrap()
+        );
+
+        // invalid oracle
+        assert!(!should_cancel_max_slippage_order(&order, None, 11, TICK_SIZE).unwrap());
+    }
+}
@@ -340,7 +340,7 @@ pub fn calculate_optimal_peg_and_budget(
     let reserve_price_before = market.amm.reserve_price()?;
 
     let mut fee_budget = calculate_fee_pool(market)?;
-    let target_price_i64 = oracle_price_data.price;
+    let target_price_i64 = market.amm.reference_price(oracle_price_data.price)?;
     let target_price = target_price_i64.cast()?;
     let mut optimal_peg = calculate_peg_from_target_price(
         market.amm.quote_asset_reserve,
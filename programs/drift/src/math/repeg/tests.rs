@@ -336,3 +336,68 @@ fn calc_adjust_amm_tests_sufficent_fee_for_repeg() {
     assert_eq!(new_peg, 34657283);
     assert_eq!(_amm_update_cost, 304289);
 }
+
+#[test]
+fn calculate_optimal_peg_and_budget_inventory_skew_test() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 65 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 63015384615,
+            terminal_quote_asset_reserve: 64 * AMM_RESERVE_PRECISION,
+            sqrt_k: 64 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 19_400_000_000,
+            base_asset_amount_with_amm: -(AMM_RESERVE_PRECISION as i128),
+            mark_std: PRICE_PRECISION as u64,
+            last_mark_price_twap_ts: 0,
+            base_spread: 250,
+            curve_update_intensity: 100,
+            max_spread: 500 * 100,
+            total_exchange_fee: QUOTE_PRECISION,
+            total_fee_minus_distributions: (1000 * QUOTE_PRECISION) as i128,
+            ..AMM::default()
+        },
+        margin_ratio_initial: 500,
+
+        ..PerpMarket::default()
+    };
+
+    let oracle_price_data = OraclePriceData {
+        price: (18_901 * PRICE_PRECISION) as i64,
+        confidence: 167,
+        delay: 21,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    // no skew, targets the oracle
+    let (optimal_peg, budget, check_lb) =
+        calculate_optimal_peg_and_budget(&market, &oracle_price_data).unwrap();
+
+    assert_eq!(optimal_peg, 19496270752);
+    assert_eq!(budget, 999500000);
+    assert!(check_lb);
+
+    // amm is long, full inventory skews the target 1% below the oracle
+    market.amm.max_inventory_skew = 10_000;
+    let reference_price = market.amm.reference_price(oracle_price_data.price).unwrap();
+    assert_eq!(reference_price, 18_711_990_000);
+
+    let (skewed_optimal_peg, budget, check_lb) =
+        calculate_optimal_peg_and_budget(&market, &oracle_price_data).unwrap();
+
+    assert_eq!(skewed_optimal_peg, 19301308044);
+    assert_eq!(
+        skewed_optimal_peg,
+        calculate_peg_from_target_price(
+            market.amm.quote_asset_reserve,
+            market.amm.base_asset_reserve,
+            reference_price as u64
+        )
+        .unwrap()
+    );
+    assert!(skewed_optimal_peg < optimal_peg);
+    assert_eq!(budget, 999500000);
+    assert!(check_lb);
+
+    market.amm.peg_multiplier = skewed_optimal_peg;
+    assert_eq!(market.amm.reserve_price().unwrap(), 18711989999);
+}
@@ -0,0 +1,279 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use arrayref::array_ref;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::oracle::{is_oracle_valid_for_action, oracle_validity, DriftAction};
+use crate::math::safe_math::SafeMath;
+use crate::state::oracle::{OraclePriceData, OracleSource};
+use crate::state::state::ValidityGuardRails;
+use crate::state::traits::Size;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum CompositeOracleMode {
+    /// median of every underlying oracle that passes the validity guard rails
+    Median,
+    /// first underlying oracle (in order) that passes the validity guard rails
+    PrimaryWithFallback,
+}
+
+impl Default for CompositeOracleMode {
+    fn default() -> Self {
+        CompositeOracleMode::Median
+    }
+}
+
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct CompositeOracle {
+    /// underlying oracles, unused slots are Pubkey::default()
+    pub oracles: [Pubkey; 4],
+    /// slot the last cached price was observed at (clock slot - underlying delay)
+    pub last_valid_slot: u64,
+    /// last aggregated price, used when the underlying oracles aren't passed in
+    /// precision: PRICE_PRECISION
+    pub price: i64,
+    /// precision: PRICE_PRECISION
+    pub confidence: u64,
+    pub oracle_sources: [OracleSource; 4],
+    pub mode: CompositeOracleMode,
+    /// number of valid underlying oracles needed for the aggregate to have sufficient data points
+    pub min_valid_oracles: u8,
+    pub has_sufficient_number_of_data_points: bool,
+    pub padding: [u8; 25],
+}
+
+impl Size for CompositeOracle {
+    const SIZE: usize = 192;
+}
+
+impl CompositeOracle {
+    pub fn number_of_oracles(&self) -> usize {
+        self.oracles
+            .iter()
+            .filter(|oracle| **oracle != Pubkey::default())
+            .count()
+    }
+
+    pub fn validate(&self) -> DriftResult {
+        let number_of_oracles = self.number_of_oracles();
+
+        validate!(
+            number_of_oracles > 0,
+            ErrorCode::InvalidOracle,
+            "composite oracle must have at least one underlying oracle"
+        )?;
+
+        validate!(
+            self.oracles[..number_of_oracles]
+                .iter()
+                .all(|oracle| *oracle != Pubkey::default()),
+            ErrorCode::InvalidOracle,
+            "composite oracle underlying oracles must be contiguous"
+        )?;
+
+        validate!(
+            self.oracle_sources[..number_of_oracles]
+                .iter()
+                .all(|source| !matches!(source, OracleSource::Composite)),
+            ErrorCode::InvalidOracle,
+            "composite oracle can not contain another composite oracle"
+        )?;
+
+        // the feed id of a pyth pull price update account is only stored per market
+        validate!(
+            self.oracle_sources[..number_of_oracles]
+                .iter()
+                .all(|source| !matches!(source, OracleSource::PythPull)),
+            ErrorCode::InvalidOracle,
+            "composite oracle can not contain a pyth pull oracle"
+        )?;
+
+        validate!(
+            self.min_valid_oracles > 0
+                && self.min_valid_oracles.cast::<usize>()? <= number_of_oracles,
+            ErrorCode::InvalidOracle,
+            "min_valid_oracles={} must be in [1, {}]",
+            self.min_valid_oracles,
+            number_of_oracles
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_cached_price_data(&self, clock_slot: u64) -> DriftResult<OraclePriceData> {
+        let delay = clock_slot
+            .cast::<i64>()?
+            .safe_sub(self.last_valid_slot.cast()?)?;
+
+        Ok(OraclePriceData {
+            price: self.price,
+            confidence: self.confidence,
+            delay,
+            has_sufficient_number_of_data_points: self.has_sufficient_number_of_data_points,
+        })
+    }
+
+    pub fn update_cached_price_data(
+        &mut self,
+        oracle_price_data: &OraclePriceData,
+        clock_slot: u64,
+    ) -> DriftResult {
+        self.price = oracle_price_data.price;
+        self.confidence = oracle_price_data.confidence;
+        self.last_valid_slot = clock_slot
+            .cast::<i64>()?
+            .safe_sub(oracle_price_data.delay)?
+            .max(0)
+            .cast()?;
+        self.has_sufficient_number_of_data_points =
+            oracle_price_data.has_sufficient_number_of_data_points;
+
+        Ok(())
+    }
+}
+
+pub fn is_composite_oracle_account(account_info: &AccountInfo) -> DriftResult<bool> {
+    if account_info.owner != &crate::id() {
+        return Ok(false);
+    }
+
+    let data = account_info
+        .try_borrow_data()
+        .or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    if data.len() < CompositeOracle::SIZE {
+        return Ok(false);
+    }
+
+    Ok(array_ref![data, 0, 8] == &CompositeOracle::discriminator())
+}
+
+pub fn get_composite_oracle_price(
+    price_oracle: &AccountInfo,
+    clock_slot: u64,
+) -> DriftResult<OraclePriceData> {
+    let composite_oracle_loader: AccountLoader<CompositeOracle> =
+        AccountLoader::try_from(price_oracle).or(Err(ErrorCode::UnableToLoadOracle))?;
+    let composite_oracle = composite_oracle_loader
+        .load()
+        .or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    composite_oracle.get_cached_price_data(clock_slot)
+}
+
+/// Combines the underlying oracles' price data (in the order configured on the composite oracle).
+/// Each oracle is checked against the median of all underlying prices with the validity guard rails,
+/// so outliers (too volatile vs the median), wide confidence and stale oracles are dropped.
+/// If no oracle survives, the primary oracle's price data is returned so downstream validity checks flag it.
+pub fn calculate_composite_price_data(
+    mode: CompositeOracleMode,
+    min_valid_oracles: u8,
+    oracle_price_data: &[OraclePriceData],
+    guard_rails: &ValidityGuardRails,
+) -> DriftResult<OraclePriceData> {
+    validate!(
+        !oracle_price_data.is_empty(),
+        ErrorCode::InvalidOracle,
+        "composite oracle has no underlying price data"
+    )?;
+
+    let primary = oracle_price_data[0];
+
+    let positive_prices: Vec<i64> = oracle_price_data
+        .iter()
+        .map(|price_data| price_data.price)
+        .filter(|price| *price > 0)
+        .collect();
+
+    if positive_prices.is_empty() {
+        return Ok(primary);
+    }
+
+    let reference_price = calculate_median(positive_prices)?;
+
+    let mut valid_price_data: Vec<OraclePriceData> = Vec::with_capacity(oracle_price_data.len());
+    for price_data in oracle_price_data.iter() {
+        let validity = oracle_validity(reference_price, price_data, guard_rails)?;
+        if is_oracle_valid_for_action(validity, Some(DriftAction::MarginCalc))? {
+            valid_price_data.push(*price_data);
+        }
+    }
+
+    if valid_price_data.is_empty() {
+        msg!("No valid underlying oracle for composite oracle");
+        return Ok(primary);
+    }
+
+    let has_enough_valid_oracles = valid_price_data.len() >= min_valid_oracles.cast()?;
+
+    let composite_price_data = match mode {
+        CompositeOracleMode::PrimaryWithFallback => {
+            let price_data = valid_price_data[0];
+            OraclePriceData {
+                has_sufficient_number_of_data_points: price_data
+                    .has_sufficient_number_of_data_points
+                    && has_enough_valid_oracles,
+                ..price_data
+            }
+        }
+        CompositeOracleMode::Median => {
+            let price = calculate_median(
+                valid_price_data
+                    .iter()
+                    .map(|price_data| price_data.price)
+                    .collect(),
+            )?;
+
+            let confidence = valid_price_data
+                .iter()
+                .map(|price_data| price_data.confidence)
+                .max()
+                .unwrap_or(0);
+
+            let delay = valid_price_data
+                .iter()
+                .map(|price_data| price_data.delay)
+                .max()
+                .unwrap_or(0);
+
+            let has_sufficient_number_of_data_points = has_enough_valid_oracles
+                && valid_price_data
+                    .iter()
+                    .all(|price_data| price_data.has_sufficient_number_of_data_points);
+
+            OraclePriceData {
+                price,
+                confidence,
+                delay,
+                has_sufficient_number_of_data_points,
+            }
+        }
+    };
+
+    Ok(composite_price_data)
+}
+
+fn calculate_median(mut prices: Vec<i64>) -> DriftResult<i64> {
+    validate!(
+        !prices.is_empty(),
+        ErrorCode::InvalidOracle,
+        "can not take median of empty prices"
+    )?;
+
+    prices.sort_unstable();
+
+    let mid = prices.len() / 2;
+    if prices.len() % 2 == 0 {
+        prices[mid - 1].safe_add(prices[mid])?.safe_div(2)
+    } else {
+        Ok(prices[mid])
+    }
+}
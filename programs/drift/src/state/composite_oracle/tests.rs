@@ -0,0 +1,251 @@
+use anchor_lang::Owner;
+use solana_program::pubkey::Pubkey;
+
+use crate::math::constants::PRICE_PRECISION_I64;
+use crate::state::composite_oracle::{
+    calculate_composite_price_data, CompositeOracle, CompositeOracleMode,
+};
+use crate::state::oracle::{get_oracle_price, OraclePriceData, OracleSource};
+use crate::state::oracle_map::OracleMap;
+use crate::state::state::OracleGuardRails;
+use crate::test_utils::*;
+use crate::{create_account_info, create_anchor_account_info};
+
+fn price_data(price: i64, confidence: u64, delay: i64) -> OraclePriceData {
+    OraclePriceData {
+        price,
+        confidence,
+        delay,
+        has_sufficient_number_of_data_points: true,
+    }
+}
+
+#[test]
+fn median() {
+    let guard_rails = OracleGuardRails::default().validity;
+
+    let oracle_price_data = [
+        price_data(100 * PRICE_PRECISION_I64, 10000, 1),
+        price_data(102 * PRICE_PRECISION_I64, 20000, 3),
+        price_data(101 * PRICE_PRECISION_I64, 30000, 2),
+    ];
+
+    let composite = calculate_composite_price_data(
+        CompositeOracleMode::Median,
+        2,
+        &oracle_price_data,
+        &guard_rails,
+    )
+    .unwrap();
+
+    assert_eq!(composite.price, 101 * PRICE_PRECISION_I64);
+    assert_eq!(composite.confidence, 30000);
+    assert_eq!(composite.delay, 3);
+    assert!(composite.has_sufficient_number_of_data_points);
+
+    // even number of oracles averages the middle two
+    let composite = calculate_composite_price_data(
+        CompositeOracleMode::Median,
+        2,
+        &oracle_price_data[..2],
+        &guard_rails,
+    )
+    .unwrap();
+    assert_eq!(composite.price, 101 * PRICE_PRECISION_I64);
+}
+
+#[test]
+fn median_rejects_outliers() {
+    let guard_rails = OracleGuardRails::default().validity;
+
+    // third oracle is 10x the others, fourth is stale for margin, fifth has too wide a confidence
+    let oracle_price_data = [
+        price_data(100 * PRICE_PRECISION_I64, 10000, 1),
+        price_data(101 * PRICE_PRECISION_I64, 10000, 1),
+        price_data(1000 * PRICE_PRECISION_I64, 10000, 1),
+        price_data(90 * PRICE_PRECISION_I64, 10000, 1000),
+        price_data(80 * PRICE_PRECISION_I64, 20 * PRICE_PRECISION_I64 as u64, 1),
+    ];
+
+    let composite = calculate_composite_price_data(
+        CompositeOracleMode::Median,
+        3,
+        &oracle_price_data,
+        &guard_rails,
+    )
+    .unwrap();
+
+    assert_eq!(composite.price, 100500000);
+    assert_eq!(composite.delay, 1);
+    // only two valid oracles, less than min_valid_oracles
+    assert!(!composite.has_sufficient_number_of_data_points);
+}
+
+#[test]
+fn primary_with_fallback() {
+    let guard_rails = OracleGuardRails::default().validity;
+
+    let oracle_price_data = [
+        price_data(100 * PRICE_PRECISION_I64, 10000, 1000),
+        price_data(101 * PRICE_PRECISION_I64, 20000, 2),
+    ];
+
+    // primary is stale, use fallback
+    let composite = calculate_composite_price_data(
+        CompositeOracleMode::PrimaryWithFallback,
+        1,
+        &oracle_price_data,
+        &guard_rails,
+    )
+    .unwrap();
+
+    assert_eq!(composite.price, 101 * PRICE_PRECISION_I64);
+    assert_eq!(composite.confidence, 20000);
+    assert_eq!(composite.delay, 2);
+    assert!(composite.has_sufficient_number_of_data_points);
+
+    // every oracle is stale, primary is returned so validity flags it
+    let oracle_price_data = [
+        price_data(100 * PRICE_PRECISION_I64, 10000, 1000),
+        price_data(101 * PRICE_PRECISION_I64, 20000, 2000),
+    ];
+
+    let composite = calculate_composite_price_data(
+        CompositeOracleMode::PrimaryWithFallback,
+        1,
+        &oracle_price_data,
+        &guard_rails,
+    )
+    .unwrap();
+
+    assert_eq!(composite.price, 100 * PRICE_PRECISION_I64);
+    assert_eq!(composite.delay, 1000);
+}
+
+#[test]
+fn oracle_map_live_and_cached() {
+    let slot = 200;
+    let pyth_program = crate::ids::pyth_program::id();
+
+    let primary_key = Pubkey::new_unique();
+    let mut primary_price = get_hardcoded_pyth_price(100 * PRICE_PRECISION_I64, 6);
+    primary_price.valid_slot = 0; // stale
+    create_account_info!(
+        primary_price,
+        &primary_key,
+        &pyth_program,
+        primary_account_info
+    );
+
+    let fallback_key = Pubkey::new_unique();
+    let mut fallback_price = get_hardcoded_pyth_price(101 * PRICE_PRECISION_I64, 6);
+    fallback_price.valid_slot = 199;
+    create_account_info!(
+        fallback_price,
+        &fallback_key,
+        &pyth_program,
+        fallback_account_info
+    );
+
+    let composite_key = Pubkey::new_unique();
+    let mut composite_oracle = CompositeOracle {
+        oracles: [
+            primary_key,
+            fallback_key,
+            Pubkey::default(),
+            Pubkey::default(),
+        ],
+        oracle_sources: [OracleSource::Pyth; 4],
+        mode: CompositeOracleMode::PrimaryWithFallback,
+        min_valid_oracles: 1,
+        price: 99 * PRICE_PRECISION_I64,
+        confidence: 1,
+        last_valid_slot: 190,
+        has_sufficient_number_of_data_points: true,
+        ..CompositeOracle::default()
+    };
+    composite_oracle.validate().unwrap();
+    create_anchor_account_info!(
+        composite_oracle,
+        &composite_key,
+        CompositeOracle,
+        composite_account_info
+    );
+
+    let oracle_account_infos = Vec::from([
+        composite_account_info.clone(),
+        primary_account_info,
+        fallback_account_info,
+    ]);
+    let mut oracle_map =
+        OracleMap::load(&mut oracle_account_infos.iter().peekable(), slot, None).unwrap();
+
+    let (oracle_price_data, _) = oracle_map
+        .get_price_data_and_validity(&composite_key, 101 * PRICE_PRECISION_I64)
+        .unwrap();
+    assert_eq!(oracle_price_data.price, 101 * PRICE_PRECISION_I64);
+    assert_eq!(oracle_price_data.delay, 1);
+
+    // without the underlying oracles, the cached aggregate is used
+    let mut oracle_map = OracleMap::load_one(&composite_account_info, slot, None).unwrap();
+    let oracle_price_data = oracle_map.get_price_data(&composite_key).unwrap();
+    assert_eq!(oracle_price_data.price, 99 * PRICE_PRECISION_I64);
+    assert_eq!(oracle_price_data.delay, 10);
+
+    let oracle_price_data =
+        get_oracle_price(&OracleSource::Composite, &composite_account_info, slot).unwrap();
+    assert_eq!(oracle_price_data.price, 99 * PRICE_PRECISION_I64);
+}
+
+#[test]
+fn update_cached_price_data() {
+    let mut composite_oracle = CompositeOracle::default();
+    composite_oracle
+        .update_cached_price_data(&price_data(101 * PRICE_PRECISION_I64, 20000, 2), 100)
+        .unwrap();
+
+    assert_eq!(composite_oracle.price, 101 * PRICE_PRECISION_I64);
+    assert_eq!(composite_oracle.confidence, 20000);
+    assert_eq!(composite_oracle.last_valid_slot, 98);
+    assert!(composite_oracle.has_sufficient_number_of_data_points);
+
+    let oracle_price_data = composite_oracle.get_cached_price_data(105).unwrap();
+    assert_eq!(oracle_price_data.delay, 7);
+}
+
+#[test]
+fn validate() {
+    let oracle = Pubkey::new_unique();
+
+    let composite_oracle = CompositeOracle::default();
+    assert!(composite_oracle.validate().is_err());
+
+    let composite_oracle = CompositeOracle {
+        oracles: [oracle, Pubkey::default(), oracle, Pubkey::default()],
+        min_valid_oracles: 1,
+        ..CompositeOracle::default()
+    };
+    assert!(composite_oracle.validate().is_err());
+
+    let composite_oracle = CompositeOracle {
+        oracles: [oracle, oracle, Pubkey::default(), Pubkey::default()],
+        min_valid_oracles: 3,
+        ..CompositeOracle::default()
+    };
+    assert!(composite_oracle.validate().is_err());
+
+    let composite_oracle = CompositeOracle {
+        oracles: [oracle, oracle, Pubkey::default(), Pubkey::default()],
+        oracle_sources: [OracleSource::Composite; 4],
+        min_valid_oracles: 1,
+        ..CompositeOracle::default()
+    };
+    assert!(composite_oracle.validate().is_err());
+
+    let composite_oracle = CompositeOracle {
+        oracles: [oracle, oracle, Pubkey::default(), Pubkey::default()],
+        min_valid_oracles: 2,
+        ..CompositeOracle::default()
+    };
+    assert!(composite_oracle.validate().is_ok());
+}
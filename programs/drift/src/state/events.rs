@@ -284,6 +284,13 @@ pub enum OrderActionExplanation {
     OrderFillWithPhoenix,
     OrderFilledWithAMMJitLPSplit,
     OrderFilledWithLPJit,
+    CanceledByLinkedOrder,
+    SelfTradePrevention,
+    HeartbeatExpired,
+    SignedQuote,
+    OrderFilledWithBatchAuction,
+    MaxSlippageExceeded,
+    OrderBookIndexFull,
 }
 
 impl Default for OrderAction {
@@ -514,6 +521,38 @@ pub struct SwapRecord {
     pub fee: u64,
 }
 
+#[event]
+#[derive(Default)]
+pub struct ModifyOrderRecord {
+    pub ts: i64,
+    pub user: Pubkey,
+    /// order being modified, 0 if it was looked up by another id and not found
+    pub order_id: u32,
+    pub user_order_id: u8,
+    pub client_order_id: u64,
+    /// replacement order, 0 unless modified
+    pub new_order_id: u32,
+    pub result: ModifyOrderResult,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
+pub enum ModifyOrderResult {
+    /// the order was canceled and a replacement placed
+    Modified,
+    /// the order wasn't found
+    Skipped,
+    /// the original order was canceled but the replacement wasn't placed, e.g. a post only order
+    /// that would have crossed
+    Failed,
+}
+
+impl Default for ModifyOrderResult {
+    // UpOnly
+    fn default() -> Self {
+        ModifyOrderResult::Skipped
+    }
+}
+
 pub fn emit_stack<T: AnchorSerialize + Discriminator, const N: usize>(event: T) -> DriftResult {
     let mut data_buf = [0u8; N];
     let mut out_buf = [0u8; N];
@@ -12,6 +12,8 @@ pub enum FillMode {
     Fill,
     PlaceAndMake,
     PlaceAndTake,
+    SignedQuote,
+    BatchAuction,
 }
 
 impl FillMode {
@@ -23,10 +25,10 @@ impl FillMode {
         tick_size: u64,
     ) -> DriftResult<Option<u64>> {
         match self {
-            FillMode::Fill | FillMode::PlaceAndMake => {
+            FillMode::Fill | FillMode::PlaceAndMake | FillMode::BatchAuction => {
                 order.get_limit_price(valid_oracle_price, None, slot, tick_size)
             }
-            FillMode::PlaceAndTake => {
+            FillMode::PlaceAndTake | FillMode::SignedQuote => {
                 if order.has_auction() {
                     calculate_auction_price(
                         order,
@@ -19,6 +19,9 @@ pub struct MarginContext {
     pub margin_type: MarginRequirementType,
     pub mode: MarginCalculationMode,
     pub strict: bool,
+    /// value assets at price - k * confidence and liabilities at price + k * confidence,
+    /// k being the market's margin_confidence_multiplier
+    pub confidence_adjusted: bool,
 }
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, AnchorSerialize, AnchorDeserialize)]
@@ -49,6 +52,7 @@ impl MarginContext {
             margin_type,
             mode: MarginCalculationMode::Standard,
             strict: false,
+            confidence_adjusted: false,
         }
     }
 
@@ -57,6 +61,11 @@ impl MarginContext {
         self
     }
 
+    pub fn confidence_adjusted(mut self, confidence_adjusted: bool) -> Self {
+        self.confidence_adjusted = confidence_adjusted;
+        self
+    }
+
     pub fn liquidation(margin_buffer: u32) -> Self {
         Self {
             margin_type: MarginRequirementType::Maintenance,
@@ -65,6 +74,7 @@ impl MarginContext {
                 market_to_track_margin_requirement: None,
             },
             strict: false,
+            confidence_adjusted: false,
         }
     }
 
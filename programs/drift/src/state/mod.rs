@@ -1,3 +1,4 @@
+pub mod composite_oracle;
 pub mod events;
 pub mod fill_mode;
 pub mod fulfillment;
@@ -6,9 +7,13 @@ pub mod insurance_fund_stake;
 pub mod margin_calculation;
 pub mod oracle;
 pub mod oracle_map;
+pub mod oracle_price_history;
+pub mod order_book_index;
 pub mod order_params;
 pub mod perp_market;
 pub mod perp_market_map;
+pub mod perp_order_book_index_map;
+pub mod prelaunch_oracle;
 pub mod spot_fulfillment_params;
 pub mod spot_market;
 pub mod spot_market_map;
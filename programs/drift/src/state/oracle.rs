@@ -1,9 +1,12 @@
 use anchor_lang::prelude::*;
+use arrayref::array_ref;
 
 use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
 use crate::math::constants::{PRICE_PRECISION, PRICE_PRECISION_I64, PRICE_PRECISION_U64};
 use crate::math::safe_math::SafeMath;
+use crate::state::composite_oracle::get_composite_oracle_price;
+use crate::state::prelaunch_oracle::get_prelaunch_oracle_price;
 
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::validate;
@@ -105,6 +108,9 @@ pub enum OracleSource {
     Pyth1K,
     Pyth1M,
     PythStableCoin,
+    Composite,
+    PythPull,
+    Prelaunch,
 }
 
 impl Default for OracleSource {
@@ -143,10 +149,10 @@ pub fn get_oracle_price(
         OracleSource::Pyth1K => get_pyth_price(price_oracle, clock_slot, 1000),
         OracleSource::Pyth1M => get_pyth_price(price_oracle, clock_slot, 1000000),
         OracleSource::PythStableCoin => get_pyth_stable_coin_price(price_oracle, clock_slot),
-        OracleSource::Switchboard => {
-            msg!("Switchboard oracle not yet supported");
-            Err(crate::error::ErrorCode::InvalidOracle)
-        }
+        OracleSource::Switchboard => get_switchboard_price(price_oracle, clock_slot),
+        OracleSource::Composite => get_composite_oracle_price(price_oracle, clock_slot),
+        OracleSource::PythPull => get_pyth_pull_price(price_oracle, clock_slot, 1),
+        OracleSource::Prelaunch => get_prelaunch_oracle_price(price_oracle, clock_slot),
         OracleSource::QuoteAsset => Ok(OraclePriceData {
             price: PRICE_PRECISION_I64,
             confidence: 1,
@@ -166,10 +172,32 @@ pub fn get_pyth_price(
         .or(Err(crate::error::ErrorCode::UnableToLoadOracle))?;
     let price_data = pyth_client::cast::<pyth_client::Price>(&pyth_price_data);
 
-    let oracle_price = price_data.agg.price;
-    let oracle_conf = price_data.agg.conf;
+    let (oracle_price_scaled, oracle_conf_scaled) = scale_pyth_price(
+        price_data.agg.price,
+        price_data.agg.conf,
+        price_data.expo,
+        multiple,
+    )?;
 
-    let oracle_precision = 10_u128.pow(price_data.expo.unsigned_abs());
+    let oracle_delay: i64 = clock_slot
+        .cast::<i64>()?
+        .safe_sub(price_data.valid_slot.cast()?)?;
+
+    Ok(OraclePriceData {
+        price: oracle_price_scaled,
+        confidence: oracle_conf_scaled,
+        delay: oracle_delay,
+        has_sufficient_number_of_data_points: true,
+    })
+}
+
+fn scale_pyth_price(
+    oracle_price: i64,
+    oracle_conf: u64,
+    expo: i32,
+    multiple: u128,
+) -> DriftResult<(i64, u64)> {
+    let oracle_precision = 10_u128.pow(expo.unsigned_abs());
 
     if oracle_precision <= multiple {
         msg!("Multiple larger than oracle precision");
@@ -199,9 +227,85 @@ pub fn get_pyth_price(
         .safe_div(oracle_scale_div)?
         .cast::<u64>()?;
 
+    Ok((oracle_price_scaled, oracle_conf_scaled))
+}
+
+pub const PYTH_PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+/// Byte offsets into a pyth receiver `PriceUpdateV2` account with full verification (including the 8 byte discriminator)
+pub const PYTH_PULL_VERIFICATION_LEVEL_OFFSET: usize = 40;
+pub const PYTH_PULL_FEED_ID_OFFSET: usize = 41;
+pub const PYTH_PULL_PRICE_OFFSET: usize = 73;
+pub const PYTH_PULL_CONF_OFFSET: usize = 81;
+pub const PYTH_PULL_EXPONENT_OFFSET: usize = 89;
+pub const PYTH_PULL_PUBLISH_TIME_OFFSET: usize = 93;
+pub const PYTH_PULL_EMA_PRICE_OFFSET: usize = 109;
+pub const PYTH_PULL_EMA_CONF_OFFSET: usize = 117;
+pub const PYTH_PULL_POSTED_SLOT_OFFSET: usize = 125;
+pub const PYTH_PULL_PRICE_UPDATE_SIZE: usize = 133;
+const PYTH_PULL_VERIFICATION_LEVEL_FULL: u8 = 1;
+
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PythPullPriceMessage {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    pub ema_price: i64,
+    pub ema_conf: u64,
+    pub posted_slot: u64,
+}
+
+impl PythPullPriceMessage {
+    pub fn load(price_oracle: &AccountInfo) -> DriftResult<Self> {
+        let data = price_oracle
+            .try_borrow_data()
+            .or(Err(crate::error::ErrorCode::UnableToLoadOracle))?;
+
+        validate!(
+            data.len() >= PYTH_PULL_PRICE_UPDATE_SIZE
+                && array_ref![data, 0, 8] == &PYTH_PRICE_UPDATE_V2_DISCRIMINATOR,
+            ErrorCode::UnableToLoadOracle,
+            "not a pyth price update account"
+        )?;
+
+        validate!(
+            data[PYTH_PULL_VERIFICATION_LEVEL_OFFSET] == PYTH_PULL_VERIFICATION_LEVEL_FULL,
+            ErrorCode::InvalidOracle,
+            "pyth price update must be fully verified"
+        )?;
+
+        Ok(PythPullPriceMessage {
+            feed_id: *array_ref![data, PYTH_PULL_FEED_ID_OFFSET, 32],
+            price: i64::from_le_bytes(*array_ref![data, PYTH_PULL_PRICE_OFFSET, 8]),
+            conf: u64::from_le_bytes(*array_ref![data, PYTH_PULL_CONF_OFFSET, 8]),
+            exponent: i32::from_le_bytes(*array_ref![data, PYTH_PULL_EXPONENT_OFFSET, 4]),
+            publish_time: i64::from_le_bytes(*array_ref![data, PYTH_PULL_PUBLISH_TIME_OFFSET, 8]),
+            ema_price: i64::from_le_bytes(*array_ref![data, PYTH_PULL_EMA_PRICE_OFFSET, 8]),
+            ema_conf: u64::from_le_bytes(*array_ref![data, PYTH_PULL_EMA_CONF_OFFSET, 8]),
+            posted_slot: u64::from_le_bytes(*array_ref![data, PYTH_PULL_POSTED_SLOT_OFFSET, 8]),
+        })
+    }
+}
+
+/// Pull updates don't carry a valid slot, the delay is measured from the slot the update was posted at
+pub fn get_pyth_pull_price(
+    price_oracle: &AccountInfo,
+    clock_slot: u64,
+    multiple: u128,
+) -> DriftResult<OraclePriceData> {
+    let price_message = PythPullPriceMessage::load(price_oracle)?;
+
+    let (oracle_price_scaled, oracle_conf_scaled) = scale_pyth_price(
+        price_message.price,
+        price_message.conf,
+        price_message.exponent,
+        multiple,
+    )?;
+
     let oracle_delay: i64 = clock_slot
         .cast::<i64>()?
-        .safe_sub(price_data.valid_slot.cast()?)?;
+        .safe_sub(price_message.posted_slot.cast()?)?;
 
     Ok(OraclePriceData {
         price: oracle_price_scaled,
@@ -211,6 +315,45 @@ pub fn get_pyth_price(
     })
 }
 
+/// Anyone can post an update for any feed to a price update account they own, so the account is
+/// only trusted for the feed id stored on the market
+pub fn validate_pyth_pull_feed_id(price_oracle: &AccountInfo, feed_id: &[u8; 32]) -> DriftResult {
+    let price_message = PythPullPriceMessage::load(price_oracle)?;
+
+    validate!(
+        &price_message.feed_id == feed_id,
+        ErrorCode::InvalidOracle,
+        "pyth price update {} is not for the market's feed id",
+        price_oracle.key
+    )?;
+
+    Ok(())
+}
+
+/// The feed id to store on a market using the oracle, zeroed for sources without one
+pub fn get_oracle_feed_id(
+    oracle_source: &OracleSource,
+    price_oracle: &AccountInfo,
+) -> DriftResult<[u8; 32]> {
+    match oracle_source {
+        OracleSource::PythPull => Ok(PythPullPriceMessage::load(price_oracle)?.feed_id),
+        _ => Ok([0; 32]),
+    }
+}
+
+pub fn get_pyth_pull_twap(price_oracle: &AccountInfo, multiple: u128) -> DriftResult<i64> {
+    let price_message = PythPullPriceMessage::load(price_oracle)?;
+
+    let (oracle_twap_scaled, _) = scale_pyth_price(
+        price_message.ema_price,
+        price_message.ema_conf,
+        price_message.exponent,
+        multiple,
+    )?;
+
+    Ok(oracle_twap_scaled)
+}
+
 pub fn get_pyth_stable_coin_price(
     price_oracle: &AccountInfo,
     clock_slot: u64,
@@ -228,51 +371,120 @@ pub fn get_pyth_stable_coin_price(
     Ok(oracle_price_data)
 }
 
-// pub fn get_switchboard_price(
-//     _price_oracle: &AccountInfo,
-//     _clock_slot: u64,
-// ) -> DriftResult<OraclePriceData> {
-//     updating solana/anchor cause this to make compiler complan
-//     fix when we're using switchboard again
-//     let aggregator_data = AggregatorAccountData::new(price_oracle)
-//         .or(Err(crate::error::ErrorCode::UnableToLoadOracle))?;
-//
-//     let price = convert_switchboard_decimal(&aggregator_data.latest_confirmed_round.result)?;
-//     let confidence =
-//         convert_switchboard_decimal(&aggregator_data.latest_confirmed_round.std_deviation)?;
-//
-//     // std deviation should always be positive, if we get a negative make it u128::MAX so it's flagged as bad value
-//     let confidence = if confidence < 0 {
-//         u128::MAX
-//     } else {
-//         let price_10bps = price
-//             .unsigned_abs()
-//             .safe_div(1000)
-//             ?;
-//         max(confidence.unsigned_abs(), price_10bps)
-//     };
-//
-//     let delay: i64 = cast_to_i64(clock_slot)?
-//         .safe_sub(cast(
-//             aggregator_data.latest_confirmed_round.round_open_slot,
-//         )?)
-//         ?;
-//
-//     let has_sufficient_number_of_data_points =
-//         aggregator_data.latest_confirmed_round.num_success >= aggregator_data.min_oracle_results;
-//
-//     Ok(OraclePriceData {
-//         price,
-//         confidence,
-//         delay,
-//         has_sufficient_number_of_data_points,
-//     })
-// }
+/// Byte offsets into a switchboard v2 `AggregatorAccountData` (packed, including the 8 byte discriminator)
+pub const SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET: usize = 236;
+pub const SWITCHBOARD_LATEST_ROUND_NUM_SUCCESS_OFFSET: usize = 341;
+pub const SWITCHBOARD_LATEST_ROUND_OPEN_SLOT_OFFSET: usize = 350;
+pub const SWITCHBOARD_LATEST_ROUND_RESULT_OFFSET: usize = 366;
+pub const SWITCHBOARD_LATEST_ROUND_STD_DEVIATION_OFFSET: usize = 386;
+pub const SWITCHBOARD_DECIMAL_SIZE: usize = 20;
+
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SwitchboardDecimal {
+    pub mantissa: i128,
+    pub scale: u32,
+}
+
+impl SwitchboardDecimal {
+    pub fn from_bytes(bytes: &[u8; SWITCHBOARD_DECIMAL_SIZE]) -> Self {
+        SwitchboardDecimal {
+            mantissa: i128::from_le_bytes(*array_ref![bytes, 0, 16]),
+            scale: u32::from_le_bytes(*array_ref![bytes, 16, 4]),
+        }
+    }
+}
+
+pub fn get_switchboard_price(
+    price_oracle: &AccountInfo,
+    clock_slot: u64,
+) -> DriftResult<OraclePriceData> {
+    let aggregator_data = price_oracle
+        .try_borrow_data()
+        .or(Err(crate::error::ErrorCode::UnableToLoadOracle))?;
+
+    validate!(
+        aggregator_data.len()
+            >= SWITCHBOARD_LATEST_ROUND_STD_DEVIATION_OFFSET + SWITCHBOARD_DECIMAL_SIZE,
+        ErrorCode::UnableToLoadOracle,
+        "switchboard aggregator account too small ({} bytes)",
+        aggregator_data.len()
+    )?;
+
+    let min_oracle_results = u32::from_le_bytes(*array_ref![
+        aggregator_data,
+        SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET,
+        4
+    ]);
+    let num_success = u32::from_le_bytes(*array_ref![
+        aggregator_data,
+        SWITCHBOARD_LATEST_ROUND_NUM_SUCCESS_OFFSET,
+        4
+    ]);
+    let round_open_slot = u64::from_le_bytes(*array_ref![
+        aggregator_data,
+        SWITCHBOARD_LATEST_ROUND_OPEN_SLOT_OFFSET,
+        8
+    ]);
+    let result = SwitchboardDecimal::from_bytes(array_ref![
+        aggregator_data,
+        SWITCHBOARD_LATEST_ROUND_RESULT_OFFSET,
+        SWITCHBOARD_DECIMAL_SIZE
+    ]);
+    let std_deviation = SwitchboardDecimal::from_bytes(array_ref![
+        aggregator_data,
+        SWITCHBOARD_LATEST_ROUND_STD_DEVIATION_OFFSET,
+        SWITCHBOARD_DECIMAL_SIZE
+    ]);
+
+    let price = convert_switchboard_decimal(&result)?.cast::<i64>()?;
+    let confidence = convert_switchboard_decimal(&std_deviation)?;
+
+    // std deviation should always be positive, if we get a negative make it u64::MAX so it's flagged as bad value
+    let confidence = if confidence < 0 {
+        u64::MAX
+    } else {
+        let price_10bps = price.unsigned_abs().safe_div(1000)?;
+        confidence.cast::<u64>()?.max(price_10bps)
+    };
+
+    let delay = clock_slot
+        .cast::<i64>()?
+        .safe_sub(round_open_slot.cast()?)?;
+
+    let has_sufficient_number_of_data_points = num_success >= min_oracle_results;
+
+    Ok(OraclePriceData {
+        price,
+        confidence,
+        delay,
+        has_sufficient_number_of_data_points,
+    })
+}
+
+/// Given a decimal number represented as a mantissa (the digits) plus a scale (number of decimals),
+/// rescale the mantissa to PRICE_PRECISION
+pub fn convert_switchboard_decimal(switchboard_decimal: &SwitchboardDecimal) -> DriftResult<i128> {
+    let switchboard_precision = 10_u128
+        .checked_pow(switchboard_decimal.scale)
+        .safe_unwrap()?;
+    if switchboard_precision > PRICE_PRECISION {
+        switchboard_decimal
+            .mantissa
+            .safe_div(switchboard_precision.safe_div(PRICE_PRECISION)?.cast()?)
+    } else {
+        switchboard_decimal
+            .mantissa
+            .safe_mul(PRICE_PRECISION.safe_div(switchboard_precision)?.cast()?)
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct StrictOraclePrice {
     pub current: i64,
     pub twap_5min: Option<i64>,
+    /// subtracted from min / added to max, e.g. k * oracle confidence
+    /// precision: PRICE_PRECISION
+    pub confidence_offset: i64,
 }
 
 impl StrictOraclePrice {
@@ -280,21 +492,31 @@ impl StrictOraclePrice {
         Self {
             current: price,
             twap_5min: if enabled { Some(twap_5min) } else { None },
+            confidence_offset: 0,
         }
     }
 
+    pub fn with_confidence_offset(mut self, confidence_offset: i64) -> Self {
+        self.confidence_offset = confidence_offset.max(0);
+        self
+    }
+
     pub fn max(&self) -> i64 {
-        match self.twap_5min {
+        let max = match self.twap_5min {
             Some(twap) => self.current.max(twap),
             None => self.current,
-        }
+        };
+
+        max.saturating_add(self.confidence_offset)
     }
 
     pub fn min(&self) -> i64 {
-        match self.twap_5min {
+        let min = match self.twap_5min {
             Some(twap) => self.current.min(twap),
             None => self.current,
-        }
+        };
+
+        min.saturating_sub(self.confidence_offset).max(0)
     }
 
     pub fn validate(&self) -> DriftResult {
@@ -324,6 +546,7 @@ impl StrictOraclePrice {
         Self {
             current: price,
             twap_5min: None,
+            confidence_offset: 0,
         }
     }
 }
@@ -3,8 +3,19 @@ use std::str::FromStr;
 use solana_program::pubkey::Pubkey;
 
 use crate::create_account_info;
-use crate::state::oracle::{get_oracle_price, OracleSource};
+use crate::math::oracle::{oracle_validity, OracleValidity};
+use crate::state::oracle::{
+    get_oracle_feed_id, get_oracle_price, get_pyth_pull_price, get_switchboard_price,
+    validate_pyth_pull_feed_id, OracleSource, SwitchboardDecimal,
+    PYTH_PRICE_UPDATE_V2_DISCRIMINATOR, PYTH_PULL_CONF_OFFSET, PYTH_PULL_EMA_PRICE_OFFSET,
+    PYTH_PULL_EXPONENT_OFFSET, PYTH_PULL_FEED_ID_OFFSET, PYTH_PULL_POSTED_SLOT_OFFSET,
+    PYTH_PULL_PRICE_OFFSET, PYTH_PULL_PRICE_UPDATE_SIZE, PYTH_PULL_VERIFICATION_LEVEL_OFFSET,
+    SWITCHBOARD_LATEST_ROUND_NUM_SUCCESS_OFFSET, SWITCHBOARD_LATEST_ROUND_OPEN_SLOT_OFFSET,
+    SWITCHBOARD_LATEST_ROUND_RESULT_OFFSET, SWITCHBOARD_LATEST_ROUND_STD_DEVIATION_OFFSET,
+    SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET,
+};
 use crate::state::perp_market::AMM;
+use crate::state::state::OracleGuardRails;
 use crate::test_utils::*;
 
 #[test]
@@ -58,3 +69,252 @@ fn pyth_1m() {
     let twap = amm.get_oracle_twap(&oracle_account_info).unwrap();
     assert_eq!(twap, Some(839400));
 }
+
+fn get_switchboard_aggregator_bytes(
+    result: SwitchboardDecimal,
+    std_deviation: SwitchboardDecimal,
+    round_open_slot: u64,
+    num_success: u32,
+    min_oracle_results: u32,
+) -> Vec<u8> {
+    let mut data = vec![0_u8; 3851];
+    data[SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET..SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET + 4]
+        .copy_from_slice(&min_oracle_results.to_le_bytes());
+    data[SWITCHBOARD_LATEST_ROUND_NUM_SUCCESS_OFFSET
+        ..SWITCHBOARD_LATEST_ROUND_NUM_SUCCESS_OFFSET + 4]
+        .copy_from_slice(&num_success.to_le_bytes());
+    data[SWITCHBOARD_LATEST_ROUND_OPEN_SLOT_OFFSET..SWITCHBOARD_LATEST_ROUND_OPEN_SLOT_OFFSET + 8]
+        .copy_from_slice(&round_open_slot.to_le_bytes());
+    for (offset, decimal) in [
+        (SWITCHBOARD_LATEST_ROUND_RESULT_OFFSET, result),
+        (SWITCHBOARD_LATEST_ROUND_STD_DEVIATION_OFFSET, std_deviation),
+    ] {
+        data[offset..offset + 16].copy_from_slice(&decimal.mantissa.to_le_bytes());
+        data[offset + 16..offset + 20].copy_from_slice(&decimal.scale.to_le_bytes());
+    }
+    data
+}
+
+#[test]
+fn switchboard() {
+    // 22.123456789 +/- 0.05
+    let mut data = get_switchboard_aggregator_bytes(
+        SwitchboardDecimal {
+            mantissa: 22123456789,
+            scale: 9,
+        },
+        SwitchboardDecimal {
+            mantissa: 5,
+            scale: 2,
+        },
+        100,
+        3,
+        2,
+    );
+    let oracle_key = Pubkey::default();
+    let owner = crate::ids::switchboard_program::id();
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+
+    let oracle_price_data =
+        get_oracle_price(&OracleSource::Switchboard, &oracle_account_info, 110).unwrap();
+    assert_eq!(oracle_price_data.price, 22123456);
+    assert_eq!(oracle_price_data.confidence, 50000);
+    assert_eq!(oracle_price_data.delay, 10);
+    assert!(oracle_price_data.has_sufficient_number_of_data_points);
+
+    let amm = AMM {
+        oracle_source: OracleSource::Switchboard,
+        ..AMM::default()
+    };
+
+    let twap = amm.get_oracle_twap(&oracle_account_info).unwrap();
+    assert_eq!(twap, None);
+}
+
+#[test]
+fn switchboard_low_precision_and_insufficient_data_points() {
+    // 1.5 with std deviation below 10 bps of price
+    let mut data = get_switchboard_aggregator_bytes(
+        SwitchboardDecimal {
+            mantissa: 15,
+            scale: 1,
+        },
+        SwitchboardDecimal {
+            mantissa: 1,
+            scale: 6,
+        },
+        100,
+        1,
+        2,
+    );
+    let oracle_key = Pubkey::default();
+    let owner = crate::ids::switchboard_program::id();
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+
+    let oracle_price_data = get_switchboard_price(&oracle_account_info, 100).unwrap();
+    assert_eq!(oracle_price_data.price, 1500000);
+    assert_eq!(oracle_price_data.confidence, 1500); // floored at 10 bps
+    assert_eq!(oracle_price_data.delay, 0);
+    assert!(!oracle_price_data.has_sufficient_number_of_data_points);
+}
+
+#[test]
+fn switchboard_negative_std_deviation() {
+    let mut data = get_switchboard_aggregator_bytes(
+        SwitchboardDecimal {
+            mantissa: 15,
+            scale: 1,
+        },
+        SwitchboardDecimal {
+            mantissa: -1,
+            scale: 1,
+        },
+        100,
+        1,
+        1,
+    );
+    let oracle_key = Pubkey::default();
+    let owner = crate::ids::switchboard_program::id();
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+
+    let oracle_price_data = get_switchboard_price(&oracle_account_info, 100).unwrap();
+    assert_eq!(oracle_price_data.confidence, u64::MAX);
+}
+
+#[test]
+fn switchboard_account_too_small() {
+    let mut data = vec![0_u8; SWITCHBOARD_LATEST_ROUND_STD_DEVIATION_OFFSET];
+    let oracle_key = Pubkey::default();
+    let owner = crate::ids::switchboard_program::id();
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+
+    assert!(get_switchboard_price(&oracle_account_info, 100).is_err());
+}
+
+fn get_pyth_pull_price_update_bytes(
+    feed_id: [u8; 32],
+    price: i64,
+    conf: u64,
+    exponent: i32,
+    ema_price: i64,
+    posted_slot: u64,
+) -> Vec<u8> {
+    let mut data = vec![0_u8; PYTH_PULL_PRICE_UPDATE_SIZE];
+    data[..8].copy_from_slice(&PYTH_PRICE_UPDATE_V2_DISCRIMINATOR);
+    data[PYTH_PULL_VERIFICATION_LEVEL_OFFSET] = 1; // Full
+    data[PYTH_PULL_FEED_ID_OFFSET..PYTH_PULL_FEED_ID_OFFSET + 32].copy_from_slice(&feed_id);
+    data[PYTH_PULL_PRICE_OFFSET..PYTH_PULL_PRICE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+    data[PYTH_PULL_CONF_OFFSET..PYTH_PULL_CONF_OFFSET + 8].copy_from_slice(&conf.to_le_bytes());
+    data[PYTH_PULL_EXPONENT_OFFSET..PYTH_PULL_EXPONENT_OFFSET + 4]
+        .copy_from_slice(&exponent.to_le_bytes());
+    data[PYTH_PULL_EMA_PRICE_OFFSET..PYTH_PULL_EMA_PRICE_OFFSET + 8]
+        .copy_from_slice(&ema_price.to_le_bytes());
+    data[PYTH_PULL_POSTED_SLOT_OFFSET..PYTH_PULL_POSTED_SLOT_OFFSET + 8]
+        .copy_from_slice(&posted_slot.to_le_bytes());
+    data
+}
+
+#[test]
+fn pyth_pull() {
+    let posted_slot = 100;
+    // $22.5 +/- .01 with expo -8
+    let mut data =
+        get_pyth_pull_price_update_bytes([1; 32], 2250000000, 1000000, -8, 2240000000, posted_slot);
+    let oracle_key = Pubkey::default();
+    let owner = crate::ids::pyth_pull_program::id();
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+
+    let oracle_price_data = get_oracle_price(
+        &OracleSource::PythPull,
+        &oracle_account_info,
+        posted_slot + 5,
+    )
+    .unwrap();
+    assert_eq!(oracle_price_data.price, 22500000);
+    assert_eq!(oracle_price_data.confidence, 10000);
+    assert_eq!(oracle_price_data.delay, 5);
+    assert!(oracle_price_data.has_sufficient_number_of_data_points);
+
+    let amm = AMM {
+        oracle_source: OracleSource::PythPull,
+        ..AMM::default()
+    };
+
+    let twap = amm.get_oracle_twap(&oracle_account_info).unwrap();
+    assert_eq!(twap, Some(22400000));
+
+    let guard_rails = OracleGuardRails::default().validity;
+    let validity = oracle_validity(22400000, &oracle_price_data, &guard_rails).unwrap();
+    assert_eq!(validity, OracleValidity::Valid);
+
+    let oracle_price_data = get_pyth_pull_price(&oracle_account_info, posted_slot + 75, 1).unwrap();
+    assert_eq!(oracle_price_data.delay, 75);
+    let validity = oracle_validity(22400000, &oracle_price_data, &guard_rails).unwrap();
+    assert_eq!(validity, OracleValidity::StaleForAMM);
+
+    let oracle_price_data =
+        get_pyth_pull_price(&oracle_account_info, posted_slot + 150, 1).unwrap();
+    assert_eq!(oracle_price_data.delay, 150);
+    let validity = oracle_validity(22400000, &oracle_price_data, &guard_rails).unwrap();
+    assert_eq!(validity, OracleValidity::StaleForMargin);
+}
+
+#[test]
+fn pyth_pull_invalid_account() {
+    let posted_slot = 100;
+
+    // partially verified update
+    let mut data =
+        get_pyth_pull_price_update_bytes([1; 32], 2250000000, 1000000, -8, 2240000000, posted_slot);
+    data[PYTH_PULL_VERIFICATION_LEVEL_OFFSET] = 0;
+    let oracle_key = Pubkey::default();
+    let owner = crate::ids::pyth_pull_program::id();
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+    assert!(get_pyth_pull_price(&oracle_account_info, posted_slot, 1).is_err());
+
+    // wrong discriminator
+    let mut data =
+        get_pyth_pull_price_update_bytes([1; 32], 2250000000, 1000000, -8, 2240000000, posted_slot);
+    data[0] = 0;
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+    assert!(get_pyth_pull_price(&oracle_account_info, posted_slot, 1).is_err());
+}
+
+#[test]
+fn pyth_pull_feed_id() {
+    let feed_id = [1; 32];
+    let mut data =
+        get_pyth_pull_price_update_bytes(feed_id, 2250000000, 1000000, -8, 2240000000, 100);
+    let oracle_key = Pubkey::default();
+    let owner = crate::ids::pyth_pull_program::id();
+    let mut lamports = 0;
+    let oracle_account_info =
+        create_account_info(&oracle_key, true, &mut lamports, &mut data[..], &owner);
+
+    assert_eq!(
+        get_oracle_feed_id(&OracleSource::PythPull, &oracle_account_info).unwrap(),
+        feed_id
+    );
+    assert_eq!(
+        get_oracle_feed_id(&OracleSource::Pyth, &oracle_account_info).unwrap(),
+        [0; 32]
+    );
+
+    assert!(validate_pyth_pull_feed_id(&oracle_account_info, &feed_id).is_ok());
+    // update re-posted for another feed
+    assert!(validate_pyth_pull_feed_id(&oracle_account_info, &[2; 32]).is_err());
+}
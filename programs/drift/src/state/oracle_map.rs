@@ -1,9 +1,22 @@
 use crate::error::{DriftResult, ErrorCode};
-use crate::ids::{bonk_oracle, pepe_oracle, pyth_program, usdc_oracle, usdt_oracle_mainnet};
+use crate::ids::{
+    bonk_oracle, pepe_oracle, pyth_program, pyth_pull_program, switchboard_program, usdc_oracle,
+    usdt_oracle_mainnet,
+};
 use crate::math::constants::PRICE_PRECISION_I64;
 use crate::math::oracle::{oracle_validity, OracleValidity};
-use crate::state::oracle::{get_oracle_price, OraclePriceData, OracleSource};
+use crate::state::composite_oracle::{
+    calculate_composite_price_data, is_composite_oracle_account, CompositeOracle,
+};
+use crate::state::oracle::{
+    get_oracle_price, validate_pyth_pull_feed_id, OraclePriceData, OracleSource,
+};
+use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::prelaunch_oracle::is_prelaunch_oracle_account;
+use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::OracleGuardRails;
+use crate::{load, validate};
+use anchor_lang::accounts::account_loader::AccountLoader;
 use anchor_lang::prelude::{AccountInfo, Pubkey};
 use anchor_lang::Key;
 use solana_program::msg;
@@ -66,7 +79,7 @@ impl<'a> OracleMap<'a> {
             }
         };
 
-        let price_data = get_oracle_price(oracle_source, account_info, self.slot)?;
+        let price_data = self.get_oracle_price_data(oracle_source, account_info)?;
 
         self.price_data.insert(*pubkey, price_data);
 
@@ -103,7 +116,7 @@ impl<'a> OracleMap<'a> {
             }
         };
 
-        let price_data = get_oracle_price(oracle_source, account_info, self.slot)?;
+        let price_data = self.get_oracle_price_data(oracle_source, account_info)?;
 
         self.price_data.insert(*pubkey, price_data);
 
@@ -144,7 +157,7 @@ impl<'a> OracleMap<'a> {
             }
         };
 
-        let price_data = get_oracle_price(oracle_source, account_info, self.slot)?;
+        let price_data = self.get_oracle_price_data(oracle_source, account_info)?;
 
         self.price_data.insert(*pubkey, price_data);
 
@@ -154,6 +167,124 @@ impl<'a> OracleMap<'a> {
         Ok((oracle_price_data, validity_guard_rails))
     }
 
+    fn get_oracle_price_data(
+        &self,
+        oracle_source: &OracleSource,
+        account_info: &AccountInfo<'a>,
+    ) -> DriftResult<OraclePriceData> {
+        match oracle_source {
+            OracleSource::Composite => self.get_composite_price_data(account_info),
+            _ => get_oracle_price(oracle_source, account_info, self.slot),
+        }
+    }
+
+    /// Aggregates the composite oracle's underlying oracles if they were all loaded into the map,
+    /// otherwise falls back to the last cached aggregate stored on the composite oracle account
+    pub fn get_composite_price_data(
+        &self,
+        account_info: &AccountInfo,
+    ) -> DriftResult<OraclePriceData> {
+        let composite_oracle_loader: AccountLoader<CompositeOracle> =
+            AccountLoader::try_from(account_info).or(Err(ErrorCode::UnableToLoadOracle))?;
+        let composite_oracle = composite_oracle_loader
+            .load()
+            .or(Err(ErrorCode::UnableToLoadOracle))?;
+
+        match self.get_live_composite_price_data(&composite_oracle)? {
+            Some(oracle_price_data) => Ok(oracle_price_data),
+            None => composite_oracle.get_cached_price_data(self.slot),
+        }
+    }
+
+    pub fn get_live_composite_price_data(
+        &self,
+        composite_oracle: &CompositeOracle,
+    ) -> DriftResult<Option<OraclePriceData>> {
+        let number_of_oracles = composite_oracle.number_of_oracles();
+        let mut oracle_price_data = Vec::with_capacity(number_of_oracles);
+
+        for (oracle, oracle_source) in composite_oracle
+            .oracles
+            .iter()
+            .zip(composite_oracle.oracle_sources.iter())
+            .take(number_of_oracles)
+        {
+            validate!(
+                !matches!(oracle_source, OracleSource::Composite),
+                ErrorCode::InvalidOracle,
+                "composite oracle can not contain another composite oracle"
+            )?;
+
+            let account_info = match self.oracles.get(oracle) {
+                Some(AccountInfoAndOracleSource { account_info, .. }) => account_info,
+                None => return Ok(None),
+            };
+
+            oracle_price_data.push(get_oracle_price(oracle_source, account_info, self.slot)?);
+        }
+
+        calculate_composite_price_data(
+            composite_oracle.mode,
+            composite_oracle.min_valid_oracles,
+            &oracle_price_data,
+            &self.oracle_guard_rails.validity,
+        )
+        .map(Some)
+    }
+
+    /// Checks the pyth pull price update accounts in the map carry the feed id of every perp
+    /// market using them as its oracle
+    pub fn validate_perp_market_pyth_pull_feed_ids(
+        &self,
+        perp_market_map: &PerpMarketMap,
+    ) -> DriftResult {
+        for perp_market_loader in perp_market_map.0.values() {
+            let perp_market = load!(perp_market_loader)?;
+            self.validate_pyth_pull_feed_id(
+                &perp_market.amm.oracle,
+                &perp_market.amm.oracle_source,
+                &perp_market.oracle_feed_id,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the pyth pull price update accounts in the map carry the feed id of every spot
+    /// market using them as its oracle
+    pub fn validate_spot_market_pyth_pull_feed_ids(
+        &self,
+        spot_market_map: &SpotMarketMap,
+    ) -> DriftResult {
+        for spot_market_loader in spot_market_map.0.values() {
+            let spot_market = load!(spot_market_loader)?;
+            self.validate_pyth_pull_feed_id(
+                &spot_market.oracle,
+                &spot_market.oracle_source,
+                &spot_market.oracle_feed_id,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_pyth_pull_feed_id(
+        &self,
+        oracle: &Pubkey,
+        oracle_source: &OracleSource,
+        feed_id: &[u8; 32],
+    ) -> DriftResult {
+        if oracle_source != &OracleSource::PythPull {
+            return Ok(());
+        }
+
+        if let Some(AccountInfoAndOracleSource { account_info, .. }) = self.oracles.get(oracle) {
+            validate_pyth_pull_feed_id(account_info, feed_id)?;
+        }
+
+        Ok(())
+    }
+
     pub fn load<'c>(
         account_info_iter: &'c mut Peekable<Iter<AccountInfo<'a>>>,
         slot: u64,
@@ -162,30 +293,19 @@ impl<'a> OracleMap<'a> {
         let mut oracles: BTreeMap<Pubkey, AccountInfoAndOracleSource<'a>> = BTreeMap::new();
 
         while let Some(account_info) = account_info_iter.peek() {
-            if account_info.owner == &pyth_program::id() {
-                let account_info = account_info_iter.next().safe_unwrap()?;
-                let pubkey = account_info.key();
-
-                let oracle_source = if pubkey == bonk_oracle::id() || pubkey == pepe_oracle::id() {
-                    OracleSource::Pyth1M
-                } else if pubkey == usdc_oracle::id() || pubkey == usdt_oracle_mainnet::id() {
-                    OracleSource::PythStableCoin
-                } else {
-                    OracleSource::Pyth
-                };
-
-                oracles.insert(
-                    pubkey,
-                    AccountInfoAndOracleSource {
-                        account_info: account_info.clone(),
-                        oracle_source,
-                    },
-                );
-
-                continue;
-            }
+            let oracle_source = match get_oracle_source_for_account(account_info)? {
+                Some(oracle_source) => oracle_source,
+                None => break,
+            };
 
-            break;
+            let account_info = account_info_iter.next().safe_unwrap()?;
+            oracles.insert(
+                account_info.key(),
+                AccountInfoAndOracleSource {
+                    account_info: account_info.clone(),
+                    oracle_source,
+                },
+            );
         }
 
         let ogr: OracleGuardRails = if let Some(o) = oracle_guard_rails {
@@ -215,17 +335,9 @@ impl<'a> OracleMap<'a> {
     ) -> DriftResult<OracleMap<'a>> {
         let mut oracles: BTreeMap<Pubkey, AccountInfoAndOracleSource<'a>> = BTreeMap::new();
 
-        if account_info.owner == &pyth_program::id() {
-            let pubkey = account_info.key();
-            let oracle_source = if pubkey == bonk_oracle::id() || pubkey == pepe_oracle::id() {
-                OracleSource::Pyth1M
-            } else if pubkey == usdc_oracle::id() || pubkey == usdt_oracle_mainnet::id() {
-                OracleSource::PythStableCoin
-            } else {
-                OracleSource::Pyth
-            };
+        if let Some(oracle_source) = get_oracle_source_for_account(account_info)? {
             oracles.insert(
-                pubkey,
+                account_info.key(),
                 AccountInfoAndOracleSource {
                     account_info: account_info.clone(),
                     oracle_source,
@@ -256,6 +368,32 @@ impl<'a> OracleMap<'a> {
     }
 }
 
+/// Infers the oracle source from the account owner (and key for pyth), None if the account isn't an oracle
+fn get_oracle_source_for_account(account_info: &AccountInfo) -> DriftResult<Option<OracleSource>> {
+    let oracle_source = if account_info.owner == &pyth_program::id() {
+        let pubkey = account_info.key();
+        if pubkey == bonk_oracle::id() || pubkey == pepe_oracle::id() {
+            OracleSource::Pyth1M
+        } else if pubkey == usdc_oracle::id() || pubkey == usdt_oracle_mainnet::id() {
+            OracleSource::PythStableCoin
+        } else {
+            OracleSource::Pyth
+        }
+    } else if account_info.owner == &pyth_pull_program::id() {
+        OracleSource::PythPull
+    } else if account_info.owner == &switchboard_program::id() {
+        OracleSource::Switchboard
+    } else if is_composite_oracle_account(account_info)? {
+        OracleSource::Composite
+    } else if is_prelaunch_oracle_account(account_info)? {
+        OracleSource::Prelaunch
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(oracle_source))
+}
+
 #[cfg(test)]
 impl<'a> OracleMap<'a> {
     pub fn empty() -> OracleMap<'a> {
@@ -0,0 +1,264 @@
+use anchor_lang::prelude::*;
+use num_integer::Roots;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::PERCENTAGE_PRECISION_I128;
+use crate::math::safe_math::SafeMath;
+use crate::state::oracle::OraclePriceData;
+use crate::state::traits::Size;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+pub const ORACLE_PRICE_HISTORY_CAPACITY: usize = 256;
+
+#[zero_copy(unsafe)]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct OraclePriceSample {
+    /// precision: PRICE_PRECISION
+    pub price: i64,
+    /// precision: PRICE_PRECISION
+    pub confidence: u64,
+    pub ts: i64,
+}
+
+/// Ring buffer of sampled oracle prices for a perp market, appended by keepers in
+/// update_amms / update_funding_rate
+#[account(zero_copy(unsafe))]
+#[derive(Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct OraclePriceHistory {
+    /// oracle the samples were taken from. history is reset if the market's oracle changes
+    pub oracle: Pubkey,
+    /// min seconds between samples
+    pub min_sample_interval: i64,
+    pub last_sample_ts: i64,
+    /// index the next sample is written to
+    pub head: u32,
+    /// number of samples stored, up to ORACLE_PRICE_HISTORY_CAPACITY
+    pub len: u32,
+    pub perp_market_index: u16,
+    pub padding: [u8; 6],
+    pub samples: [OraclePriceSample; ORACLE_PRICE_HISTORY_CAPACITY],
+}
+
+impl Default for OraclePriceHistory {
+    fn default() -> Self {
+        OraclePriceHistory {
+            oracle: Pubkey::default(),
+            min_sample_interval: 0,
+            last_sample_ts: 0,
+            head: 0,
+            len: 0,
+            perp_market_index: 0,
+            padding: [0; 6],
+            samples: [OraclePriceSample::default(); ORACLE_PRICE_HISTORY_CAPACITY],
+        }
+    }
+}
+
+impl Size for OraclePriceHistory {
+    const SIZE: usize = 6216;
+}
+
+impl OraclePriceHistory {
+    pub fn reset(&mut self, oracle: Pubkey) {
+        self.oracle = oracle;
+        self.last_sample_ts = 0;
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Returns true if a sample was appended
+    pub fn append(&mut self, oracle_price_data: &OraclePriceData, now: i64) -> DriftResult<bool> {
+        if oracle_price_data.price <= 0 {
+            return Ok(false);
+        }
+
+        if self.len > 0 && now.safe_sub(self.last_sample_ts)? < self.min_sample_interval {
+            return Ok(false);
+        }
+
+        self.samples[self.head.cast::<usize>()?] = OraclePriceSample {
+            price: oracle_price_data.price,
+            confidence: oracle_price_data.confidence,
+            ts: now,
+        };
+
+        self.head = self.head.safe_add(1)? % ORACLE_PRICE_HISTORY_CAPACITY.cast::<u32>()?;
+        self.len = self
+            .len
+            .safe_add(1)?
+            .min(ORACLE_PRICE_HISTORY_CAPACITY.cast()?);
+        self.last_sample_ts = now;
+
+        Ok(true)
+    }
+
+    /// i-th most recent sample, 0 is the latest
+    pub fn get_sample(&self, i: u32) -> DriftResult<&OraclePriceSample> {
+        validate!(
+            i < self.len,
+            ErrorCode::InvalidOraclePriceHistory,
+            "sample {} out of bounds (len = {})",
+            i,
+            self.len
+        )?;
+
+        let capacity = ORACLE_PRICE_HISTORY_CAPACITY.cast::<u32>()?;
+        let index = self.head.safe_add(capacity)?.safe_sub(1)?.safe_sub(i)? % capacity;
+
+        Ok(&self.samples[index.cast::<usize>()?])
+    }
+
+    /// Samples taken within the window, oldest first
+    fn get_samples_in_window(&self, now: i64, window: i64) -> DriftResult<Vec<OraclePriceSample>> {
+        let window_start = now.safe_sub(window)?;
+
+        let mut samples = Vec::new();
+        for i in 0..self.len {
+            let sample = self.get_sample(i)?;
+            if sample.ts < window_start {
+                break;
+            }
+            samples.push(*sample);
+        }
+        samples.reverse();
+
+        Ok(samples)
+    }
+
+    /// Time weighted average price over the last `window` seconds. Each sample is weighted by how
+    /// long it was the latest sample. Returns None if the history doesn't cover the full window
+    pub fn get_twap(&self, now: i64, window: i64) -> DriftResult<Option<i64>> {
+        validate!(
+            window > 0,
+            ErrorCode::InvalidOraclePriceHistory,
+            "window must be positive"
+        )?;
+
+        if self.len == 0 {
+            return Ok(None);
+        }
+
+        let window_start = now.safe_sub(window)?;
+        if self.get_sample(self.len.safe_sub(1)?)?.ts > window_start {
+            return Ok(None);
+        }
+
+        let mut weighted_price_sum: i128 = 0;
+        let mut total_duration: i128 = 0;
+        let mut end_ts = now;
+        for i in 0..self.len {
+            let sample = self.get_sample(i)?;
+            let start_ts = sample.ts.max(window_start);
+            let duration = end_ts.safe_sub(start_ts)?.max(0).cast::<i128>()?;
+
+            weighted_price_sum =
+                weighted_price_sum.safe_add(sample.price.cast::<i128>()?.safe_mul(duration)?)?;
+            total_duration = total_duration.safe_add(duration)?;
+
+            if sample.ts <= window_start {
+                break;
+            }
+            end_ts = sample.ts;
+        }
+
+        if total_duration == 0 {
+            return Ok(Some(self.get_sample(0)?.price));
+        }
+
+        Ok(Some(
+            weighted_price_sum.safe_div(total_duration)?.cast::<i64>()?,
+        ))
+    }
+
+    /// Min and max price over the last `window` seconds, including the sample that was live at the start of the window
+    pub fn get_min_max_price(&self, now: i64, window: i64) -> DriftResult<Option<(i64, i64)>> {
+        let window_start = now.safe_sub(window)?;
+
+        let mut min_max: Option<(i64, i64)> = None;
+        for i in 0..self.len {
+            let sample = self.get_sample(i)?;
+
+            min_max = Some(match min_max {
+                Some((min, max)) => (min.min(sample.price), max.max(sample.price)),
+                None => (sample.price, sample.price),
+            });
+
+            if sample.ts <= window_start {
+                break;
+            }
+        }
+
+        Ok(min_max)
+    }
+
+    /// Realized volatility over the last `window` seconds, the root mean square of sample to sample returns.
+    /// Not annualized, it is per sample interval
+    /// precision: PERCENTAGE_PRECISION
+    pub fn get_realized_volatility(&self, now: i64, window: i64) -> DriftResult<Option<u64>> {
+        let samples = self.get_samples_in_window(now, window)?;
+
+        if samples.len() < 2 {
+            return Ok(None);
+        }
+
+        let mut sum_squared_returns: u128 = 0;
+        for pair in samples.windows(2) {
+            let last_price = pair[0].price.cast::<i128>()?;
+            let price_return = pair[1]
+                .price
+                .cast::<i128>()?
+                .safe_sub(last_price)?
+                .safe_mul(PERCENTAGE_PRECISION_I128)?
+                .safe_div(last_price)?
+                .unsigned_abs();
+
+            sum_squared_returns =
+                sum_squared_returns.safe_add(price_return.safe_mul(price_return)?)?;
+        }
+
+        let number_of_returns = samples.len().safe_sub(1)?.cast::<u128>()?;
+
+        Ok(Some(
+            sum_squared_returns
+                .safe_div(number_of_returns)?
+                .nth_root(2)
+                .cast::<u64>()?,
+        ))
+    }
+
+    /// Standard deviation of sampled prices around the twap over the last `window` seconds.
+    /// Same units as amm.oracle_std, so it can be used in its place when calculating spreads
+    /// precision: PRICE_PRECISION
+    pub fn get_price_std(&self, now: i64, window: i64) -> DriftResult<Option<u64>> {
+        let twap = match self.get_twap(now, window)? {
+            Some(twap) => twap.cast::<i128>()?,
+            None => return Ok(None),
+        };
+
+        let samples = self.get_samples_in_window(now, window)?;
+
+        if samples.is_empty() {
+            return Ok(None);
+        }
+
+        let mut sum_squared_deviations: u128 = 0;
+        for sample in samples.iter() {
+            let deviation = sample.price.cast::<i128>()?.safe_sub(twap)?.unsigned_abs();
+            sum_squared_deviations =
+                sum_squared_deviations.safe_add(deviation.safe_mul(deviation)?)?;
+        }
+
+        Ok(Some(
+            sum_squared_deviations
+                .safe_div(samples.len().cast()?)?
+                .nth_root(2)
+                .cast::<u64>()?,
+        ))
+    }
+}
@@ -0,0 +1,174 @@
+use crate::math::constants::PRICE_PRECISION_I64;
+use crate::state::oracle::OraclePriceData;
+use crate::state::oracle_price_history::{OraclePriceHistory, ORACLE_PRICE_HISTORY_CAPACITY};
+
+fn price_data(price: i64) -> OraclePriceData {
+    OraclePriceData {
+        price,
+        confidence: 1000,
+        delay: 1,
+        has_sufficient_number_of_data_points: true,
+    }
+}
+
+fn history_with_samples(samples: &[(i64, i64)]) -> OraclePriceHistory {
+    let mut oracle_price_history = OraclePriceHistory::default();
+    for (ts, price) in samples.iter() {
+        assert!(oracle_price_history
+            .append(&price_data(*price), *ts)
+            .unwrap());
+    }
+    oracle_price_history
+}
+
+#[test]
+fn append() {
+    let mut oracle_price_history = OraclePriceHistory {
+        min_sample_interval: 10,
+        ..OraclePriceHistory::default()
+    };
+
+    assert!(oracle_price_history
+        .append(&price_data(100 * PRICE_PRECISION_I64), 0)
+        .unwrap());
+    // too soon
+    assert!(!oracle_price_history
+        .append(&price_data(101 * PRICE_PRECISION_I64), 5)
+        .unwrap());
+    assert!(oracle_price_history
+        .append(&price_data(102 * PRICE_PRECISION_I64), 10)
+        .unwrap());
+    // invalid price
+    assert!(!oracle_price_history.append(&price_data(0), 30).unwrap());
+
+    assert_eq!(oracle_price_history.len, 2);
+    assert_eq!(oracle_price_history.last_sample_ts, 10);
+
+    let latest = oracle_price_history.get_sample(0).unwrap();
+    assert_eq!(latest.price, 102 * PRICE_PRECISION_I64);
+    assert_eq!(latest.confidence, 1000);
+    assert_eq!(latest.ts, 10);
+    assert_eq!(oracle_price_history.get_sample(1).unwrap().ts, 0);
+    assert!(oracle_price_history.get_sample(2).is_err());
+
+    oracle_price_history.reset(oracle_price_history.oracle);
+    assert_eq!(oracle_price_history.len, 0);
+    assert!(oracle_price_history.get_sample(0).is_err());
+}
+
+#[test]
+fn wraps_around() {
+    let mut oracle_price_history = OraclePriceHistory::default();
+
+    let number_of_samples = ORACLE_PRICE_HISTORY_CAPACITY as i64 + 10;
+    for i in 0..number_of_samples {
+        oracle_price_history
+            .append(&price_data((i + 1) * PRICE_PRECISION_I64), i)
+            .unwrap();
+    }
+
+    assert_eq!(
+        oracle_price_history.len as usize,
+        ORACLE_PRICE_HISTORY_CAPACITY
+    );
+    assert_eq!(oracle_price_history.head, 10);
+
+    let latest = oracle_price_history.get_sample(0).unwrap();
+    assert_eq!(latest.ts, number_of_samples - 1);
+    assert_eq!(latest.price, number_of_samples * PRICE_PRECISION_I64);
+
+    let oldest = oracle_price_history
+        .get_sample(ORACLE_PRICE_HISTORY_CAPACITY as u32 - 1)
+        .unwrap();
+    assert_eq!(oldest.ts, 10);
+    assert!(oracle_price_history
+        .get_sample(ORACLE_PRICE_HISTORY_CAPACITY as u32)
+        .is_err());
+}
+
+#[test]
+fn twap() {
+    let oracle_price_history = history_with_samples(&[
+        (0, 100 * PRICE_PRECISION_I64),
+        (30, 110 * PRICE_PRECISION_I64),
+        (45, 120 * PRICE_PRECISION_I64),
+    ]);
+    let now = 60;
+
+    // 100 * 30s + 110 * 15s + 120 * 15s
+    let twap = oracle_price_history.get_twap(now, 60).unwrap();
+    assert_eq!(twap, Some(107_500_000));
+
+    // first sample only counts for the 10s it was live inside the window
+    let twap = oracle_price_history.get_twap(now, 40).unwrap();
+    assert_eq!(twap, Some(111_250_000));
+
+    // history doesn't cover the window
+    let twap = oracle_price_history.get_twap(now, 61).unwrap();
+    assert_eq!(twap, None);
+
+    assert!(oracle_price_history.get_twap(now, 0).is_err());
+    assert_eq!(
+        OraclePriceHistory::default().get_twap(now, 60).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn min_max_price() {
+    let oracle_price_history = history_with_samples(&[
+        (0, 100 * PRICE_PRECISION_I64),
+        (30, 110 * PRICE_PRECISION_I64),
+        (45, 120 * PRICE_PRECISION_I64),
+    ]);
+    let now = 60;
+
+    let min_max = oracle_price_history.get_min_max_price(now, 60).unwrap();
+    assert_eq!(
+        min_max,
+        Some((100 * PRICE_PRECISION_I64, 120 * PRICE_PRECISION_I64))
+    );
+
+    // sample at 30 was still live at the start of the window
+    let min_max = oracle_price_history.get_min_max_price(now, 20).unwrap();
+    assert_eq!(
+        min_max,
+        Some((110 * PRICE_PRECISION_I64, 120 * PRICE_PRECISION_I64))
+    );
+
+    assert_eq!(
+        OraclePriceHistory::default()
+            .get_min_max_price(now, 60)
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn realized_volatility_and_price_std() {
+    let oracle_price_history = history_with_samples(&[
+        (0, 100 * PRICE_PRECISION_I64),
+        (30, 110 * PRICE_PRECISION_I64),
+        (45, 120 * PRICE_PRECISION_I64),
+    ]);
+    let now = 60;
+
+    // returns of 10% and 9.09%
+    let realized_volatility = oracle_price_history
+        .get_realized_volatility(now, 60)
+        .unwrap();
+    assert_eq!(realized_volatility, Some(95562));
+
+    // only one sample in window
+    let realized_volatility = oracle_price_history
+        .get_realized_volatility(now, 20)
+        .unwrap();
+    assert_eq!(realized_volatility, None);
+
+    // deviations of 7.5, 2.5 and 12.5 from the 107.5 twap
+    let price_std = oracle_price_history.get_price_std(now, 60).unwrap();
+    assert_eq!(price_std, Some(8539125));
+
+    let price_std = oracle_price_history.get_price_std(now, 61).unwrap();
+    assert_eq!(price_std, None);
+}
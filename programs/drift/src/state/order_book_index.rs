@@ -0,0 +1,285 @@
+use anchor_lang::prelude::*;
+
+use crate::controller::position::PositionDirection;
+use crate::error::DriftResult;
+use crate::math::casting::Cast;
+use crate::math::matching::do_orders_cross;
+use crate::math::safe_math::SafeMath;
+use crate::state::traits::Size;
+use crate::state::user::{MarketType, Order, OrderStatus, User};
+
+#[cfg(test)]
+mod tests;
+
+pub const PERP_ORDER_BOOK_INDEX_CAPACITY: usize = 64;
+/// Bounds the accounts a batch auction clear has to be passed, every indexed user and their stats
+pub const PERP_ORDER_BOOK_INDEX_MAX_USERS: usize = 24;
+pub const PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER: usize = 8;
+
+#[zero_copy(unsafe)]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct OrderBookIndexEntry {
+    pub user: Pubkey,
+    /// precision: PRICE_PRECISION
+    pub price: u64,
+    /// unfilled base asset amount of the order, excluding the hidden part of an iceberg
+    /// precision: BASE_PRECISION
+    pub base_asset_amount: u64,
+    pub order_id: u32,
+    pub padding: [u8; 4],
+}
+
+/// Price sorted references to resting limit orders in a perp market. Controller functions that
+/// place, cancel, fill, trigger or expire orders sync it whenever it's passed to the instruction.
+/// Fillers read it off chain to find the best makers to pass to a fill, it doesn't supply maker
+/// accounts itself. Fills visit the makers passed in index order, so equal priced orders keep time
+/// priority. Only fixed price orders are indexed, up to PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER
+/// per user and PERP_ORDER_BOOK_INDEX_MAX_USERS users. Batch auction markets require it for every
+/// order, so it holds the full batch
+#[account(zero_copy(unsafe))]
+#[derive(Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct PerpOrderBookIndex {
+    pub perp_market_index: u16,
+    pub bids_len: u16,
+    pub asks_len: u16,
+    pub padding: [u8; 2],
+    /// sorted best (highest) price first, then by time
+    pub bids: [OrderBookIndexEntry; PERP_ORDER_BOOK_INDEX_CAPACITY],
+    /// sorted best (lowest) price first, then by time
+    pub asks: [OrderBookIndexEntry; PERP_ORDER_BOOK_INDEX_CAPACITY],
+}
+
+impl Default for PerpOrderBookIndex {
+    fn default() -> Self {
+        PerpOrderBookIndex {
+            perp_market_index: 0,
+            bids_len: 0,
+            asks_len: 0,
+            padding: [0; 2],
+            bids: [OrderBookIndexEntry::default(); PERP_ORDER_BOOK_INDEX_CAPACITY],
+            asks: [OrderBookIndexEntry::default(); PERP_ORDER_BOOK_INDEX_CAPACITY],
+        }
+    }
+}
+
+impl Size for PerpOrderBookIndex {
+    const SIZE: usize = 7184;
+}
+
+impl PerpOrderBookIndex {
+    pub fn get_orders(&self, direction: PositionDirection) -> &[OrderBookIndexEntry] {
+        match direction {
+            PositionDirection::Long => &self.bids[..self.bids_len as usize],
+            PositionDirection::Short => &self.asks[..self.asks_len as usize],
+        }
+    }
+
+    fn get_side_mut(
+        &mut self,
+        direction: PositionDirection,
+    ) -> (
+        &mut [OrderBookIndexEntry; PERP_ORDER_BOOK_INDEX_CAPACITY],
+        &mut u16,
+    ) {
+        match direction {
+            PositionDirection::Long => (&mut self.bids, &mut self.bids_len),
+            PositionDirection::Short => (&mut self.asks, &mut self.asks_len),
+        }
+    }
+
+    pub fn get_user_order_count(&self, user: &Pubkey) -> usize {
+        [PositionDirection::Long, PositionDirection::Short]
+            .iter()
+            .map(|direction| {
+                self.get_orders(*direction)
+                    .iter()
+                    .filter(|entry| &entry.user == user)
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Users with indexed orders, each once
+    pub fn get_users(&self) -> Vec<Pubkey> {
+        let mut users: Vec<Pubkey> = vec![];
+        for direction in [PositionDirection::Long, PositionDirection::Short] {
+            for entry in self.get_orders(direction) {
+                if !users.contains(&entry.user) {
+                    users.push(entry.user);
+                }
+            }
+        }
+
+        users
+    }
+
+    pub fn contains_order(&self, user: &Pubkey, order_id: u32) -> bool {
+        [PositionDirection::Long, PositionDirection::Short]
+            .iter()
+            .any(|direction| {
+                self.get_orders(*direction)
+                    .iter()
+                    .any(|entry| &entry.user == user && entry.order_id == order_id)
+            })
+    }
+
+    /// Inserts behind orders at the same or a better price, unless the user has the max number of
+    /// orders indexed. When the side is full the worst order is dropped to make room. When the index
+    /// has too many users the worst orders of the longer side are dropped until it doesn't, so
+    /// squatting the index takes better prices than everyone else. Returns true if it was inserted
+    pub fn insert(
+        &mut self,
+        direction: PositionDirection,
+        entry: OrderBookIndexEntry,
+    ) -> DriftResult<bool> {
+        if self.get_user_order_count(&entry.user) >= PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER {
+            return Ok(false);
+        }
+
+        let (entries, len) = self.get_side_mut(direction);
+        let current_len = *len as usize;
+
+        let index = entries[..current_len]
+            .iter()
+            .position(|existing| match direction {
+                PositionDirection::Long => existing.price < entry.price,
+                PositionDirection::Short => existing.price > entry.price,
+            })
+            .unwrap_or(current_len);
+
+        if index >= PERP_ORDER_BOOK_INDEX_CAPACITY {
+            return Ok(false);
+        }
+
+        let new_len = (current_len + 1).min(PERP_ORDER_BOOK_INDEX_CAPACITY);
+        for i in (index + 1..new_len).rev() {
+            entries[i] = entries[i - 1];
+        }
+        entries[index] = entry;
+        *len = new_len.cast()?;
+
+        while self.get_users().len() > PERP_ORDER_BOOK_INDEX_MAX_USERS {
+            let longer_side =
+                if self.get_orders(direction.opposite()).len() > self.get_orders(direction).len() {
+                    direction.opposite()
+                } else {
+                    direction
+                };
+            self.remove_worst(longer_side)?;
+        }
+
+        Ok(self.contains_order(&entry.user, entry.order_id))
+    }
+
+    fn remove_worst(&mut self, direction: PositionDirection) -> DriftResult {
+        let (entries, len) = self.get_side_mut(direction);
+        let new_len = (*len as usize).safe_sub(1)?;
+        entries[new_len] = OrderBookIndexEntry::default();
+        *len = new_len.cast()?;
+
+        Ok(())
+    }
+
+    /// Syncs the index with a user's orders. Entries for orders that are no longer resting or whose
+    /// price changed are removed, remaining entries have their size updated and newly resting
+    /// orders are inserted
+    pub fn update_user_orders(&mut self, user_key: &Pubkey, user: &User) -> DriftResult {
+        let market_index = self.perp_market_index;
+
+        for direction in [PositionDirection::Long, PositionDirection::Short] {
+            let (entries, len) = self.get_side_mut(direction);
+            let current_len = *len as usize;
+
+            let mut new_len = 0_usize;
+            for i in 0..current_len {
+                let mut entry = entries[i];
+                if &entry.user == user_key {
+                    match user.get_order(entry.order_id) {
+                        Some(order)
+                            if is_indexable_order(order, market_index)
+                                && order.direction == direction
+                                && order.price == entry.price =>
+                        {
+                            entry.base_asset_amount = get_displayed_base_asset_amount(order)?;
+                        }
+                        _ => continue,
+                    }
+                }
+                entries[new_len] = entry;
+                new_len += 1;
+            }
+
+            for entry in entries[new_len..current_len].iter_mut() {
+                *entry = OrderBookIndexEntry::default();
+            }
+            *len = new_len.cast()?;
+        }
+
+        for order in user.orders.iter() {
+            if !is_indexable_order(order, market_index)
+                || self.contains_order(user_key, order.order_id)
+            {
+                continue;
+            }
+
+            self.insert(
+                order.direction,
+                OrderBookIndexEntry {
+                    user: *user_key,
+                    price: order.price,
+                    base_asset_amount: get_displayed_base_asset_amount(order)?,
+                    order_id: order.order_id,
+                    padding: [0; 4],
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Makers with indexed orders crossing the taker price, best price then time priority
+    pub fn get_crossing_makers(
+        &self,
+        taker_direction: PositionDirection,
+        taker_price: u64,
+        max_makers: usize,
+    ) -> Vec<Pubkey> {
+        let maker_direction = taker_direction.opposite();
+        let mut makers: Vec<Pubkey> = Vec::with_capacity(max_makers);
+
+        for entry in self.get_orders(maker_direction) {
+            if makers.len() >= max_makers
+                || !do_orders_cross(maker_direction, entry.price, taker_price)
+            {
+                break;
+            }
+
+            if !makers.contains(&entry.user) {
+                makers.push(entry.user);
+            }
+        }
+
+        makers
+    }
+}
+
+/// Whether the order rests on the book at a fixed price. Untriggered and pending linked orders
+/// are indexed once they activate
+pub fn is_indexable_order(order: &Order, market_index: u16) -> bool {
+    order.status == OrderStatus::Open
+        && order.market_type == MarketType::Perp
+        && order.market_index == market_index
+        && order.is_limit_order()
+        && !(order.must_be_triggered() && !order.triggered())
+        && !order.is_pending_linked_order()
+        && order.price != 0
+        && !order.has_oracle_price_offset()
+}
+
+fn get_displayed_base_asset_amount(order: &Order) -> DriftResult<u64> {
+    order
+        .base_asset_amount
+        .safe_sub(order.base_asset_amount_filled)
+}
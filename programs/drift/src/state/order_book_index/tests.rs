@@ -0,0 +1,339 @@
+use anchor_lang::prelude::Pubkey;
+
+use crate::controller::position::PositionDirection;
+use crate::math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+use crate::state::order_book_index::{
+    OrderBookIndexEntry, PerpOrderBookIndex, PERP_ORDER_BOOK_INDEX_CAPACITY,
+    PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER, PERP_ORDER_BOOK_INDEX_MAX_USERS,
+};
+use crate::state::user::{MarketType, Order, OrderStatus, OrderTriggerCondition, OrderType, User};
+
+fn entry(user: Pubkey, order_id: u32, price: u64) -> OrderBookIndexEntry {
+    OrderBookIndexEntry {
+        user,
+        price,
+        base_asset_amount: BASE_PRECISION_U64,
+        order_id,
+        padding: [0; 4],
+    }
+}
+
+fn resting_order(order_id: u32, direction: PositionDirection, price: u64) -> Order {
+    Order {
+        status: OrderStatus::Open,
+        market_type: MarketType::Perp,
+        order_type: OrderType::Limit,
+        order_id,
+        direction,
+        price,
+        base_asset_amount: BASE_PRECISION_U64,
+        post_only: true,
+        ..Order::default()
+    }
+}
+
+#[test]
+fn insert() {
+    let user = Pubkey::new_unique();
+    let mut order_book_index = PerpOrderBookIndex::default();
+
+    for (order_id, price) in [(1, 100), (2, 101), (3, 100), (4, 99)] {
+        assert!(order_book_index
+            .insert(
+                PositionDirection::Long,
+                entry(user, order_id, price * PRICE_PRECISION_U64)
+            )
+            .unwrap());
+    }
+
+    // best price first, then time priority
+    let order_ids: Vec<u32> = order_book_index
+        .get_orders(PositionDirection::Long)
+        .iter()
+        .map(|entry| entry.order_id)
+        .collect();
+    assert_eq!(order_ids, vec![2, 1, 3, 4]);
+
+    for (order_id, price) in [(5, 100), (6, 99)] {
+        assert!(order_book_index
+            .insert(
+                PositionDirection::Short,
+                entry(user, order_id, price * PRICE_PRECISION_U64)
+            )
+            .unwrap());
+    }
+
+    let order_ids: Vec<u32> = order_book_index
+        .get_orders(PositionDirection::Short)
+        .iter()
+        .map(|entry| entry.order_id)
+        .collect();
+    assert_eq!(order_ids, vec![6, 5]);
+}
+
+#[test]
+fn insert_when_full() {
+    let users: Vec<Pubkey> = (0..PERP_ORDER_BOOK_INDEX_CAPACITY
+        / PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER)
+        .map(|_| Pubkey::new_unique())
+        .collect();
+    let mut order_book_index = PerpOrderBookIndex::default();
+
+    for i in 0..PERP_ORDER_BOOK_INDEX_CAPACITY {
+        assert!(order_book_index
+            .insert(
+                PositionDirection::Short,
+                entry(
+                    users[i / PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER],
+                    i as u32,
+                    100 * PRICE_PRECISION_U64
+                ),
+            )
+            .unwrap());
+    }
+    assert_eq!(
+        order_book_index.asks_len as usize,
+        PERP_ORDER_BOOK_INDEX_CAPACITY
+    );
+
+    // worse than everything indexed
+    let user = Pubkey::new_unique();
+    assert!(!order_book_index
+        .insert(
+            PositionDirection::Short,
+            entry(user, 100, 101 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+    assert!(!order_book_index.contains_order(&user, 100));
+
+    // better price drops the last order
+    let last_order_id = PERP_ORDER_BOOK_INDEX_CAPACITY as u32 - 1;
+    assert!(order_book_index
+        .insert(
+            PositionDirection::Short,
+            entry(user, 101, 99 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+    assert!(order_book_index.contains_order(&user, 101));
+    assert!(!order_book_index.contains_order(users.last().unwrap(), last_order_id));
+    assert_eq!(
+        order_book_index.asks_len as usize,
+        PERP_ORDER_BOOK_INDEX_CAPACITY
+    );
+    assert_eq!(
+        order_book_index.get_orders(PositionDirection::Short)[0].order_id,
+        101
+    );
+}
+
+#[test]
+fn insert_max_orders_per_user() {
+    let user = Pubkey::new_unique();
+    let mut order_book_index = PerpOrderBookIndex::default();
+
+    for i in 0..PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER {
+        assert!(order_book_index
+            .insert(
+                PositionDirection::Long,
+                entry(user, i as u32, 100 * PRICE_PRECISION_U64)
+            )
+            .unwrap());
+    }
+
+    // even at a better price or on the other side
+    let order_id = PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER as u32;
+    assert!(!order_book_index
+        .insert(
+            PositionDirection::Long,
+            entry(user, order_id, 101 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+    assert!(!order_book_index
+        .insert(
+            PositionDirection::Short,
+            entry(user, order_id, 101 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+    assert_eq!(
+        order_book_index.get_user_order_count(&user),
+        PERP_ORDER_BOOK_INDEX_MAX_ORDERS_PER_USER
+    );
+
+    // other users still fit
+    assert!(order_book_index
+        .insert(
+            PositionDirection::Long,
+            entry(Pubkey::new_unique(), 1, 100 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+}
+
+#[test]
+fn insert_max_users() {
+    let mut order_book_index = PerpOrderBookIndex::default();
+
+    // bids far from the market from as many users as fit
+    let users: Vec<Pubkey> = (0..PERP_ORDER_BOOK_INDEX_MAX_USERS)
+        .map(|_| Pubkey::new_unique())
+        .collect();
+    for (i, user) in users.iter().enumerate() {
+        assert!(order_book_index
+            .insert(
+                PositionDirection::Long,
+                entry(*user, 1, (50 + i as u64) * PRICE_PRECISION_U64)
+            )
+            .unwrap());
+    }
+    assert_eq!(
+        order_book_index.get_users().len(),
+        PERP_ORDER_BOOK_INDEX_MAX_USERS
+    );
+
+    // a new user's ask drops the worst bid from the longer side
+    let asker = Pubkey::new_unique();
+    assert!(order_book_index
+        .insert(
+            PositionDirection::Short,
+            entry(asker, 1, 100 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+    assert!(order_book_index.contains_order(&asker, 1));
+    assert!(!order_book_index.contains_order(&users[0], 1));
+    assert_eq!(
+        order_book_index.get_users().len(),
+        PERP_ORDER_BOOK_INDEX_MAX_USERS
+    );
+
+    // a new user's bid worse than every bid is dropped itself
+    let bidder = Pubkey::new_unique();
+    assert!(!order_book_index
+        .insert(
+            PositionDirection::Long,
+            entry(bidder, 1, 40 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+    assert!(!order_book_index.contains_order(&bidder, 1));
+
+    // a better bid drops the worst one
+    assert!(order_book_index
+        .insert(
+            PositionDirection::Long,
+            entry(bidder, 1, 99 * PRICE_PRECISION_U64)
+        )
+        .unwrap());
+    assert!(!order_book_index.contains_order(&users[1], 1));
+    assert_eq!(
+        order_book_index.get_users().len(),
+        PERP_ORDER_BOOK_INDEX_MAX_USERS
+    );
+    assert_eq!(
+        order_book_index.get_orders(PositionDirection::Long)[0].user,
+        bidder
+    );
+}
+
+#[test]
+fn update_user_orders() {
+    let user_key = Pubkey::new_unique();
+    let other_user_key = Pubkey::new_unique();
+    let mut order_book_index = PerpOrderBookIndex::default();
+
+    order_book_index
+        .insert(
+            PositionDirection::Long,
+            entry(other_user_key, 1, 100 * PRICE_PRECISION_U64),
+        )
+        .unwrap();
+
+    let mut user = User::default();
+    user.orders[0] = resting_order(1, PositionDirection::Long, 100 * PRICE_PRECISION_U64);
+    user.orders[1] = resting_order(2, PositionDirection::Short, 101 * PRICE_PRECISION_U64);
+    // not triggered
+    user.orders[2] = Order {
+        order_type: OrderType::TriggerLimit,
+        trigger_condition: OrderTriggerCondition::Above,
+        ..resting_order(3, PositionDirection::Short, 102 * PRICE_PRECISION_U64)
+    };
+    // floating price
+    user.orders[3] = Order {
+        oracle_price_offset: PRICE_PRECISION_U64 as i32,
+        ..resting_order(4, PositionDirection::Short, 102 * PRICE_PRECISION_U64)
+    };
+    // other market
+    user.orders[4] = Order {
+        market_index: 1,
+        ..resting_order(5, PositionDirection::Short, 102 * PRICE_PRECISION_U64)
+    };
+
+    order_book_index
+        .update_user_orders(&user_key, &user)
+        .unwrap();
+
+    // new order queues behind the existing order at the same price
+    let bids = order_book_index.get_orders(PositionDirection::Long);
+    assert_eq!(bids.len(), 2);
+    assert_eq!(bids[0].user, other_user_key);
+    assert_eq!(bids[1].user, user_key);
+    let asks = order_book_index.get_orders(PositionDirection::Short);
+    assert_eq!(asks.len(), 1);
+    assert_eq!(asks[0].order_id, 2);
+
+    // partial fill resizes the entry, cancel removes it
+    user.orders[0].base_asset_amount_filled = BASE_PRECISION_U64 / 2;
+    user.orders[1].status = OrderStatus::Canceled;
+    order_book_index
+        .update_user_orders(&user_key, &user)
+        .unwrap();
+
+    let bids = order_book_index.get_orders(PositionDirection::Long);
+    assert_eq!(bids.len(), 2);
+    assert_eq!(bids[1].base_asset_amount, BASE_PRECISION_U64 / 2);
+    assert_eq!(order_book_index.asks_len, 0);
+    assert_eq!(order_book_index.asks[0], OrderBookIndexEntry::default());
+
+    // other users are untouched
+    order_book_index
+        .update_user_orders(&user_key, &User::default())
+        .unwrap();
+    let bids = order_book_index.get_orders(PositionDirection::Long);
+    assert_eq!(bids.len(), 1);
+    assert_eq!(bids[0].user, other_user_key);
+}
+
+#[test]
+fn get_crossing_makers() {
+    let maker_a = Pubkey::new_unique();
+    let maker_b = Pubkey::new_unique();
+    let maker_c = Pubkey::new_unique();
+    let mut order_book_index = PerpOrderBookIndex::default();
+
+    for (maker, order_id, price) in [
+        (maker_a, 1, 100),
+        (maker_a, 2, 101),
+        (maker_b, 1, 101),
+        (maker_c, 1, 103),
+    ] {
+        order_book_index
+            .insert(
+                PositionDirection::Short,
+                entry(maker, order_id, price * PRICE_PRECISION_U64),
+            )
+            .unwrap();
+    }
+
+    let makers =
+        order_book_index.get_crossing_makers(PositionDirection::Long, 102 * PRICE_PRECISION_U64, 4);
+    assert_eq!(makers, vec![maker_a, maker_b]);
+
+    let makers =
+        order_book_index.get_crossing_makers(PositionDirection::Long, 103 * PRICE_PRECISION_U64, 1);
+    assert_eq!(makers, vec![maker_a]);
+
+    let makers =
+        order_book_index.get_crossing_makers(PositionDirection::Long, 99 * PRICE_PRECISION_U64, 4);
+    assert!(makers.is_empty());
+
+    // no bids
+    let makers = order_book_index.get_crossing_makers(PositionDirection::Short, 0, 4);
+    assert!(makers.is_empty());
+}
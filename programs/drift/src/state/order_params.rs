@@ -1,8 +1,19 @@
 use crate::controller::position::PositionDirection;
-use crate::state::user::{MarketType, OrderTriggerCondition, OrderType};
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::orders::{standardize_base_asset_amount, standardize_price};
+use crate::math::safe_math::SafeMath;
+use crate::state::user::{
+    MarketType, OrderLinkType, OrderTriggerCondition, OrderType, TrailingStopType,
+    TriggerPriceSource,
+};
+use crate::validate;
 use anchor_lang::prelude::*;
 use borsh::{BorshDeserialize, BorshSerialize};
 
+#[cfg(test)]
+mod tests;
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, Copy)]
 pub struct OrderParams {
     pub order_type: OrderType,
@@ -22,6 +33,33 @@ pub struct OrderParams {
     pub auction_duration: Option<u8>,
     pub auction_start_price: Option<i64>,
     pub auction_end_price: Option<i64>,
+    pub trailing_stop_type: TrailingStopType,
+    /// trailing stops only. how far the trigger price trails the oracle price
+    /// precision: PRICE_PRECISION for FixedOffset, PERCENTAGE_PRECISION for Percentage
+    pub trailing_stop_offset: u32,
+    pub link_id: u8,
+    pub link_type: OrderLinkType,
+    /// number of slices a twap order's base_asset_amount is split into
+    pub twap_slice_count: u16,
+    /// seconds between twap slices
+    pub twap_interval: u32,
+    /// place_and_take only. instruction fails unless the full base_asset_amount is filled
+    pub fill_or_kill: bool,
+    /// price the trigger condition is checked against. Only relevant for trigger orders
+    pub trigger_price_source: TriggerPriceSource,
+    /// market the trigger price source is read from, ignored for TriggerPriceSource::Oracle
+    pub trigger_market_index: u16,
+    /// limit orders only. if non-zero, the order is an iceberg that only displays this much at a time
+    pub display_base_asset_amount: u64,
+    /// perp only. the order is reduce only and sized to the full position when it's triggered or
+    /// filled, base_asset_amount is ignored
+    pub close_position: bool,
+    /// market, trigger market and oracle orders only. max bps the fill price can be worse than the
+    /// oracle price at fill time, the order is canceled once the auction is over and it can't fill
+    /// within the bound
+    pub max_slippage_bps: Option<u16>,
+    /// client chosen id, must be unique among the user's open orders if non-zero
+    pub client_order_id: u64,
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
@@ -54,6 +92,7 @@ pub struct ModifyOrderParams {
     pub auction_start_price: Option<i64>,
     pub auction_end_price: Option<i64>,
     pub policy: Option<ModifyOrderPolicy>,
+    pub max_slippage_bps: Option<u16>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Eq, PartialEq)]
@@ -68,10 +107,305 @@ impl Default for ModifyOrderPolicy {
     }
 }
 
+/// Matches non-zero client order ids whose highest `bits` bits equal those of `prefix`, e.g. a
+/// strategy id kept in the high bits
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ClientOrderIdPrefix {
+    pub prefix: u64,
+    pub bits: u8,
+}
+
+impl ClientOrderIdPrefix {
+    pub fn matches(&self, client_order_id: u64) -> bool {
+        if client_order_id == 0 {
+            return false;
+        }
+
+        let shift = 64_u32.saturating_sub(self.bits as u32);
+        if shift >= 64 {
+            return true;
+        }
+
+        client_order_id >> shift == self.prefix >> shift
+    }
+}
+
+/// One order of a modify_orders batch
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct BatchModifyOrderParams {
+    /// order to modify
+    pub order_id: Option<u32>,
+    /// used if order_id is None. if also None, the order is found by user_order_id
+    pub client_order_id: Option<u64>,
+    pub user_order_id: u8,
+    /// fields to change, modify_order_params.policy also applies to replacement orders
+    pub modify_order_params: ModifyOrderParams,
+    /// if set, the order is canceled and replaced by this order instead of being modified
+    pub replacement_order_params: Option<OrderParams>,
+}
+
+/// Ladder of evenly spaced limit orders from start_price to end_price
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, Copy)]
+pub struct ScaleOrderParams {
+    pub market_type: MarketType,
+    pub direction: PositionDirection,
+    pub market_index: u16,
+    /// split across the orders according to size_distribution
+    pub total_base_asset_amount: u64,
+    /// price of the first order
+    /// precision: PRICE_PRECISION
+    pub start_price: u64,
+    /// price of the last order
+    /// precision: PRICE_PRECISION
+    pub end_price: u64,
+    pub order_count: u8,
+    pub size_distribution: SizeDistribution,
+    pub reduce_only: bool,
+    pub post_only: PostOnlyParam,
+    pub max_ts: Option<i64>,
+    /// cancel the user's open orders for the market and direction before placing the ladder
+    pub cancel_existing_orders: bool,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum SizeDistribution {
+    /// same size for every order
+    Flat,
+    /// size grows from the start price order to the end price order
+    Ascending,
+    /// size shrinks from the start price order to the end price order
+    Descending,
+}
+
+impl Default for SizeDistribution {
+    fn default() -> Self {
+        SizeDistribution::Flat
+    }
+}
+
+pub const MAX_SCALE_ORDER_COUNT: u8 = 32;
+
+impl ScaleOrderParams {
+    fn get_size_weight(&self, order_index: u64) -> DriftResult<u64> {
+        match self.size_distribution {
+            SizeDistribution::Flat => Ok(1),
+            SizeDistribution::Ascending => order_index.safe_add(1),
+            SizeDistribution::Descending => self.order_count.cast::<u64>()?.safe_sub(order_index),
+        }
+    }
+
+    /// Sizes are rounded down to the step size with the remainder going to the largest order.
+    /// Prices are rounded to the tick size in the order's favor
+    pub fn get_order_params(
+        &self,
+        step_size: u64,
+        tick_size: u64,
+    ) -> DriftResult<Vec<OrderParams>> {
+        validate!(
+            self.order_count > 0 && self.order_count <= MAX_SCALE_ORDER_COUNT,
+            ErrorCode::InvalidScaleOrderParams,
+            "order_count ({}) must be between 1 and {}",
+            self.order_count,
+            MAX_SCALE_ORDER_COUNT
+        )?;
+
+        validate!(
+            self.start_price > 0 && self.end_price > 0,
+            ErrorCode::InvalidScaleOrderParams,
+            "start_price ({}) and end_price ({}) must be greater than 0",
+            self.start_price,
+            self.end_price
+        )?;
+
+        let order_count = self.order_count.cast::<u64>()?;
+        let total_base_asset_amount =
+            standardize_base_asset_amount(self.total_base_asset_amount, step_size)?;
+
+        let mut weight_sum = 0_u64;
+        let mut largest_order_index = 0_u64;
+        for order_index in 0..order_count {
+            let weight = self.get_size_weight(order_index)?;
+            if weight > self.get_size_weight(largest_order_index)? {
+                largest_order_index = order_index;
+            }
+            weight_sum = weight_sum.safe_add(weight)?;
+        }
+
+        let mut base_asset_amounts = Vec::with_capacity(self.order_count.cast()?);
+        for order_index in 0..order_count {
+            let base_asset_amount = total_base_asset_amount
+                .cast::<u128>()?
+                .safe_mul(self.get_size_weight(order_index)?.cast()?)?
+                .safe_div(weight_sum.cast()?)?
+                .cast::<u64>()?;
+
+            let base_asset_amount = standardize_base_asset_amount(base_asset_amount, step_size)?;
+
+            validate!(
+                base_asset_amount > 0,
+                ErrorCode::InvalidScaleOrderParams,
+                "total_base_asset_amount ({}) too small for {} orders of step size {}",
+                self.total_base_asset_amount,
+                self.order_count,
+                step_size
+            )?;
+
+            base_asset_amounts.push(base_asset_amount);
+        }
+
+        let remainder = total_base_asset_amount.safe_sub(base_asset_amounts.iter().sum::<u64>())?;
+        let largest_order_index = largest_order_index.cast::<usize>()?;
+        base_asset_amounts[largest_order_index] =
+            base_asset_amounts[largest_order_index].safe_add(remainder)?;
+
+        let start_price = self.start_price.cast::<i128>()?;
+        let price_range = self.end_price.cast::<i128>()?.safe_sub(start_price)?;
+        let price_steps = order_count.saturating_sub(1).max(1).cast::<i128>()?;
+
+        let mut order_params = Vec::with_capacity(self.order_count.cast()?);
+        for (order_index, base_asset_amount) in base_asset_amounts.into_iter().enumerate() {
+            let price = start_price
+                .safe_add(
+                    price_range
+                        .safe_mul(order_index.cast()?)?
+                        .safe_div(price_steps)?,
+                )?
+                .cast::<u64>()?;
+
+            let price = standardize_price(price, tick_size, self.direction)?;
+
+            validate!(
+                price > 0,
+                ErrorCode::InvalidScaleOrderParams,
+                "order {} price rounds to 0 with tick size {}",
+                order_index,
+                tick_size
+            )?;
+
+            order_params.push(OrderParams {
+                order_type: OrderType::Limit,
+                market_type: self.market_type,
+                direction: self.direction,
+                base_asset_amount,
+                price,
+                market_index: self.market_index,
+                reduce_only: self.reduce_only,
+                post_only: self.post_only,
+                max_ts: self.max_ts,
+                ..OrderParams::default()
+            });
+        }
+
+        Ok(order_params)
+    }
+}
+
+/// Perp quote signed off-chain by a maker's authority or delegate. The borsh serialized quote is the
+/// message of an ed25519 program instruction placed before the instruction filling it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SignedQuote {
+    /// The maker user account the quote fills against
+    pub maker: Pubkey,
+    pub market_index: u16,
+    /// The maker's side of the trade
+    pub direction: PositionDirection,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount: u64,
+    /// precision: PRICE_PRECISION
+    pub price: u64,
+    /// The quote can't be filled after this ts
+    pub expiry_ts: i64,
+    /// Each nonce can only be filled once per authority
+    pub nonce: u64,
+}
+
+impl SignedQuote {
+    /// The maker side of the quote as an immediate or cancel limit order. It isn't post only since it
+    /// only fills against the taker the quote was signed for, so it can cross the amm
+    pub fn get_maker_order_params(&self) -> OrderParams {
+        OrderParams {
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: self.direction,
+            base_asset_amount: self.base_asset_amount,
+            price: self.price,
+            market_index: self.market_index,
+            immediate_or_cancel: true,
+            ..OrderParams::default()
+        }
+    }
+}
+
+/// Taker order signed off-chain by a user's authority or delegate, placed by any keeper. The borsh
+/// serialized message is the message of an ed25519 program instruction placed before the instruction
+/// placing it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct SignedOrderParamsMessage {
+    pub signed_order_params: OrderParams,
+    /// The user account the order is placed for
+    pub user: Pubkey,
+    /// The order can't be placed after this slot
+    pub expiry_slot: u64,
+    /// Each nonce can only be placed once per authority
+    pub nonce: u64,
+    pub take_profit_order_params: Option<SignedOrderTriggerParams>,
+    pub stop_loss_order_params: Option<SignedOrderTriggerParams>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SignedOrderTriggerParams {
+    /// precision: PRICE_PRECISION
+    pub trigger_price: u64,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount: u64,
+}
+
+impl SignedOrderParamsMessage {
+    pub fn has_trigger_orders(&self) -> bool {
+        self.take_profit_order_params.is_some() || self.stop_loss_order_params.is_some()
+    }
+
+    /// Reduce only trigger market orders for the take profit and stop loss. They are pending children
+    /// of the signed order, so they only become active once it fills
+    pub fn get_trigger_order_params(&self, link_id: u8) -> Vec<OrderParams> {
+        let params = &self.signed_order_params;
+        let (take_profit_condition, stop_loss_condition) = match params.direction {
+            PositionDirection::Long => (OrderTriggerCondition::Above, OrderTriggerCondition::Below),
+            PositionDirection::Short => {
+                (OrderTriggerCondition::Below, OrderTriggerCondition::Above)
+            }
+        };
+
+        [
+            (self.take_profit_order_params, take_profit_condition),
+            (self.stop_loss_order_params, stop_loss_condition),
+        ]
+        .iter()
+        .filter_map(|(trigger_params, trigger_condition)| {
+            trigger_params.map(|trigger_params| OrderParams {
+                order_type: OrderType::TriggerMarket,
+                market_type: MarketType::Perp,
+                direction: params.direction.opposite(),
+                base_asset_amount: trigger_params.base_asset_amount,
+                market_index: params.market_index,
+                reduce_only: true,
+                trigger_price: Some(trigger_params.trigger_price),
+                trigger_condition: *trigger_condition,
+                link_id,
+                link_type: OrderLinkType::PendingChild,
+                ..OrderParams::default()
+            })
+        })
+        .collect()
+    }
+}
+
 pub struct PlaceOrderOptions {
     pub try_expire_orders: bool,
     pub enforce_margin_check: bool,
     pub risk_increasing: bool,
+    /// Whether placing the order counts as a heartbeat, false when the user didn't sign the transaction
+    pub update_heartbeat: bool,
 }
 
 impl Default for PlaceOrderOptions {
@@ -80,6 +414,7 @@ impl Default for PlaceOrderOptions {
             try_expire_orders: true,
             enforce_margin_check: true,
             risk_increasing: false,
+            update_heartbeat: true,
         }
     }
 }
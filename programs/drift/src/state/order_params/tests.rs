@@ -0,0 +1,242 @@
+mod get_order_params {
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+    use crate::state::order_params::{ScaleOrderParams, SizeDistribution};
+    use crate::state::user::{MarketType, OrderType};
+
+    #[test]
+    fn flat() {
+        let params = ScaleOrderParams {
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            total_base_asset_amount: 10 * BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 90 * PRICE_PRECISION_U64,
+            order_count: 3,
+            size_distribution: SizeDistribution::Flat,
+            ..ScaleOrderParams::default()
+        };
+        let step_size = BASE_PRECISION_U64 / 10;
+        let tick_size = PRICE_PRECISION_U64 / 100;
+
+        let order_params = params.get_order_params(step_size, tick_size).unwrap();
+
+        let base_asset_amounts: Vec<u64> = order_params
+            .iter()
+            .map(|params| params.base_asset_amount)
+            .collect();
+        // remainder goes to first order
+        assert_eq!(
+            base_asset_amounts,
+            vec![3_400_000_000, 3_300_000_000, 3_300_000_000]
+        );
+
+        let prices: Vec<u64> = order_params.iter().map(|params| params.price).collect();
+        assert_eq!(prices, vec![100_000_000, 95_000_000, 90_000_000]);
+
+        assert!(order_params
+            .iter()
+            .all(|params| params.order_type == OrderType::Limit
+                && params.market_type == MarketType::Perp
+                && params.direction == PositionDirection::Long));
+    }
+
+    #[test]
+    fn ascending_and_descending() {
+        let mut params = ScaleOrderParams {
+            market_type: MarketType::Spot,
+            direction: PositionDirection::Short,
+            total_base_asset_amount: 10 * BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 101 * PRICE_PRECISION_U64,
+            order_count: 4,
+            size_distribution: SizeDistribution::Ascending,
+            ..ScaleOrderParams::default()
+        };
+        let step_size = BASE_PRECISION_U64;
+        let tick_size = PRICE_PRECISION_U64 / 10;
+
+        let order_params = params.get_order_params(step_size, tick_size).unwrap();
+
+        let base_asset_amounts: Vec<u64> = order_params
+            .iter()
+            .map(|params| params.base_asset_amount)
+            .collect();
+        assert_eq!(
+            base_asset_amounts,
+            vec![
+                BASE_PRECISION_U64,
+                2 * BASE_PRECISION_U64,
+                3 * BASE_PRECISION_U64,
+                4 * BASE_PRECISION_U64
+            ]
+        );
+
+        // 100.333 and 100.666 rounded up for shorts
+        let prices: Vec<u64> = order_params.iter().map(|params| params.price).collect();
+        assert_eq!(
+            prices,
+            vec![100_000_000, 100_400_000, 100_700_000, 101_000_000]
+        );
+
+        params.size_distribution = SizeDistribution::Descending;
+        let order_params = params.get_order_params(step_size, tick_size).unwrap();
+        let base_asset_amounts: Vec<u64> = order_params
+            .iter()
+            .map(|params| params.base_asset_amount)
+            .collect();
+        assert_eq!(
+            base_asset_amounts,
+            vec![
+                4 * BASE_PRECISION_U64,
+                3 * BASE_PRECISION_U64,
+                2 * BASE_PRECISION_U64,
+                BASE_PRECISION_U64
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let params = ScaleOrderParams {
+            total_base_asset_amount: BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 90 * PRICE_PRECISION_U64,
+            order_count: 0,
+            ..ScaleOrderParams::default()
+        };
+        let step_size = BASE_PRECISION_U64 / 10;
+        let tick_size = PRICE_PRECISION_U64 / 100;
+
+        assert!(params.get_order_params(step_size, tick_size).is_err());
+
+        // more orders than steps in total size
+        let params = ScaleOrderParams {
+            order_count: 11,
+            ..params
+        };
+        assert!(params.get_order_params(step_size, tick_size).is_err());
+
+        let params = ScaleOrderParams {
+            order_count: 33,
+            total_base_asset_amount: 100 * BASE_PRECISION_U64,
+            ..params
+        };
+        assert!(params.get_order_params(step_size, tick_size).is_err());
+
+        let params = ScaleOrderParams {
+            order_count: 2,
+            end_price: 0,
+            ..params
+        };
+        assert!(params.get_order_params(step_size, tick_size).is_err());
+    }
+}
+
+mod get_trigger_order_params {
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+    use crate::state::order_params::{
+        OrderParams, SignedOrderParamsMessage, SignedOrderTriggerParams,
+    };
+    use crate::state::user::{OrderLinkType, OrderTriggerCondition, OrderType};
+
+    #[test]
+    fn take_profit_and_stop_loss() {
+        let signed_message = SignedOrderParamsMessage {
+            signed_order_params: OrderParams {
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                base_asset_amount: 2 * BASE_PRECISION_U64,
+                market_index: 1,
+                ..OrderParams::default()
+            },
+            take_profit_order_params: Some(SignedOrderTriggerParams {
+                trigger_price: 110 * PRICE_PRECISION_U64,
+                base_asset_amount: BASE_PRECISION_U64,
+            }),
+            stop_loss_order_params: Some(SignedOrderTriggerParams {
+                trigger_price: 90 * PRICE_PRECISION_U64,
+                base_asset_amount: 2 * BASE_PRECISION_U64,
+            }),
+            ..SignedOrderParamsMessage::default()
+        };
+
+        assert!(signed_message.has_trigger_orders());
+
+        let trigger_order_params = signed_message.get_trigger_order_params(3);
+        assert_eq!(trigger_order_params.len(), 2);
+
+        let take_profit = &trigger_order_params[0];
+        assert_eq!(take_profit.order_type, OrderType::TriggerMarket);
+        assert_eq!(take_profit.direction, PositionDirection::Short);
+        assert_eq!(take_profit.trigger_condition, OrderTriggerCondition::Above);
+        assert_eq!(take_profit.trigger_price, Some(110 * PRICE_PRECISION_U64));
+        assert_eq!(take_profit.base_asset_amount, BASE_PRECISION_U64);
+        assert_eq!(take_profit.market_index, 1);
+        assert!(take_profit.reduce_only);
+        assert_eq!(take_profit.link_id, 3);
+        assert_eq!(take_profit.link_type, OrderLinkType::PendingChild);
+
+        let stop_loss = &trigger_order_params[1];
+        assert_eq!(stop_loss.trigger_condition, OrderTriggerCondition::Below);
+        assert_eq!(stop_loss.trigger_price, Some(90 * PRICE_PRECISION_U64));
+
+        // short only has a stop loss
+        let signed_message = SignedOrderParamsMessage {
+            signed_order_params: OrderParams {
+                direction: PositionDirection::Short,
+                ..signed_message.signed_order_params
+            },
+            take_profit_order_params: None,
+            ..signed_message
+        };
+        let trigger_order_params = signed_message.get_trigger_order_params(3);
+        assert_eq!(trigger_order_params.len(), 1);
+        assert_eq!(trigger_order_params[0].direction, PositionDirection::Long);
+        assert_eq!(
+            trigger_order_params[0].trigger_condition,
+            OrderTriggerCondition::Above
+        );
+
+        let signed_message = SignedOrderParamsMessage {
+            stop_loss_order_params: None,
+            ..signed_message
+        };
+        assert!(!signed_message.has_trigger_orders());
+        assert!(signed_message.get_trigger_order_params(3).is_empty());
+    }
+}
+
+mod client_order_id_prefix {
+    use crate::state::order_params::ClientOrderIdPrefix;
+
+    #[test]
+    fn matches() {
+        let strategy_id: u64 = 7 << 48;
+        let prefix = ClientOrderIdPrefix {
+            prefix: strategy_id,
+            bits: 16,
+        };
+
+        assert!(prefix.matches(strategy_id + 1));
+        assert!(prefix.matches(strategy_id + (1 << 47)));
+        assert!(!prefix.matches((8 << 48) + 1));
+        // untagged orders never match
+        assert!(!prefix.matches(0));
+
+        // whole id
+        let prefix = ClientOrderIdPrefix {
+            prefix: strategy_id + 1,
+            bits: 64,
+        };
+        assert!(prefix.matches(strategy_id + 1));
+        assert!(!prefix.matches(strategy_id + 2));
+
+        // every tagged order
+        let prefix = ClientOrderIdPrefix::default();
+        assert!(prefix.matches(1));
+        assert!(prefix.matches(u64::MAX));
+        assert!(!prefix.matches(0));
+    }
+}
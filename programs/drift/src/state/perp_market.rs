@@ -5,6 +5,7 @@ use std::cmp::max;
 use crate::controller::position::{PositionDelta, PositionDirection};
 use crate::error::{DriftResult, ErrorCode};
 use crate::math::amm;
+use crate::math::amm_spread;
 use crate::math::casting::Cast;
 #[cfg(test)]
 use crate::math::constants::{
@@ -24,7 +25,8 @@ use crate::math::safe_math::SafeMath;
 use crate::math::stats;
 use crate::state::events::OrderActionExplanation;
 
-use crate::state::oracle::{HistoricalOracleData, OracleSource};
+use crate::state::oracle::{get_pyth_pull_twap, HistoricalOracleData, OracleSource};
+use crate::state::prelaunch_oracle::get_prelaunch_oracle_twap;
 use crate::state::spot_market::{AssetTier, SpotBalance, SpotBalanceType};
 use crate::state::traits::{MarketIndexOffset, Size};
 use crate::{AMM_TO_QUOTE_PRECISION_RATIO, PRICE_PRECISION};
@@ -202,7 +204,19 @@ pub struct PerpMarket {
     /// E.g. if this is -50 and the fee is 5bps, the new fee will be 2.5bps
     /// if this is 50 and the fee is 5bps, the new fee will be 7.5bps
     pub fee_adjustment: i16,
-    pub padding: [u8; 46],
+    /// Multiple of the oracle confidence interval to value positions against the user
+    /// in confidence adjusted margin calculations
+    /// disabled when 0
+    /// precision: MARGIN_PRECISION
+    pub margin_confidence_multiplier: u16,
+    /// Number of slots taker orders collect for before clearing at a uniform price
+    /// continuous fills are disabled when non zero
+    pub batch_auction_duration: u32,
+    /// The last slot a batch auction cleared
+    pub last_batch_auction_clear_slot: u64,
+    /// Feed id the pyth pull price update account must carry
+    /// zeroed for other oracle sources
+    pub oracle_feed_id: [u8; 32],
 }
 
 impl Default for PerpMarket {
@@ -236,7 +250,10 @@ impl Default for PerpMarket {
             padding1: 0,
             quote_spot_market_index: 0,
             fee_adjustment: 0,
-            padding: [0; 46],
+            margin_confidence_multiplier: 0,
+            batch_auction_duration: 0,
+            last_batch_auction_clear_slot: 0,
+            oracle_feed_id: [0; 32],
         }
     }
 }
@@ -263,6 +280,17 @@ impl PerpMarket {
         Ok(self.status == MarketStatus::ReduceOnly)
     }
 
+    pub fn is_batch_auction_market(&self) -> bool {
+        self.batch_auction_duration != 0
+    }
+
+    pub fn can_clear_batch_auction(&self, slot: u64) -> DriftResult<bool> {
+        Ok(slot
+            >= self
+                .last_batch_auction_clear_slot
+                .safe_add(self.batch_auction_duration.cast()?)?)
+    }
+
     pub fn get_sanitize_clamp_denominator(self) -> DriftResult<Option<i64>> {
         Ok(match self.contract_tier {
             ContractTier::A => Some(10_i64),   // 10%
@@ -654,7 +682,15 @@ pub struct AMM {
     pub padding1: u8,
     pub padding2: u16,
     pub total_fee_earned_per_lp: u64,
-    pub padding: [u8; 32],
+    /// the max skew of the reference price the amm re-centers its reserves on vs the oracle, toward
+    /// unloading its inventory. 0 re-centers on the oracle
+    /// precision: BID_ASK_SPREAD_PRECISION
+    pub max_inventory_skew: u32,
+    /// the skew of the reference price vs the oracle at the last spread update. positive when users are
+    /// net long and the amm quotes above the oracle
+    /// precision: BID_ASK_SPREAD_PRECISION
+    pub inventory_skew: i32,
+    pub padding: [u8; 24],
 }
 
 impl Default for AMM {
@@ -740,7 +776,9 @@ impl Default for AMM {
             padding1: 0,
             padding2: 0,
             total_fee_earned_per_lp: 0,
-            padding: [0; 32],
+            max_inventory_skew: 0,
+            inventory_skew: 0,
+            padding: [0; 24],
         }
     }
 }
@@ -913,6 +951,30 @@ impl AMM {
         self.amm_jit_intensity > 100
     }
 
+    pub fn inventory_skew_enabled(&self) -> bool {
+        self.max_inventory_skew > 0
+    }
+
+    pub fn calculate_inventory_skew(&self) -> DriftResult<i64> {
+        amm_spread::calculate_inventory_skew(
+            self.base_asset_amount_with_amm,
+            self.base_asset_reserve,
+            self.min_base_asset_reserve,
+            self.max_base_asset_reserve,
+            self.max_inventory_skew,
+        )
+    }
+
+    /// The price the amm re-centers its reserves on. The oracle price, skewed by the amm's inventory
+    /// if inventory skew is enabled
+    pub fn reference_price(&self, oracle_price: i64) -> DriftResult<i64> {
+        if !self.inventory_skew_enabled() {
+            return Ok(oracle_price);
+        }
+
+        amm_spread::calculate_reference_price(oracle_price, self.calculate_inventory_skew()?)
+    }
+
     pub fn reserve_price(&self) -> DriftResult<u64> {
         amm::calculate_price(
             self.quote_asset_reserve,
@@ -959,7 +1021,9 @@ impl AMM {
             }
             OracleSource::Pyth1K => Ok(Some(self.get_pyth_twap(price_oracle, 1000)?)),
             OracleSource::Pyth1M => Ok(Some(self.get_pyth_twap(price_oracle, 1000000)?)),
-            OracleSource::Switchboard => Ok(None),
+            OracleSource::PythPull => Ok(Some(get_pyth_pull_twap(price_oracle, 1)?)),
+            OracleSource::Prelaunch => Ok(Some(get_prelaunch_oracle_twap(price_oracle)?)),
+            OracleSource::Switchboard | OracleSource::Composite => Ok(None),
             OracleSource::QuoteAsset => {
                 msg!("Can't get oracle twap for quote asset");
                 Err(ErrorCode::DefaultError)
@@ -0,0 +1,103 @@
+use anchor_lang::accounts::account_loader::AccountLoader;
+use std::cell::RefMut;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::slice::Iter;
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+
+use anchor_lang::Discriminator;
+use arrayref::array_ref;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::load_mut;
+use crate::math::safe_unwrap::SafeUnwrap;
+use crate::state::order_book_index::PerpOrderBookIndex;
+use crate::state::traits::Size;
+use crate::state::user::User;
+use crate::validate;
+use solana_program::msg;
+
+/// Order book indexes passed to an instruction, by perp market index. Indexes are optional, orders
+/// in markets whose index isn't passed are left for the next instruction that passes it
+pub struct PerpOrderBookIndexMap<'a>(pub BTreeMap<u16, AccountLoader<'a, PerpOrderBookIndex>>);
+
+impl<'a> PerpOrderBookIndexMap<'a> {
+    pub fn get_ref_mut(
+        &self,
+        market_index: &u16,
+    ) -> DriftResult<Option<RefMut<PerpOrderBookIndex>>> {
+        match self.0.get(market_index) {
+            Some(loader) => Ok(Some(load_mut!(loader)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn contains(&self, market_index: &u16) -> bool {
+        self.0.contains_key(market_index)
+    }
+
+    /// Syncs every index with the user's orders. Called whenever an instruction changes a user's orders
+    pub fn update_user_orders(&self, user_key: &Pubkey, user: &User) -> DriftResult {
+        for perp_order_book_index in self.0.values() {
+            load_mut!(perp_order_book_index)?.update_user_orders(user_key, user)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn empty() -> Self {
+        PerpOrderBookIndexMap(BTreeMap::new())
+    }
+
+    pub fn load<'c>(
+        account_info_iter: &'c mut Peekable<Iter<AccountInfo<'a>>>,
+    ) -> DriftResult<PerpOrderBookIndexMap<'a>> {
+        let mut perp_order_book_index_map = PerpOrderBookIndexMap::empty();
+
+        let perp_order_book_index_discriminator: [u8; 8] = PerpOrderBookIndex::discriminator();
+        while let Some(account_info) = account_info_iter.peek() {
+            let data = account_info
+                .try_borrow_data()
+                .or(Err(ErrorCode::InvalidPerpOrderBookIndex))?;
+
+            if data.len() < PerpOrderBookIndex::SIZE {
+                break;
+            }
+
+            let account_discriminator = array_ref![data, 0, 8];
+            if account_discriminator != &perp_order_book_index_discriminator {
+                break;
+            }
+
+            // perp market index 8 bytes from front of account
+            let market_index = u16::from_le_bytes(*array_ref![data, 8, 2]);
+
+            if perp_order_book_index_map.0.contains_key(&market_index) {
+                msg!(
+                    "Can not include same perp order book index twice {}",
+                    market_index
+                );
+                return Err(ErrorCode::InvalidPerpOrderBookIndex);
+            }
+
+            let account_info = account_info_iter.next().safe_unwrap()?;
+
+            validate!(
+                account_info.is_writable,
+                ErrorCode::InvalidPerpOrderBookIndex,
+                "perp order book index must be writable"
+            )?;
+
+            let perp_order_book_index: AccountLoader<PerpOrderBookIndex> =
+                AccountLoader::try_from(account_info)
+                    .or(Err(ErrorCode::InvalidPerpOrderBookIndex))?;
+
+            perp_order_book_index_map
+                .0
+                .insert(market_index, perp_order_book_index);
+        }
+
+        Ok(perp_order_book_index_map)
+    }
+}
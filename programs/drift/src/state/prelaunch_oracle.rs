@@ -0,0 +1,225 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use arrayref::array_ref;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::PERCENTAGE_PRECISION_U64;
+use crate::math::safe_math::SafeMath;
+use crate::math::stats::calculate_weighted_average;
+use crate::state::oracle::OraclePriceData;
+use crate::state::traits::Size;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct PrelaunchOracle {
+    /// precision: PRICE_PRECISION
+    pub price: i64,
+    /// slot weighted twap of the price over PRELAUNCH_ORACLE_TWAP_PERIOD_SLOTS
+    /// precision: PRICE_PRECISION
+    pub twap: i64,
+    /// price can never be set above this, 0 is no cap
+    /// precision: PRICE_PRECISION
+    pub max_price: i64,
+    /// precision: PRICE_PRECISION
+    pub confidence: u64,
+    pub last_update_slot: u64,
+    /// price is flagged as having insufficient data points if not updated within this many slots, 0 is no limit
+    pub max_staleness_slots: u64,
+    /// keeper allowed to set the price directly
+    pub authority: Pubkey,
+    /// max change in price per update, 0 is no limit
+    /// precision: PERCENTAGE_PRECISION
+    pub max_price_change_per_update: u32,
+    pub perp_market_index: u16,
+    pub padding: [u8; 42],
+}
+
+#[derive(Debug, Clone, Copy, AnchorSerialize, AnchorDeserialize, PartialEq, Eq, Default)]
+pub struct PrelaunchOracleParams {
+    pub perp_market_index: u16,
+    pub price: Option<i64>,
+    pub max_price: Option<i64>,
+    pub confidence: Option<u64>,
+    pub max_staleness_slots: Option<u64>,
+    pub max_price_change_per_update: Option<u32>,
+    pub authority: Option<Pubkey>,
+}
+
+impl Size for PrelaunchOracle {
+    const SIZE: usize = 136;
+}
+
+pub const PRELAUNCH_ORACLE_TWAP_PERIOD_SLOTS: i64 = 9_000; // ~1 hour
+
+impl PrelaunchOracle {
+    pub fn get_price_data(&self, clock_slot: u64) -> DriftResult<OraclePriceData> {
+        let delay = clock_slot
+            .cast::<i64>()?
+            .safe_sub(self.last_update_slot.cast()?)?;
+
+        let has_sufficient_number_of_data_points =
+            self.max_staleness_slots == 0 || delay <= self.max_staleness_slots.cast()?;
+
+        Ok(OraclePriceData {
+            price: self.price,
+            confidence: self.confidence,
+            delay,
+            has_sufficient_number_of_data_points,
+        })
+    }
+
+    /// Moves the price toward new_price, bounded by max_price_change_per_update and max_price
+    pub fn update_price(&mut self, new_price: i64, clock_slot: u64) -> DriftResult {
+        validate!(
+            new_price > 0,
+            ErrorCode::InvalidOracle,
+            "prelaunch oracle price must be positive"
+        )?;
+
+        let mut price = new_price;
+
+        if self.price > 0 && self.max_price_change_per_update != 0 {
+            let max_change = self
+                .price
+                .safe_mul(self.max_price_change_per_update.cast()?)?
+                .safe_div(PERCENTAGE_PRECISION_U64.cast()?)?;
+
+            price = price
+                .max(self.price.safe_sub(max_change)?)
+                .min(self.price.safe_add(max_change)?);
+        }
+
+        if self.max_price > 0 {
+            price = price.min(self.max_price);
+        }
+
+        self.set_price(price, clock_slot)
+    }
+
+    fn set_price(&mut self, price: i64, clock_slot: u64) -> DriftResult {
+        self.price = price;
+
+        self.twap = if self.twap == 0 {
+            price
+        } else {
+            let since_last = clock_slot
+                .cast::<i64>()?
+                .safe_sub(self.last_update_slot.cast()?)?
+                .max(0);
+            let from_start = PRELAUNCH_ORACLE_TWAP_PERIOD_SLOTS
+                .safe_sub(since_last)?
+                .max(0);
+
+            calculate_weighted_average(self.twap, price, from_start, since_last)?
+        };
+
+        self.last_update_slot = clock_slot;
+
+        Ok(())
+    }
+
+    /// Admin update, price is set directly without the per update change bound
+    pub fn update_params(
+        &mut self,
+        params: &PrelaunchOracleParams,
+        clock_slot: u64,
+    ) -> DriftResult {
+        if let Some(max_price) = params.max_price {
+            validate!(
+                max_price >= 0,
+                ErrorCode::InvalidOracle,
+                "max_price must be non-negative"
+            )?;
+            self.max_price = max_price;
+        }
+
+        if let Some(price) = params.price {
+            validate!(
+                price > 0 && (self.max_price == 0 || price <= self.max_price),
+                ErrorCode::InvalidOracle,
+                "price={} must be positive and <= max_price={}",
+                price,
+                self.max_price
+            )?;
+            self.set_price(price, clock_slot)?;
+        }
+
+        if let Some(confidence) = params.confidence {
+            self.confidence = confidence;
+        }
+
+        if let Some(max_staleness_slots) = params.max_staleness_slots {
+            self.max_staleness_slots = max_staleness_slots;
+        }
+
+        if let Some(max_price_change_per_update) = params.max_price_change_per_update {
+            validate!(
+                max_price_change_per_update.cast::<u64>()? <= PERCENTAGE_PRECISION_U64,
+                ErrorCode::InvalidOracle,
+                "max_price_change_per_update must be <= PERCENTAGE_PRECISION"
+            )?;
+            self.max_price_change_per_update = max_price_change_per_update;
+        }
+
+        if let Some(authority) = params.authority {
+            self.authority = authority;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn is_prelaunch_oracle_account(account_info: &AccountInfo) -> DriftResult<bool> {
+    if account_info.owner != &crate::id() {
+        return Ok(false);
+    }
+
+    let data = account_info
+        .try_borrow_data()
+        .or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    if data.len() < PrelaunchOracle::SIZE {
+        return Ok(false);
+    }
+
+    Ok(array_ref![data, 0, 8] == &PrelaunchOracle::discriminator())
+}
+
+pub fn get_prelaunch_oracle_price(
+    price_oracle: &AccountInfo,
+    clock_slot: u64,
+) -> DriftResult<OraclePriceData> {
+    let prelaunch_oracle_loader: AccountLoader<PrelaunchOracle> =
+        AccountLoader::try_from(price_oracle).or(Err(ErrorCode::UnableToLoadOracle))?;
+    let prelaunch_oracle = prelaunch_oracle_loader
+        .load()
+        .or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    prelaunch_oracle.get_price_data(clock_slot)
+}
+
+pub fn get_prelaunch_oracle_twap(price_oracle: &AccountInfo) -> DriftResult<i64> {
+    let prelaunch_oracle_loader: AccountLoader<PrelaunchOracle> =
+        AccountLoader::try_from(price_oracle).or(Err(ErrorCode::UnableToLoadOracle))?;
+    let prelaunch_oracle = prelaunch_oracle_loader
+        .load()
+        .or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    Ok(prelaunch_oracle.twap)
+}
+
+pub fn get_prelaunch_oracle_market_index(price_oracle: &AccountInfo) -> DriftResult<u16> {
+    let prelaunch_oracle_loader: AccountLoader<PrelaunchOracle> =
+        AccountLoader::try_from(price_oracle).or(Err(ErrorCode::UnableToLoadOracle))?;
+    let prelaunch_oracle = prelaunch_oracle_loader
+        .load()
+        .or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    Ok(prelaunch_oracle.perp_market_index)
+}
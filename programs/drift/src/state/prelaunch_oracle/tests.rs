@@ -0,0 +1,166 @@
+use anchor_lang::Owner;
+use solana_program::pubkey::Pubkey;
+
+use crate::create_anchor_account_info;
+use crate::math::constants::{PERCENTAGE_PRECISION_U64, PRICE_PRECISION_I64};
+use crate::state::oracle::{get_oracle_price, OracleSource};
+use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market::AMM;
+use crate::state::prelaunch_oracle::{PrelaunchOracle, PrelaunchOracleParams};
+use crate::test_utils::*;
+
+#[test]
+fn update_price_bounded_per_update() {
+    let mut oracle = PrelaunchOracle {
+        price: 10 * PRICE_PRECISION_I64,
+        max_price: 50 * PRICE_PRECISION_I64,
+        max_price_change_per_update: (PERCENTAGE_PRECISION_U64 / 10) as u32, // 10%
+        ..PrelaunchOracle::default()
+    };
+
+    // within bound
+    oracle
+        .update_price(105 * PRICE_PRECISION_I64 / 10, 1)
+        .unwrap();
+    assert_eq!(oracle.price, 105 * PRICE_PRECISION_I64 / 10);
+    assert_eq!(oracle.last_update_slot, 1);
+
+    // capped at +10%
+    oracle.update_price(20 * PRICE_PRECISION_I64, 2).unwrap();
+    assert_eq!(oracle.price, 11550000);
+
+    // capped at -10%
+    oracle.update_price(PRICE_PRECISION_I64, 3).unwrap();
+    assert_eq!(oracle.price, 10395000);
+
+    // capped at max_price
+    oracle.max_price_change_per_update = 0;
+    oracle.update_price(100 * PRICE_PRECISION_I64, 4).unwrap();
+    assert_eq!(oracle.price, 50 * PRICE_PRECISION_I64);
+
+    assert!(oracle.update_price(0, 5).is_err());
+    assert!(oracle.update_price(-1, 5).is_err());
+}
+
+#[test]
+fn twap() {
+    let mut oracle = PrelaunchOracle::default();
+
+    // first price sets the twap
+    oracle
+        .update_params(
+            &PrelaunchOracleParams {
+                price: Some(10 * PRICE_PRECISION_I64),
+                ..PrelaunchOracleParams::default()
+            },
+            0,
+        )
+        .unwrap();
+    assert_eq!(oracle.twap, 10 * PRICE_PRECISION_I64);
+
+    // a tenth of the period in
+    oracle.update_price(20 * PRICE_PRECISION_I64, 900).unwrap();
+    assert_eq!(oracle.price, 20 * PRICE_PRECISION_I64);
+    assert_eq!(oracle.twap, 10999999);
+
+    // a full period without updates
+    oracle
+        .update_price(30 * PRICE_PRECISION_I64, 900 + 9_000)
+        .unwrap();
+    assert_eq!(oracle.twap, 30 * PRICE_PRECISION_I64);
+}
+
+#[test]
+fn max_staleness() {
+    let oracle = PrelaunchOracle {
+        price: 10 * PRICE_PRECISION_I64,
+        confidence: PRICE_PRECISION_I64 as u64 / 10,
+        last_update_slot: 100,
+        max_staleness_slots: 50,
+        ..PrelaunchOracle::default()
+    };
+
+    let oracle_price_data = oracle.get_price_data(150).unwrap();
+    assert_eq!(oracle_price_data.price, 10 * PRICE_PRECISION_I64);
+    assert_eq!(oracle_price_data.confidence, 100000);
+    assert_eq!(oracle_price_data.delay, 50);
+    assert!(oracle_price_data.has_sufficient_number_of_data_points);
+
+    let oracle_price_data = oracle.get_price_data(151).unwrap();
+    assert_eq!(oracle_price_data.delay, 51);
+    assert!(!oracle_price_data.has_sufficient_number_of_data_points);
+}
+
+#[test]
+fn update_params() {
+    let mut oracle = PrelaunchOracle::default();
+    let authority = Pubkey::new_unique();
+
+    oracle
+        .update_params(
+            &PrelaunchOracleParams {
+                perp_market_index: 0,
+                price: Some(10 * PRICE_PRECISION_I64),
+                max_price: Some(50 * PRICE_PRECISION_I64),
+                authority: Some(authority),
+                ..PrelaunchOracleParams::default()
+            },
+            10,
+        )
+        .unwrap();
+    assert_eq!(oracle.price, 10 * PRICE_PRECISION_I64);
+    assert_eq!(oracle.last_update_slot, 10);
+    assert_eq!(oracle.authority, authority);
+
+    // price above max price
+    assert!(oracle
+        .update_params(
+            &PrelaunchOracleParams {
+                price: Some(60 * PRICE_PRECISION_I64),
+                ..PrelaunchOracleParams::default()
+            },
+            11,
+        )
+        .is_err());
+
+    // change bound above 100%
+    assert!(oracle
+        .update_params(
+            &PrelaunchOracleParams {
+                max_price_change_per_update: Some(PERCENTAGE_PRECISION_U64 as u32 + 1),
+                ..PrelaunchOracleParams::default()
+            },
+            11,
+        )
+        .is_err());
+}
+
+#[test]
+fn oracle_map() {
+    let oracle_key = Pubkey::new_unique();
+    let mut oracle = PrelaunchOracle {
+        price: 10 * PRICE_PRECISION_I64,
+        twap: 9 * PRICE_PRECISION_I64,
+        confidence: 1000,
+        last_update_slot: 100,
+        perp_market_index: 1,
+        ..PrelaunchOracle::default()
+    };
+    create_anchor_account_info!(oracle, &oracle_key, PrelaunchOracle, oracle_account_info);
+
+    let mut oracle_map = OracleMap::load_one(&oracle_account_info, 105, None).unwrap();
+    let oracle_price_data = oracle_map.get_price_data(&oracle_key).unwrap();
+    assert_eq!(oracle_price_data.price, 10 * PRICE_PRECISION_I64);
+    assert_eq!(oracle_price_data.delay, 5);
+
+    let oracle_price_data =
+        get_oracle_price(&OracleSource::Prelaunch, &oracle_account_info, 105).unwrap();
+    assert_eq!(oracle_price_data.price, 10 * PRICE_PRECISION_I64);
+
+    let amm = AMM {
+        oracle_source: OracleSource::Prelaunch,
+        ..AMM::default()
+    };
+    let twap = amm.get_oracle_twap(&oracle_account_info).unwrap();
+    assert_eq!(twap, Some(9 * PRICE_PRECISION_I64));
+}
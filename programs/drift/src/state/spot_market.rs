@@ -171,7 +171,15 @@ pub struct SpotMarket {
     /// disabled when 0
     /// precision: QUOTE_PRECISION
     pub scale_initial_asset_weight_start: u64,
-    pub padding: [u8; 48],
+    /// Multiple of the oracle confidence interval to value deposits below and borrows above the oracle price
+    /// in confidence adjusted margin calculations
+    /// disabled when 0
+    /// precision: MARGIN_PRECISION
+    pub margin_confidence_multiplier: u16,
+    /// Feed id the pyth pull price update account must carry
+    /// zeroed for other oracle sources
+    pub oracle_feed_id: [u8; 32],
+    pub padding: [u8; 14],
 }
 
 impl Default for SpotMarket {
@@ -229,7 +237,9 @@ impl Default for SpotMarket {
             flash_loan_initial_token_amount: 0,
             total_swap_fee: 0,
             scale_initial_asset_weight_start: 0,
-            padding: [0; 48],
+            margin_confidence_multiplier: 0,
+            oracle_feed_id: [0; 32],
+            padding: [0; 14],
         }
     }
 }
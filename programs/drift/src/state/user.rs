@@ -10,7 +10,9 @@ use crate::math::constants::{
 };
 use crate::math::lp::{calculate_lp_open_bids_asks, calculate_settle_lp_metrics};
 use crate::math::margin::MarginRequirementType;
-use crate::math::orders::{standardize_base_asset_amount, standardize_price};
+use crate::math::orders::{
+    calculate_max_slippage_price, standardize_base_asset_amount, standardize_price,
+};
 use crate::math::position::{
     calculate_base_asset_value_and_pnl_with_oracle_price,
     calculate_base_asset_value_with_oracle_price,
@@ -29,9 +31,11 @@ use crate::{get_then_update_id, QUOTE_PRECISION_U64};
 use crate::{math_error, SPOT_WEIGHT_PRECISION_I128};
 use crate::{safe_increment, SPOT_WEIGHT_PRECISION};
 use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::msg;
 use std::cmp::max;
+use std::mem::size_of;
 use std::ops::Neg;
 use std::panic::Location;
 
@@ -48,11 +52,11 @@ pub enum UserStatus {
 
 // implement SIZE const for User
 impl Size for User {
-    const SIZE: usize = 4376;
+    const SIZE: usize = 10072;
 }
 
 #[account(zero_copy(unsafe))]
-#[derive(Default, Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug)]
 #[repr(C)]
 pub struct User {
     /// The owner/authority of the account
@@ -62,11 +66,11 @@ pub struct User {
     /// Encoded display name e.g. "toly"
     pub name: [u8; 32],
     /// The user's spot positions
-    pub spot_positions: [SpotPosition; 8],
+    pub spot_positions: [SpotPosition; 16],
     /// The user's perp positions
-    pub perp_positions: [PerpPosition; 8],
-    /// The user's orders
-    pub orders: [Order; 32],
+    pub perp_positions: [PerpPosition; 16],
+    /// The user's orders. Bounded so the account stays under the 10 KiB a cpi can create
+    pub orders: [Order; 48],
     /// The last time the user added perp lp positions
     pub last_add_perp_lp_shares_ts: i64,
     /// The total values of deposits the user has made
@@ -116,7 +120,114 @@ pub struct User {
     pub open_auctions: u8,
     /// Whether or not user has open order with auction
     pub has_open_auction: bool,
-    pub padding: [u8; 21],
+    /// What happens when the user's taker order would match a maker order from the same authority
+    pub self_trade_prevention_mode: SelfTradePreventionMode,
+    /// Seconds without a heartbeat or order action before keepers can cancel the user's open orders
+    /// 0 disables the dead man's switch
+    pub heartbeat_timeout: u32,
+    /// The last time the user sent a heartbeat or took an order action
+    pub last_heartbeat_ts: i64,
+    pub padding: [u8; 8],
+}
+
+impl Default for User {
+    fn default() -> Self {
+        User {
+            authority: Pubkey::default(),
+            delegate: Pubkey::default(),
+            name: [0; 32],
+            spot_positions: [SpotPosition::default(); 16],
+            perp_positions: [PerpPosition::default(); 16],
+            orders: [Order::default(); 48],
+            last_add_perp_lp_shares_ts: 0,
+            total_deposits: 0,
+            total_withdraws: 0,
+            total_social_loss: 0,
+            settled_perp_pnl: 0,
+            cumulative_spot_fees: 0,
+            cumulative_perp_funding: 0,
+            liquidation_margin_freed: 0,
+            last_active_slot: 0,
+            next_order_id: 0,
+            max_margin_ratio: 0,
+            next_liquidation_id: 0,
+            sub_account_id: 0,
+            status: 0,
+            is_margin_trading_enabled: false,
+            idle: false,
+            open_orders: 0,
+            has_open_order: false,
+            open_auctions: 0,
+            has_open_auction: false,
+            self_trade_prevention_mode: SelfTradePreventionMode::default(),
+            heartbeat_timeout: 0,
+            last_heartbeat_ts: 0,
+            padding: [0; 8],
+        }
+    }
+}
+
+/// Size of user accounts created before users could hold 16 spot positions, 16 perp positions and
+/// 48 orders. They held 8, 8 and 32
+pub const LEGACY_USER_SIZE: usize = 4376;
+/// Size of orders in legacy user accounts. Orders are padded past it so fields can be added without
+/// changing the user account layout again
+pub const LEGACY_ORDER_SIZE: usize = 96;
+
+/// Moves a legacy user account's data into the current layout. The account must already be
+/// resized to User::SIZE with the added bytes zeroed
+pub fn migrate_legacy_user_data(data: &mut [u8]) -> DriftResult {
+    validate!(
+        data.len() == User::SIZE && data[..8] == User::discriminator(),
+        ErrorCode::InvalidUserMigration,
+        "account is not a resized legacy user account"
+    )?;
+
+    let legacy_data = data[..LEGACY_USER_SIZE].to_vec();
+
+    // discriminator, authority, delegate and name stay in place
+    let header_len = 8 + 3 * 32;
+    data[header_len..].fill(0);
+
+    let mut legacy_offset = header_len;
+    let mut offset = header_len;
+    for (legacy_item_size, item_size, legacy_len, len) in [
+        (size_of::<SpotPosition>(), size_of::<SpotPosition>(), 8, 16),
+        (size_of::<PerpPosition>(), size_of::<PerpPosition>(), 8, 16),
+        // legacy orders end at link_type, the padding after it is left zeroed
+        (LEGACY_ORDER_SIZE, size_of::<Order>(), 32, 48),
+    ] {
+        for i in 0..legacy_len {
+            let legacy_item_offset = legacy_offset + i * legacy_item_size;
+            let item_offset = offset + i * item_size;
+            data[item_offset..item_offset + legacy_item_size].copy_from_slice(
+                &legacy_data[legacy_item_offset..legacy_item_offset + legacy_item_size],
+            );
+        }
+        legacy_offset += legacy_item_size * legacy_len;
+        offset += item_size * len;
+    }
+
+    // fields after the orders keep their order
+    validate!(
+        data.len() - offset == legacy_data.len() - legacy_offset,
+        ErrorCode::InvalidUserMigration,
+        "legacy user fields after orders dont match current layout"
+    )?;
+    data[offset..].copy_from_slice(&legacy_data[legacy_offset..]);
+
+    Ok(())
+}
+
+/// Legacy user accounts have to be resized with migrate_user before they can be loaded, the current
+/// layout reads past the end of their data
+pub fn validate_user_migrated(user_account_info: &AccountInfo) -> DriftResult {
+    validate!(
+        user_account_info.data_len() >= User::SIZE,
+        ErrorCode::UserNotMigrated,
+        "user {} has not been migrated, resize it with migrate_user",
+        user_account_info.key
+    )
 }
 
 impl User {
@@ -251,6 +362,17 @@ impl User {
             .ok_or(ErrorCode::OrderDoesNotExist)
     }
 
+    pub fn get_order_index_by_client_order_id(&self, client_order_id: u64) -> DriftResult<usize> {
+        self.orders
+            .iter()
+            .position(|order| {
+                client_order_id != 0
+                    && order.client_order_id == client_order_id
+                    && order.status == OrderStatus::Open
+            })
+            .ok_or(ErrorCode::OrderDoesNotExist)
+    }
+
     pub fn get_order(&self, order_id: u32) -> Option<&Order> {
         self.orders.iter().find(|order| order.order_id == order_id)
     }
@@ -263,6 +385,19 @@ impl User {
         }
     }
 
+    /// Lowest link id not used by any open linked order
+    pub fn get_unused_link_id(&self) -> DriftResult<u8> {
+        (1..=u8::MAX)
+            .find(|link_id| {
+                !self.orders.iter().any(|order| {
+                    order.status == OrderStatus::Open
+                        && order.link_type != OrderLinkType::None
+                        && order.link_id == *link_id
+                })
+            })
+            .ok_or(ErrorCode::InvalidLinkedOrder)
+    }
+
     pub fn increment_total_deposits(
         &mut self,
         amount: u64,
@@ -351,6 +486,18 @@ impl User {
         self.idle = false;
     }
 
+    pub fn update_last_heartbeat_ts(&mut self, now: i64) {
+        self.last_heartbeat_ts = now;
+    }
+
+    pub fn is_heartbeat_expired(&self, now: i64) -> DriftResult<bool> {
+        if self.heartbeat_timeout == 0 {
+            return Ok(false);
+        }
+
+        Ok(now.safe_sub(self.last_heartbeat_ts)? > self.heartbeat_timeout.cast()?)
+    }
+
     pub fn increment_open_orders(&mut self, is_auction: bool) {
         self.open_orders = self.open_orders.saturating_add(1);
         self.has_open_order = self.open_orders > 0;
@@ -913,7 +1060,7 @@ impl PerpPosition {
     }
 }
 
-pub type PerpPositions = [PerpPosition; 8];
+pub type PerpPositions = [PerpPosition; 16];
 
 #[zero_copy(unsafe)]
 #[repr(C)]
@@ -976,7 +1123,42 @@ pub struct Order {
     pub trigger_condition: OrderTriggerCondition,
     /// How many slots the auction lasts
     pub auction_duration: u8,
-    pub padding: [u8; 3],
+    /// If set, the trigger price trails the best oracle price seen since placement by trailing_stop_offset.
+    /// Only relevant for untriggered trigger orders
+    pub trailing_stop_type: TrailingStopType,
+    /// Orders with the same non-zero link id are linked. Only relevant if link_type is set
+    pub link_id: u8,
+    /// How the order behaves when a linked order is filled or triggered
+    pub link_type: OrderLinkType,
+    /// Client chosen id, unique among the user's open orders if non-zero
+    pub client_order_id: u64,
+    /// When the next twap slice is released. Only relevant for twap orders
+    pub twap_next_slice_ts: i64,
+    /// Size not released into base_asset_amount yet. Only relevant for twap and iceberg orders
+    /// precision: same as base_asset_amount
+    pub hidden_base_asset_amount: u64,
+    /// Size of each twap slice, the last slice also releases the remainder. For iceberg orders,
+    /// the size displayed at a time
+    /// precision: same as base_asset_amount
+    pub slice_base_asset_amount: u64,
+    /// How far the trigger price trails the oracle price. Only relevant for trailing stops
+    /// precision: PRICE_PRECISION for FixedOffset, PERCENTAGE_PRECISION for Percentage
+    pub trailing_stop_offset: u32,
+    /// Seconds between twap slices
+    pub twap_interval: u32,
+    /// If non-zero, the order can't fill more than this many bps worse than the oracle price at fill
+    /// time. Only relevant for market, trigger market and oracle orders
+    pub max_slippage_bps: u16,
+    /// Twap slices not released yet
+    pub twap_slices_remaining: u16,
+    /// Market the trigger price source is read from. Only relevant for trigger orders
+    pub trigger_market_index: u16,
+    /// Perp only. The order is reduce only and its unfilled size is reset to the full position when
+    /// it's triggered or filled
+    pub close_position: bool,
+    /// Price the trigger condition is checked against. Only relevant for trigger orders
+    pub trigger_price_source: TriggerPriceSource,
+    pub padding: [u8; 16],
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
@@ -1034,6 +1216,29 @@ impl Order {
         Ok(price)
     }
 
+    /// Worst price the order can fill at given its max slippage from the oracle price, if it has one
+    pub fn get_max_slippage_price(
+        &self,
+        oracle_price: i64,
+        tick_size: u64,
+    ) -> DriftResult<Option<u64>> {
+        if !self.has_max_slippage() {
+            return Ok(None);
+        }
+
+        calculate_max_slippage_price(
+            oracle_price,
+            self.direction,
+            self.max_slippage_bps,
+            tick_size,
+        )
+        .map(Some)
+    }
+
+    pub fn has_max_slippage(&self) -> bool {
+        self.max_slippage_bps != 0
+    }
+
     #[track_caller]
     #[inline(always)]
     pub fn force_get_limit_price(
@@ -1094,7 +1299,9 @@ impl Order {
         let existing_position = match existing_position {
             Some(existing_position) => existing_position,
             None => {
-                return Ok(base_asset_amount_unfilled);
+                // twap slices that haven't been released yet and the hidden part of an iceberg
+                // are still part of the order
+                return base_asset_amount_unfilled.safe_add(self.hidden_base_asset_amount);
             }
         };
 
@@ -1145,6 +1352,43 @@ impl Order {
         )
     }
 
+    /// Price source and market an untriggered order's trigger condition is checked against
+    pub fn get_trigger_price_source(&self) -> (TriggerPriceSource, u16) {
+        if !self.must_be_triggered()
+            || self.triggered()
+            || self.trigger_price_source == TriggerPriceSource::Oracle
+        {
+            return (TriggerPriceSource::Oracle, self.market_index);
+        }
+
+        (self.trigger_price_source, self.trigger_market_index)
+    }
+
+    pub fn is_trailing_stop(&self) -> bool {
+        self.trailing_stop_type != TrailingStopType::None
+    }
+
+    pub fn is_linked_to(&self, other: &Order) -> bool {
+        self.link_type != OrderLinkType::None
+            && self.link_id != 0
+            && self.link_id == other.link_id
+            && self.order_id != other.order_id
+    }
+
+    /// Child order waiting on its parent to fill. Can't be filled or triggered
+    pub fn is_pending_linked_order(&self) -> bool {
+        self.link_type == OrderLinkType::PendingChild
+    }
+
+    pub fn is_twap(&self) -> bool {
+        self.order_type == OrderType::Twap
+    }
+
+    /// Twap order whose current slice is filled. Can't be filled until the next slice is released
+    pub fn is_waiting_for_twap_slice(&self) -> bool {
+        self.is_twap() && self.base_asset_amount_filled == self.base_asset_amount
+    }
+
     pub fn triggered(&self) -> bool {
         matches!(
             self.trigger_condition,
@@ -1174,7 +1418,7 @@ impl Order {
     pub fn is_market_order(&self) -> bool {
         matches!(
             self.order_type,
-            OrderType::Market | OrderType::TriggerMarket | OrderType::Oracle
+            OrderType::Market | OrderType::TriggerMarket | OrderType::Oracle | OrderType::Twap
         )
     }
 
@@ -1182,6 +1426,12 @@ impl Order {
         matches!(self.order_type, OrderType::Limit | OrderType::TriggerLimit)
     }
 
+    /// Limit order that only displays slice_base_asset_amount at a time, refilled from
+    /// hidden_base_asset_amount each time the displayed amount fills
+    pub fn is_iceberg(&self) -> bool {
+        self.order_type == OrderType::Limit && self.slice_base_asset_amount != 0
+    }
+
     pub fn is_resting_limit_order(&self, slot: u64) -> DriftResult<bool> {
         Ok(self.is_limit_order() && (self.post_only || self.is_auction_complete(slot)?))
     }
@@ -1213,7 +1463,21 @@ impl Default for Order {
             auction_end_price: 0,
             auction_duration: 0,
             max_ts: 0,
-            padding: [0; 3],
+            trailing_stop_type: TrailingStopType::None,
+            link_id: 0,
+            link_type: OrderLinkType::None,
+            client_order_id: 0,
+            twap_next_slice_ts: 0,
+            hidden_base_asset_amount: 0,
+            slice_base_asset_amount: 0,
+            trailing_stop_offset: 0,
+            twap_interval: 0,
+            max_slippage_bps: 0,
+            twap_slices_remaining: 0,
+            trigger_market_index: 0,
+            close_position: false,
+            trigger_price_source: TriggerPriceSource::Oracle,
+            padding: [0; 16],
         }
     }
 }
@@ -1238,6 +1502,10 @@ pub enum OrderType {
     TriggerLimit,
     /// Market order where the auction prices are oracle offsets
     Oracle,
+    /// Market order split into equal slices released over time. Each slice is auctioned like a
+    /// market order. base_asset_amount is the size released so far, the rest is held in
+    /// hidden_base_asset_amount
+    Twap,
 }
 
 impl Default for OrderType {
@@ -1260,6 +1528,82 @@ impl Default for OrderTriggerCondition {
     }
 }
 
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum TrailingStopType {
+    None,
+    /// trigger price trails the oracle price by trailing_stop_offset
+    /// precision: PRICE_PRECISION
+    FixedOffset,
+    /// trigger price trails the oracle price by trailing_stop_offset percent of the oracle price
+    /// precision: PERCENTAGE_PRECISION
+    Percentage,
+}
+
+impl Default for TrailingStopType {
+    fn default() -> Self {
+        TrailingStopType::None
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum TriggerPriceSource {
+    /// oracle of the order's own market
+    Oracle,
+    /// oracle of the perp market at trigger_market_index
+    PerpOracle,
+    /// oracle of the spot market at trigger_market_index
+    SpotOracle,
+    /// 5 minute mark price twap of the perp market at trigger_market_index. Uses the twap rather
+    /// than the reserve price so it can't be moved within a transaction to trigger orders
+    PerpMarkPrice,
+    /// last_mark_price_twap of the perp market at trigger_market_index
+    PerpMarkPriceTwap,
+}
+
+impl Default for TriggerPriceSource {
+    fn default() -> Self {
+        TriggerPriceSource::Oracle
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum OrderLinkType {
+    None,
+    /// a fill or trigger cancels the other OneCancelsOther orders with the same link id
+    OneCancelsOther,
+    /// first fill activates the pending children with the same link id.
+    /// canceling it before any fill cancels the pending children
+    Parent,
+    /// becomes one cancels other once the parent with the same link id fills
+    PendingChild,
+}
+
+impl Default for OrderLinkType {
+    fn default() -> Self {
+        OrderLinkType::None
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum SelfTradePreventionMode {
+    /// orders from the same authority can match
+    None,
+    /// cancel the maker order
+    CancelMaker,
+    /// cancel the taker order
+    CancelTaker,
+    /// cancel both orders
+    CancelBoth,
+    /// reduce both orders by the smaller order's size, canceling the smaller order
+    DecrementAndCancel,
+}
+
+impl Default for SelfTradePreventionMode {
+    fn default() -> Self {
+        SelfTradePreventionMode::None
+    }
+}
+
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
 pub enum MarketType {
     Spot,
@@ -1313,7 +1657,16 @@ pub struct UserStats {
     /// Whether the user is a referrer. Sub account 0 can not be deleted if user is a referrer
     pub is_referrer: bool,
     pub disable_update_perp_bid_ask_twap: bool,
-    pub padding: [u8; 50],
+    pub padding1: [u8; 2],
+    /// The highest signed quote nonce filled
+    pub last_quote_nonce: u64,
+    /// Which of the 64 nonces up to last_quote_nonce have been filled. Bit i is last_quote_nonce - i
+    pub quote_nonce_bitmap: u64,
+    /// The highest signed taker order nonce placed
+    pub last_signed_order_nonce: u64,
+    /// Which of the 64 nonces up to last_signed_order_nonce have been placed. Bit i is last_signed_order_nonce - i
+    pub signed_order_nonce_bitmap: u64,
+    pub padding: [u8; 16],
 }
 
 impl Default for UserStats {
@@ -1334,7 +1687,12 @@ impl Default for UserStats {
             number_of_sub_accounts_created: 0,
             is_referrer: false,
             disable_update_perp_bid_ask_twap: false,
-            padding: [0; 50],
+            padding1: [0; 2],
+            last_quote_nonce: 0,
+            quote_nonce_bitmap: 0,
+            last_signed_order_nonce: 0,
+            signed_order_nonce_bitmap: 0,
+            padding: [0; 16],
         }
     }
 }
@@ -1344,6 +1702,40 @@ impl Size for UserStats {
 }
 
 impl UserStats {
+    /// Marks a signed quote nonce as filled so the quote can't be replayed
+    pub fn use_quote_nonce(&mut self, nonce: u64) -> DriftResult {
+        validate!(
+            use_nonce(
+                nonce,
+                &mut self.last_quote_nonce,
+                &mut self.quote_nonce_bitmap
+            )?,
+            ErrorCode::SignedQuoteNonceAlreadyUsed,
+            "quote nonce {} already used or too old (last quote nonce = {})",
+            nonce,
+            self.last_quote_nonce
+        )?;
+
+        Ok(())
+    }
+
+    /// Marks a signed taker order nonce as placed so the order can't be replayed
+    pub fn use_signed_order_nonce(&mut self, nonce: u64) -> DriftResult {
+        validate!(
+            use_nonce(
+                nonce,
+                &mut self.last_signed_order_nonce,
+                &mut self.signed_order_nonce_bitmap
+            )?,
+            ErrorCode::SignedOrderNonceAlreadyUsed,
+            "signed order nonce {} already used or too old (last signed order nonce = {})",
+            nonce,
+            self.last_signed_order_nonce
+        )?;
+
+        Ok(())
+    }
+
     pub fn update_maker_volume_30d(&mut self, quote_asset_amount: u64, now: i64) -> DriftResult {
         let since_last = max(1_i64, now.safe_sub(self.last_maker_volume_30d_ts)?);
 
@@ -1436,6 +1828,31 @@ impl UserStats {
     }
 }
 
+/// Marks nonce as used in a 64 nonce window ending at last_nonce. Nonces can be used out of order as
+/// long as they are within the window. Returns false if the nonce was already used or is too old
+fn use_nonce(nonce: u64, last_nonce: &mut u64, nonce_bitmap: &mut u64) -> DriftResult<bool> {
+    if nonce > *last_nonce || *nonce_bitmap == 0 {
+        let shift = nonce.saturating_sub(*last_nonce);
+        *nonce_bitmap = if shift >= 64 {
+            0
+        } else {
+            *nonce_bitmap << shift
+        };
+        *nonce_bitmap |= 1;
+        *last_nonce = nonce;
+        return Ok(true);
+    }
+
+    let age = last_nonce.safe_sub(nonce)?;
+    if age >= 64 || *nonce_bitmap & (1 << age) != 0 {
+        return Ok(false);
+    }
+
+    *nonce_bitmap |= 1 << age;
+
+    Ok(true)
+}
+
 #[account(zero_copy(unsafe))]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(C)]
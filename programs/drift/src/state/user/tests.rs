@@ -626,6 +626,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -651,6 +652,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -676,6 +678,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -723,6 +726,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
 
         let OrderFillSimulation {
@@ -749,6 +753,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
 
         let OrderFillSimulation {
@@ -775,6 +780,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
 
         let OrderFillSimulation {
@@ -823,6 +829,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -848,6 +855,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -873,6 +881,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -920,6 +929,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -945,6 +955,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -970,6 +981,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1017,6 +1029,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1042,6 +1055,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1067,6 +1081,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1114,6 +1129,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1139,6 +1155,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1164,6 +1181,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1211,6 +1229,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1236,6 +1255,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1261,6 +1281,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1308,6 +1329,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: None,
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1333,6 +1355,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(110 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1358,6 +1381,7 @@ mod get_worst_case_fill_simulation {
         let strict_price = StrictOraclePrice {
             current: oracle_price_data.price,
             twap_5min: Some(90 * PRICE_PRECISION_I64),
+            confidence_offset: 0,
         };
         let OrderFillSimulation {
             token_amount: worst_case_token_amount,
@@ -1762,3 +1786,249 @@ mod update_user_status {
         assert!(user.status & UserStatus::ReduceOnly as u8 > 0);
     }
 }
+
+mod trigger_price_source {
+    use crate::state::user::{Order, OrderTriggerCondition, OrderType, TriggerPriceSource};
+
+    #[test]
+    fn test() {
+        let mut order = Order {
+            order_type: OrderType::TriggerMarket,
+            market_index: 1,
+            trigger_condition: OrderTriggerCondition::Below,
+            ..Order::default()
+        };
+
+        assert_eq!(
+            order.get_trigger_price_source(),
+            (TriggerPriceSource::Oracle, 1)
+        );
+
+        order.trigger_price_source = TriggerPriceSource::PerpMarkPriceTwap;
+        order.trigger_market_index = 2;
+        assert_eq!(
+            order.get_trigger_price_source(),
+            (TriggerPriceSource::PerpMarkPriceTwap, 2)
+        );
+        assert!(!order.has_auction());
+
+        order.trigger_price_source = TriggerPriceSource::SpotOracle;
+        order.trigger_market_index = 0;
+        assert_eq!(
+            order.get_trigger_price_source(),
+            (TriggerPriceSource::SpotOracle, 0)
+        );
+
+        // once triggered the order's own oracle is used
+        order.trigger_condition = OrderTriggerCondition::TriggeredBelow;
+        assert_eq!(
+            order.get_trigger_price_source(),
+            (TriggerPriceSource::Oracle, 1)
+        );
+
+        // only trigger orders have a trigger price source
+        let order = Order {
+            order_type: OrderType::Limit,
+            market_index: 1,
+            trigger_price_source: TriggerPriceSource::PerpOracle,
+            trigger_market_index: 2,
+            ..Order::default()
+        };
+        assert_eq!(
+            order.get_trigger_price_source(),
+            (TriggerPriceSource::Oracle, 1)
+        );
+    }
+}
+
+mod use_quote_nonce {
+    use crate::state::user::UserStats;
+
+    #[test]
+    fn test() {
+        let mut user_stats = UserStats::default();
+
+        user_stats.use_quote_nonce(5).unwrap();
+        assert_eq!(user_stats.last_quote_nonce, 5);
+        assert!(user_stats.use_quote_nonce(5).is_err());
+
+        // out of order within window
+        user_stats.use_quote_nonce(3).unwrap();
+        assert!(user_stats.use_quote_nonce(3).is_err());
+        assert_eq!(user_stats.last_quote_nonce, 5);
+
+        user_stats.use_quote_nonce(10).unwrap();
+        assert_eq!(user_stats.last_quote_nonce, 10);
+        assert!(user_stats.use_quote_nonce(5).is_err());
+        user_stats.use_quote_nonce(4).unwrap();
+
+        // jump past the window, older nonces can no longer be used
+        user_stats.use_quote_nonce(100).unwrap();
+        assert!(user_stats.use_quote_nonce(36).is_err());
+        user_stats.use_quote_nonce(37).unwrap();
+        assert!(user_stats.use_quote_nonce(37).is_err());
+
+        // signed orders have their own nonces
+        user_stats.use_signed_order_nonce(37).unwrap();
+        assert!(user_stats.use_signed_order_nonce(37).is_err());
+        assert_eq!(user_stats.last_signed_order_nonce, 37);
+        assert_eq!(user_stats.last_quote_nonce, 100);
+    }
+}
+
+mod get_unused_link_id {
+    use crate::state::user::{Order, OrderLinkType, OrderStatus, User};
+    use crate::test_utils::get_orders;
+
+    #[test]
+    fn test() {
+        let user = User::default();
+        assert_eq!(user.get_unused_link_id().unwrap(), 1);
+
+        let user = User {
+            orders: get_orders(Order {
+                status: OrderStatus::Open,
+                link_id: 1,
+                link_type: OrderLinkType::OneCancelsOther,
+                ..Order::default()
+            }),
+            ..User::default()
+        };
+        assert_eq!(user.get_unused_link_id().unwrap(), 2);
+
+        // closed orders don't use their link id
+        let user = User {
+            orders: get_orders(Order {
+                status: OrderStatus::Init,
+                link_id: 1,
+                link_type: OrderLinkType::OneCancelsOther,
+                ..Order::default()
+            }),
+            ..User::default()
+        };
+        assert_eq!(user.get_unused_link_id().unwrap(), 1);
+    }
+}
+
+mod migrate_legacy_user_data {
+    use std::mem::size_of;
+
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::Discriminator;
+
+    use crate::error::ErrorCode;
+    use crate::state::traits::Size;
+    use crate::state::user::{
+        migrate_legacy_user_data, validate_user_migrated, Order, OrderStatus, PerpPosition,
+        SpotPosition, User, LEGACY_ORDER_SIZE, LEGACY_USER_SIZE,
+    };
+    use crate::test_utils::{create_account_info, get_orders, get_positions, get_spot_positions};
+
+    #[test]
+    fn moves_positions_orders_and_fields() {
+        let mut user = User {
+            authority: Pubkey::new_unique(),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 1,
+                scaled_balance: 100,
+                ..SpotPosition::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 2,
+                base_asset_amount: 10,
+                ..PerpPosition::default()
+            }),
+            orders: get_orders(Order {
+                order_id: 3,
+                status: OrderStatus::Open,
+                ..Order::default()
+            }),
+            next_order_id: 4,
+            open_orders: 1,
+            last_heartbeat_ts: 5,
+            ..User::default()
+        };
+        user.spot_positions[7].market_index = 7;
+        user.perp_positions[7].market_index = 7;
+        user.orders[31].order_id = 31;
+
+        let data = [User::discriminator().as_slice(), bytemuck::bytes_of(&user)].concat();
+        assert_eq!(data.len(), User::SIZE);
+
+        // legacy layout only has room for the first 8 positions and 32 orders
+        let header_len = 8 + 3 * 32;
+        let perp_positions_offset = header_len + 16 * size_of::<SpotPosition>();
+        let orders_offset = perp_positions_offset + 16 * size_of::<PerpPosition>();
+        let fields_offset = orders_offset + 48 * size_of::<Order>();
+        let mut legacy_data = [
+            &data[..header_len + 8 * size_of::<SpotPosition>()],
+            &data[perp_positions_offset..perp_positions_offset + 8 * size_of::<PerpPosition>()],
+        ]
+        .concat();
+        for i in 0..32 {
+            let order_offset = orders_offset + i * size_of::<Order>();
+            legacy_data.extend_from_slice(&data[order_offset..order_offset + LEGACY_ORDER_SIZE]);
+        }
+        legacy_data.extend_from_slice(&data[fields_offset..]);
+        assert_eq!(legacy_data.len(), LEGACY_USER_SIZE);
+
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut account_data = legacy_data.clone();
+        let legacy_account_info =
+            create_account_info(&key, true, &mut lamports, &mut account_data, &crate::id());
+        assert_eq!(
+            validate_user_migrated(&legacy_account_info),
+            Err(ErrorCode::UserNotMigrated)
+        );
+
+        let mut migrated_data = legacy_data.clone();
+        migrated_data.resize(User::SIZE, 0);
+        migrate_legacy_user_data(&mut migrated_data).unwrap();
+        assert_eq!(migrated_data, data);
+
+        let mut lamports = 0;
+        let migrated_account_info =
+            create_account_info(&key, true, &mut lamports, &mut migrated_data, &crate::id());
+        assert_eq!(validate_user_migrated(&migrated_account_info), Ok(()));
+
+        // not resized
+        assert!(migrate_legacy_user_data(&mut legacy_data).is_err());
+
+        // not a user
+        let mut data = vec![0; User::SIZE];
+        assert!(migrate_legacy_user_data(&mut data).is_err());
+    }
+}
+
+mod get_order_index_by_client_order_id {
+    use crate::state::user::{Order, OrderStatus, User};
+
+    #[test]
+    fn test() {
+        let mut user = User::default();
+        user.orders[1] = Order {
+            status: OrderStatus::Open,
+            order_id: 1,
+            client_order_id: u64::MAX,
+            ..Order::default()
+        };
+        user.orders[2] = Order {
+            status: OrderStatus::Open,
+            order_id: 2,
+            ..Order::default()
+        };
+
+        assert_eq!(
+            user.get_order_index_by_client_order_id(u64::MAX).unwrap(),
+            1
+        );
+
+        // orders without a client order id can't be looked up by it
+        assert!(user.get_order_index_by_client_order_id(0).is_err());
+
+        // closed orders don't keep their client order id
+        user.orders[1].status = OrderStatus::Canceled;
+        assert!(user.get_order_index_by_client_order_id(u64::MAX).is_err());
+    }
+}
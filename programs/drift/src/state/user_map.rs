@@ -289,8 +289,7 @@ pub fn load_user_maps<'a>(
             .try_borrow_data()
             .or(Err(ErrorCode::CouldNotLoadUserData))?;
 
-        let expected_data_len = User::SIZE;
-        if data.len() < expected_data_len {
+        if data.len() < 8 {
             break;
         }
 
@@ -299,6 +298,15 @@ pub fn load_user_maps<'a>(
             break;
         }
 
+        let expected_data_len = User::SIZE;
+        if data.len() < expected_data_len {
+            msg!(
+                "user {} has not been migrated, resize it with migrate_user",
+                user_key
+            );
+            return Err(ErrorCode::UserNotMigrated);
+        }
+
         let user_account_info = account_info_iter.next().safe_unwrap()?;
 
         let is_writable = user_account_info.is_writable;
@@ -6,14 +6,14 @@ use pyth::pc::Price;
 
 use crate::state::user::{Order, PerpPosition, SpotPosition};
 
-pub fn get_positions(position: PerpPosition) -> [PerpPosition; 8] {
-    let mut positions = [PerpPosition::default(); 8];
+pub fn get_positions(position: PerpPosition) -> [PerpPosition; 16] {
+    let mut positions = [PerpPosition::default(); 16];
     positions[0] = position;
     positions
 }
 
-pub fn get_orders(order: Order) -> [Order; 32] {
-    let mut orders = [Order::default(); 32];
+pub fn get_orders(order: Order) -> [Order; 48] {
+    let mut orders = [Order::default(); 48];
     orders[0] = order;
     orders
 }
@@ -22,7 +22,7 @@ pub fn get_orders(order: Order) -> [Order; 32] {
 macro_rules! get_orders {
     ($($order: expr),+) => {
         {
-            let mut orders = [Order::default(); 32];
+            let mut orders = [Order::default(); 48];
             let mut index = 0;
             $(
                 index += 1;
@@ -33,8 +33,8 @@ macro_rules! get_orders {
     };
 }
 
-pub fn get_spot_positions(spot_position: SpotPosition) -> [SpotPosition; 8] {
-    let mut spot_positions = [SpotPosition::default(); 8];
+pub fn get_spot_positions(spot_position: SpotPosition) -> [SpotPosition; 16] {
+    let mut spot_positions = [SpotPosition::default(); 16];
     if spot_position.market_index == 0 {
         spot_positions[0] = spot_position;
     } else {
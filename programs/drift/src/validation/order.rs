@@ -4,19 +4,34 @@ use crate::controller::position::PositionDirection;
 use crate::error::{DriftResult, ErrorCode};
 
 use crate::math::casting::Cast;
+use crate::math::constants::{ONE_BPS_DENOMINATOR, PERCENTAGE_PRECISION};
 use crate::math::orders::{
     calculate_base_asset_amount_to_fill_up_to_limit_price, is_multiple_of_step_size,
 };
+use crate::math::safe_math::SafeMath;
+use crate::state::order_params::OrderParams;
 use crate::state::perp_market::PerpMarket;
-use crate::state::user::{Order, OrderTriggerCondition, OrderType};
+use crate::state::user::{
+    Order, OrderLinkType, OrderStatus, OrderTriggerCondition, OrderType, TrailingStopType,
+};
 use crate::validate;
 
+#[cfg(test)]
+mod tests;
+
 pub fn validate_order(
     order: &Order,
     market: &PerpMarket,
     valid_oracle_price: Option<i64>,
     slot: u64,
 ) -> DriftResult {
+    if order.is_trailing_stop() && !order.must_be_triggered() {
+        msg!("Only trigger orders can be trailing stops");
+        return Err(ErrorCode::InvalidOrderTrigger);
+    }
+
+    validate_max_slippage(order)?;
+
     match order.order_type {
         OrderType::Market => {
             validate_market_order(order, market.amm.order_step_size, market.amm.min_order_size)?
@@ -35,11 +50,39 @@ pub fn validate_order(
         OrderType::Oracle => {
             validate_oracle_order(order, market.amm.order_step_size, market.amm.min_order_size)?
         }
+        OrderType::Twap => {
+            validate_twap_order(order, market.amm.order_step_size, market.amm.min_order_size)?
+        }
     }
 
     Ok(())
 }
 
+fn validate_max_slippage(order: &Order) -> DriftResult {
+    if !order.has_max_slippage() {
+        return Ok(());
+    }
+
+    validate!(
+        matches!(
+            order.order_type,
+            OrderType::Market | OrderType::TriggerMarket | OrderType::Oracle
+        ),
+        ErrorCode::InvalidOrderMaxSlippage,
+        "Max slippage only supported for market, trigger market and oracle orders"
+    )?;
+
+    validate!(
+        order.max_slippage_bps.cast::<u32>()? < ONE_BPS_DENOMINATOR,
+        ErrorCode::InvalidOrderMaxSlippage,
+        "Max slippage bps ({}) must be below {}",
+        order.max_slippage_bps,
+        ONE_BPS_DENOMINATOR
+    )?;
+
+    Ok(())
+}
+
 fn validate_market_order(order: &Order, step_size: u64, min_order_size: u64) -> DriftResult {
     validate_base_asset_amount(order, step_size, min_order_size, order.reduce_only)?;
 
@@ -145,6 +188,60 @@ fn validate_oracle_order(order: &Order, step_size: u64, min_order_size: u64) ->
     Ok(())
 }
 
+fn validate_twap_order(order: &Order, step_size: u64, min_order_size: u64) -> DriftResult {
+    validate_base_asset_amount(order, step_size, min_order_size, order.reduce_only)?;
+
+    validate!(
+        order.auction_start_price > 0 && order.auction_end_price > 0,
+        ErrorCode::InvalidOrderAuction,
+        "Auction start and end price must be greater than 0"
+    )?;
+
+    validate_auction_params(order)?;
+
+    validate!(
+        order.twap_interval > 0 && order.twap_next_slice_ts > 0,
+        ErrorCode::InvalidTwapOrder,
+        "Twap interval ({}) and next slice ts ({}) must be set",
+        order.twap_interval,
+        order.twap_next_slice_ts
+    )?;
+
+    validate!(
+        order.slice_base_asset_amount > 0
+            && is_multiple_of_step_size(order.hidden_base_asset_amount, step_size)?,
+        ErrorCode::InvalidTwapOrder,
+        "Twap slice size ({}) must be set and unreleased size ({}) a multiple of step size ({})",
+        order.slice_base_asset_amount,
+        order.hidden_base_asset_amount,
+        step_size
+    )?;
+
+    validate!(
+        order.twap_slices_remaining > 0 || order.hidden_base_asset_amount == 0,
+        ErrorCode::InvalidTwapOrder,
+        "Twap has unreleased size ({}) but no slices remaining",
+        order.hidden_base_asset_amount
+    )?;
+
+    if order.has_oracle_price_offset() {
+        msg!("Twap order can not have oracle offset");
+        return Err(ErrorCode::InvalidOrderOracleOffset);
+    }
+
+    if order.post_only {
+        msg!("Twap order can not be post only");
+        return Err(ErrorCode::InvalidOrderPostOnly);
+    }
+
+    if order.immediate_or_cancel {
+        msg!("Twap order can not be immediate or cancel");
+        return Err(ErrorCode::InvalidOrderIOC);
+    }
+
+    Ok(())
+}
+
 fn validate_limit_order(
     order: &Order,
     market: &PerpMarket,
@@ -168,9 +265,8 @@ fn validate_limit_order(
         return Err(ErrorCode::InvalidOrderLimitPrice);
     }
 
-    if order.trigger_price > 0 {
-        msg!("Limit order should not have trigger price");
-        return Err(ErrorCode::InvalidOrderTrigger);
+    if order.is_iceberg() {
+        validate_iceberg_order(order, market.amm.order_step_size)?;
     }
 
     if order.post_only {
@@ -188,6 +284,31 @@ fn validate_limit_order(
     Ok(())
 }
 
+fn validate_iceberg_order(order: &Order, step_size: u64) -> DriftResult {
+    validate!(
+        is_multiple_of_step_size(order.hidden_base_asset_amount, step_size)?,
+        ErrorCode::InvalidIcebergOrder,
+        "Iceberg hidden base asset amount ({}) not a multiple of step size ({})",
+        order.hidden_base_asset_amount,
+        step_size
+    )?;
+
+    // a new auction would start each time the displayed amount is refilled
+    validate!(
+        !order.has_auction(),
+        ErrorCode::InvalidIcebergOrder,
+        "Iceberg order can not have an auction"
+    )?;
+
+    validate!(
+        !order.immediate_or_cancel,
+        ErrorCode::InvalidIcebergOrder,
+        "Iceberg order can not be immediate or cancel"
+    )?;
+
+    Ok(())
+}
+
 fn validate_limit_order_auction_params(order: &Order) -> DriftResult {
     if order.has_auction() {
         validate!(
@@ -289,6 +410,10 @@ fn validate_trigger_limit_order(order: &Order, step_size: u64, min_order_size: u
         return Err(ErrorCode::InvalidOrderPostOnly);
     }
 
+    if order.is_trailing_stop() {
+        validate_trailing_stop(order)?;
+    }
+
     if order.has_oracle_price_offset() {
         msg!("Trigger limit can not have oracle offset");
         return Err(ErrorCode::InvalidOrderOracleOffset);
@@ -327,6 +452,10 @@ fn validate_trigger_market_order(
         return Err(ErrorCode::InvalidOrderPostOnly);
     }
 
+    if order.is_trailing_stop() {
+        validate_trailing_stop(order)?;
+    }
+
     if order.has_oracle_price_offset() {
         msg!("Trigger market order can not have oracle offset");
         return Err(ErrorCode::InvalidOrderOracleOffset);
@@ -335,6 +464,25 @@ fn validate_trigger_market_order(
     Ok(())
 }
 
+fn validate_trailing_stop(order: &Order) -> DriftResult {
+    if order.trailing_stop_offset == 0 {
+        msg!("Trailing stop trailing_stop_offset must be greater than 0");
+        return Err(ErrorCode::InvalidOrderOracleOffset);
+    }
+
+    if order.trailing_stop_type == TrailingStopType::Percentage
+        && order.trailing_stop_offset.cast::<u128>()? >= PERCENTAGE_PRECISION
+    {
+        msg!(
+            "Trailing stop percentage ({}) must be less than PERCENTAGE_PRECISION",
+            order.trailing_stop_offset
+        );
+        return Err(ErrorCode::InvalidOrderOracleOffset);
+    }
+
+    Ok(())
+}
+
 fn validate_base_asset_amount(
     order: &Order,
     step_size: u64,
@@ -423,6 +571,13 @@ fn validate_auction_params(order: &Order) -> DriftResult {
 }
 
 pub fn validate_spot_order(order: &Order, step_size: u64, min_order_size: u64) -> DriftResult {
+    if order.is_trailing_stop() && !order.must_be_triggered() {
+        msg!("Only trigger orders can be trailing stops");
+        return Err(ErrorCode::InvalidOrderTrigger);
+    }
+
+    validate_max_slippage(order)?;
+
     match order.order_type {
         OrderType::Market => validate_market_order(order, step_size, min_order_size)?,
         OrderType::Limit => validate_spot_limit_order(order, step_size, min_order_size)?,
@@ -431,6 +586,7 @@ pub fn validate_spot_order(order: &Order, step_size: u64, min_order_size: u64) -
         }
         OrderType::TriggerLimit => validate_trigger_limit_order(order, step_size, min_order_size)?,
         OrderType::Oracle => validate_oracle_order(order, step_size, min_order_size)?,
+        OrderType::Twap => validate_twap_order(order, step_size, min_order_size)?,
     }
 
     Ok(())
@@ -449,9 +605,8 @@ fn validate_spot_limit_order(order: &Order, step_size: u64, min_order_size: u64)
         return Err(ErrorCode::InvalidOrderOracleOffset);
     }
 
-    if order.trigger_price > 0 {
-        msg!("Limit order should not have trigger price");
-        return Err(ErrorCode::InvalidOrderTrigger);
+    if order.is_iceberg() {
+        validate_iceberg_order(order, step_size)?;
     }
 
     if order.post_only {
@@ -467,6 +622,61 @@ fn validate_spot_limit_order(order: &Order, step_size: u64, min_order_size: u64)
     Ok(())
 }
 
+pub fn validate_linked_order(order: &Order, user_orders: &[Order]) -> DriftResult {
+    if order.link_type == OrderLinkType::None {
+        validate!(
+            order.link_id == 0,
+            ErrorCode::InvalidLinkedOrder,
+            "Order with link_id {} must have a link type",
+            order.link_id
+        )?;
+        return Ok(());
+    }
+
+    validate!(
+        order.link_id != 0,
+        ErrorCode::InvalidLinkedOrder,
+        "Linked order must have a non-zero link_id"
+    )?;
+
+    let mut has_parent = false;
+    for linked_order in user_orders.iter() {
+        if linked_order.status != OrderStatus::Open || !linked_order.is_linked_to(order) {
+            continue;
+        }
+
+        validate!(
+            linked_order.market_index == order.market_index
+                && linked_order.market_type == order.market_type,
+            ErrorCode::InvalidLinkedOrder,
+            "Linked orders must be for the same market (order id {} is for {:?} market {})",
+            linked_order.order_id,
+            linked_order.market_type,
+            linked_order.market_index
+        )?;
+
+        has_parent |= linked_order.link_type == OrderLinkType::Parent;
+    }
+
+    match order.link_type {
+        OrderLinkType::Parent => validate!(
+            !has_parent,
+            ErrorCode::InvalidLinkedOrder,
+            "link_id {} already has a parent order",
+            order.link_id
+        )?,
+        OrderLinkType::PendingChild => validate!(
+            has_parent,
+            ErrorCode::InvalidLinkedOrder,
+            "link_id {} has no parent order",
+            order.link_id
+        )?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
 pub fn validate_order_for_force_reduce_only(order: &Order, existing_position: i64) -> DriftResult {
     validate!(
         order.reduce_only,
@@ -494,3 +704,54 @@ pub fn validate_order_for_force_reduce_only(order: &Order, existing_position: i6
 
     Ok(())
 }
+
+/// fill_or_kill orders are only allowed in instructions that fill the order right after placing it
+pub fn validate_fill_or_kill_order_params(
+    params: &OrderParams,
+    fills_after_place: bool,
+) -> DriftResult {
+    if !params.fill_or_kill {
+        return Ok(());
+    }
+
+    validate!(
+        fills_after_place,
+        ErrorCode::InvalidOrderFillOrKill,
+        "fill_or_kill order must be in place_and_take"
+    )?;
+
+    validate!(
+        params.order_type != OrderType::Twap,
+        ErrorCode::InvalidOrderFillOrKill,
+        "fill_or_kill cant be used with twap orders"
+    )?;
+
+    // the order is resized or only partly displayed after placement, so the placed size isn't the size to fill
+    validate!(
+        params.display_base_asset_amount == 0,
+        ErrorCode::InvalidOrderFillOrKill,
+        "fill_or_kill cant be used with iceberg orders"
+    )?;
+
+    validate!(
+        !params.close_position,
+        ErrorCode::InvalidOrderFillOrKill,
+        "fill_or_kill cant be used with close position orders"
+    )?;
+
+    Ok(())
+}
+
+/// Fails the whole instruction so no partial fill is left on the user
+pub fn validate_fill_or_kill_order_filled(
+    base_asset_amount_filled: u64,
+    order_base_asset_amount: u64,
+) -> DriftResult {
+    validate!(
+        base_asset_amount_filled >= order_base_asset_amount,
+        ErrorCode::FillOrKillOrderNotFilled,
+        "fill_or_kill order filled {} of {}",
+        base_asset_amount_filled,
+        order_base_asset_amount
+    )
+}
@@ -0,0 +1,76 @@
+mod fill_or_kill {
+    use crate::error::ErrorCode;
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::state::order_params::OrderParams;
+    use crate::state::user::OrderType;
+    use crate::validation::order::{
+        validate_fill_or_kill_order_filled, validate_fill_or_kill_order_params,
+    };
+
+    #[test]
+    fn params() {
+        let params = OrderParams {
+            order_type: OrderType::Market,
+            fill_or_kill: true,
+            ..OrderParams::default()
+        };
+
+        // place_and_take
+        assert!(validate_fill_or_kill_order_params(&params, true).is_ok());
+        // place_perp_order, place_orders, place_and_make etc
+        assert_eq!(
+            validate_fill_or_kill_order_params(&params, false),
+            Err(ErrorCode::InvalidOrderFillOrKill)
+        );
+
+        let twap_params = OrderParams {
+            order_type: OrderType::Twap,
+            ..params
+        };
+        assert_eq!(
+            validate_fill_or_kill_order_params(&twap_params, true),
+            Err(ErrorCode::InvalidOrderFillOrKill)
+        );
+
+        // only the displayed slice would be checked against the fill
+        let iceberg_params = OrderParams {
+            order_type: OrderType::Limit,
+            base_asset_amount: 10 * BASE_PRECISION_U64,
+            display_base_asset_amount: BASE_PRECISION_U64,
+            ..params
+        };
+        assert_eq!(
+            validate_fill_or_kill_order_params(&iceberg_params, true),
+            Err(ErrorCode::InvalidOrderFillOrKill)
+        );
+
+        let close_position_params = OrderParams {
+            close_position: true,
+            reduce_only: true,
+            ..params
+        };
+        assert_eq!(
+            validate_fill_or_kill_order_params(&close_position_params, true),
+            Err(ErrorCode::InvalidOrderFillOrKill)
+        );
+
+        let params = OrderParams {
+            fill_or_kill: false,
+            ..params
+        };
+        assert!(validate_fill_or_kill_order_params(&params, false).is_ok());
+    }
+
+    #[test]
+    fn filled() {
+        assert!(validate_fill_or_kill_order_filled(BASE_PRECISION_U64, BASE_PRECISION_U64).is_ok());
+        assert_eq!(
+            validate_fill_or_kill_order_filled(BASE_PRECISION_U64 / 2, BASE_PRECISION_U64),
+            Err(ErrorCode::FillOrKillOrderNotFilled)
+        );
+        assert_eq!(
+            validate_fill_or_kill_order_filled(0, BASE_PRECISION_U64),
+            Err(ErrorCode::FillOrKillOrderNotFilled)
+        );
+    }
+}
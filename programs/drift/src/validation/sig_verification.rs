@@ -0,0 +1,179 @@
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar::instructions;
+use arrayref::array_ref;
+use solana_program::msg;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::safe_math::SafeMath;
+use crate::state::order_params::{OrderParams, SignedOrderParamsMessage, SignedQuote};
+use crate::state::user::OrderLinkType;
+use crate::state::user::User;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+const SIGNATURE_OFFSETS_START: usize = 2;
+const SIGNATURE_OFFSETS_SIZE: usize = 14;
+const PUBKEY_SIZE: usize = 32;
+/// instruction index the ed25519 program uses for data in its own instruction
+const CURRENT_INSTRUCTION_INDEX: u16 = u16::MAX;
+
+/// Returns the signer and message of an ed25519 program instruction verifying a single signature.
+/// The runtime has already verified the signature, so only the offsets are checked. The signature,
+/// pubkey and message must all be in the instruction's own data
+pub fn get_ed25519_signer_and_message(ix: &Instruction) -> DriftResult<(Pubkey, &[u8])> {
+    validate!(
+        ix.program_id == ed25519_program::id(),
+        ErrorCode::InvalidEd25519Instruction,
+        "instruction is not an ed25519 program instruction"
+    )?;
+
+    let data = &ix.data;
+    validate!(
+        data.len() >= SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_SIZE && data[0] == 1,
+        ErrorCode::InvalidEd25519Instruction,
+        "ed25519 instruction must verify exactly one signature"
+    )?;
+
+    let read_u16 =
+        |offset: usize| u16::from_le_bytes(*array_ref![data, SIGNATURE_OFFSETS_START + offset, 2]);
+    let signature_instruction_index = read_u16(2);
+    let public_key_offset = read_u16(4) as usize;
+    let public_key_instruction_index = read_u16(6);
+    let message_data_offset = read_u16(8) as usize;
+    let message_data_size = read_u16(10) as usize;
+    let message_instruction_index = read_u16(12);
+
+    validate!(
+        signature_instruction_index == CURRENT_INSTRUCTION_INDEX
+            && public_key_instruction_index == CURRENT_INSTRUCTION_INDEX
+            && message_instruction_index == CURRENT_INSTRUCTION_INDEX,
+        ErrorCode::InvalidEd25519Instruction,
+        "ed25519 signature, pubkey and message must be in the ed25519 instruction"
+    )?;
+
+    let public_key_end = public_key_offset.safe_add(PUBKEY_SIZE)?;
+    let message_data_end = message_data_offset.safe_add(message_data_size)?;
+    validate!(
+        public_key_end <= data.len() && message_data_end <= data.len(),
+        ErrorCode::InvalidEd25519Instruction,
+        "ed25519 instruction offsets out of bounds"
+    )?;
+
+    let signer = Pubkey::new_from_array(*array_ref![data, public_key_offset, PUBKEY_SIZE]);
+
+    Ok((signer, &data[message_data_offset..message_data_end]))
+}
+
+/// Signer and message of the ed25519 program instruction right before the current instruction
+pub fn get_preceding_ed25519_signer_and_message(
+    instructions_sysvar: &AccountInfo,
+) -> DriftResult<(Pubkey, Vec<u8>)> {
+    let current_index = instructions::load_current_index_checked(instructions_sysvar)
+        .or(Err(ErrorCode::InvalidEd25519Instruction))? as usize;
+    validate!(
+        current_index > 0,
+        ErrorCode::InvalidEd25519Instruction,
+        "instruction must follow an ed25519 instruction"
+    )?;
+
+    let ix = instructions::load_instruction_at_checked(current_index - 1, instructions_sysvar)
+        .or(Err(ErrorCode::InvalidEd25519Instruction))?;
+    let (signer, message) = get_ed25519_signer_and_message(&ix)?;
+
+    Ok((signer, message.to_vec()))
+}
+
+pub fn validate_signed_quote(
+    quote: &SignedQuote,
+    signer: &Pubkey,
+    maker_key: &Pubkey,
+    maker: &User,
+    taker_key: &Pubkey,
+    taker_params: &OrderParams,
+    now: i64,
+) -> DriftResult {
+    validate!(
+        &quote.maker == maker_key,
+        ErrorCode::InvalidSignedQuote,
+        "quote maker ({:?}) does not match maker account ({:?})",
+        quote.maker,
+        maker_key
+    )?;
+
+    validate!(
+        signer == &maker.authority
+            || (signer == &maker.delegate && maker.delegate != Pubkey::default()),
+        ErrorCode::InvalidSignedQuote,
+        "quote signer ({:?}) is not the maker's authority or delegate",
+        signer
+    )?;
+
+    validate!(
+        maker_key != taker_key,
+        ErrorCode::InvalidSignedQuote,
+        "maker and taker can not be the same user"
+    )?;
+
+    validate!(
+        quote.market_index == taker_params.market_index
+            && quote.direction == taker_params.direction.opposite(),
+        ErrorCode::InvalidSignedQuote,
+        "taker order must be for market {} on the other side of the quote",
+        quote.market_index
+    )?;
+
+    validate!(
+        now <= quote.expiry_ts,
+        ErrorCode::SignedQuoteExpired,
+        "quote expired at {} (now = {})",
+        quote.expiry_ts,
+        now
+    )?;
+
+    Ok(())
+}
+
+pub fn validate_signed_order_params_message(
+    message: &SignedOrderParamsMessage,
+    signer: &Pubkey,
+    user_key: &Pubkey,
+    user: &User,
+    slot: u64,
+) -> DriftResult {
+    validate!(
+        &message.user == user_key,
+        ErrorCode::InvalidSignedOrder,
+        "signed order user ({:?}) does not match user account ({:?})",
+        message.user,
+        user_key
+    )?;
+
+    validate!(
+        signer == &user.authority
+            || (signer == &user.delegate && user.delegate != Pubkey::default()),
+        ErrorCode::InvalidSignedOrder,
+        "signed order signer ({:?}) is not the user's authority or delegate",
+        signer
+    )?;
+
+    validate!(
+        slot <= message.expiry_slot,
+        ErrorCode::SignedOrderExpired,
+        "signed order expired at slot {} (slot = {})",
+        message.expiry_slot,
+        slot
+    )?;
+
+    validate!(
+        !message.has_trigger_orders()
+            || message.signed_order_params.link_type == OrderLinkType::None,
+        ErrorCode::InvalidSignedOrder,
+        "signed order with take profit or stop loss can not set its own link type"
+    )?;
+
+    Ok(())
+}
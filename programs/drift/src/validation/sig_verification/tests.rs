@@ -0,0 +1,284 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+
+use crate::controller::position::PositionDirection;
+use crate::math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+use crate::state::order_params::{
+    OrderParams, SignedOrderParamsMessage, SignedOrderTriggerParams, SignedQuote,
+};
+use crate::state::user::{OrderLinkType, User};
+use crate::validation::sig_verification::{
+    get_ed25519_signer_and_message, validate_signed_order_params_message, validate_signed_quote,
+};
+
+fn ed25519_ix(signer: &Pubkey, message: &[u8], instruction_index: u16) -> Instruction {
+    let public_key_offset: u16 = 16;
+    let signature_offset: u16 = public_key_offset + 32;
+    let message_data_offset: u16 = signature_offset + 64;
+
+    let mut data = vec![1_u8, 0];
+    for value in [
+        signature_offset,
+        instruction_index,
+        public_key_offset,
+        instruction_index,
+        message_data_offset,
+        message.len() as u16,
+        instruction_index,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&signer.to_bytes());
+    data.extend_from_slice(&[0; 64]);
+    data.extend_from_slice(message);
+
+    Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    }
+}
+
+#[test]
+fn signer_and_message() {
+    let signer = Pubkey::new_unique();
+    let message = [1, 2, 3, 4];
+
+    let ix = ed25519_ix(&signer, &message, u16::MAX);
+    let (ix_signer, ix_message) = get_ed25519_signer_and_message(&ix).unwrap();
+    assert_eq!(ix_signer, signer);
+    assert_eq!(ix_message, message);
+
+    // message in another instruction
+    let ix = ed25519_ix(&signer, &message, 0);
+    assert!(get_ed25519_signer_and_message(&ix).is_err());
+
+    // not the ed25519 program
+    let ix = Instruction {
+        program_id: Pubkey::new_unique(),
+        ..ed25519_ix(&signer, &message, u16::MAX)
+    };
+    assert!(get_ed25519_signer_and_message(&ix).is_err());
+
+    // message out of bounds
+    let mut ix = ed25519_ix(&signer, &message, u16::MAX);
+    ix.data.truncate(ix.data.len() - 1);
+    assert!(get_ed25519_signer_and_message(&ix).is_err());
+}
+
+#[test]
+fn signed_quote() {
+    let maker_key = Pubkey::new_unique();
+    let taker_key = Pubkey::new_unique();
+    let maker = User {
+        authority: Pubkey::new_unique(),
+        delegate: Pubkey::new_unique(),
+        ..User::default()
+    };
+
+    let quote = SignedQuote {
+        maker: maker_key,
+        market_index: 0,
+        direction: PositionDirection::Short,
+        base_asset_amount: BASE_PRECISION_U64,
+        price: 100 * PRICE_PRECISION_U64,
+        expiry_ts: 100,
+        nonce: 1,
+    };
+
+    // quote round trips through the signed message
+    let ix = ed25519_ix(&maker.authority, &quote.try_to_vec().unwrap(), u16::MAX);
+    let (signer, message) = get_ed25519_signer_and_message(&ix).unwrap();
+    let quote = SignedQuote::try_from_slice(message).unwrap();
+
+    let taker_params = OrderParams {
+        direction: PositionDirection::Long,
+        market_index: 0,
+        base_asset_amount: BASE_PRECISION_U64,
+        ..OrderParams::default()
+    };
+
+    validate_signed_quote(
+        &quote,
+        &signer,
+        &maker_key,
+        &maker,
+        &taker_key,
+        &taker_params,
+        100,
+    )
+    .unwrap();
+
+    // delegate can sign
+    validate_signed_quote(
+        &quote,
+        &maker.delegate,
+        &maker_key,
+        &maker,
+        &taker_key,
+        &taker_params,
+        100,
+    )
+    .unwrap();
+
+    // wrong signer
+    assert!(validate_signed_quote(
+        &quote,
+        &Pubkey::new_unique(),
+        &maker_key,
+        &maker,
+        &taker_key,
+        &taker_params,
+        100,
+    )
+    .is_err());
+
+    // unset delegate can't sign
+    let maker_without_delegate = User {
+        delegate: Pubkey::default(),
+        ..maker
+    };
+    assert!(validate_signed_quote(
+        &quote,
+        &Pubkey::default(),
+        &maker_key,
+        &maker_without_delegate,
+        &taker_key,
+        &taker_params,
+        100,
+    )
+    .is_err());
+
+    // expired
+    assert!(validate_signed_quote(
+        &quote,
+        &signer,
+        &maker_key,
+        &maker,
+        &taker_key,
+        &taker_params,
+        101,
+    )
+    .is_err());
+
+    // taker on same side as maker
+    let same_side_params = OrderParams {
+        direction: PositionDirection::Short,
+        ..taker_params
+    };
+    assert!(validate_signed_quote(
+        &quote,
+        &signer,
+        &maker_key,
+        &maker,
+        &taker_key,
+        &same_side_params,
+        100,
+    )
+    .is_err());
+
+    // self fill
+    assert!(validate_signed_quote(
+        &quote,
+        &signer,
+        &maker_key,
+        &maker,
+        &maker_key,
+        &taker_params,
+        100,
+    )
+    .is_err());
+}
+
+#[test]
+fn signed_order_params_message() {
+    let user_key = Pubkey::new_unique();
+    let user = User {
+        authority: Pubkey::new_unique(),
+        delegate: Pubkey::new_unique(),
+        ..User::default()
+    };
+
+    let signed_message = SignedOrderParamsMessage {
+        signed_order_params: OrderParams {
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            ..OrderParams::default()
+        },
+        user: user_key,
+        expiry_slot: 100,
+        nonce: 1,
+        take_profit_order_params: Some(SignedOrderTriggerParams {
+            trigger_price: 110 * PRICE_PRECISION_U64,
+            base_asset_amount: BASE_PRECISION_U64,
+        }),
+        stop_loss_order_params: None,
+    };
+
+    // message round trips through the signed message
+    let ix = ed25519_ix(
+        &user.delegate,
+        &signed_message.try_to_vec().unwrap(),
+        u16::MAX,
+    );
+    let (signer, message) = get_ed25519_signer_and_message(&ix).unwrap();
+    let signed_message = SignedOrderParamsMessage::try_from_slice(message).unwrap();
+
+    validate_signed_order_params_message(&signed_message, &signer, &user_key, &user, 100).unwrap();
+
+    // expired
+    assert!(
+        validate_signed_order_params_message(&signed_message, &signer, &user_key, &user, 101)
+            .is_err()
+    );
+
+    // wrong user
+    assert!(validate_signed_order_params_message(
+        &signed_message,
+        &signer,
+        &Pubkey::new_unique(),
+        &user,
+        100
+    )
+    .is_err());
+
+    // wrong signer
+    assert!(validate_signed_order_params_message(
+        &signed_message,
+        &Pubkey::new_unique(),
+        &user_key,
+        &user,
+        100
+    )
+    .is_err());
+
+    // unset delegate can't sign
+    let user_without_delegate = User {
+        delegate: Pubkey::default(),
+        ..user
+    };
+    assert!(validate_signed_order_params_message(
+        &signed_message,
+        &Pubkey::default(),
+        &user_key,
+        &user_without_delegate,
+        100
+    )
+    .is_err());
+
+    // take profit needs the link
+    let signed_message = SignedOrderParamsMessage {
+        signed_order_params: OrderParams {
+            link_id: 1,
+            link_type: OrderLinkType::OneCancelsOther,
+            ..signed_message.signed_order_params
+        },
+        ..signed_message
+    };
+    assert!(
+        validate_signed_order_params_message(&signed_message, &signer, &user_key, &user, 100)
+            .is_err()
+    );
+}
@@ -15,7 +15,7 @@ export function getUserFilter(): MemcmpFilter {
 export function getNonIdleUserFilter(): MemcmpFilter {
 	return {
 		memcmp: {
-			offset: 4350,
+			offset: 12606,
 			bytes: bs58.encode(Uint8Array.from([0])),
 		},
 	};
@@ -24,7 +24,7 @@ export function getNonIdleUserFilter(): MemcmpFilter {
 export function getUserWithOrderFilter(): MemcmpFilter {
 	return {
 		memcmp: {
-			offset: 4352,
+			offset: 12608,
 			bytes: bs58.encode(Uint8Array.from([1])),
 		},
 	};
@@ -33,7 +33,7 @@ export function getUserWithOrderFilter(): MemcmpFilter {
 export function getUserWithAuctionFilter(): MemcmpFilter {
 	return {
 		memcmp: {
-			offset: 4354,
+			offset: 12610,
 			bytes: bs58.encode(Uint8Array.from([1])),
 		},
 	};
@@ -42,7 +42,7 @@ export function getUserWithAuctionFilter(): MemcmpFilter {
 export function getUserThatHasBeenLP(): MemcmpFilter {
 	return {
 		memcmp: {
-			offset: 4267,
+			offset: 12523,
 			bytes: bs58.encode(Uint8Array.from([99])),
 		},
 	};
@@ -78,9 +78,12 @@ export class OracleSource {
 	static readonly PYTH = { pyth: {} };
 	static readonly PYTH_1K = { pyth1K: {} };
 	static readonly PYTH_1M = { pyth1M: {} };
-	// static readonly SWITCHBOARD = { switchboard: {} };
+	static readonly SWITCHBOARD = { switchboard: {} };
 	static readonly QUOTE_ASSET = { quoteAsset: {} };
 	static readonly PYTH_STABLE_COIN = { pythStableCoin: {} };
+	static readonly COMPOSITE = { composite: {} };
+	static readonly PYTH_PULL = { pythPull: {} };
+	static readonly PRELAUNCH = { prelaunch: {} };
 }
 
 export class OrderType {
@@ -89,9 +92,18 @@ export class OrderType {
 	static readonly TRIGGER_LIMIT = { triggerLimit: {} };
 	static readonly MARKET = { market: {} };
 	static readonly ORACLE = { oracle: {} };
+	static readonly TWAP = { twap: {} };
 }
 
 export declare type MarketTypeStr = 'perp' | 'spot';
+export class SelfTradePreventionMode {
+	static readonly NONE = { none: {} };
+	static readonly CANCEL_MAKER = { cancelMaker: {} };
+	static readonly CANCEL_TAKER = { cancelTaker: {} };
+	static readonly CANCEL_BOTH = { cancelBoth: {} };
+	static readonly DECREMENT_AND_CANCEL = { decrementAndCancel: {} };
+}
+
 export class MarketType {
 	static readonly SPOT = { spot: {} };
 	static readonly PERP = { perp: {} };
@@ -160,6 +172,27 @@ export class OrderActionExplanation {
 	static readonly REDUCE_ONLY_ORDER_INCREASED_POSITION = {
 		reduceOnlyOrderIncreasedPosition: {},
 	};
+	static readonly CANCELED_BY_LINKED_ORDER = {
+		canceledByLinkedOrder: {},
+	};
+	static readonly SELF_TRADE_PREVENTION = {
+		selfTradePrevention: {},
+	};
+	static readonly HEARTBEAT_EXPIRED = {
+		heartbeatExpired: {},
+	};
+	static readonly SIGNED_QUOTE = {
+		signedQuote: {},
+	};
+	static readonly ORDER_FILLED_WITH_BATCH_AUCTION = {
+		orderFilledWithBatchAuction: {},
+	};
+	static readonly MAX_SLIPPAGE_EXCEEDED = {
+		maxSlippageExceeded: {},
+	};
+	static readonly ORDER_BOOK_INDEX_FULL = {
+		orderBookIndexFull: {},
+	};
 }
 
 export class OrderTriggerCondition {
@@ -169,6 +202,27 @@ export class OrderTriggerCondition {
 	static readonly TRIGGERED_BELOW = { triggeredBelow: {} }; // below condition has been triggered
 }
 
+export class TrailingStopType {
+	static readonly NONE = { none: {} };
+	static readonly FIXED_OFFSET = { fixedOffset: {} }; // trigger price trails oracle by oraclePriceOffset
+	static readonly PERCENTAGE = { percentage: {} }; // trigger price trails oracle by oraclePriceOffset percent
+}
+
+export class TriggerPriceSource {
+	static readonly ORACLE = { oracle: {} };
+	static readonly PERP_ORACLE = { perpOracle: {} };
+	static readonly SPOT_ORACLE = { spotOracle: {} };
+	static readonly PERP_MARK_PRICE = { perpMarkPrice: {} };
+	static readonly PERP_MARK_PRICE_TWAP = { perpMarkPriceTwap: {} };
+}
+
+export class OrderLinkType {
+	static readonly NONE = { none: {} };
+	static readonly ONE_CANCELS_OTHER = { oneCancelsOther: {} }; // fill or trigger cancels other oco orders with same linkId
+	static readonly PARENT = { parent: {} }; // first fill activates pending children with same linkId
+	static readonly PENDING_CHILD = { pendingChild: {} }; // becomes oco once parent fills
+}
+
 export class SpotFulfillmentType {
 	static readonly EXTERNAL = { external: {} };
 	static readonly MATCH = { match: {} };
@@ -528,6 +582,22 @@ export type SwapRecord = {
 	fee: BN;
 };
 
+export class ModifyOrderResult {
+	static readonly MODIFIED = { modified: {} };
+	static readonly SKIPPED = { skipped: {} };
+	static readonly FAILED = { failed: {} };
+}
+
+export type ModifyOrderRecord = {
+	ts: BN;
+	user: PublicKey;
+	orderId: number;
+	userOrderId: number;
+	clientOrderId: BN;
+	newOrderId: number;
+	result: ModifyOrderResult;
+};
+
 export type StateAccount = {
 	admin: PublicKey;
 	exchangeStatus: number;
@@ -587,6 +657,10 @@ export type PerpMarketAccount = {
 	};
 	quoteSpotMarketIndex: number;
 	feeAdjustment: number;
+	marginConfidenceMultiplier: number;
+	batchAuctionDuration: number;
+	lastBatchAuctionClearSlot: BN;
+	oracleFeedId: number[];
 };
 
 export type HistoricalOracleData = {
@@ -658,6 +732,8 @@ export type SpotMarketAccount = {
 	liquidatorFee: number;
 	imfFactor: number;
 	scaleInitialAssetWeightStart: BN;
+	marginConfidenceMultiplier: number;
+	oracleFeedId: number[];
 
 	withdrawGuardThreshold: BN;
 	depositTokenTwap: BN;
@@ -775,6 +851,9 @@ export type AMM = {
 	askQuoteAssetReserve: BN;
 
 	perLpBase: number; // i8
+
+	maxInventorySkew: number;
+	inventorySkew: number;
 };
 
 // # User Account Types
@@ -817,6 +896,10 @@ export type UserStatsAccount = {
 	isReferrer: boolean;
 	authority: PublicKey;
 	ifStakedQuoteAssetAmount: BN;
+	lastQuoteNonce: BN;
+	quoteNonceBitmap: BN;
+	lastSignedOrderNonce: BN;
+	signedOrderNonceBitmap: BN;
 };
 
 export type UserAccount = {
@@ -846,6 +929,9 @@ export type UserAccount = {
 	hasOpenOrder: boolean;
 	openAuctions: number;
 	hasOpenAuction: boolean;
+	selfTradePreventionMode: SelfTradePreventionMode;
+	heartbeatTimeout: number;
+	lastHeartbeatTs: BN;
 };
 
 export type SpotPosition = {
@@ -883,6 +969,20 @@ export type Order = {
 	auctionStartPrice: BN;
 	auctionEndPrice: BN;
 	maxTs: BN;
+	trailingStopType: TrailingStopType;
+	linkId: number;
+	linkType: OrderLinkType;
+	clientOrderId: BN;
+	twapNextSliceTs: BN;
+	hiddenBaseAssetAmount: BN;
+	sliceBaseAssetAmount: BN;
+	trailingStopOffset: number;
+	twapInterval: number;
+	maxSlippageBps: number;
+	twapSlicesRemaining: number;
+	triggerMarketIndex: number;
+	closePosition: boolean;
+	triggerPriceSource: TriggerPriceSource;
 };
 
 export type OrderParams = {
@@ -903,6 +1003,24 @@ export type OrderParams = {
 	maxTs: BN | null;
 	auctionStartPrice: BN | null;
 	auctionEndPrice: BN | null;
+	trailingStopType: TrailingStopType;
+	trailingStopOffset: number;
+	linkId: number;
+	linkType: OrderLinkType;
+	twapSliceCount: number;
+	twapInterval: number;
+	fillOrKill: boolean;
+	triggerPriceSource: TriggerPriceSource;
+	triggerMarketIndex: number;
+	displayBaseAssetAmount: BN;
+	closePosition: boolean;
+	maxSlippageBps: number | null;
+	clientOrderId: BN;
+};
+
+export type ClientOrderIdPrefix = {
+	prefix: BN;
+	bits: number;
 };
 
 export class PostOnlyParams {
@@ -932,6 +1050,14 @@ export class ModifyOrderPolicy {
 	static readonly TRY_MODIFY = { tryModify: {} };
 }
 
+export type BatchModifyOrderParams = {
+	orderId: number | null;
+	clientOrderId: BN | null;
+	userOrderId: number;
+	modifyOrderParams: ModifyOrderParams;
+	replacementOrderParams: OrderParams | null;
+};
+
 export const DefaultOrderParams: OrderParams = {
 	orderType: OrderType.MARKET,
 	marketType: MarketType.PERP,
@@ -950,6 +1076,64 @@ export const DefaultOrderParams: OrderParams = {
 	maxTs: null,
 	auctionStartPrice: null,
 	auctionEndPrice: null,
+	trailingStopType: TrailingStopType.NONE,
+	trailingStopOffset: 0,
+	linkId: 0,
+	linkType: OrderLinkType.NONE,
+	twapSliceCount: 0,
+	twapInterval: 0,
+	fillOrKill: false,
+	triggerPriceSource: TriggerPriceSource.ORACLE,
+	triggerMarketIndex: 0,
+	displayBaseAssetAmount: ZERO,
+	closePosition: false,
+	maxSlippageBps: null,
+	clientOrderId: ZERO,
+};
+
+export class SizeDistribution {
+	static readonly FLAT = { flat: {} };
+	static readonly ASCENDING = { ascending: {} };
+	static readonly DESCENDING = { descending: {} };
+}
+
+export type ScaleOrderParams = {
+	marketType: MarketType;
+	direction: PositionDirection;
+	marketIndex: number;
+	totalBaseAssetAmount: BN;
+	startPrice: BN;
+	endPrice: BN;
+	orderCount: number;
+	sizeDistribution: SizeDistribution;
+	reduceOnly: boolean;
+	postOnly: PostOnlyParams;
+	maxTs: BN | null;
+	cancelExistingOrders: boolean;
+};
+
+export type SignedQuote = {
+	maker: PublicKey;
+	marketIndex: number;
+	direction: PositionDirection;
+	baseAssetAmount: BN;
+	price: BN;
+	expiryTs: BN;
+	nonce: BN;
+};
+
+export type SignedOrderTriggerParams = {
+	triggerPrice: BN;
+	baseAssetAmount: BN;
+};
+
+export type SignedOrderParamsMessage = {
+	signedOrderParams: OrderParams;
+	user: PublicKey;
+	expirySlot: BN;
+	nonce: BN;
+	takeProfitOrderParams: SignedOrderTriggerParams | null;
+	stopLossOrderParams: SignedOrderTriggerParams | null;
 };
 
 export type MakerInfo = {
@@ -1081,6 +1265,21 @@ export type PhoenixV1FulfillmentConfigAccount = {
 	status: SpotFulfillmentStatus;
 };
 
+export type OrderBookIndexEntry = {
+	user: PublicKey;
+	price: BN;
+	baseAssetAmount: BN;
+	orderId: number;
+};
+
+export type PerpOrderBookIndexAccount = {
+	perpMarketIndex: number;
+	bidsLen: number;
+	asksLen: number;
+	bids: OrderBookIndexEntry[];
+	asks: OrderBookIndexEntry[];
+};
+
 export type ReferrerNameAccount = {
 	name: number[];
 	user: PublicKey;